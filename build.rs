@@ -0,0 +1,24 @@
+// Builds the liblxc stand-in from tests/stub when the `lxc-stub` feature is
+// enabled, so the crate links against it instead of the system liblxc.
+
+#[cfg(feature = "lxc-stub")]
+extern crate cc;
+
+#[cfg(feature = "lxc-stub")]
+fn main() {
+  use std::env;
+
+  println!("cargo:rerun-if-changed=tests/stub/lxc.c");
+  cc::Build::new()
+    .file("tests/stub/lxc.c")
+    .flag_if_supported("-std=gnu99")
+    .warnings(true)
+    .cargo_metadata(false)
+    .compile("lxc");
+  let out_dir = env::var("OUT_DIR").unwrap();
+  println!("cargo:rustc-link-search=native={}", out_dir);
+  println!("cargo:rustc-link-lib=pthread");
+}
+
+#[cfg(not(feature = "lxc-stub"))]
+fn main() {}
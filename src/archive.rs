@@ -0,0 +1,425 @@
+//! Portable container archives, enabled with the `archive` feature.
+//!
+//! `LxcContainer::export` writes a dir-backed container to a tar archive, and
+//! `LxcContainer::import` recreates it, possibly on another host, under a new name.
+//! The archive mirrors the container directory:
+//!
+//! ```text
+//! manifest.json             ArchiveManifest
+//! config                    configuration, as saved by liblxc
+//! rootfs/...                root filesystem
+//! snaps/snapN/config        each snapshot, if exported with snapshots
+//! snaps/snapN/comment
+//! snaps/snapN/rootfs/...
+//! ```
+//!
+//! On import the configuration is rewritten the way `clone` does it: paths under
+//! the old container directory move to the new one, the hostname becomes the new
+//! name, also in the rootfs' `/etc/hostname`, and network interfaces get new MAC
+//! addresses.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::features::config_key;
+use crate::image::{io_error, open_tarball};
+use super::{version, LxcContainer, LxcSnapshot};
+
+/// Version of the archive layout written by `export`.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Name of the manifest in an archive.
+const MANIFEST: &str = "manifest.json";
+
+/// How `LxcContainer::export` archives a container.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+  /// Archive the snapshots of the container too.
+  pub snapshots: bool,
+  /// Freeze a running container while it is archived, instead of stopping it.
+  pub freeze: bool,
+  /// Compress the archive with gzip.
+  pub gzip: bool,
+}
+
+/// Snapshot stored in an archive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedSnapshot {
+  /// Name of the snapshot, for example "snap0".
+  pub name: String,
+  /// Time the snapshot was created, formatted as "YYYY:MM:DD HH:MM:SS".
+  pub timestamp: String,
+  /// Whether the snapshot has a comment.
+  pub comment: bool,
+}
+
+/// Description of an archive, stored first in it as `manifest.json`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+  /// Version of the archive layout, `ARCHIVE_VERSION` when written by this crate.
+  pub version: u32,
+  /// Name of the exported container.
+  pub name: String,
+  /// lxcpath the container was exported from.
+  pub lxcpath: String,
+  /// Version of liblxc the container was exported with.
+  pub lxc_version: String,
+  /// Time of the export, in seconds since the epoch.
+  pub created: u64,
+  /// Snapshots in the archive, oldest first.
+  pub snapshots: Vec<ArchivedSnapshot>,
+}
+
+impl LxcContainer {
+
+  /// Archive the container, with the dir backing store, to `path`.
+  ///
+  /// # Parameters
+  /// `path` - file to write the archive to.
+  ///
+  /// `options` - what to archive, and how to deal with a running container.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the manifest of the archive, else `Err` with a description
+  /// of the error. On failure the archive is removed again.
+  ///
+  /// ## Note
+  /// A running container is stopped, or frozen if `options.freeze` is set, and
+  /// started or unfrozen again once it is archived. It is started without
+  /// arguments, so a container started with a custom command line is better
+  /// stopped beforehand.
+  pub fn export<P: AsRef<Path>>(&self, path: P, options: &ExportOptions) -> Result<ArchiveManifest, String> {
+    let path = path.as_ref();
+    if !self.is_defined() {
+      return Err(format!("Container {} doesn't exist", self.name()));
+    }
+    let running = self.is_running();
+    if running {
+      let paused = if options.freeze { self.freeze() } else { self.stop() };
+      if !paused {
+        return Err(format!("Failed to {} container {}", if options.freeze { "freeze" } else { "stop" }, self.name()));
+      }
+    }
+
+    let result = self.write_archive(path, options);
+    if result.is_err() {
+      let _ = fs::remove_file(path);
+    }
+    if running {
+      let resumed = if options.freeze { self.unfreeze() } else { self.start(0, None) };
+      if !resumed && result.is_ok() {
+        return Err(format!("Archived, but failed to {} container {} again",
+                           if options.freeze { "unfreeze" } else { "start" }, self.name()));
+      }
+    }
+    result
+  }
+
+  /// Recreate an archived container as `name` in `lxcpath_option`.
+  ///
+  /// # Parameters
+  /// `path` - archive written by `export`, gzip-compressed or not.
+  ///
+  /// `name` - name of the new container.
+  ///
+  /// `lxcpath_option` - `Option` with the lxcpath of the new container. If `None`,
+  /// the default lxcpath is used.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the new container, else `Err` with a description of the
+  /// error. On failure nothing is left of the new container.
+  pub fn import<P: AsRef<Path>>(path: P, name: &str, lxcpath_option: Option<&str>) -> Result<LxcContainer, String> {
+    let path = path.as_ref();
+    let container = LxcContainer::new(name, lxcpath_option)?;
+    let dir = Path::new(&container.get_config_path()).join(name);
+    let mut manifest = None;
+    container.create_with_rootfs(|rootfs| {
+      let unpacked = unpack_archive(path, &dir)?;
+      let rewrite = Rewrite::new(&unpacked, &dir, name);
+      rewrite.hostname(rootfs)?;
+      let items = rewrite.items(&dir.join("config"))?;
+      manifest = Some(unpacked);
+      Ok(items)
+    })?;
+    let manifest = match manifest {
+      Some(manifest) => manifest,
+      None => unreachable!("the container was created"),
+    };
+    let rewrite = Rewrite::new(&manifest, &dir, name);
+    for snapshot in manifest.snapshots.iter() {
+      if let Err(e) = rewrite.snapshot(&dir, snapshot) {
+        container.destroy_with_snapshots();
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+      }
+    }
+    Ok(container)
+  }
+
+  fn write_archive(&self, path: &Path, options: &ExportOptions) -> Result<ArchiveManifest, String> {
+    let snapshots = if options.snapshots { self.snapshot_list()? } else { Vec::new() };
+    let manifest = ArchiveManifest {
+      version: ARCHIVE_VERSION,
+      name: self.name(),
+      lxcpath: self.get_config_path(),
+      lxc_version: version(),
+      created: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+      snapshots: snapshots.iter().map(|s| ArchivedSnapshot {
+        name: s.name.clone(),
+        timestamp: s.timestamp.clone(),
+        comment: s.comment_pathname.is_some(),
+      }).collect(),
+    };
+
+    let file = File::create(path).map_err(|e| io_error(path, e))?;
+    let error = |e: io::Error| io_error(path, e);
+    if options.gzip {
+      let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+      self.append_entries(&mut builder, path, &manifest, &snapshots)?;
+      builder.into_inner().and_then(|encoder| encoder.finish()).map_err(error)?;
+    }
+    else {
+      let mut builder = tar::Builder::new(file);
+      self.append_entries(&mut builder, path, &manifest, &snapshots)?;
+      builder.into_inner().and_then(|mut file| file.flush()).map_err(error)?;
+    }
+    Ok(manifest)
+  }
+
+  fn append_entries<W: Write>(&self, builder: &mut tar::Builder<W>, path: &Path,
+                              manifest: &ArchiveManifest, snapshots: &[LxcSnapshot]) -> Result<(), String> {
+    let error = |e: io::Error| io_error(path, e);
+    builder.follow_symlinks(false);
+    let json = ::serde_json::to_vec_pretty(manifest).map_err(|e| e.to_string())?;
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_size(json.len() as u64);
+    header.set_mtime(manifest.created);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST, &json[..]).map_err(error)?;
+    append_container(builder, path, self, Path::new(""))?;
+    for snapshot in snapshots.iter() {
+      let archived = Path::new("snaps").join(&snapshot.name);
+      let container = LxcContainer::new(&snapshot.name, Some(&snapshot.lxcpath))?;
+      append_container(builder, path, &container, &archived)?;
+      if let Some(ref comment) = snapshot.comment_pathname {
+        builder.append_path_with_name(comment, archived.join("comment")).map_err(error)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Append the configuration and rootfs of `container` under `archived`.
+fn append_container<W: Write>(builder: &mut tar::Builder<W>, path: &Path,
+                              container: &LxcContainer, archived: &Path) -> Result<(), String> {
+  let rootfs = container.rootfs_dir()?;
+  let config = path.with_extension("config.tmp");
+  if !container.save_config(&config.to_string_lossy()) {
+    return Err(format!("Failed to save the configuration of {}", container.name()));
+  }
+  let result = builder.append_path_with_name(&config, archived.join("config"))
+                      .and_then(|()| builder.append_dir_all(archived.join("rootfs"), &rootfs));
+  let _ = fs::remove_file(&config);
+  result.map_err(|e| io_error(path, e))
+}
+
+/// Whether `name` is a snapshot name as liblxc gives them, "snap" and a number.
+/// Names from a manifest are checked with it before they are used as paths.
+fn is_snapshot_name(name: &str) -> bool {
+  name.strip_prefix("snap").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Whether `path` is something an archive may hold: the manifest, the
+/// configuration, the rootfs, or the configuration, comment or rootfs of a snapshot.
+fn is_archived(path: &Path) -> bool {
+  let components: Vec<&str> = match path.components().filter(|c| *c != Component::CurDir).map(|c| match c {
+    Component::Normal(name) => name.to_str().ok_or(()),
+    _ => Err(()),
+  }).collect() {
+    Ok(components) => components,
+    Err(()) => return false,
+  };
+  match components[..] {
+    [MANIFEST] | ["config"] | ["rootfs", ..] => true,
+    ["snaps", snapshot, "config"] | ["snaps", snapshot, "comment"] | ["snaps", snapshot, "rootfs", ..] => {
+      is_snapshot_name(snapshot)
+    }
+    _ => false,
+  }
+}
+
+/// Unpack an archive into the container directory `dir`.
+///
+/// # Return value
+/// Returns `Ok` with the manifest of the archive, else `Err` if it isn't a
+/// container archive.
+fn unpack_archive(path: &Path, dir: &Path) -> Result<ArchiveManifest, String> {
+  let error = |e: io::Error| io_error(path, e);
+  let mut manifest: Option<ArchiveManifest> = None;
+  let mut archive = open_tarball(path)?;
+  for entry in archive.entries().map_err(error)? {
+    let mut entry = entry.map_err(error)?;
+    let entry_path = entry.path().map_err(error)?.into_owned();
+    if !is_archived(&entry_path) {
+      return Err(format!("{}: unexpected {} in a container archive", path.display(), entry_path.display()));
+    }
+    if entry_path.ends_with(MANIFEST) {
+      let mut json = String::new();
+      entry.read_to_string(&mut json).map_err(error)?;
+      let parsed: ArchiveManifest = ::serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+      if parsed.version != ARCHIVE_VERSION {
+        return Err(format!("{}: unsupported archive version {}", path.display(), parsed.version));
+      }
+      if let Some(snapshot) = parsed.snapshots.iter().find(|s| !is_snapshot_name(&s.name)) {
+        return Err(format!("{}: invalid snapshot name {}", path.display(), snapshot.name));
+      }
+      manifest = Some(parsed);
+    }
+    else if manifest.is_none() {
+      return Err(format!("{}: not a container archive", path.display()));
+    }
+    else {
+      entry.unpack_in(dir).map_err(error)?;
+    }
+  }
+  let manifest = manifest.ok_or_else(|| format!("{}: not a container archive", path.display()))?;
+  if !dir.join("config").is_file() {
+    return Err(format!("{}: the archive has no configuration", path.display()));
+  }
+  for snapshot in manifest.snapshots.iter() {
+    let snapshot_dir = dir.join("snaps").join(&snapshot.name);
+    if !snapshot_dir.join("config").is_file() || !snapshot_dir.join("rootfs").is_dir() {
+      return Err(format!("{}: snapshot {} is missing", path.display(), snapshot.name));
+    }
+  }
+  Ok(manifest)
+}
+
+/// Configuration items of a configuration file, in order.
+fn read_config(path: &Path) -> Result<Vec<(String, String)>, String> {
+  let content = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+  let mut items = Vec::new();
+  for line in content.lines() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    match line.find('=') {
+      Some(i) => items.push((line[..i].trim().to_string(), line[i + 1..].trim().to_string())),
+      None => return Err(format!("{}: invalid line {}", path.display(), line)),
+    }
+  }
+  Ok(items)
+}
+
+fn is_hwaddr_key(key: &str) -> bool {
+  let parts: Vec<&str> = key.split('.').collect();
+  match parts[..] {
+    ["lxc", "network", "hwaddr"] => true,
+    ["lxc", "network", index, "hwaddr"] | ["lxc", "net", index, "hwaddr"] => index.parse::<u32>().is_ok(),
+    _ => false,
+  }
+}
+
+/// Random MAC address with the prefix liblxc uses, 00:16:3e.
+fn random_hwaddr() -> Result<String, String> {
+  let mut bytes = [0u8; 3];
+  File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes))
+                            .map_err(|e| io_error(Path::new("/dev/urandom"), e))?;
+  Ok(format!("00:16:3e:{:02x}:{:02x}:{:02x}", bytes[0] & 0x7f, bytes[1], bytes[2]))
+}
+
+/// How the configuration of an archived container is rewritten for its new place.
+struct Rewrite<'a> {
+  old_name: &'a str,
+  old_dir: String,
+  new_dir: String,
+  name: &'a str,
+}
+
+impl<'a> Rewrite<'a> {
+  fn new(manifest: &'a ArchiveManifest, dir: &Path, name: &'a str) -> Rewrite<'a> {
+    Rewrite {
+      old_name: &manifest.name,
+      old_dir: Path::new(&manifest.lxcpath).join(&manifest.name).to_string_lossy().into_owned(),
+      new_dir: dir.to_string_lossy().into_owned(),
+      name,
+    }
+  }
+
+  /// Items of configuration file `config`, except the rootfs and hostname, which
+  /// are set when the container is created.
+  fn items(&self, config: &Path) -> Result<Vec<(String, String)>, String> {
+    let mut items = Vec::new();
+    for (key, value) in read_config(config)? {
+      if key == "lxc.rootfs" || key == "lxc.rootfs.path" || key == "lxc.utsname" || key == "lxc.uts.name" {
+        continue;
+      }
+      let value = if is_hwaddr_key(&key) { random_hwaddr()? } else { self.move_paths(&value) };
+      items.push((key, value));
+    }
+    Ok(items)
+  }
+
+  /// Replace the old container directory with the new one where a path in `value`
+  /// starts with it, so that `/var/lib/lxc/web2` stays when `web` moves.
+  fn move_paths(&self, value: &str) -> String {
+    if self.old_dir.is_empty() {
+      return value.to_string();
+    }
+    let is_separator = |c: char| c.is_whitespace() || c == ':';
+    let mut moved = String::new();
+    let mut rest = value;
+    while let Some(i) = rest.find(&self.old_dir[..]) {
+      let after = &rest[i + self.old_dir.len()..];
+      let starts = rest[..i].chars().next_back().map_or(moved.is_empty() || moved.ends_with(is_separator), is_separator);
+      let ends = after.chars().next().is_none_or(|c| c == '/' || is_separator(c));
+      moved.push_str(&rest[..i]);
+      moved.push_str(if starts && ends { &self.new_dir } else { &self.old_dir });
+      rest = after;
+    }
+    moved.push_str(rest);
+    moved
+  }
+
+  /// Replace the old name in the rootfs' `/etc/hostname`, as `clone` does.
+  fn hostname(&self, rootfs: &Path) -> Result<(), String> {
+    let path = rootfs.join("etc/hostname");
+    // Don't follow links out of the rootfs.
+    if !fs::symlink_metadata(&path).map(|m| m.is_file()).unwrap_or(false) {
+      return Ok(());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    if content.trim() == self.old_name {
+      fs::write(&path, format!("{}\n", self.name)).map_err(|e| io_error(&path, e))?;
+    }
+    Ok(())
+  }
+
+  /// Define an unpacked snapshot of the container in directory `dir`.
+  fn snapshot(&self, dir: &Path, snapshot: &ArchivedSnapshot) -> Result<(), String> {
+    let snaps = dir.join("snaps");
+    let snapshot_dir = snaps.join(&snapshot.name);
+    let container = LxcContainer::new(&snapshot.name, Some(&snaps.to_string_lossy()))?;
+    let rootfs = snapshot_dir.join("rootfs");
+    self.hostname(&rootfs)?;
+    let mut items = vec![(config_key("lxc.rootfs.path").to_string(), rootfs.to_string_lossy().into_owned()),
+                         (config_key("lxc.uts.name").to_string(), self.name.to_string())];
+    items.extend(self.items(&snapshot_dir.join("config"))?);
+    for (key, value) in items.iter() {
+      if !container.set_config_item(key, value) {
+        return Err(format!("Failed to set {} = {} for snapshot {}", key, value, snapshot.name));
+      }
+    }
+    let config_file = container.config_file_name()?;
+    if !container.save_config(&config_file) {
+      return Err(format!("Failed to save {}", config_file));
+    }
+    // liblxc reads the creation time of a snapshot from this file.
+    let ts = snapshot_dir.join("ts");
+    fs::write(&ts, &snapshot.timestamp).map_err(|e| io_error(&ts, e))
+  }
+}
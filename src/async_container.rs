@@ -0,0 +1,159 @@
+//! Asynchronous container API, enabled with the `async` feature.
+//!
+//! liblxc calls block, some of them for minutes. `AsyncLxcContainer` runs each call
+//! on tokio's blocking thread pool, so they never stall the executor, and serializes
+//! calls on the same container through `SharedLxcContainer`.
+
+use std::panic;
+use std::time::{Duration, Instant};
+use tokio::task;
+use tokio::time;
+use super::{BDevSpecs, LxcCloneFlag, LxcContainer, LxcCreateFlag, SharedLxcContainer};
+
+/// Container handle whose long-running operations return futures.
+///
+/// Must be used from within a tokio runtime.
+pub struct AsyncLxcContainer {
+  shared: SharedLxcContainer
+}
+
+impl AsyncLxcContainer {
+
+  /// Wraps `container` for use from asynchronous code.
+  pub fn new(container: LxcContainer) -> AsyncLxcContainer {
+    AsyncLxcContainer { shared: SharedLxcContainer::new(container) }
+  }
+
+  /// Shared handle to the underlying container, for blocking calls outside of
+  /// the executor or for creating another `AsyncLxcContainer` with `from`.
+  pub fn shared(&self) -> &SharedLxcContainer {
+    &self.shared
+  }
+
+  async fn run<T, F>(&self, f: F) -> T
+    where F: FnOnce(&LxcContainer) -> T + Send + 'static,
+          T: Send + 'static {
+    let shared = self.shared.clone();
+    match task::spawn_blocking(move || f(&shared.lock())).await {
+      Ok(result) => result,
+      Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+  }
+
+  /// Determine state of container. See `LxcContainer::state`.
+  pub async fn state(&self) -> String {
+    self.run(|c| c.state()).await
+  }
+
+  /// Start the container. See `LxcContainer::start`.
+  pub async fn start(&self, use_init: i32, argv_option: Option<Vec<&str>>) -> bool {
+    let argv = argv_option.map(to_owned_vec);
+    self.run(move |c| c.start(use_init, argv.as_ref().map(|a| to_str_vec(a)))).await
+  }
+
+  /// Stop the container. See `LxcContainer::stop`.
+  pub async fn stop(&self) -> bool {
+    self.run(|c| c.stop()).await
+  }
+
+  /// Request the container shutdown. See `LxcContainer::shutdown`.
+  pub async fn shutdown(&self, timeout: i32) -> bool {
+    self.run(move |c| c.shutdown(timeout)).await
+  }
+
+  /// Wait for container to reach a particular state. See `LxcContainer::wait`.
+  ///
+  /// ## Note
+  /// This occupies a blocking pool thread and the container for up to `timeout` seconds;
+  /// prefer `wait_for_state` when other tasks use the same container meanwhile.
+  pub async fn wait(&self, state: &str, timeout: i32) -> bool {
+    let state = state.to_string();
+    self.run(move |c| c.wait(&state, timeout)).await
+  }
+
+  /// Wait for container to reach a particular state by polling it every `interval`.
+  ///
+  /// The container is only locked for the duration of each poll and the executor
+  /// is never blocked.
+  ///
+  /// # Return value
+  /// Returns `true` if state reached within `timeout`, else `false`.
+  pub async fn wait_for_state(&self, state: &str, timeout: Duration, interval: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+      if self.state().await == state {
+        return true;
+      }
+      let now = Instant::now();
+      if now >= deadline {
+        return false;
+      }
+      time::sleep(if deadline - now < interval { deadline - now } else { interval }).await;
+    }
+  }
+
+  /// Create a container. See `LxcContainer::create`.
+  pub async fn create(&self, template: &str,
+                             bdevtype_option: Option<&str>,
+                             bdev_specs: BDevSpecs,
+                             flags: LxcCreateFlag,
+                             argv_option: Option<Vec<&str>>) -> bool {
+    let template = template.to_string();
+    let bdevtype = bdevtype_option.map(|b| b.to_string());
+    let argv = argv_option.map(to_owned_vec);
+    self.run(move |c| c.create(&template,
+                               bdevtype.as_ref().map(|b| &b[..]),
+                               bdev_specs,
+                               flags,
+                               argv.as_ref().map(|a| to_str_vec(a)))).await
+  }
+
+  /// Copy a stopped container. See `LxcContainer::clone`.
+  #[allow(clippy::too_many_arguments)]
+  pub async fn clone(&self,
+                     newname: Option<&str>,
+                     lxcpath: Option<&str>,
+                     flags: LxcCloneFlag,
+                     bdevtype: Option<&str>,
+                     bdevdata: Option<&str>,
+                     newsize: u64,
+                     argv_option: Option<Vec<&str>>)
+                                        -> Result<AsyncLxcContainer, &'static str> {
+    let newname = newname.map(|s| s.to_string());
+    let lxcpath = lxcpath.map(|s| s.to_string());
+    let bdevtype = bdevtype.map(|s| s.to_string());
+    let bdevdata = bdevdata.map(|s| s.to_string());
+    let argv = argv_option.map(to_owned_vec);
+    self.run(move |c| c.clone(newname.as_ref().map(|s| &s[..]),
+                              lxcpath.as_ref().map(|s| &s[..]),
+                              flags,
+                              bdevtype.as_ref().map(|s| &s[..]),
+                              bdevdata.as_ref().map(|s| &s[..]),
+                              newsize,
+                              argv.as_ref().map(|a| to_str_vec(a))))
+        .await
+        .map(AsyncLxcContainer::new)
+  }
+
+  /// Run a program inside the container and wait for it to finish.
+  /// See `LxcContainer::attach_run_wait`.
+  pub async fn attach_run_wait(&self, program: &str, argv: Vec<&str>) -> Result<i32, &'static str> {
+    let program = program.to_string();
+    let argv = to_owned_vec(argv);
+    self.run(move |c| c.attach_run_wait(&program, to_str_vec(&argv))).await
+  }
+}
+
+impl From<SharedLxcContainer> for AsyncLxcContainer {
+  fn from(shared: SharedLxcContainer) -> AsyncLxcContainer {
+    AsyncLxcContainer { shared }
+  }
+}
+
+fn to_owned_vec(v: Vec<&str>) -> Vec<String> {
+  v.iter().map(|s| s.to_string()).collect()
+}
+
+fn to_str_vec(v: &[String]) -> Vec<&str> {
+  v.iter().map(|s| &s[..]).collect()
+}
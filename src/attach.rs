@@ -0,0 +1,144 @@
+//! Running programs inside a container with a chosen environment, directory and user.
+
+use crate::ffi::attach_options::{lxc_attach_run_command, LxcAttachCommand, LxcAttachEnvPolicy,
+                                 LxcAttachOptions, LXC_ATTACH_DEFAULT};
+use crate::ffi::sys::{kill, waitpid, WNOHANG};
+use crate::helper::*;
+use libc::{c_char, c_int, c_long, c_void, pid_t};
+use std::ffi::CString;
+use std::io;
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+use super::{fork_guard, LxcContainer};
+
+/// How `LxcContainer::attach_run_wait_with` runs a program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttachOptions {
+  /// Working directory of the program. If `None`, the current directory of the
+  /// caller is used if it exists in the container, else `/`.
+  pub cwd: Option<String>,
+  /// User id to run as. If `None`, the init user of the container.
+  pub uid: Option<u32>,
+  /// Group id to run as. If `None`, the init group of the container.
+  pub gid: Option<u32>,
+  /// Start from an empty environment instead of the caller's.
+  pub clear_env: bool,
+  /// Additional environment variables, as `NAME=VALUE`.
+  pub env: Vec<String>,
+}
+
+impl LxcContainer {
+
+  /// Run a program inside the container with `options` and wait for it to finish.
+  ///
+  /// # Parameters
+  /// `options` - environment, working directory and user of the program.
+  ///
+  /// `program` - full path inside the container of the program to run.
+  ///
+  /// `argv` - vector of arguments to pass to `program`, starting with the program name itself.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the exit code of `program`, or `Err` if it couldn't be run
+  /// or was terminated by a signal.
+  pub fn attach_run_wait_with(&self, options: &AttachOptions,
+                              program: &str, argv: Vec<&str>) -> Result<i32, &'static str> {
+    self.attach_run_wait_stdio(options, [0, 1, 2], program, argv)
+  }
+
+  /// Like `attach_run_wait_with`, with the standard input, output and error of
+  /// `program` connected to `stdio`.
+  pub(crate) fn attach_run_wait_stdio(&self, options: &AttachOptions, stdio: [c_int; 3],
+                                      program: &str, argv: Vec<&str>) -> Result<i32, &'static str> {
+    let pid = self.attach_stdio(options, stdio, program, argv)?;
+    wait_attached(pid, None)
+  }
+
+  /// Start `program` inside the container without waiting for it, see
+  /// `attach_run_wait_stdio`.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the PID of the program, a child of the calling process that
+  /// has to be reaped with `wait_attached`, or `Err` if it couldn't be started.
+  pub(crate) fn attach_stdio(&self, options: &AttachOptions, stdio: [c_int; 3],
+                             program: &str, argv: Vec<&str>) -> Result<pid_t, &'static str> {
+    unsafe {
+      let cwd_cstring = options.cwd.as_ref().map(|cwd| str_to_cstring(cwd));
+      let env_cstring: Vec<CString> = options.env.iter().map(|var| str_to_cstring(var)).collect();
+      let mut env_ptrs: Vec<*mut c_char> = env_cstring.iter().map(|var| var.as_ptr() as *mut c_char).collect();
+      env_ptrs.push(ptr::null_mut());
+      let mut attach_options = LxcAttachOptions {
+        attach_flags: LXC_ATTACH_DEFAULT as c_int,
+        namespaces: -1,
+        personality: -1 as c_long,
+        initial_cwd: cwd_cstring.as_ref().map_or(ptr::null_mut(), |cwd| cwd.as_ptr() as *mut c_char),
+        uid: options.uid.unwrap_or(!0),
+        gid: options.gid.unwrap_or(!0),
+        env_policy: if options.clear_env { LxcAttachEnvPolicy::LXC_ATTACH_CLEAR_ENV }
+                    else { LxcAttachEnvPolicy::LXC_ATTACH_KEEP_ENV },
+        extra_env_vars: if options.env.is_empty() { ptr::null_mut() } else { env_ptrs.as_mut_ptr() },
+        extra_keep_env: ptr::null_mut(),
+        stdin_fd: stdio[0],
+        stdout_fd: stdio[1],
+        stderr_fd: stdio[2],
+      };
+      let program_cstring = str_to_cstring(program);
+      let argv_cstring = vec_str_to_cstring(argv);
+      let argv_ptrs = vec_cstring_to_ptr(&argv_cstring);
+      let mut command = LxcAttachCommand {
+        program: program_cstring.as_ptr() as *mut c_char,
+        argv: argv_ptrs.as_ptr() as *mut *mut c_char,
+      };
+      let mut pid: pid_t = -1;
+      // liblxc returns right after forking, the program is waited for unlocked.
+      let result = {
+        let _fork = fork_guard();
+        ((*self.underlying).attach)(self.underlying, run_command, &mut command as *mut LxcAttachCommand as *mut c_void,
+                                    &mut attach_options, &mut pid)
+      };
+      if result < 0 || pid <= 0 {
+        Err("Couldn't attach to container")
+      }
+      else {
+        Ok(pid)
+      }
+    }
+  }
+}
+
+/// Error of `wait_attached` when the program didn't exit in time.
+pub(crate) const ATTACH_TIMEOUT: &str = "Attached process timed out";
+
+extern "C" fn run_command(payload: *mut c_void) -> c_int {
+  unsafe { lxc_attach_run_command(payload) }
+}
+
+/// Wait for `pid`, started by `LxcContainer::attach_stdio`, to exit. If it's still
+/// running after `timeout_option`, it's killed with SIGKILL and reaped.
+///
+/// # Return value
+/// Returns `Ok` with the exit code of the program, or `Err` if it timed out, was
+/// terminated by a signal or couldn't be waited for.
+pub(crate) fn wait_attached(pid: pid_t, timeout_option: Option<Duration>) -> Result<i32, &'static str> {
+  let deadline = timeout_option.map(|timeout| Instant::now() + timeout);
+  let mut status = 0;
+  loop {
+    let flags = if deadline.is_some() { WNOHANG } else { 0 };
+    match unsafe { waitpid(pid, &mut status, flags) } {
+      -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+      -1 => return Err("Couldn't wait for attached process"),
+      0 => {}
+      _ => return wait_status_to_exit_code(status),
+    }
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+      unsafe {
+        kill(pid, libc::SIGKILL);
+        while waitpid(pid, &mut status, 0) < 0
+              && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {}
+      }
+      return Err(ATTACH_TIMEOUT);
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+}
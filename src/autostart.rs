@@ -0,0 +1,211 @@
+//! Autostart settings and group operations, as done by lxc-autostart.
+
+use std::thread;
+use std::time::Duration;
+use super::{list_all_containers, LxcContainer};
+
+impl LxcContainer {
+
+  /// Determine whether the container is started when its host boots (`lxc.start.auto`).
+  pub fn start_auto(&self) -> bool {
+    self.int_config_item("lxc.start.auto") != 0
+  }
+
+  /// Set whether the container is started when its host boots (`lxc.start.auto`).
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  ///
+  /// ## Note
+  /// Like `set_config_item`, this only changes the loaded configuration; use
+  /// `save_config` to make it permanent. The same applies to the other setters here.
+  pub fn set_start_auto(&self, auto: bool) -> bool {
+    self.set_config_item("lxc.start.auto", if auto { "1" } else { "0" })
+  }
+
+  /// Determine the start order of the container (`lxc.start.order`). Containers with
+  /// a higher order start earlier and stop later.
+  pub fn start_order(&self) -> i32 {
+    self.int_config_item("lxc.start.order")
+  }
+
+  /// Set the start order of the container (`lxc.start.order`).
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn set_start_order(&self, order: i32) -> bool {
+    self.set_config_item("lxc.start.order", &order.to_string())
+  }
+
+  /// Determine how many seconds to wait after starting the container before starting
+  /// the next one (`lxc.start.delay`).
+  pub fn start_delay(&self) -> i32 {
+    self.int_config_item("lxc.start.delay")
+  }
+
+  /// Set how many seconds to wait after starting the container (`lxc.start.delay`).
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn set_start_delay(&self, delay: i32) -> bool {
+    self.set_config_item("lxc.start.delay", &delay.to_string())
+  }
+
+  /// Determine the groups the container belongs to (`lxc.group`).
+  pub fn groups(&self) -> Vec<String> {
+    self.get_config_item("lxc.group")
+        .unwrap_or_default()
+        .split(['\n', ','])
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect()
+  }
+
+  /// Replace the groups the container belongs to (`lxc.group`).
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn set_groups(&self, groups: &[&str]) -> bool {
+    self.clear_config_item("lxc.group") && groups.iter().all(|g| self.set_config_item("lxc.group", g))
+  }
+
+  /// Add the container to a group (`lxc.group`), unless it already belongs to it.
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn add_group(&self, group: &str) -> bool {
+    self.groups().iter().any(|g| g == group) || self.set_config_item("lxc.group", group)
+  }
+
+  fn int_config_item(&self, key: &str) -> i32 {
+    self.get_config_item(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(0)
+  }
+}
+
+/// Operation performed on a group of containers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutostartAction {
+  /// Start containers that are not running, waiting `lxc.start.delay` after each.
+  Start,
+  /// Shut running containers down, killing them if they don't stop within the timeout.
+  Stop,
+  /// Reboot running containers.
+  Reboot,
+  /// Kill running containers.
+  Kill,
+}
+
+/// Which containers an autostart operation applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutostartOptions {
+  /// lxcpath of the containers. If `None`, the default lxcpath is used.
+  pub lxcpath: Option<String>,
+  /// Groups to act on, in order. An empty string selects containers without a group.
+  pub groups: Vec<String>,
+  /// Act on containers of all groups, ignoring `groups`.
+  pub all_groups: bool,
+  /// Also act on containers with `lxc.start.auto` unset.
+  pub ignore_auto: bool,
+  /// Seconds `AutostartAction::Stop` waits for a clean shutdown.
+  pub timeout: i32,
+}
+
+impl Default for AutostartOptions {
+  /// Containers without a group and with `lxc.start.auto` set in the default lxcpath,
+  /// as lxc-autostart does without arguments.
+  fn default() -> AutostartOptions {
+    AutostartOptions {
+      lxcpath: None,
+      groups: vec![String::new()],
+      all_groups: false,
+      ignore_auto: false,
+      timeout: 60,
+    }
+  }
+}
+
+/// Result of an autostart operation on one container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutostartOutcome {
+  /// Name of the container.
+  pub name: String,
+  /// `Ok` if the operation succeeded or wasn't needed, else `Err` with what failed.
+  pub result: Result<(), &'static str>,
+}
+
+/// Select the containers an autostart operation applies to, in start order.
+///
+/// Containers are ordered by the position of their first selected group in
+/// `options.groups`, then by `lxc.start.order` (highest first), then by name.
+///
+/// # Return value
+/// Returns `Ok` with the containers, else `Err` if the containers couldn't be listed.
+pub fn list_autostart_containers(options: &AutostartOptions) -> Result<Vec<LxcContainer>, &'static str> {
+  let lxcpath = options.lxcpath.as_ref().map(|p| &p[..]);
+  let mut selected = Vec::new();
+  for name in list_all_containers(lxcpath)? {
+    let container = match LxcContainer::new(&name, lxcpath) {
+      Ok(container) => container,
+      Err(_) => continue,
+    };
+    if !options.ignore_auto && !container.start_auto() {
+      continue;
+    }
+    let groups = container.groups();
+    let rank = if options.all_groups {
+      Some(0)
+    }
+    else {
+      options.groups.iter().position(|g| {
+        if g.is_empty() { groups.is_empty() } else { groups.contains(g) }
+      })
+    };
+    if let Some(rank) = rank {
+      selected.push((rank, -container.start_order(), name, container));
+    }
+  }
+  selected.sort_by(|a, b| (a.0, a.1, &a.2).cmp(&(b.0, b.1, &b.2)));
+  Ok(selected.into_iter().map(|(_, _, _, container)| container).collect())
+}
+
+/// Start, stop, reboot or kill a group of containers, like lxc-autostart.
+///
+/// Containers are started and rebooted in the order of `list_autostart_containers`
+/// and stopped and killed in reverse. A failure doesn't stop the operation on the
+/// remaining containers.
+///
+/// # Return value
+/// Returns `Ok` with the outcome for each selected container, else `Err` if the
+/// containers couldn't be listed.
+pub fn autostart(action: AutostartAction, options: &AutostartOptions)
+                                       -> Result<Vec<AutostartOutcome>, &'static str> {
+  let mut containers = list_autostart_containers(options)?;
+  if action == AutostartAction::Stop || action == AutostartAction::Kill {
+    containers.reverse();
+  }
+  Ok(containers.iter().map(|container| {
+    let running = container.is_running();
+    let result = match action {
+      AutostartAction::Start if !running => {
+        if container.start(0, None) {
+          thread::sleep(Duration::from_secs(container.start_delay().max(0) as u64));
+          Ok(())
+        }
+        else {
+          Err("failed to start")
+        }
+      }
+      AutostartAction::Stop if running => {
+        if container.shutdown(options.timeout) || container.stop() { Ok(()) } else { Err("failed to stop") }
+      }
+      AutostartAction::Reboot if running => {
+        if container.reboot() { Ok(()) } else { Err("failed to reboot") }
+      }
+      AutostartAction::Kill if running => {
+        if container.stop() { Ok(()) } else { Err("failed to kill") }
+      }
+      _ => Ok(()),
+    };
+    AutostartOutcome { name: container.name(), result }
+  }).collect())
+}
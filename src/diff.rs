@@ -0,0 +1,362 @@
+//! Differences between the root filesystems of a container and its snapshots.
+//!
+//! `LxcContainer::diff_snapshot` compares a snapshot with the current rootfs of a
+//! container, and `LxcContainer::diff_snapshots` two snapshots with each other.
+//! Both only work with the dir backing store. Changes are produced by an iterator
+//! walking both trees side by side in sorted order, one directory at a time, so
+//! that large trees can be compared without holding them in memory:
+//!
+//! ```no_run
+//! use liblxc::*;
+//! let c = LxcContainer::new("web", None).unwrap();
+//! for change in c.diff_snapshot("snap0", &DiffOptions::default()).unwrap() {
+//!   let change = change.unwrap();
+//!   println!("{} /{}", change.kind.as_char(), change.path.display());
+//! }
+//! ```
+//!
+//! A directory that was added or removed is reported along with everything in it.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use crate::features::config_key;
+use super::LxcContainer;
+
+/// How files are compared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiffOptions {
+  /// Compare the content of regular files of the same size. If `false`, files of
+  /// the same size are only considered changed if their modification time differs.
+  pub check_content: bool,
+  /// Report files whose modification time is the only change, except directories,
+  /// whose modification time changes with their content.
+  pub include_mtime: bool,
+}
+
+impl Default for DiffOptions {
+  /// Compare content, ignoring modification times.
+  fn default() -> DiffOptions {
+    DiffOptions { check_content: true, include_mtime: false }
+  }
+}
+
+/// Type of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+  File,
+  Dir,
+  Symlink,
+  /// Device, fifo or socket.
+  Other,
+}
+
+/// Metadata of a file compared by a diff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMeta {
+  /// Type of the file.
+  pub file_type: FileType,
+  /// Permission bits, with setuid, setgid and sticky.
+  pub mode: u32,
+  /// Owner.
+  pub uid: u32,
+  /// Group.
+  pub gid: u32,
+  /// Size in bytes.
+  pub size: u64,
+  /// Modification time, in seconds since the epoch.
+  pub mtime: i64,
+  /// Target of a symbolic link.
+  pub link_target: Option<PathBuf>,
+}
+
+impl FileMeta {
+  fn read(path: &Path) -> io::Result<FileMeta> {
+    let metadata = fs::symlink_metadata(path)?;
+    let file_type = metadata.file_type();
+    let file_type = if file_type.is_symlink() {
+      FileType::Symlink
+    }
+    else if file_type.is_dir() {
+      FileType::Dir
+    }
+    else if file_type.is_file() {
+      FileType::File
+    }
+    else {
+      FileType::Other
+    };
+    Ok(FileMeta {
+      file_type,
+      mode: metadata.mode() & 0o7777,
+      uid: metadata.uid(),
+      gid: metadata.gid(),
+      size: metadata.size(),
+      mtime: metadata.mtime(),
+      link_target: if file_type == FileType::Symlink { Some(fs::read_link(path)?) } else { None },
+    })
+  }
+}
+
+/// What happened to a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+  Added,
+  Removed,
+  Modified,
+}
+
+impl ChangeKind {
+  /// 'A', 'D' or 'M', as in `git status`.
+  pub fn as_char(&self) -> char {
+    match *self {
+      ChangeKind::Added => 'A',
+      ChangeKind::Removed => 'D',
+      ChangeKind::Modified => 'M',
+    }
+  }
+}
+
+/// A file that differs between two root filesystems.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsChange {
+  /// Path of the file, relative to the root of the filesystems.
+  pub path: PathBuf,
+  /// What happened to the file.
+  pub kind: ChangeKind,
+  /// Metadata in the old filesystem, `None` if added.
+  pub old: Option<FileMeta>,
+  /// Metadata in the new filesystem, `None` if removed.
+  pub new: Option<FileMeta>,
+  /// Whether the content of a regular file, or the target of a link, changed.
+  pub content_changed: bool,
+}
+
+impl FsChange {
+  fn differs<T: PartialEq, F: Fn(&FileMeta) -> T>(&self, field: F) -> bool {
+    match (&self.old, &self.new) {
+      (Some(old), Some(new)) => field(old) != field(new),
+      _ => false,
+    }
+  }
+
+  /// Whether the file changed type, for example from a file to a directory.
+  pub fn type_changed(&self) -> bool {
+    self.differs(|m| m.file_type)
+  }
+
+  /// Whether the permission bits changed.
+  pub fn mode_changed(&self) -> bool {
+    self.differs(|m| m.mode)
+  }
+
+  /// Whether the owner or group changed.
+  pub fn owner_changed(&self) -> bool {
+    self.differs(|m| (m.uid, m.gid))
+  }
+
+  /// Whether the modification time changed.
+  pub fn mtime_changed(&self) -> bool {
+    self.differs(|m| m.mtime)
+  }
+}
+
+/// Directory being compared: the sorted names in either tree, with whether the
+/// old and new trees have them.
+struct Frame {
+  dir: PathBuf,
+  entries: ::std::vec::IntoIter<(OsString, bool, bool)>,
+}
+
+/// Iterator over the differences between two root filesystems, see the module
+/// documentation. Errors reading a file are returned in place of its change, and
+/// the walk goes on.
+pub struct RootfsDiff {
+  old_root: PathBuf,
+  new_root: PathBuf,
+  options: DiffOptions,
+  stack: Vec<Frame>,
+}
+
+fn read_names(dir: &Path) -> io::Result<Vec<OsString>> {
+  let mut names = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    names.push(entry?.file_name());
+  }
+  Ok(names)
+}
+
+fn same_content(old: &Path, new: &Path) -> io::Result<bool> {
+  let mut old = File::open(old)?;
+  let mut new = File::open(new)?;
+  let mut old_buf = [0u8; 64 * 1024];
+  let mut new_buf = [0u8; 64 * 1024];
+  loop {
+    let n = old.read(&mut old_buf)?;
+    if n == 0 {
+      return Ok(new.read(&mut new_buf[..1])? == 0);
+    }
+    if new.read_exact(&mut new_buf[..n]).is_err() || old_buf[..n] != new_buf[..n] {
+      return Ok(false);
+    }
+  }
+}
+
+impl RootfsDiff {
+
+  /// Compare directory `old_root` with directory `new_root`.
+  pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(old_root: P, new_root: Q, options: &DiffOptions) -> Result<RootfsDiff, String> {
+    let mut diff = RootfsDiff {
+      old_root: old_root.as_ref().to_path_buf(),
+      new_root: new_root.as_ref().to_path_buf(),
+      options: *options,
+      stack: Vec::new(),
+    };
+    for root in [&diff.old_root, &diff.new_root].iter() {
+      if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+      }
+    }
+    let frame = diff.frame(Path::new(""), true, true)?;
+    diff.stack.push(frame);
+    Ok(diff)
+  }
+
+  fn frame(&self, dir: &Path, in_old: bool, in_new: bool) -> Result<Frame, String> {
+    let error = |root: &Path, e: io::Error| format!("{}: {}", root.join(dir).display(), e);
+    let mut old = if in_old { read_names(&self.old_root.join(dir)).map_err(|e| error(&self.old_root, e))? } else { Vec::new() };
+    let mut new = if in_new { read_names(&self.new_root.join(dir)).map_err(|e| error(&self.new_root, e))? } else { Vec::new() };
+    old.sort();
+    new.sort();
+    let mut entries = Vec::with_capacity(old.len().max(new.len()));
+    let (mut old, mut new) = (old.into_iter().peekable(), new.into_iter().peekable());
+    loop {
+      let entry = match (old.peek(), new.peek()) {
+        (Some(o), Some(n)) if o == n => { new.next(); (old.next().unwrap(), true, true) }
+        (Some(o), Some(n)) if o < n => (old.next().unwrap(), true, false),
+        (Some(_), Some(_)) | (None, Some(_)) => (new.next().unwrap(), false, true),
+        (Some(_), None) => (old.next().unwrap(), true, false),
+        (None, None) => break,
+      };
+      entries.push(entry);
+    }
+    Ok(Frame { dir: dir.to_path_buf(), entries: entries.into_iter() })
+  }
+
+  /// Compare `path` in both trees, and descend into it if it is a directory on either side.
+  fn compare(&mut self, path: PathBuf, in_old: bool, in_new: bool) -> Result<Option<FsChange>, String> {
+    let old_path = self.old_root.join(&path);
+    let new_path = self.new_root.join(&path);
+    let read = |p: &Path| FileMeta::read(p).map_err(|e| format!("{}: {}", p.display(), e));
+    let old = if in_old { Some(read(&old_path)?) } else { None };
+    let new = if in_new { Some(read(&new_path)?) } else { None };
+
+    let old_dir = old.as_ref().is_some_and(|m| m.file_type == FileType::Dir);
+    let new_dir = new.as_ref().is_some_and(|m| m.file_type == FileType::Dir);
+    if old_dir || new_dir {
+      let frame = self.frame(&path, old_dir, new_dir)?;
+      self.stack.push(frame);
+    }
+
+    let (kind, content_changed) = match (&old, &new) {
+      (&Some(_), &None) => (ChangeKind::Removed, false),
+      (&None, &Some(_)) => (ChangeKind::Added, false),
+      (Some(old), Some(new)) => {
+        let content_changed = if old.file_type != new.file_type {
+          false
+        }
+        else if old.file_type == FileType::Symlink {
+          old.link_target != new.link_target
+        }
+        else if old.file_type != FileType::File {
+          false
+        }
+        else if old.size != new.size {
+          true
+        }
+        else if self.options.check_content {
+          !same_content(&old_path, &new_path).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        else {
+          old.mtime != new.mtime
+        };
+        let modified = content_changed || old.file_type != new.file_type || old.mode != new.mode ||
+                       old.uid != new.uid || old.gid != new.gid ||
+                       (self.options.include_mtime && old.mtime != new.mtime && old.file_type != FileType::Dir);
+        if !modified {
+          return Ok(None);
+        }
+        (ChangeKind::Modified, content_changed)
+      }
+      (&None, &None) => return Ok(None),
+    };
+    Ok(Some(FsChange { path, kind, old, new, content_changed }))
+  }
+}
+
+impl Iterator for RootfsDiff {
+  type Item = Result<FsChange, String>;
+
+  fn next(&mut self) -> Option<Result<FsChange, String>> {
+    loop {
+      let (path, in_old, in_new) = {
+        let frame = self.stack.last_mut()?;
+        match frame.entries.next() {
+          Some((name, in_old, in_new)) => (frame.dir.join(name), in_old, in_new),
+          None => {
+            self.stack.pop();
+            continue;
+          }
+        }
+      };
+      match self.compare(path, in_old, in_new) {
+        Ok(Some(change)) => return Some(Ok(change)),
+        Ok(None) => (),
+        Err(e) => return Some(Err(e)),
+      }
+    }
+  }
+}
+
+impl LxcContainer {
+
+  /// Directory of the rootfs of the container, for the dir backing store.
+  pub(crate) fn rootfs_dir(&self) -> Result<PathBuf, String> {
+    let rootfs = self.get_config_item(config_key("lxc.rootfs.path"))?;
+    let dir = rootfs.strip_prefix("dir:").unwrap_or(&rootfs);
+    if dir.is_empty() || !Path::new(dir).is_dir() {
+      return Err(format!("Container {} has no directory rootfs", self.name()));
+    }
+    Ok(PathBuf::from(dir))
+  }
+
+  fn snapshot_rootfs_dir(&self, snapname: &str) -> Result<PathBuf, String> {
+    let snapshot = self.snapshot_list()?.into_iter().find(|s| s.name == snapname)
+                       .ok_or_else(|| format!("Container {} has no snapshot {}", self.name(), snapname))?;
+    LxcContainer::new(&snapshot.name, Some(&snapshot.lxcpath))?.rootfs_dir()
+  }
+
+  /// Compare snapshot `snapname` with the current rootfs of the container.
+  ///
+  /// # Return value
+  /// Returns `Ok` with an iterator over the changes made since the snapshot, else
+  /// `Err` if the snapshot doesn't exist or either rootfs isn't a directory.
+  ///
+  /// ## Note
+  /// The rootfs of a running container may change while it is compared.
+  pub fn diff_snapshot(&self, snapname: &str, options: &DiffOptions) -> Result<RootfsDiff, String> {
+    RootfsDiff::new(self.snapshot_rootfs_dir(snapname)?, self.rootfs_dir()?, options)
+  }
+
+  /// Compare two snapshots of the container.
+  ///
+  /// # Return value
+  /// Returns `Ok` with an iterator over the changes from snapshot `from` to
+  /// snapshot `to`, else `Err` if either snapshot doesn't exist or its rootfs isn't
+  /// a directory.
+  pub fn diff_snapshots(&self, from: &str, to: &str, options: &DiffOptions) -> Result<RootfsDiff, String> {
+    RootfsDiff::new(self.snapshot_rootfs_dir(from)?, self.snapshot_rootfs_dir(to)?, options)
+  }
+}
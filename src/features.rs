@@ -0,0 +1,341 @@
+//! liblxc versions and the optional functionality they bring.
+//!
+//! `ffi::LxcContainer` matches the layout of liblxc 1.1 and newer up to
+//! `snapshot_destroy_all`, where liblxc 1.1 ends. The slots 1.1 added, from
+//! `attach_interface` on and the last two after `restore`, are past the end of the
+//! function table of older libraries, so methods calling them check
+//! `LxcVersion::current` first and fail instead of calling a pointer that isn't
+//! there. Configuration keys of later releases, like `lxc.cgroup2.*`, aren't passed
+//! to older ones either, nor are the names liblxc 3.0 dropped, like `lxc.rootfs`,
+//! passed to newer ones.
+//!
+//! ```no_run
+//! use liblxc::*;
+//! let c = LxcContainer::new("web", None).unwrap();
+//! if has_feature(LxcFeature::CheckpointRestore) {
+//!   c.checkpoint("/var/lib/checkpoints/web", true, false).unwrap();
+//! }
+//! else {
+//!   c.stop();
+//! }
+//! ```
+
+use crate::ffi;
+use crate::helper::*;
+use libc::c_char;
+use std::cmp::Ordering;
+use std::ffi::CStr;
+use std::fmt;
+use std::ptr;
+use std::sync::Mutex;
+use super::{version, LxcContainer};
+
+/// `lxc_get_version` string and the version parsed from it.
+type ParsedVersion = (Vec<u8>, Result<LxcVersion, &'static str>);
+
+/// Last parsed version. liblxc's never changes, but the test stub's can, so the
+/// string is compared before the version is reused.
+static CURRENT: Mutex<Option<ParsedVersion>> = Mutex::new(None);
+
+/// Version of liblxc, like 4.0.12.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LxcVersion {
+  pub major: u32,
+  pub minor: u32,
+  pub micro: u32,
+  /// Whatever follows the numbers, like "-devel" or ".rc1". Versions with a
+  /// suffix come before the same numbers without one.
+  pub suffix: String,
+}
+
+impl LxcVersion {
+  /// Release `major`.`minor`.`micro`.
+  pub fn new(major: u32, minor: u32, micro: u32) -> LxcVersion {
+    LxcVersion { major, minor, micro, suffix: String::new() }
+  }
+
+  /// Version of the loaded liblxc, parsed from `version()` once.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the version, or `Err` if it can't be parsed.
+  pub fn current() -> Result<LxcVersion, &'static str> {
+    let raw = unsafe { CStr::from_ptr(ffi::lxc_get_version()) }.to_bytes();
+    let mut current = CURRENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match *current {
+      Some((ref cached, ref parsed)) if cached[..] == *raw => parsed.clone(),
+      _ => {
+        let parsed = String::from_utf8_lossy(raw).parse();
+        *current = Some((raw.to_vec(), parsed.clone()));
+        parsed
+      }
+    }
+  }
+
+  /// Determine whether this version of liblxc has `feature`.
+  pub fn supports(&self, feature: LxcFeature) -> bool {
+    *self >= feature.since()
+  }
+
+  /// Features this version of liblxc has, in the order of `LxcFeature::ALL`.
+  pub fn features(&self) -> Vec<LxcFeature> {
+    LxcFeature::ALL.iter().cloned().filter(|feature| self.supports(*feature)).collect()
+  }
+}
+
+impl Ord for LxcVersion {
+  fn cmp(&self, other: &LxcVersion) -> Ordering {
+    (self.major, self.minor, self.micro).cmp(&(other.major, other.minor, other.micro))
+      .then_with(|| match (self.suffix.is_empty(), other.suffix.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => self.suffix.cmp(&other.suffix),
+      })
+  }
+}
+
+impl PartialOrd for LxcVersion {
+  fn partial_cmp(&self, other: &LxcVersion) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl std::str::FromStr for LxcVersion {
+  type Err = &'static str;
+
+  /// Parses versions like "4.0.12", "2.1" or "3.0.0.rc1". At least major and minor
+  /// number are needed.
+  fn from_str(s: &str) -> Result<LxcVersion, &'static str> {
+    let s = s.trim();
+    let mut numbers = Vec::new();
+    let mut rest = s;
+    while numbers.len() < 3 {
+      let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+      if digits == 0 {
+        break;
+      }
+      numbers.push(rest[..digits].parse().map_err(|_| "Invalid liblxc version")?);
+      rest = &rest[digits..];
+      match rest.strip_prefix('.') {
+        Some(after) if numbers.len() < 3 && after.starts_with(|c: char| c.is_ascii_digit()) => rest = after,
+        _ => break,
+      }
+    }
+    if numbers.len() < 2 {
+      return Err("Invalid liblxc version");
+    }
+    Ok(LxcVersion {
+      major: numbers[0],
+      minor: numbers[1],
+      micro: numbers.get(2).cloned().unwrap_or(0),
+      suffix: rest.to_string(),
+    })
+  }
+}
+
+impl fmt::Display for LxcVersion {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}.{}.{}{}", self.major, self.minor, self.micro, self.suffix)
+  }
+}
+
+/// Optional liblxc functionality, available from some version on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LxcFeature {
+  /// `checkpoint` and `restore`, which also need CRIU on the host.
+  CheckpointRestore,
+  /// `attach_interface` and `detach_interface`.
+  AttachInterface,
+  /// The configuration key names of liblxc 2.1, like `lxc.rootfs.path` for
+  /// `lxc.rootfs`. The old names are gone since 3.0.
+  NewConfigKeys,
+  /// The console ring buffer (`lxc.console.buffer.size`) and `console_log`.
+  ConsoleLog,
+  /// Running containers on hosts with only the unified cgroup2 hierarchy.
+  Cgroup2,
+}
+
+impl LxcFeature {
+  /// All features, oldest first.
+  pub const ALL: [LxcFeature; 5] = [LxcFeature::CheckpointRestore, LxcFeature::AttachInterface,
+                                    LxcFeature::NewConfigKeys, LxcFeature::ConsoleLog,
+                                    LxcFeature::Cgroup2];
+
+  /// Name of the feature, like "console_log".
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      LxcFeature::CheckpointRestore => "checkpoint",
+      LxcFeature::AttachInterface   => "attach_interface",
+      LxcFeature::NewConfigKeys     => "new_config_keys",
+      LxcFeature::ConsoleLog        => "console_log",
+      LxcFeature::Cgroup2           => "cgroup2",
+    }
+  }
+
+  /// First liblxc release with the feature.
+  pub fn since(&self) -> LxcVersion {
+    match *self {
+      LxcFeature::CheckpointRestore => LxcVersion::new(1, 1, 0),
+      LxcFeature::AttachInterface   => LxcVersion::new(1, 1, 0),
+      LxcFeature::NewConfigKeys     => LxcVersion::new(2, 1, 0),
+      LxcFeature::ConsoleLog        => LxcVersion::new(3, 0, 0),
+      LxcFeature::Cgroup2           => LxcVersion::new(4, 0, 0),
+    }
+  }
+}
+
+impl std::str::FromStr for LxcFeature {
+  type Err = &'static str;
+
+  fn from_str(s: &str) -> Result<LxcFeature, &'static str> {
+    LxcFeature::ALL.iter().find(|feature| feature.as_str() == s).cloned().ok_or("Unknown liblxc feature")
+  }
+}
+
+impl fmt::Display for LxcFeature {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Determine whether the loaded liblxc has `feature`. An unparsable version has none.
+pub fn has_feature(feature: LxcFeature) -> bool {
+  LxcVersion::current().map(|version| version.supports(feature)).unwrap_or(false)
+}
+
+/// Configuration keys liblxc 2.1 renamed, by new and old name.
+const RENAMED_KEYS: [(&str, &str); 6] = [("lxc.rootfs.path", "lxc.rootfs"),
+                                         ("lxc.uts.name", "lxc.utsname"),
+                                         ("lxc.idmap", "lxc.id_map"),
+                                         ("lxc.init.cmd", "lxc.init_cmd"),
+                                         ("lxc.init.uid", "lxc.init_uid"),
+                                         ("lxc.init.gid", "lxc.init_gid")];
+
+/// Feature needed for configuration key `key`: `Cgroup2` for `lxc.cgroup2.*`,
+/// `ConsoleLog` for the console buffer and log rotation keys and `NewConfigKeys`
+/// for the new names of renamed keys, like `lxc.rootfs.path`, and `lxc.init.cwd`.
+///
+/// # Return value
+/// Returns `Some` with the feature, or `None` if every liblxc knows the key.
+pub fn key_feature(key: &str) -> Option<LxcFeature> {
+  match key {
+    _ if key.starts_with("lxc.cgroup2.") => Some(LxcFeature::Cgroup2),
+    "lxc.console.buffer.size" | "lxc.console.size" | "lxc.console.rotate" => Some(LxcFeature::ConsoleLog),
+    "lxc.init.cwd" => Some(LxcFeature::NewConfigKeys),
+    _ if RENAMED_KEYS.iter().any(|&(new, _)| new == key) => Some(LxcFeature::NewConfigKeys),
+    _ => None,
+  }
+}
+
+/// Determine whether the loaded liblxc knows configuration key `key`, see
+/// `key_feature`. Old names of renamed keys are only known before liblxc 3.0.
+pub(crate) fn has_key(key: &str) -> bool {
+  if RENAMED_KEYS.iter().any(|&(_, old)| old == key) {
+    return !LxcVersion::current().map(|version| version.major >= 3).unwrap_or(false);
+  }
+  key_feature(key).is_none_or(has_feature)
+}
+
+/// Name of configuration key `key`, given by its liblxc 2.1 name, that the loaded
+/// liblxc knows: `key` itself, or its old name before 2.1.
+pub(crate) fn config_key(key: &'static str) -> &'static str {
+  match RENAMED_KEYS.iter().find(|&&(new, _)| new == key) {
+    Some(&(_, old)) if !has_key(key) => old,
+    _ => key,
+  }
+}
+
+/// Determine whether the loaded liblxc is at least `major`.`minor`, so that the
+/// slots it added to `ffi::LxcContainer` can be called.
+pub(crate) fn has_slots_of(major: u32, minor: u32) -> bool {
+  LxcVersion::current().map(|version| version >= LxcVersion::new(major, minor, 0)).unwrap_or(false)
+}
+
+/// Checks that the loaded liblxc has `feature`.
+fn require(feature: LxcFeature) -> Result<(), String> {
+  if has_feature(feature) {
+    Ok(())
+  }
+  else {
+    Err(format!("{} needs liblxc {} or newer, not {}", feature, feature.since(), version()))
+  }
+}
+
+impl LxcContainer {
+
+  /// Checkpoint the running container with CRIU.
+  ///
+  /// # Parameters
+  /// `directory` - directory to write the images to.
+  ///
+  /// `stop` - stop the container once it's dumped.
+  ///
+  /// `verbose` - make CRIU log more.
+  ///
+  /// # Return value
+  /// Returns `Ok` on success, else `Err` with the reason, like liblxc being older
+  /// than 1.1.
+  pub fn checkpoint(&self, directory: &str, stop: bool, verbose: bool) -> Result<(), String> {
+    require(LxcFeature::CheckpointRestore)?;
+    let directory_cstring = str_to_cstring(directory);
+    let done = unsafe {
+      ((*self.underlying).checkpoint)(self.underlying, directory_cstring.as_ptr() as *mut c_char,
+                                      stop as c_char, verbose as c_char) != 0
+    };
+    if done { Ok(()) } else { Err("Couldn't checkpoint container".to_string()) }
+  }
+
+  /// Restore the stopped container from a checkpoint in `directory`, see `checkpoint`.
+  ///
+  /// # Return value
+  /// Returns `Ok` on success, else `Err` with the reason, like liblxc being older
+  /// than 1.1.
+  pub fn restore(&self, directory: &str, verbose: bool) -> Result<(), String> {
+    require(LxcFeature::CheckpointRestore)?;
+    let directory_cstring = str_to_cstring(directory);
+    let done = unsafe {
+      ((*self.underlying).restore)(self.underlying, directory_cstring.as_ptr() as *mut c_char, verbose as c_char) != 0
+    };
+    if done { Ok(()) } else { Err("Couldn't restore container".to_string()) }
+  }
+
+  /// Move host network interface `dev` into the running container.
+  ///
+  /// # Parameters
+  /// `dst_dev_option` - `Option` with the name of the interface in the container,
+  /// or `None` to keep `dev`.
+  ///
+  /// # Return value
+  /// Returns `Ok` on success, else `Err` with the reason, like liblxc being older
+  /// than 1.1.
+  pub fn attach_interface(&self, dev: &str, dst_dev_option: Option<&str>) -> Result<(), String> {
+    require(LxcFeature::AttachInterface)?;
+    let dev_cstring = str_to_cstring(dev);
+    let dst_dev_cstring = dst_dev_option.map(str_to_cstring);
+    let done = unsafe {
+      ((*self.underlying).attach_interface)(self.underlying, dev_cstring.as_ptr(),
+                                            dst_dev_cstring.as_ref().map_or(ptr::null(), |d| d.as_ptr())) != 0
+    };
+    if done { Ok(()) } else { Err("Couldn't attach interface".to_string()) }
+  }
+
+  /// Move network interface `dev` of the running container back to the host.
+  ///
+  /// # Parameters
+  /// `dst_dev_option` - `Option` with the name of the interface on the host, or
+  /// `None` to keep `dev`.
+  ///
+  /// # Return value
+  /// Returns `Ok` on success, else `Err` with the reason, like liblxc being older
+  /// than 1.1.
+  pub fn detach_interface(&self, dev: &str, dst_dev_option: Option<&str>) -> Result<(), String> {
+    require(LxcFeature::AttachInterface)?;
+    let dev_cstring = str_to_cstring(dev);
+    let dst_dev_cstring = dst_dev_option.map(str_to_cstring);
+    let done = unsafe {
+      ((*self.underlying).detach_interface)(self.underlying, dev_cstring.as_ptr(),
+                                            dst_dev_cstring.as_ref().map_or(ptr::null(), |d| d.as_ptr())) != 0
+    };
+    if done { Ok(()) } else { Err("Couldn't detach interface".to_string()) }
+  }
+}
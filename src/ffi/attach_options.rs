@@ -37,32 +37,32 @@ pub const LXC_ATTACH_REMOUNT_PROC_SYS   : u32 = 0x00010000;
 pub const LXC_ATTACH_LSM_NOW            : u32 = 0x00020000;
 // enable some options
 pub const LXC_ATTACH_DEFAULT            : u32 = 0x0000FFFF;
-pub const LXC_ATTACH_LSM                : u32 = (LXC_ATTACH_LSM_EXEC | LXC_ATTACH_LSM_NOW);
+pub const LXC_ATTACH_LSM                : u32 = LXC_ATTACH_LSM_EXEC | LXC_ATTACH_LSM_NOW;
 
 #[repr(C)]
 pub struct LxcAttachOptions {
-  attach_flags: c_int,
-  namespaces: c_int,
-  personality: c_long,
-  initial_cwd: *mut c_char,
-  uid: uid_t,
-  gid: gid_t,
-  env_policy: LxcAttachEnvPolicy,
-  extra_env_vars: *mut*mut c_char,
-  extra_keep_env: *mut*mut c_char,
-  stdin_fd: c_int,
-  stdout_fd: c_int, 
-  stderr_fd: c_int, 
+  pub attach_flags: c_int,
+  pub namespaces: c_int,
+  pub personality: c_long,
+  pub initial_cwd: *mut c_char,
+  pub uid: uid_t,
+  pub gid: gid_t,
+  pub env_policy: LxcAttachEnvPolicy,
+  pub extra_env_vars: *mut*mut c_char,
+  pub extra_keep_env: *mut*mut c_char,
+  pub stdin_fd: c_int,
+  pub stdout_fd: c_int, 
+  pub stderr_fd: c_int, 
 }
 
 #[repr(C)]
 pub struct LxcAttachCommand {
-  program: *mut c_char,
-  argv: *mut*mut c_char
+  pub program: *mut c_char,
+  pub argv: *mut*mut c_char
 }
 
 #[link(name = "lxc")]
-extern {
+extern "C" {
   pub fn lxc_attach_run_command(payload: *mut c_void) -> c_int;
   pub fn lxc_attach_run_shell(payload: *mut c_void) -> c_int;
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+//! Linux system calls and constants missing from the libc crate version in use.
+
+use libc::{c_char, c_int, c_long, c_ulong, c_void, gid_t, pid_t, size_t};
+
+pub const CLONE_NEWNS               : c_int = 0x00020000;
+pub const CLONE_NEWCGROUP           : c_int = 0x02000000;
+pub const CLONE_NEWUTS              : c_int = 0x04000000;
+pub const CLONE_NEWIPC              : c_int = 0x08000000;
+pub const CLONE_NEWUSER             : c_int = 0x10000000;
+pub const CLONE_NEWPID              : c_int = 0x20000000;
+pub const CLONE_NEWNET              : c_int = 0x40000000;
+
+pub const WNOHANG                   : c_int = 1;
+
+pub const MS_RDONLY                 : c_ulong = 1;
+pub const MS_NOSUID                 : c_ulong = 2;
+pub const MS_NODEV                  : c_ulong = 4;
+pub const MS_NOEXEC                 : c_ulong = 8;
+pub const MS_SYNCHRONOUS            : c_ulong = 16;
+pub const MS_REMOUNT                : c_ulong = 32;
+pub const MS_NOATIME                : c_ulong = 1024;
+pub const MS_NODIRATIME             : c_ulong = 2048;
+pub const MS_BIND                   : c_ulong = 4096;
+pub const MS_REC                    : c_ulong = 16384;
+pub const MS_PRIVATE                : c_ulong = 1 << 18;
+pub const MS_RELATIME               : c_ulong = 1 << 21;
+pub const MS_STRICTATIME            : c_ulong = 1 << 24;
+pub const MNT_DETACH                : c_int = 2;
+
+pub const AT_FDCWD                  : c_int = -100;
+pub const AT_RECURSIVE              : c_int = 0x8000;
+pub const OPEN_TREE_CLONE           : c_int = 1;
+pub const OPEN_TREE_CLOEXEC         : c_int = 0o2000000;
+pub const MOVE_MOUNT_F_EMPTY_PATH   : c_int = 0x00000004;
+
+/// Same number on all architectures, like every system call added since Linux 5.1.
+pub const SYS_OPEN_TREE             : c_long = 428;
+pub const SYS_MOVE_MOUNT            : c_long = 429;
+pub const SYS_CLOSE_RANGE           : c_long = 436;
+
+#[cfg(target_arch = "x86_64")]
+pub const SYS_PIVOT_ROOT            : c_long = 155;
+#[cfg(target_arch = "x86")]
+pub const SYS_PIVOT_ROOT            : c_long = 217;
+#[cfg(target_arch = "arm")]
+pub const SYS_PIVOT_ROOT            : c_long = 218;
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+pub const SYS_PIVOT_ROOT            : c_long = 41;
+
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+pub const O_DIRECTORY               : c_int = 0o40000;
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+pub const O_NOFOLLOW                : c_int = 0o100000;
+#[cfg(not(any(target_arch = "arm", target_arch = "aarch64")))]
+pub const O_DIRECTORY               : c_int = 0o200000;
+#[cfg(not(any(target_arch = "arm", target_arch = "aarch64")))]
+pub const O_NOFOLLOW                : c_int = 0o400000;
+
+extern "C" {
+  pub fn kill(pid: pid_t, sig: c_int) -> c_int;
+  pub fn setns(fd: c_int, nstype: c_int) -> c_int;
+  pub fn mount(source: *const c_char, target: *const c_char, fstype: *const c_char,
+               flags: c_ulong, data: *const c_void) -> c_int;
+  pub fn syscall(number: c_long, ...) -> c_long;
+  pub fn unshare(flags: c_int) -> c_int;
+  pub fn umount2(target: *const c_char, flags: c_int) -> c_int;
+  pub fn sethostname(name: *const c_char, len: size_t) -> c_int;
+  pub fn setgroups(size: size_t, list: *const gid_t) -> c_int;
+  pub fn flock(fd: c_int, operation: c_int) -> c_int;
+  pub fn waitpid(pid: pid_t, status: *mut c_int, options: c_int) -> pid_t;
+}
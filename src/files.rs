@@ -0,0 +1,445 @@
+//! Copying files between the host and a container.
+//!
+//! `LxcContainer::push_file` and `LxcContainer::pull_file` copy a single file,
+//! `LxcContainer::push_dir` and `LxcContainer::pull_dir` a directory tree with its
+//! subdirectories, regular files and symlinks.
+//!
+//! Files of a running container are streamed through a shell attached to it, so
+//! any backing store works. The container needs `sh`, `cat`, `stat` and `find`,
+//! as provided by busybox or coreutils. A stopped container with the dir backing
+//! store is accessed directly in its rootfs instead; symlinks are resolved inside
+//! the rootfs and owners are translated to host ids through `lxc.idmap`, or
+//! `lxc.id_map` before liblxc 3.0.
+//!
+//! Paths in the container are absolute. A pushed file replaces the old one only
+//! once it is complete, and so does a pulled file on the host.
+
+use crate::attach::AttachOptions;
+use crate::ffi::sys::{O_DIRECTORY, O_NOFOLLOW};
+use crate::rootfs::resolve;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::fs::{self as unix_fs, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use super::LxcContainer;
+
+/// Replaces `$1` with standard input, owned by `$3:$4` with mode `$2`.
+const WRITE_SCRIPT: &str =
+  "t=\"$1.lxc-push.$$\"; \
+   cat > \"$t\" && chown \"$3:$4\" \"$t\" && chmod \"$2\" \"$t\" && mv -f \"$t\" \"$1\" || { rm -f \"$t\"; exit 1; }";
+
+/// Creates directory `$1` with its parents, owned by `$3:$4` with mode `$2`.
+const MKDIR_SCRIPT: &str = "mkdir -p \"$1\" && chown \"$3:$4\" \"$1\" && chmod \"$2\" \"$1\"";
+
+/// Replaces `$1` with a symlink to `$2`, owned by `$3:$4`.
+const SYMLINK_SCRIPT: &str = "rm -f \"$1\" && ln -s \"$2\" \"$1\" && chown -h \"$3:$4\" \"$1\"";
+
+/// Prints the mode of regular file `$1` in octal on a line, then its content.
+const READ_SCRIPT: &str = "[ -f \"$1\" ] && stat -L -c %a \"$1\" && exec cat \"$1\"";
+
+/// Lists the tree below directory `$1` in pre-order, one entry per line:
+/// "d MODE PATH", "f MODE PATH" or "l TARGET PATH", separated by tabs.
+const LIST_SCRIPT: &str =
+  "cd \"$1\" || exit 1; \
+   find . -mindepth 1 | while IFS= read -r p; do \
+     if [ -L \"$p\" ]; then printf 'l\\t%s\\t%s\\n' \"$(readlink \"$p\")\" \"${p#./}\"; \
+     elif [ -d \"$p\" ]; then printf 'd\\t%s\\t%s\\n' \"$(stat -c %a \"$p\")\" \"${p#./}\"; \
+     elif [ -f \"$p\" ]; then printf 'f\\t%s\\t%s\\n' \"$(stat -c %a \"$p\")\" \"${p#./}\"; \
+     fi; \
+   done";
+
+/// A range of `lxc.idmap`.
+struct IdMap {
+  container: u32,
+  host: u32,
+  count: u32,
+}
+
+/// Translates container id `id` to a host id. Without maps, ids are the same.
+fn map_id(maps: &[IdMap], id: u32, kind: &str) -> Result<u32, String> {
+  if maps.is_empty() {
+    return Ok(id);
+  }
+  maps.iter()
+      .find(|map| id >= map.container && id - map.container < map.count)
+      .map(|map| map.host + (id - map.container))
+      .ok_or_else(|| format!("{} {} isn't mapped to the host", kind, id))
+}
+
+/// Entry of a directory tree, relative to its root.
+enum Entry {
+  Dir(PathBuf, u32),
+  File(PathBuf, u32),
+  Symlink(PathBuf, PathBuf),
+}
+
+/// How the files of a container are reached.
+enum Access<'a> {
+  /// Through a shell attached to the running container.
+  Attached(&'a LxcContainer),
+  /// In the rootfs directory of the stopped container.
+  Direct { rootfs: PathBuf, uid_maps: Vec<IdMap>, gid_maps: Vec<IdMap> },
+}
+
+impl<'a> Access<'a> {
+
+  fn write_file(&self, path: &str, input: &mut File, mode: u32, uid: u32, gid: u32) -> Result<(), String> {
+    match *self {
+      Access::Attached(container) => {
+        let args = [path, &format!("{:o}", mode), &uid.to_string(), &gid.to_string()];
+        container.run_script(&format!("write {}", path), WRITE_SCRIPT, &args, Some(input), |_| Ok(()))
+      }
+      Access::Direct { ref rootfs, ref uid_maps, ref gid_maps } => {
+        let target = resolve(rootfs, path, true)?;
+        let (uid, gid) = (map_id(uid_maps, uid, "User")?, map_id(gid_maps, gid, "Group")?);
+        replace(&target, |temp| {
+          let mut write = || -> io::Result<()> {
+            let mut file = create_temp(temp)?;
+            io::copy(input, &mut file)?;
+            unix_fs::fchown(&file, Some(uid), Some(gid))?;
+            file.set_permissions(fs::Permissions::from_mode(mode))
+          };
+          write().map_err(|e| format!("Couldn't write {}: {}", path, e))
+        })
+      }
+    }
+  }
+
+  fn make_dir(&self, path: &str, mode: u32, uid: u32, gid: u32) -> Result<(), String> {
+    match *self {
+      Access::Attached(container) => {
+        let args = [path, &format!("{:o}", mode), &uid.to_string(), &gid.to_string()];
+        container.run_script(&format!("create directory {}", path), MKDIR_SCRIPT, &args, None, |_| Ok(()))
+      }
+      Access::Direct { ref rootfs, ref uid_maps, ref gid_maps } => {
+        let target = resolve(rootfs, path, true)?;
+        let (uid, gid) = (map_id(uid_maps, uid, "User")?, map_id(gid_maps, gid, "Group")?);
+        fs::create_dir_all(&target)
+          .and_then(|_| open_dir(&target))
+          .and_then(|dir| {
+            unix_fs::fchown(&dir, Some(uid), Some(gid))?;
+            dir.set_permissions(fs::Permissions::from_mode(mode))
+          })
+          .map_err(|e| format!("Couldn't create directory {}: {}", path, e))
+      }
+    }
+  }
+
+  fn make_symlink(&self, path: &str, target: &Path, uid: u32, gid: u32) -> Result<(), String> {
+    let target_str = target.to_str().ok_or_else(|| format!("Invalid symlink target {}", target.display()))?;
+    match *self {
+      Access::Attached(container) => {
+        let args = [path, target_str, &uid.to_string(), &gid.to_string()];
+        container.run_script(&format!("create symlink {}", path), SYMLINK_SCRIPT, &args, None, |_| Ok(()))
+      }
+      Access::Direct { ref rootfs, ref uid_maps, ref gid_maps } => {
+        let link = resolve(rootfs, path, false)?;
+        let (uid, gid) = (map_id(uid_maps, uid, "User")?, map_id(gid_maps, gid, "Group")?);
+        let removed = match fs::symlink_metadata(&link) {
+          Ok(ref metadata) if metadata.is_dir() => Err(io::Error::other("Is a directory")),
+          Ok(_) => fs::remove_file(&link),
+          Err(_) => Ok(()),
+        };
+        removed.and_then(|_| unix_fs::symlink(target, &link))
+               .and_then(|_| unix_fs::lchown(&link, Some(uid), Some(gid)))
+               .map_err(|e| format!("Couldn't create symlink {}: {}", path, e))
+      }
+    }
+  }
+
+  /// Copies file `path` to `output` and returns its mode.
+  fn read_file(&self, path: &str, output: &mut File) -> Result<u32, String> {
+    match *self {
+      Access::Attached(container) => {
+        container.run_script(&format!("read {}", path), READ_SCRIPT, &[path], None, |stdout| {
+          let mut stdout = BufReader::new(stdout);
+          let mut mode = String::new();
+          stdout.read_line(&mut mode)?;
+          io::copy(&mut stdout, output)?;
+          Ok(u32::from_str_radix(mode.trim(), 8).unwrap_or(0o644))
+        })
+      }
+      Access::Direct { ref rootfs, .. } => {
+        let source = resolve(rootfs, path, true)?;
+        let mut read = || -> io::Result<u32> {
+          let mut file = File::open(&source)?;
+          let metadata = file.metadata()?;
+          if !metadata.is_file() {
+            return Err(io::Error::other("Not a regular file"));
+          }
+          io::copy(&mut file, output)?;
+          Ok(metadata.mode() & 0o7777)
+        };
+        read().map_err(|e| format!("Couldn't read {}: {}", path, e))
+      }
+    }
+  }
+
+  /// Lists the tree below directory `path`, parents before their content.
+  fn list(&self, path: &str) -> Result<Vec<Entry>, String> {
+    match *self {
+      Access::Attached(container) => {
+        let listing = container.run_script(&format!("list {}", path), LIST_SCRIPT, &[path], None, |stdout| {
+          let mut listing = String::new();
+          stdout.read_to_string(&mut listing)?;
+          Ok(listing)
+        })?;
+        listing.lines().map(|line| {
+          let mut fields = line.splitn(3, '\t');
+          match (fields.next(), fields.next(), fields.next()) {
+            (Some("l"), Some(target), Some(path)) => Ok(Entry::Symlink(PathBuf::from(path), PathBuf::from(target))),
+            (Some(kind), Some(mode), Some(path)) => {
+              let mode = u32::from_str_radix(mode, 8).map_err(|_| format!("Invalid listing line: {}", line))?;
+              if kind == "d" { Ok(Entry::Dir(PathBuf::from(path), mode)) }
+              else { Ok(Entry::File(PathBuf::from(path), mode)) }
+            }
+            _ => Err(format!("Invalid listing line: {}", line)),
+          }
+        }).collect()
+      }
+      Access::Direct { ref rootfs, .. } => {
+        let root = resolve(rootfs, path, true)?;
+        let mut entries = Vec::new();
+        list_dir(&root, Path::new(""), &mut entries).map_err(|e| format!("Couldn't list {}: {}", path, e))?;
+        Ok(entries)
+      }
+    }
+  }
+}
+
+/// Appends the tree below `root.join(relative)` to `entries`, in sorted pre-order.
+fn list_dir(root: &Path, relative: &Path, entries: &mut Vec<Entry>) -> io::Result<()> {
+  let mut names = fs::read_dir(root.join(relative))?
+                    .map(|entry| entry.map(|entry| entry.file_name()))
+                    .collect::<io::Result<Vec<OsString>>>()?;
+  names.sort();
+  for name in names {
+    let path = relative.join(&name);
+    let metadata = fs::symlink_metadata(root.join(&path))?;
+    if metadata.file_type().is_symlink() {
+      entries.push(Entry::Symlink(path.clone(), fs::read_link(root.join(&path))?));
+    }
+    else if metadata.is_dir() {
+      entries.push(Entry::Dir(path.clone(), metadata.mode() & 0o7777));
+      list_dir(root, &path, entries)?;
+    }
+    else if metadata.is_file() {
+      entries.push(Entry::File(path, metadata.mode() & 0o7777));
+    }
+  }
+  Ok(())
+}
+
+/// Opens directory `path` to change it through the descriptor, failing if a
+/// symlink took its place.
+fn open_dir(path: &Path) -> io::Result<File> {
+  OpenOptions::new().read(true).custom_flags(O_NOFOLLOW | O_DIRECTORY).open(path)
+}
+
+/// Creates temporary file `path` for `replace`. Anything already there, like a
+/// symlink planted in a container's rootfs, makes it fail instead of being followed.
+fn create_temp(path: &Path) -> io::Result<File> {
+  OpenOptions::new().write(true).create_new(true).mode(0o600).custom_flags(O_NOFOLLOW).open(path)
+}
+
+/// Creates `path` through a temporary file next to it, written by `write`.
+fn replace<F: FnOnce(&Path) -> Result<(), String>>(path: &Path, write: F) -> Result<(), String> {
+  let name = path.file_name().ok_or_else(|| format!("Invalid file name {}", path.display()))?;
+  let mut temp_name = OsString::from(".");
+  temp_name.push(name);
+  temp_name.push(".lxc-copy");
+  let temp = path.with_file_name(temp_name);
+  let result = write(&temp).and_then(|_| {
+    fs::rename(&temp, path).map_err(|e| format!("Couldn't replace {}: {}", path.display(), e))
+  });
+  if result.is_err() {
+    let _ = fs::remove_file(&temp);
+  }
+  result
+}
+
+/// Joins container path `dir` and relative path `path`.
+fn container_join(dir: &str, path: &Path) -> Result<String, String> {
+  let path = path.to_str().ok_or_else(|| format!("Invalid file name {}", path.display()))?;
+  Ok(format!("{}/{}", dir.trim_end_matches('/'), path))
+}
+
+impl LxcContainer {
+
+  /// Runs shell `script` in the container with positional parameters `args`.
+  /// `input` is copied to its standard input while `output` reads its standard
+  /// output on another thread. `what` describes the operation in errors.
+  fn run_script<T, F>(&self, what: &str, script: &str, args: &[&str],
+                      input: Option<&mut File>, output: F) -> Result<T, String>
+    where T: Send, F: FnOnce(&mut io::PipeReader) -> io::Result<T> + Send {
+    let error = |e: io::Error| format!("Couldn't {} in container {}: {}", what, self.name(), e);
+    let (stdin_reader, mut stdin_writer) = io::pipe().map_err(error)?;
+    let (mut stdout_reader, stdout_writer) = io::pipe().map_err(error)?;
+    let mut argv = vec!["sh", "-c", script, "sh"];
+    argv.extend_from_slice(args);
+    let stdio = [stdin_reader.as_raw_fd(), stdout_writer.as_raw_fd(), 2];
+    thread::scope(|scope| {
+      let feeder = scope.spawn(move || match input {
+        Some(input) => io::copy(input, &mut stdin_writer).map(|_| ()),
+        None => Ok(()),
+      });
+      let reader = scope.spawn(move || output(&mut stdout_reader));
+      let status = self.attach_run_wait_stdio(&AttachOptions::default(), stdio, "/bin/sh", argv);
+      // Without these, the threads could wait for a process which is gone.
+      drop(stdin_reader);
+      drop(stdout_writer);
+      let fed = feeder.join().unwrap();
+      let result = reader.join().unwrap();
+      match status {
+        Ok(0) => fed.and(result).map_err(error),
+        Ok(_) => Err(format!("Couldn't {} in container {}", what, self.name())),
+        Err(e) => Err(format!("Couldn't {} in container {}: {}", what, self.name(), e)),
+      }
+    })
+  }
+
+  fn file_access(&self) -> Result<Access<'_>, String> {
+    let state = self.state();
+    if state == "RUNNING" {
+      return Ok(Access::Attached(self));
+    }
+    if state != "STOPPED" {
+      return Err(format!("Container {} is {}", self.name(), state));
+    }
+    let rootfs = self.rootfs_dir()?;
+    let mut uid_maps = Vec::new();
+    let mut gid_maps = Vec::new();
+    // liblxc 3.0 renamed lxc.id_map to lxc.idmap, 2.1 knows both.
+    let id_maps = match (self.get_config_item("lxc.idmap"), self.get_config_item("lxc.id_map")) {
+      (Ok(idmap), Ok(id_map)) => if idmap.is_empty() { id_map } else { idmap },
+      (Ok(maps), Err(_)) | (Err(_), Ok(maps)) => maps,
+      (Err(e), Err(_)) => return Err(format!("Couldn't get the ID maps of container {}: {}", self.name(), e)),
+    };
+    for line in id_maps.lines() {
+      let fields: Vec<&str> = line.split_whitespace().collect();
+      let numbers: Vec<u32> = fields.iter().skip(1).filter_map(|n| n.parse().ok()).collect();
+      if fields.len() != 4 || numbers.len() != 3 {
+        return Err(format!("Invalid ID map {}", line));
+      }
+      let map = IdMap { container: numbers[0], host: numbers[1], count: numbers[2] };
+      match fields[0] {
+        "u" => uid_maps.push(map),
+        "g" => gid_maps.push(map),
+        _ => return Err(format!("Invalid ID map {}", line)),
+      }
+    }
+    Ok(Access::Direct { rootfs, uid_maps, gid_maps })
+  }
+
+  /// Copy file `host_path` into the container.
+  ///
+  /// # Parameters
+  /// `host_path` - regular file on the host.
+  ///
+  /// `container_path` - absolute path in the container. Its directory has to exist.
+  ///
+  /// `mode` - permission bits of the file, for example 0o644.
+  ///
+  /// `uid`, `gid` - owner of the file, as ids in the container.
+  ///
+  /// # Return value
+  /// Returns `Ok` if the file was copied, else `Err`.
+  ///
+  /// ## Note
+  /// The container has to be running, or stopped with a directory rootfs.
+  pub fn push_file<P: AsRef<Path>>(&self, host_path: P, container_path: &str,
+                                   mode: u32, uid: u32, gid: u32) -> Result<(), String> {
+    let host_path = host_path.as_ref();
+    let mut input = File::open(host_path).map_err(|e| format!("Couldn't open {}: {}", host_path.display(), e))?;
+    self.file_access()?.write_file(container_path, &mut input, mode, uid, gid)
+  }
+
+  /// Copy file `container_path` from the container to `host_path`, with the same
+  /// permission bits. The copy is owned by the caller.
+  ///
+  /// # Return value
+  /// Returns `Ok` if the file was copied, else `Err`.
+  pub fn pull_file<P: AsRef<Path>>(&self, container_path: &str, host_path: P) -> Result<(), String> {
+    let access = self.file_access()?;
+    pull(&access, container_path, host_path.as_ref())
+  }
+
+  /// Copy directory `host_dir` with everything in it into the container, keeping
+  /// permission bits. Directories are created as needed, files and symlinks replaced.
+  /// Devices, fifos and sockets are skipped.
+  ///
+  /// # Parameters
+  /// `uid`, `gid` - owner of everything copied, as ids in the container.
+  ///
+  /// # Return value
+  /// Returns `Ok` if everything was copied, else `Err` for the first failure.
+  pub fn push_dir<P: AsRef<Path>>(&self, host_dir: P, container_dir: &str,
+                                  uid: u32, gid: u32) -> Result<(), String> {
+    let host_dir = host_dir.as_ref();
+    let error = |e: io::Error| format!("Couldn't read {}: {}", host_dir.display(), e);
+    let access = self.file_access()?;
+    let mut entries = Vec::new();
+    list_dir(host_dir, Path::new(""), &mut entries).map_err(error)?;
+    access.make_dir(container_dir, fs::metadata(host_dir).map_err(error)?.mode() & 0o7777, uid, gid)?;
+    for entry in entries {
+      match entry {
+        Entry::Dir(path, mode) => access.make_dir(&container_join(container_dir, &path)?, mode, uid, gid)?,
+        Entry::File(path, mode) => {
+          let mut input = File::open(host_dir.join(&path)).map_err(error)?;
+          access.write_file(&container_join(container_dir, &path)?, &mut input, mode, uid, gid)?
+        }
+        Entry::Symlink(path, target) => {
+          access.make_symlink(&container_join(container_dir, &path)?, &target, uid, gid)?
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Copy directory `container_dir` with everything in it from the container to
+  /// `host_dir`, keeping permission bits. The copies are owned by the caller.
+  ///
+  /// # Return value
+  /// Returns `Ok` if everything was copied, else `Err` for the first failure.
+  pub fn pull_dir<P: AsRef<Path>>(&self, container_dir: &str, host_dir: P) -> Result<(), String> {
+    let host_dir = host_dir.as_ref();
+    let access = self.file_access()?;
+    let entries = access.list(container_dir)?;
+    fs::create_dir_all(host_dir).map_err(|e| format!("Couldn't create {}: {}", host_dir.display(), e))?;
+    // Modes of directories are set last, so that read-only ones can be filled.
+    let mut dirs = Vec::new();
+    for entry in entries {
+      match entry {
+        Entry::Dir(path, mode) => {
+          let dir = host_dir.join(&path);
+          fs::create_dir_all(&dir).map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+          dirs.push((dir, mode));
+        }
+        Entry::File(path, _) => pull(&access, &container_join(container_dir, &path)?, &host_dir.join(&path))?,
+        Entry::Symlink(path, target) => {
+          let link = host_dir.join(&path);
+          let _ = fs::remove_file(&link);
+          unix_fs::symlink(&target, &link).map_err(|e| format!("Couldn't create {}: {}", link.display(), e))?;
+        }
+      }
+    }
+    for (dir, mode) in dirs.into_iter().rev() {
+      open_dir(&dir).and_then(|file| file.set_permissions(fs::Permissions::from_mode(mode)))
+        .map_err(|e| format!("Couldn't change mode of {}: {}", dir.display(), e))?;
+    }
+    Ok(())
+  }
+}
+
+/// Copies file `container_path` to `host_path` with its permission bits.
+fn pull(access: &Access, container_path: &str, host_path: &Path) -> Result<(), String> {
+  replace(host_path, |temp| {
+    let error = |e: io::Error| format!("Couldn't write {}: {}", host_path.display(), e);
+    let mut output = create_temp(temp).map_err(error)?;
+    let mode = access.read_file(container_path, &mut output)?;
+    output.set_permissions(fs::Permissions::from_mode(mode)).map_err(error)
+  })
+}
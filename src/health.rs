@@ -0,0 +1,231 @@
+//! Health checks run inside containers, like Docker's HEALTHCHECK.
+//!
+//! A `HealthCheck` describes a command and how often it runs; a `HealthRunner`
+//! runs it against a container and tracks whether the container is starting,
+//! healthy or unhealthy. Runners are polled by their owner, for example the
+//! `Supervisor`, which restarts unhealthy containers, or a stack waiting for its
+//! members to come up.
+//!
+//! With the `serde` feature `HealthCheck` implements `Serialize` and `Deserialize`,
+//! with durations in seconds.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::attach::{wait_attached, ATTACH_TIMEOUT};
+use super::{AttachOptions, LxcContainer};
+
+/// Number of probes kept in the history of a `HealthRunner`.
+pub const HEALTH_HISTORY: usize = 5;
+
+/// Command checking that a container works, and when to run it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct HealthCheck {
+  /// Command run inside the container; exit status 0 means healthy.
+  pub command: Vec<String>,
+  /// Time between probes.
+  #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+  pub interval: Duration,
+  /// Time after which a probe counts as failed.
+  #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+  pub timeout: Duration,
+  /// Consecutive failures after which the container is unhealthy.
+  pub retries: u32,
+  /// Time after the container started during which failures don't count.
+  #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+  pub start_period: Duration,
+}
+
+impl Default for HealthCheck {
+  /// No command, probed every 30 seconds with a 30 second timeout, unhealthy after
+  /// 3 failures, without start period.
+  fn default() -> HealthCheck {
+    HealthCheck {
+      command: Vec::new(),
+      interval: Duration::from_secs(30),
+      timeout: Duration::from_secs(30),
+      retries: 3,
+      start_period: Duration::from_secs(0),
+    }
+  }
+}
+
+/// Health of a container as seen by its `HealthRunner`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthStatus {
+  /// The container isn't running, or no probe succeeded or exhausted the retries yet.
+  Starting,
+  /// The last probe succeeded.
+  Healthy,
+  /// The last `retries` probes failed.
+  Unhealthy,
+}
+
+impl HealthStatus {
+  /// Lower-case name of the status, for example "healthy".
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      HealthStatus::Starting => "starting",
+      HealthStatus::Healthy => "healthy",
+      HealthStatus::Unhealthy => "unhealthy",
+    }
+  }
+}
+
+/// Result of one run of the health check command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthProbe {
+  /// When the probe started.
+  pub started: Instant,
+  /// How long the probe took, up to the timeout.
+  pub duration: Duration,
+  /// Exit status of the command, or `Err` if it couldn't be run or timed out.
+  pub result: Result<i32, &'static str>,
+}
+
+impl HealthProbe {
+  /// Whether the command exited with status 0.
+  pub fn is_success(&self) -> bool {
+    self.result == Ok(0)
+  }
+}
+
+/// Runs a `HealthCheck` against a container and tracks its health.
+pub struct HealthRunner {
+  check: HealthCheck,
+  status: HealthStatus,
+  failing_streak: u32,
+  history: VecDeque<HealthProbe>,
+  running_since: Option<Instant>,
+  next_probe: Option<Instant>,
+}
+
+impl HealthRunner {
+
+  /// Creates a runner for `check`, starting in `HealthStatus::Starting`.
+  pub fn new(check: HealthCheck) -> HealthRunner {
+    HealthRunner {
+      check,
+      status: HealthStatus::Starting,
+      failing_streak: 0,
+      history: VecDeque::new(),
+      running_since: None,
+      next_probe: None,
+    }
+  }
+
+  /// The health check this runner runs.
+  pub fn check(&self) -> &HealthCheck {
+    &self.check
+  }
+
+  /// Current health status.
+  pub fn status(&self) -> HealthStatus {
+    self.status
+  }
+
+  /// Number of consecutive failed probes that counted.
+  pub fn failing_streak(&self) -> u32 {
+    self.failing_streak
+  }
+
+  /// Last `HEALTH_HISTORY` probes, oldest first.
+  pub fn history(&self) -> &VecDeque<HealthProbe> {
+    &self.history
+  }
+
+  /// Forget the status, as when the container restarts. The history is kept.
+  pub fn reset(&mut self) {
+    self.status = HealthStatus::Starting;
+    self.failing_streak = 0;
+    self.running_since = None;
+    self.next_probe = None;
+  }
+
+  /// Probe `container` if it is running and a probe is due: the first one right
+  /// after the container is seen running, then every `interval`.
+  ///
+  /// # Return value
+  /// Returns the updated health status.
+  ///
+  /// ## Note
+  /// This blocks for up to the check's `timeout`. A command that times out is
+  /// killed; processes it started inside the container keep running.
+  pub fn poll(&mut self, container: &LxcContainer) -> HealthStatus {
+    if !container.is_running() {
+      self.reset();
+      return self.status;
+    }
+    let now = Instant::now();
+    self.running_since.get_or_insert(now);
+    if self.next_probe.is_none_or(|due| due <= now) {
+      self.probe(container);
+    }
+    self.status
+  }
+
+  /// Probe `container` now, whether a probe is due or not.
+  ///
+  /// # Return value
+  /// Returns the updated health status.
+  pub fn probe(&mut self, container: &LxcContainer) -> HealthStatus {
+    let started = Instant::now();
+    let running_since = *self.running_since.get_or_insert(started);
+    let result = if container.is_running() {
+      run_command(container, &self.check)
+    }
+    else {
+      Err("not running")
+    };
+    let probe = HealthProbe { started, duration: started.elapsed(), result };
+
+    if probe.is_success() {
+      self.failing_streak = 0;
+      self.status = HealthStatus::Healthy;
+    }
+    else if self.status != HealthStatus::Starting || started - running_since >= self.check.start_period {
+      self.failing_streak += 1;
+      if self.failing_streak >= self.check.retries.max(1) {
+        self.status = HealthStatus::Unhealthy;
+      }
+    }
+    if self.history.len() == HEALTH_HISTORY {
+      self.history.pop_front();
+    }
+    self.history.push_back(probe);
+    self.next_probe = Some(Instant::now() + self.check.interval);
+    self.status
+  }
+}
+
+fn run_command(container: &LxcContainer, check: &HealthCheck) -> Result<i32, &'static str> {
+  if check.command.is_empty() {
+    return Err("Empty health check command");
+  }
+  let argv = check.command.iter().map(|a| &a[..]).collect();
+  let pid = container.attach_stdio(&AttachOptions::default(), [0, 1, 2], &check.command[0], argv)?;
+  // A command that times out is killed, so that probes don't pile up.
+  wait_attached(pid, Some(check.timeout)).map_err(|e| match e {
+    ATTACH_TIMEOUT => "Health check timed out",
+    e => e,
+  })
+}
+
+#[cfg(feature = "serde")]
+mod seconds {
+  use serde::{Deserialize, Deserializer, Serializer};
+  use std::time::Duration;
+
+  pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let secs = f64::deserialize(deserializer)?;
+    if !(secs >= 0.0 && secs.is_finite()) {
+      return Err(serde::de::Error::custom("duration must be a non-negative number of seconds"));
+    }
+    Ok(Duration::from_secs_f64(secs))
+  }
+}
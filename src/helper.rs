@@ -1,9 +1,10 @@
-use libc::{c_char};
+//! Module containing helper functions, which will be used only internally.
+
+use libc::{c_char, c_int, c_void};
 use std::ffi::{CString, CStr};
+use std::ptr;
 use std::str;
 
-/// Module containing helper functions, which will be used only internally.
-
 /// Converts `&str` to `CString`.
 pub fn str_to_cstring(s: &str) -> CString {
     // Maybe better fail handling should be used
@@ -14,8 +15,39 @@ pub fn str_to_cstring(s: &str) -> CString {
 pub fn ptr_to_str(ptr: *const c_char) -> String {
   unsafe {
     let bytes = CStr::from_ptr(ptr).to_bytes();
-    str::from_utf8(bytes).ok().expect("Invalid UTF8 string").to_string()
+    str::from_utf8(bytes).expect("Invalid UTF8 string").to_string()
+  }
+}
+
+/// Converts C-string pointer allocated by liblxc to `String` and frees it.
+pub fn ptr_to_owned_str(ptr: *mut c_char) -> String {
+  let s = ptr_to_str(ptr);
+  unsafe {
+    libc::free(ptr as *mut c_void);
+  }
+  s
+}
+
+/// Converts NULL-terminated array of C-strings allocated by liblxc to `String`
+/// vector and frees both the strings and the array. NULL gives an empty vector.
+pub fn ptr_array_to_owned_vec(array: *mut *mut c_char) -> Vec<String> {
+  let mut list = Vec::new();
+  if array.is_null() {
+    return list;
   }
+  unsafe {
+    let mut i = 0;
+    loop {
+      let item_ptr = *array.offset(i);
+      if item_ptr.is_null() {
+        break;
+      }
+      list.push(ptr_to_owned_str(item_ptr));
+      i += 1;
+    }
+    libc::free(array as *mut c_void);
+  }
+  list
 }
 
 /// Converts `&str` vector to `CString` vector
@@ -23,4 +55,24 @@ pub fn vec_str_to_cstring(vec: Vec<&str>) -> Vec<CString> {
     vec.iter()
        .map(|s| str_to_cstring(s))
        .collect::<Vec<CString>>()
-}
\ No newline at end of file
+}
+
+/// Builds NULL-terminated argv from `CString` vector. The result borrows from
+/// `vec`, so `vec` has to outlive any use of the returned pointers.
+pub fn vec_cstring_to_ptr(vec: &[CString]) -> Vec<*const c_char> {
+  let mut ptrs = vec.iter()
+                    .map(|s| s.as_ptr())
+                    .collect::<Vec<*const c_char>>();
+  ptrs.push(ptr::null());
+  ptrs
+}
+
+/// Converts status returned by `waitpid` to the exit code of the process.
+pub fn wait_status_to_exit_code(status: c_int) -> Result<i32, &'static str> {
+  if status & 0x7f == 0 {
+    Ok((status >> 8) & 0xff)
+  }
+  else {
+    Err("Process terminated by a signal")
+  }
+}
@@ -0,0 +1,322 @@
+//! Containers created from local root filesystems, enabled with the `image` feature.
+//!
+//! `LxcContainer::create_from_rootfs` creates a container with the dir backing
+//! store from a rootfs tarball (plain or gzip-compressed) or directory, without a
+//! template or network access: the rootfs is unpacked into the container's
+//! directory and a minimal configuration is written.
+//!
+//! An `ImageCache` keeps rootfs tarballs in a local directory under the hex SHA-256
+//! of their content, so that a build farm can import an image once and create
+//! containers from it by hash:
+//!
+//! ```no_run
+//! use liblxc::LxcContainer;
+//! use liblxc::image::ImageCache;
+//!
+//! let cache = ImageCache::new("/var/cache/rust-lxc/images");
+//! let image = cache.import("alpine-3.4-amd64.tar.gz").unwrap();
+//! let c = LxcContainer::new("builder", None).unwrap();
+//! cache.create_container(&image.hash, &c, &[("lxc.arch", "amd64")]).unwrap();
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{lchown, symlink, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use crate::features::config_key;
+use super::LxcContainer;
+
+/// Root filesystem to create a container from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RootfsSource {
+  /// Tar archive of the rootfs, optionally gzip-compressed.
+  Tarball(PathBuf),
+  /// Directory holding the rootfs, copied with its permissions and, when running as
+  /// root, its ownership. Device nodes, fifos and sockets are skipped.
+  Directory(PathBuf),
+}
+
+/// Imports started by this process, numbering their partial files.
+static IMPORTS: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn io_error(path: &Path, e: io::Error) -> String {
+  format!("{}: {}", path.display(), e)
+}
+
+fn is_root() -> bool {
+  unsafe { ::libc::geteuid() == 0 }
+}
+
+impl LxcContainer {
+
+  /// Create the container from a local root filesystem, with the dir backing store.
+  ///
+  /// The rootfs is unpacked to `LXCPATH/NAME/rootfs`, then `lxc.rootfs` and
+  /// `lxc.utsname` are set, followed by the items in `config`, and the configuration
+  /// is saved.
+  ///
+  /// # Parameters
+  /// `source` - tarball or directory holding the rootfs.
+  ///
+  /// `config` - additional configuration items, in order, for example `("lxc.arch", "amd64")`.
+  ///
+  /// # Return value
+  /// Returns `Ok(())` on success, else `Err` with a description of the error. On
+  /// failure the unpacked rootfs is removed again.
+  pub fn create_from_rootfs(&self, source: &RootfsSource, config: &[(&str, &str)]) -> Result<(), String> {
+    self.create_with_rootfs(|rootfs| {
+      match *source {
+        RootfsSource::Tarball(ref tarball) => unpack_tarball(tarball, rootfs)?,
+        RootfsSource::Directory(ref source_dir) => copy_rootfs(source_dir, rootfs)?,
+      }
+      Ok(config.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect())
+    })
+  }
+
+  /// Create the container around a rootfs filled by `populate`, which returns the
+  /// configuration items to set after `lxc.rootfs` and `lxc.utsname`.
+  pub(crate) fn create_with_rootfs<F>(&self, populate: F) -> Result<(), String>
+                                      where F: FnOnce(&Path) -> Result<Vec<(String, String)>, String> {
+    let name = self.name();
+    if self.is_defined() {
+      return Err(format!("Container {} already exists", name));
+    }
+    let dir = Path::new(&self.get_config_path()).join(&name);
+    let rootfs = dir.join("rootfs");
+    if rootfs.exists() {
+      return Err(format!("{} already exists", rootfs.display()));
+    }
+    let created_dir = !dir.exists();
+    fs::create_dir_all(&rootfs).map_err(|e| io_error(&rootfs, e))?;
+
+    let result = populate(&rootfs).and_then(|config| self.write_rootfs_config(&rootfs, &config));
+    if result.is_err() {
+      let _ = fs::remove_dir_all(if created_dir { &dir } else { &rootfs });
+    }
+    result
+  }
+
+  fn write_rootfs_config(&self, rootfs: &Path, config: &[(String, String)]) -> Result<(), String> {
+    let items = [(config_key("lxc.rootfs.path").to_string(), rootfs.to_string_lossy().into_owned()),
+                 (config_key("lxc.uts.name").to_string(), self.name())];
+    for (key, value) in items.iter().chain(config.iter()) {
+      if !self.set_config_item(key, value) {
+        return Err(format!("Failed to set {} = {}", key, value));
+      }
+    }
+    let config_file = self.config_file_name()?;
+    if !self.save_config(&config_file) {
+      return Err(format!("Failed to save {}", config_file));
+    }
+    Ok(())
+  }
+}
+
+/// Open a tar archive, gzip-compressed or not, set up to unpack a rootfs.
+pub(crate) fn open_tarball(tarball: &Path) -> Result<tar::Archive<Box<dyn Read>>, String> {
+  let mut file = File::open(tarball).map_err(|e| io_error(tarball, e))?;
+  let mut magic = [0u8; 2];
+  let gzip = file.read(&mut magic).map_err(|e| io_error(tarball, e))? == 2 && magic == [0x1f, 0x8b];
+  file.seek(SeekFrom::Start(0)).map_err(|e| io_error(tarball, e))?;
+  let reader: Box<dyn Read> = if gzip { Box::new(GzDecoder::new(file)) } else { Box::new(file) };
+  let mut archive = tar::Archive::new(reader);
+  archive.set_preserve_permissions(true);
+  archive.set_preserve_ownerships(is_root());
+  Ok(archive)
+}
+
+/// Unpack a tar archive, gzip-compressed or not, into `dir`.
+pub(crate) fn unpack_tarball(tarball: &Path, dir: &Path) -> Result<(), String> {
+  open_tarball(tarball)?.unpack(dir).map_err(|e| io_error(tarball, e))
+}
+
+fn copy_rootfs(source: &Path, rootfs: &Path) -> Result<(), String> {
+  let metadata = fs::metadata(source).map_err(|e| io_error(source, e))?;
+  if !metadata.is_dir() {
+    return Err(format!("{} is not a directory", source.display()));
+  }
+  copy_tree(source, rootfs, is_root()).map_err(|e| io_error(source, e))?;
+  copy_attributes(&metadata, rootfs, is_root()).map_err(|e| io_error(rootfs, e))
+}
+
+fn copy_tree(from_dir: &Path, to_dir: &Path, chown: bool) -> io::Result<()> {
+  for entry in fs::read_dir(from_dir)? {
+    let entry = entry?;
+    let from = entry.path();
+    let to = to_dir.join(entry.file_name());
+    let metadata = fs::symlink_metadata(&from)?;
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+      symlink(fs::read_link(&from)?, &to)?;
+      if chown {
+        lchown(&to, Some(metadata.uid()), Some(metadata.gid()))?;
+      }
+      continue;
+    }
+    if file_type.is_dir() {
+      fs::create_dir(&to)?;
+      copy_tree(&from, &to, chown)?;
+    }
+    else if file_type.is_file() {
+      fs::copy(&from, &to)?;
+    }
+    else {
+      continue;
+    }
+    copy_attributes(&metadata, &to, chown)?;
+  }
+  Ok(())
+}
+
+fn copy_attributes(metadata: &fs::Metadata, to: &Path, chown: bool) -> io::Result<()> {
+  if chown {
+    lchown(to, Some(metadata.uid()), Some(metadata.gid()))?;
+  }
+  // After chown, which clears setuid and setgid bits.
+  fs::set_permissions(to, metadata.permissions())
+}
+
+/// Hex SHA-256 of everything `reader` yields, copied to `sink` on the way.
+pub(crate) fn sha256_copy<R: Read, W: Write>(reader: &mut R, sink: &mut W) -> io::Result<String> {
+  let mut hasher = Sha256::new();
+  let mut buf = [0u8; 64 * 1024];
+  loop {
+    let n = match reader.read(&mut buf) {
+      Ok(0) => break,
+      Ok(n) => n,
+      Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
+    };
+    hasher.update(&buf[..n]);
+    sink.write_all(&buf[..n])?;
+  }
+  Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Hex SHA-256 of the content of file `path`.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String, String> {
+  let path = path.as_ref();
+  let mut file = File::open(path).map_err(|e| io_error(path, e))?;
+  sha256_copy(&mut file, &mut io::sink()).map_err(|e| io_error(path, e))
+}
+
+fn is_hash(name: &str) -> bool {
+  name.len() == 64 && name.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Image stored in an `ImageCache`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedImage {
+  /// Hex SHA-256 of the tarball.
+  pub hash: String,
+  /// Path of the tarball in the cache.
+  pub path: PathBuf,
+  /// Size of the tarball in bytes.
+  pub size: u64,
+}
+
+/// Directory of rootfs tarballs named by the SHA-256 of their content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageCache {
+  dir: PathBuf,
+}
+
+impl ImageCache {
+
+  /// Cache in directory `dir`, which is created by the first import.
+  pub fn new<P: AsRef<Path>>(dir: P) -> ImageCache {
+    ImageCache { dir: dir.as_ref().to_path_buf() }
+  }
+
+  /// Directory of the cache.
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Copy a tarball into the cache. Importing the same content again is a no-op.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the cached image, else `Err` with a description of the error.
+  pub fn import<P: AsRef<Path>>(&self, tarball: P) -> Result<CachedImage, String> {
+    let tarball = tarball.as_ref();
+    fs::create_dir_all(&self.dir).map_err(|e| io_error(&self.dir, e))?;
+    let mut source = File::open(tarball).map_err(|e| io_error(tarball, e))?;
+    // Unique per import, so that concurrent imports don't write the same file.
+    let partial = self.dir.join(format!(".import-{}-{}", ::std::process::id(),
+                                        IMPORTS.fetch_add(1, Ordering::Relaxed)));
+    let hash = OpenOptions::new().write(true).create_new(true).open(&partial)
+      .and_then(|mut copy| sha256_copy(&mut source, &mut copy))
+      .map_err(|e| { let _ = fs::remove_file(&partial); io_error(tarball, e) })?;
+    let path = self.dir.join(&hash);
+    let stored = if path.exists() { fs::remove_file(&partial) } else { fs::rename(&partial, &path) };
+    stored.map_err(|e| io_error(&path, e))?;
+    self.get(&hash).ok_or_else(|| format!("{} vanished from the cache", hash))
+  }
+
+  /// Look up image `hash`, with or without a `sha256:` prefix.
+  pub fn get(&self, hash: &str) -> Option<CachedImage> {
+    let hash = hash.trim_start_matches("sha256:");
+    if !is_hash(hash) {
+      return None;
+    }
+    let path = self.dir.join(hash);
+    match fs::metadata(&path) {
+      Ok(ref metadata) if metadata.is_file() => {
+        Some(CachedImage { hash: hash.to_string(), path, size: metadata.len() })
+      }
+      _ => None,
+    }
+  }
+
+  /// List the cached images, sorted by hash. A cache that wasn't created yet is empty.
+  pub fn list(&self) -> Result<Vec<CachedImage>, String> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(io_error(&self.dir, e)),
+    };
+    let mut images = Vec::new();
+    for entry in entries {
+      let entry = entry.map_err(|e| io_error(&self.dir, e))?;
+      if let Some(image) = self.get(&entry.file_name().to_string_lossy()) {
+        images.push(image);
+      }
+    }
+    images.sort_by(|a, b| a.hash.cmp(&b.hash));
+    Ok(images)
+  }
+
+  /// Remove image `hash` from the cache.
+  ///
+  /// # Return value
+  /// Returns `Ok(true)` if it was removed, `Ok(false)` if it wasn't cached, else `Err`.
+  pub fn remove(&self, hash: &str) -> Result<bool, String> {
+    match self.get(hash) {
+      Some(image) => fs::remove_file(&image.path).map(|()| true).map_err(|e| io_error(&image.path, e)),
+      None => Ok(false),
+    }
+  }
+
+  /// Check that the content of image `hash` still matches its hash.
+  ///
+  /// # Return value
+  /// Returns `Ok(true)` if it matches, `Ok(false)` if it doesn't, else `Err` if the
+  /// image isn't cached or can't be read.
+  pub fn verify(&self, hash: &str) -> Result<bool, String> {
+    let image = self.get(hash).ok_or_else(|| format!("Image {} is not in the cache", hash))?;
+    Ok(sha256_file(&image.path)? == image.hash)
+  }
+
+  /// Create `container` from image `hash`, see `LxcContainer::create_from_rootfs`.
+  ///
+  /// # Return value
+  /// Returns `Ok(())` on success, else `Err` with a description of the error.
+  pub fn create_container(&self, hash: &str, container: &LxcContainer,
+                          config: &[(&str, &str)]) -> Result<(), String> {
+    let image = self.get(hash).ok_or_else(|| format!("Image {} is not in the cache", hash))?;
+    container.create_from_rootfs(&RootfsSource::Tarball(image.path), config)
+  }
+}
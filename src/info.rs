@@ -0,0 +1,73 @@
+//! Summary of a container for inventory and monitoring.
+//!
+//! With the `serde` feature `ContainerInfo` implements `Serialize` and
+//! `Deserialize`, so it can be emitted as JSON instead of parsing `lxc-info`.
+
+use std::collections::BTreeMap;
+use super::{LxcContainer, LxcSnapshot};
+
+/// cgroup items collected into `ContainerInfo::cgroup` while the container is active,
+/// by cgroup v1 name and the cgroup2 item read instead when the first isn't there.
+pub const CGROUP_STATS: [(&str, &str); 5] = [("cpuacct.usage", "cpu.stat"),
+                                             ("memory.usage_in_bytes", "memory.current"),
+                                             ("memory.limit_in_bytes", "memory.max"),
+                                             ("memory.memsw.usage_in_bytes", "memory.swap.current"),
+                                             ("pids.current", "pids.current")];
+
+/// Snapshot of everything liblxc reports about a container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContainerInfo {
+  /// Name of the container.
+  pub name: String,
+  /// Upper-case state, as returned by `LxcContainer::state`.
+  pub state: String,
+  /// Pid of init as seen from the host, `None` unless the container is active.
+  pub pid: Option<i32>,
+  /// Network interfaces inside the container.
+  pub interfaces: Vec<String>,
+  /// IP addresses of all interfaces.
+  pub ips: Vec<String>,
+  /// lxcpath the container belongs to.
+  pub config_path: String,
+  /// Full path to the configuration file, `None` if liblxc doesn't know it.
+  pub config_file: Option<String>,
+  /// Snapshots of the container.
+  pub snapshots: Vec<LxcSnapshot>,
+  /// Values of those `CGROUP_STATS` the container's cgroups provide, by the name
+  /// of the item read.
+  pub cgroup: BTreeMap<String, String>,
+}
+
+impl LxcContainer {
+
+  /// Gather state, addresses, paths, snapshots and cgroup statistics of the container.
+  ///
+  /// ## Note
+  /// Items that can't be retrieved are left empty, as the corresponding getters do;
+  /// the information is not gathered atomically.
+  pub fn info(&self) -> ContainerInfo {
+    let pid = self.init_pid();
+    let active = pid > 0;
+    let mut cgroup = BTreeMap::new();
+    if active {
+      for &(v1_key, v2_key) in CGROUP_STATS.iter() {
+        let read = |key: &'static str| self.get_cgroup_item(key).map(|value| (key, value));
+        if let Ok((key, value)) = read(v1_key).or_else(|_| read(v2_key)) {
+          cgroup.insert(key.to_string(), value.trim_end().to_string());
+        }
+      }
+    }
+    ContainerInfo {
+      name: self.name(),
+      state: self.state(),
+      pid: if active { Some(pid) } else { None },
+      interfaces: if active { self.get_interfaces() } else { Vec::new() },
+      ips: if active { self.get_ips(None, None, 0) } else { Vec::new() },
+      config_path: self.get_config_path(),
+      config_file: self.config_file_name().ok(),
+      snapshots: if self.is_defined() { self.snapshot_list().unwrap_or_default() } else { Vec::new() },
+      cgroup,
+    }
+  }
+}
@@ -1,16 +1,115 @@
 extern crate libc;
 
+#[cfg(not(any(feature = "liblxc", feature = "native")))]
+compile_error!("LxcContainer needs the `liblxc` or the `native` feature");
+
+#[cfg(all(feature = "liblxc", feature = "archive"))]
+pub mod archive;
+#[cfg(feature = "liblxc")]
+mod attach;
+#[cfg(feature = "liblxc")]
+mod autostart;
+#[cfg(feature = "liblxc")]
+pub mod diff;
+#[cfg(feature = "liblxc")]
+mod features;
+#[cfg(feature = "liblxc")]
 mod ffi;
+// Without liblxc only the system calls it doesn't provide are bound.
+#[cfg(not(feature = "liblxc"))]
+#[allow(dead_code)]
+mod ffi {
+  pub mod sys;
+}
+#[cfg(feature = "liblxc")]
+mod files;
+#[cfg(feature = "liblxc")]
+mod health;
+#[cfg_attr(not(feature = "liblxc"), allow(dead_code))]
 mod helper;
+#[cfg(all(feature = "liblxc", feature = "image"))]
+pub mod image;
+#[cfg(feature = "liblxc")]
+mod info;
+#[cfg(feature = "liblxc")]
+mod lock;
+#[cfg(feature = "liblxc")]
+pub mod monitor;
+pub mod mount;
+pub mod namespace;
+#[cfg(feature = "native")]
+pub mod native;
+pub mod process;
+mod rootfs;
+#[cfg(all(feature = "liblxc", feature = "oci"))]
+pub mod oci;
+mod shared;
+#[cfg(feature = "liblxc")]
+pub mod snapshot_policy;
+#[cfg(feature = "liblxc")]
+mod stop;
+#[cfg(feature = "liblxc")]
+pub mod supervisor;
+#[cfg(feature = "liblxc")]
+mod template;
+#[cfg(all(feature = "liblxc", feature = "spec"))]
+pub mod spec;
+#[cfg(all(feature = "liblxc", feature = "spec"))]
+pub mod stack;
+#[cfg(all(feature = "liblxc", feature = "async"))]
+mod async_container;
+#[cfg(feature = "liblxc")]
 use helper::*;
+#[cfg(feature = "liblxc")]
 use std::ptr;
+#[cfg(feature = "liblxc")]
+pub use attach::AttachOptions;
+#[cfg(feature = "liblxc")]
+pub use autostart::{autostart, list_autostart_containers, AutostartAction, AutostartOptions,
+                    AutostartOutcome};
+#[cfg(feature = "liblxc")]
+pub use diff::{ChangeKind, DiffOptions, FsChange, RootfsDiff};
+#[cfg(feature = "liblxc")]
+pub use features::{has_feature, key_feature, LxcFeature, LxcVersion};
+#[cfg(feature = "liblxc")]
+pub use health::{HealthCheck, HealthProbe, HealthRunner, HealthStatus, HEALTH_HISTORY};
+#[cfg(feature = "liblxc")]
+pub use info::{ContainerInfo, CGROUP_STATS};
+#[cfg(feature = "liblxc")]
+pub use lock::{LxcLock, LxcLockGuard, LxcContainerLockGuard};
+#[cfg(feature = "liblxc")]
+pub use snapshot_policy::{Retention, SnapshotPolicy, SnapshotScheduler};
+#[cfg(feature = "liblxc")]
+pub use shared::SharedLxcContainer;
+pub use shared::{ForkGuard, fork_guard};
+#[cfg(feature = "liblxc")]
+pub use monitor::{Monitor, MonitorEvent};
+pub use mount::{MountCreate, MountEntry};
+pub use namespace::{ContainerNamespaces, Namespace, NamespaceFd};
+pub use process::{signal_number, ContainerProcess, SignalTarget};
+#[cfg(feature = "liblxc")]
+pub use stop::{StopPolicy, StopReport, StopStage};
+#[cfg(feature = "liblxc")]
+pub use supervisor::{RestartPolicy, Supervisor};
+#[cfg(feature = "liblxc")]
+pub use template::{list_templates, DebianTemplate, DownloadTemplate, Template, UbuntuTemplate,
+                   TEMPLATES_DIR};
+#[cfg(all(feature = "liblxc", feature = "async"))]
+pub use async_container::AsyncLxcContainer;
+#[cfg(feature = "native")]
+pub use native::NativeContainer;
+/// Without liblxc, containers are run by the `native` backend, with the lifecycle
+/// subset of the API.
+#[cfg(all(feature = "native", not(feature = "liblxc")))]
+pub use native::NativeContainer as LxcContainer;
 
-/// Returns liblxc version.
+/// Returns liblxc version, see `LxcVersion::current` for it parsed.
 ///
 /// # Example
 /// ```
 /// let lxc_version = liblxc::version();
 /// ```
+#[cfg(feature = "liblxc")]
 pub fn version() -> String {
   unsafe {
     let ptr = ffi::lxc_get_version();
@@ -18,6 +117,156 @@ pub fn version() -> String {
   }
 }
 
+/// Retrieve the value of an item from the global LXC configuration (lxc.conf).
+///
+/// # Parameters
+/// `key` - name of option to get, for example "lxc.lxcpath".
+///
+/// # Return value
+/// Returns `Ok` with the value of the item or `Err` if it is not set.
+#[cfg(feature = "liblxc")]
+pub fn get_global_config_item(key: &str) -> Result<String, &'static str> {
+  unsafe {
+    let key_cstring = str_to_cstring(key);
+    let value_ptr = ffi::lxc_get_global_config_item(key_cstring.as_ptr());
+    if value_ptr.is_null() {
+      Err("Couldn't get global config item value")
+    }
+    else {
+      Ok(ptr_to_str(value_ptr))
+    }
+  }
+}
+
+/// Names of containers defined (having a configuration) in an lxcpath.
+///
+/// # Parameters
+/// `lxcpath_option` - `Option` with lxcpath to look in. If `None`, the default lxcpath is used.
+///
+/// # Return value
+/// Returns `Ok` with sorted container names or `Err` in case of error.
+#[cfg(feature = "liblxc")]
+pub fn list_defined_containers(lxcpath_option: Option<&str>) -> Result<Vec<String>, &'static str> {
+  list_containers(ffi::list_defined_containers, lxcpath_option)
+}
+
+/// Names of active (running, frozen, starting...) containers in an lxcpath.
+///
+/// # Parameters
+/// `lxcpath_option` - `Option` with lxcpath to look in. If `None`, the default lxcpath is used.
+///
+/// # Return value
+/// Returns `Ok` with sorted container names or `Err` in case of error.
+#[cfg(feature = "liblxc")]
+pub fn list_active_containers(lxcpath_option: Option<&str>) -> Result<Vec<String>, &'static str> {
+  list_containers(ffi::list_active_containers, lxcpath_option)
+}
+
+/// Names of all defined and active containers in an lxcpath.
+///
+/// # Parameters
+/// `lxcpath_option` - `Option` with lxcpath to look in. If `None`, the default lxcpath is used.
+///
+/// # Return value
+/// Returns `Ok` with sorted container names or `Err` in case of error.
+#[cfg(feature = "liblxc")]
+pub fn list_all_containers(lxcpath_option: Option<&str>) -> Result<Vec<String>, &'static str> {
+  list_containers(ffi::list_all_containers, lxcpath_option)
+}
+
+#[cfg(feature = "liblxc")]
+fn list_containers(list: unsafe extern "C" fn(*const libc::c_char,
+                                          *mut*mut*mut libc::c_char,
+                                          *mut*mut*mut ffi::LxcContainer) -> libc::c_int,
+                   lxcpath_option: Option<&str>) -> Result<Vec<String>, &'static str> {
+  unsafe {
+    let lxcpath_cstring;
+    let lxcpath_ptr = match lxcpath_option {
+      Some(lxcpath) => {
+                         lxcpath_cstring = str_to_cstring(lxcpath);
+                         lxcpath_cstring.as_ptr()
+                       }
+      None => ptr::null()
+    };
+    let mut names_ptr: *mut*mut libc::c_char = ptr::null_mut();
+    let count = list(lxcpath_ptr, &mut names_ptr, ptr::null_mut());
+    if count < 0 {
+      Err("Couldn't list containers")
+    }
+    else {
+      let mut names = Vec::with_capacity(count as usize);
+      for i in 0..count as isize {
+        names.push(ptr_to_owned_str(*names_ptr.offset(i)));
+      }
+      if !names_ptr.is_null() {
+        libc::free(names_ptr as *mut libc::c_void);
+      }
+      Ok(names)
+    }
+  }
+}
+
+/// State of a container, in the order of liblxc's `lxc_state_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LxcState {
+  Stopped,
+  Starting,
+  Running,
+  Stopping,
+  Aborting,
+  Freezing,
+  Frozen,
+  Thawed,
+}
+
+impl LxcState {
+  const ALL: [LxcState; 8] = [LxcState::Stopped, LxcState::Starting, LxcState::Running,
+                              LxcState::Stopping, LxcState::Aborting, LxcState::Freezing,
+                              LxcState::Frozen, LxcState::Thawed];
+
+  /// Upper-case name of the state, as returned by `LxcContainer::state` and accepted by `wait`.
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      LxcState::Stopped  => "STOPPED",
+      LxcState::Starting => "STARTING",
+      LxcState::Running  => "RUNNING",
+      LxcState::Stopping => "STOPPING",
+      LxcState::Aborting => "ABORTING",
+      LxcState::Freezing => "FREEZING",
+      LxcState::Frozen   => "FROZEN",
+      LxcState::Thawed   => "THAWED",
+    }
+  }
+
+  /// State with the given `lxc_state_t` value.
+  pub fn from_index(index: i32) -> Option<LxcState> {
+    if index >= 0 && (index as usize) < LxcState::ALL.len() {
+      Some(LxcState::ALL[index as usize])
+    }
+    else {
+      None
+    }
+  }
+}
+
+impl std::str::FromStr for LxcState {
+  type Err = &'static str;
+
+  fn from_str(s: &str) -> Result<LxcState, &'static str> {
+    LxcState::ALL.iter()
+                 .find(|state| state.as_str() == s)
+                 .cloned()
+                 .ok_or("Unknown container state")
+  }
+}
+
+impl std::fmt::Display for LxcState {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+#[cfg(feature = "liblxc")]
 pub enum LxcCreateFlag {
   /// Leave stdin, stdout and stderr as is
   Verbose  = 0x00,
@@ -27,6 +276,7 @@ pub enum LxcCreateFlag {
   Maxflags = 0x02,
 }
 
+#[cfg(feature = "liblxc")]
 pub enum LxcCloneFlag {
   /// Do not change the cloning behaviour
   Void          = 0x00,
@@ -44,6 +294,7 @@ pub enum LxcCloneFlag {
   Maxflags      = 0x20,
 }
 
+#[cfg(feature = "liblxc")]
 pub enum LxcAttachFlag {
   MoveToCgroup     = 0x00000001,
   DropCapabilities = 0x00000002,
@@ -57,10 +308,14 @@ pub enum LxcAttachFlag {
 }
 
 /// Struct representing lxc container.
+///
+/// The handle is `Send` but not `Sync`; see `SharedLxcContainer` for sharing it between threads.
+#[cfg(feature = "liblxc")]
 pub struct LxcContainer {
     underlying: *mut ffi::LxcContainer
 }
 
+#[cfg(feature = "liblxc")]
 impl LxcContainer {
 
   /// Creates new lxc container object - it does not create lxc container in the host system.
@@ -75,7 +330,7 @@ impl LxcContainer {
   ///
   /// # Examples
   /// ```
-  /// let c = liblxc::LxcContainer::new("example", "");
+  /// let c = liblxc::LxcContainer::new("example", None);
   /// # assert!(c.is_ok())
   /// ```
   pub fn new(name: &str, config_path_option: Option<&str>) -> Result<LxcContainer, &'static str> {
@@ -101,7 +356,7 @@ impl LxcContainer {
       Err("Cannot create LxcContainer")
     }
     else {
-      Ok(LxcContainer { underlying: underlying })
+      Ok(LxcContainer { underlying })
     }
   }
 
@@ -197,14 +452,15 @@ impl LxcContainer {
   /// Returns `true` on success, else `false`.
   pub fn start(&self, use_init: i32, argv_option: Option<Vec<&str>>) -> bool {
     unsafe {
+      // Without daemonizing, the container runs in this thread until it stops.
+      let _fork = if (*self.underlying).daemonize != 0 { Some(fork_guard()) } else { None };
       let argv_cstring;
+      let argv_ptrs;
       let argv_ptr = match argv_option {
         Some(argv) => {
                         argv_cstring = vec_str_to_cstring(argv);
-                        argv_cstring.iter()
-                                    .map(|s| s.as_ptr())
-                                    .collect::<Vec<*const libc::c_char>>()
-                                    .as_ptr()
+                        argv_ptrs = vec_cstring_to_ptr(&argv_cstring);
+                        argv_ptrs.as_ptr()
                       }
         None => ptr::null()
       };
@@ -254,12 +510,13 @@ impl LxcContainer {
   /// Returns `Ok` with config file name or `Err` in case of error.
   pub fn config_file_name(&self) -> Result<String, &'static str> {
     unsafe {
+      let _lock = self.mem_lock()?;
       let config_name_ptr = ((*self.underlying).config_file_name)(self.underlying);
-      if config_name_ptr == ptr::null_mut() {
+      if config_name_ptr.is_null() {
         Err("Couldn't get config file name")
       }
       else {
-        Ok(ptr_to_str(config_name_ptr))
+        Ok(ptr_to_owned_str(config_name_ptr))
       }
     }
   }
@@ -290,7 +547,14 @@ impl LxcContainer {
   /// 
   /// # Return value
   /// Returns `true` on success, else `false`.
+  ///
+  /// ## Note
+  /// Keys the loaded liblxc is too old for, see `key_feature`, fail without being
+  /// passed to it. The same goes for getting and clearing them.
   pub fn set_config_item(&self, key: &str, value: &str) -> bool {
+    if !features::has_key(key) {
+      return false;
+    }
     unsafe {
       let key_cstring = str_to_cstring(key);
       let key_ptr = key_cstring.as_ptr();
@@ -316,11 +580,15 @@ impl LxcContainer {
   /// Delete the container and all its snapshots.
   ///
   /// ## Note
-  /// Container must be stopped.
+  /// Container must be stopped. Always fails with liblxc older than 1.1, which
+  /// lacks it.
   /// 
   /// # Return value
   /// Returns `true` on success, else `false`.
   pub fn destroy_with_snapshots(&self) -> bool {
+    if !features::has_slots_of(1, 1) {
+      return false;
+    }
     unsafe {
       ((*self.underlying).destroy_with_snapshots)(self.underlying) != 0
     }
@@ -373,13 +641,12 @@ impl LxcContainer {
         None => ptr::null()
       };
       let argv_cstring;
+      let argv_ptrs;
       let argv_ptr = match argv_option {
         Some(argv) => {
                         argv_cstring = vec_str_to_cstring(argv);
-                        argv_cstring.iter()
-                                    .map(|s| s.as_ptr())
-                                    .collect::<Vec<*const libc::c_char>>()
-                                    .as_ptr()
+                        argv_ptrs = vec_cstring_to_ptr(&argv_cstring);
+                        argv_ptrs.as_ptr()
                       }
         None => ptr::null()
       };
@@ -431,9 +698,17 @@ impl LxcContainer {
   }
 
   /// Completely clear the containers in-memory configuration.
-  pub fn clear_config(&self) {
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false` if the memory lock couldn't be taken.
+  pub fn clear_config(&self) -> bool {
     unsafe {
-      ((*self.underlying).clear_config)(self.underlying)
+      let _lock = match self.mem_lock() {
+        Ok(lock) => lock,
+        Err(_) => return false,
+      };
+      ((*self.underlying).clear_config)(self.underlying);
+      true
     }
   }
 
@@ -448,6 +723,9 @@ impl LxcContainer {
   /// ## Note
   /// Analog of `set_config_item()`.
   pub fn clear_config_item(&self, key: &str) -> bool {
+    if !features::has_key(key) {
+      return false;
+    }
     unsafe {
       let key_cstring = str_to_cstring(key);
       let key_ptr = key_cstring.as_ptr();
@@ -463,6 +741,9 @@ impl LxcContainer {
   /// # Return value
   /// Returns `Ok` with the value of a config item or `Err` in case of error.
   pub fn get_config_item(&self, key: &str) -> Result<String, &'static str> {
+    if !features::has_key(key) {
+      return Err("Config item needs a newer liblxc");
+    }
     unsafe {
       let key_cstring = str_to_cstring(key);
       let key_ptr = key_cstring.as_ptr();
@@ -471,16 +752,16 @@ impl LxcContainer {
         Err("Couldn't get config item value")
       }
       else {
-        let mut retv = Vec::with_capacity(retv_len as usize);
-        for _ in 0..retv_len {
+        let mut retv = Vec::with_capacity(retv_len as usize + 1);
+        for _ in 0..retv_len + 1 {
           retv.push(' ' as libc::c_char);
         }
-        ((*self.underlying).get_config_item)(self.underlying, key_ptr, retv.as_mut_ptr(), retv_len);
+        ((*self.underlying).get_config_item)(self.underlying, key_ptr, retv.as_mut_ptr(), retv_len + 1);
         retv.pop(); // pop null placed at the end
         Ok(String::from_utf8(retv.iter()
                               .map(|c| *c as u8)
                               .collect::<Vec<u8>>())
-                              .ok().expect("Invalid UTF8 string"))
+                              .expect("Invalid UTF8 string"))
       }
     }
   }
@@ -493,15 +774,18 @@ impl LxcContainer {
   /// # Return value
   /// Returns `Ok` with the value of a config item or `Err` in case of error.
   pub fn get_running_config_item(&self, key: &str) -> Result<String, &'static str> {
+    if !features::has_key(key) {
+      return Err("Config item needs a newer liblxc");
+    }
     unsafe {
       let key_cstring = str_to_cstring(key);
       let key_ptr = key_cstring.as_ptr();
       let config_item_ptr = ((*self.underlying).get_running_config_item)(self.underlying, key_ptr);
-      if config_item_ptr == ptr::null_mut() {
+      if config_item_ptr.is_null() {
         Err("Couldn't get running config item value")
       }
       else {
-        Ok(ptr_to_str(config_item_ptr))
+        Ok(ptr_to_owned_str(config_item_ptr))
       }
     }
   }
@@ -522,16 +806,16 @@ impl LxcContainer {
         Err("Couldn't get list of config item keys")
       }
       else {
-        let mut retv = Vec::with_capacity(retv_len as usize);
-        for _ in 0..retv_len {
+        let mut retv = Vec::with_capacity(retv_len as usize + 1);
+        for _ in 0..retv_len + 1 {
           retv.push(' ' as libc::c_char);
         }
-        ((*self.underlying).get_keys)(self.underlying, key_prefix_ptr, retv.as_mut_ptr(), retv_len);
+        ((*self.underlying).get_keys)(self.underlying, key_prefix_ptr, retv.as_mut_ptr(), retv_len + 1);
         retv.pop(); // pop null placed at the end
         let keys_newline_separated = String::from_utf8(retv.iter()
                                                            .map(|c| *c as u8)
                                                            .collect::<Vec<u8>>())
-                                                           .ok().expect("Invalid UTF8 string");
+                                                           .expect("Invalid UTF8 string");
         let keys_list : Vec<String> = keys_newline_separated.split('\n').map(|key| key.to_string()).collect();
         Ok(keys_list)
       }
@@ -549,24 +833,7 @@ impl LxcContainer {
   pub fn get_interfaces(&self) -> Vec<String> {
     unsafe {
       let interfaces_ptr = ((*self.underlying).get_interfaces)(self.underlying);
-      if interfaces_ptr == ptr::null_mut() {
-        Vec::new()
-      }
-      else {
-        let mut interfaces_list = Vec::new();
-        let mut i = 0;
-        loop {
-          let interface_name_ptr = *interfaces_ptr.offset(i);
-          if interface_name_ptr == ptr::null_mut() {
-            break;
-          }
-          else {
-            interfaces_list.push(ptr_to_str(interface_name_ptr));
-          }
-          i += 1;
-        }
-        interfaces_list
-      }
+      ptr_array_to_owned_vec(interfaces_ptr)
     }
   }
 
@@ -608,24 +875,7 @@ impl LxcContainer {
         None => ptr::null()
       };
       let ips_ptr = ((*self.underlying).get_ips)(self.underlying, interface_ptr, family_ptr, scope);
-      if ips_ptr == ptr::null_mut() {
-        Vec::new()
-      }
-      else {
-        let mut ips_list = Vec::new();
-        let mut i = 0;
-        loop {
-          let ip_address_ptr = *ips_ptr.offset(i);
-          if ip_address_ptr == ptr::null_mut() {
-            break;
-          }
-          else {
-            ips_list.push(ptr_to_str(ip_address_ptr));
-          }
-          i += 1;
-        }
-        ips_list
-      }
+      ptr_array_to_owned_vec(ips_ptr)
     }
   }
 
@@ -645,16 +895,16 @@ impl LxcContainer {
         Err("Couldn't get specified cgroup subsystem value")
       }
       else {
-        let mut retv = Vec::with_capacity(retv_len as usize);
-        for _ in 0..retv_len {
+        let mut retv = Vec::with_capacity(retv_len as usize + 1);
+        for _ in 0..retv_len + 1 {
           retv.push(' ' as libc::c_char);
         }
-        ((*self.underlying).get_cgroup_item)(self.underlying, subsys_ptr, retv.as_mut_ptr(), retv_len);
+        ((*self.underlying).get_cgroup_item)(self.underlying, subsys_ptr, retv.as_mut_ptr(), retv_len + 1);
         retv.pop(); // pop null placed at the end
         let subsys_value = String::from_utf8(retv.iter()
                                                  .map(|c| *c as u8)
                                                  .collect::<Vec<u8>>())
-                                                 .ok().expect("Invalid UTF8 string");
+                                                 .expect("Invalid UTF8 string");
         Ok(subsys_value)
       }
     }
@@ -689,8 +939,12 @@ impl LxcContainer {
   /// 
   /// # Returns
   /// Returns full path to configuration file.
+  ///
+  /// ## Note
+  /// Panics if the memory lock of the container can't be taken.
   pub fn get_config_path(&self) -> String {
     unsafe {
+      let _lock = self.mem_lock().expect("Couldn't read the config path");
       let config_path_ptr = ((*self.underlying).get_config_path)(self.underlying);
       ptr_to_str(config_path_ptr)
     }
@@ -757,6 +1011,7 @@ impl LxcContainer {
   /// [`Snapshot`](enum.LxcCloneFlag.html) then use the native `bdevtype`
   /// if possible, else use an overlayfs.
   ///
+  #[allow(clippy::too_many_arguments)]
   pub fn clone(&self,
               newname: Option<&str>,
               lxcpath: Option<&str>,
@@ -800,13 +1055,12 @@ impl LxcContainer {
         None => ptr::null()
       };
       let argv_cstring;
+      let argv_ptrs;
       let argv_ptr = match argv_option {
         Some(argv) => {
                         argv_cstring = vec_str_to_cstring(argv);
-                        argv_cstring.iter()
-                                    .map(|s| s.as_ptr() as *mut libc::c_char)
-                                    .collect::<Vec<*mut libc::c_char>>()
-                                    .as_mut_ptr()
+                        argv_ptrs = vec_cstring_to_ptr(&argv_cstring);
+                        argv_ptrs.as_ptr() as *mut *mut libc::c_char
                       }
         None => ptr::null_mut()
       };
@@ -817,17 +1071,210 @@ impl LxcContainer {
       LxcContainer::parse_creation_result(cloned)
     }
   }
+
+  /// Run a program inside the container and wait for it to finish.
+  ///
+  /// # Parameters
+  /// `program` - full path inside the container of the program to run.
+  ///
+  /// `argv` - vector of arguments to pass to `program`, starting with the program name itself.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the exit code of `program`, or `Err` if it couldn't be run
+  /// or was terminated by a signal.
+  pub fn attach_run_wait(&self, program: &str, argv: Vec<&str>) -> Result<i32, &'static str> {
+    self.attach_run_wait_with(&AttachOptions::default(), program, argv)
+  }
+
+  /// Name of the container.
+  pub fn name(&self) -> String {
+    unsafe {
+      ptr_to_str((*self.underlying).name)
+    }
+  }
+
+  /// Attach to a tty of the container.
+  ///
+  /// # Parameters
+  /// `ttynum` - tty number to attach to, `-1` for the next available one.
+  ///
+  /// `stdinfd`, `stdoutfd`, `stderrfd` - file descriptors to connect to the tty.
+  ///
+  /// `escape` - the escape character, `1` meaning Ctrl-a.
+  ///
+  /// # Return value
+  /// Returns `true` once the console session ended normally, else `false`.
+  pub fn console(&self, ttynum: i32, stdinfd: i32, stdoutfd: i32, stderrfd: i32, escape: i32) -> bool {
+    unsafe {
+      ((*self.underlying).console)(self.underlying, ttynum, stdinfd, stdoutfd, stderrfd, escape) == 0
+    }
+  }
+
+  /// Create a snapshot of the container.
+  ///
+  /// # Parameters
+  /// `commentfile_option` - `Option` with full path to a file containing a description of the snapshot.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the number of the snapshot (snapshot `N` is named `snapN`) or `Err` in case of error.
+  pub fn snapshot(&self, commentfile_option: Option<&str>) -> Result<i32, &'static str> {
+    unsafe {
+      let commentfile_cstring;
+      let commentfile_ptr = match commentfile_option {
+        Some(commentfile) => {
+                               commentfile_cstring = str_to_cstring(commentfile);
+                               commentfile_cstring.as_ptr()
+                             }
+        None => ptr::null()
+      };
+      let index = ((*self.underlying).snapshot)(self.underlying, commentfile_ptr);
+      if index < 0 {
+        Err("Couldn't create snapshot")
+      }
+      else {
+        Ok(index)
+      }
+    }
+  }
+
+  /// Obtain a list of container snapshots.
+  ///
+  /// # Return value
+  /// Returns `Ok` with a vector of snapshots or `Err` in case of error.
+  pub fn snapshot_list(&self) -> Result<Vec<LxcSnapshot>, &'static str> {
+    unsafe {
+      let mut snapshots_ptr: *mut ffi::LxcSnapshot = ptr::null_mut();
+      let count = ((*self.underlying).snapshot_list)(self.underlying, &mut snapshots_ptr);
+      if count < 0 {
+        Err("Couldn't list snapshots")
+      }
+      else {
+        let mut snapshots = Vec::with_capacity(count as usize);
+        for i in 0..count as isize {
+          let snapshot = snapshots_ptr.offset(i);
+          snapshots.push(LxcSnapshot::from_raw(&*snapshot));
+          ((*snapshot).free)(snapshot);
+        }
+        if !snapshots_ptr.is_null() {
+          libc::free(snapshots_ptr as *mut libc::c_void);
+        }
+        Ok(snapshots)
+      }
+    }
+  }
+
+  /// Create a new container based on a snapshot.
+  ///
+  /// # Parameters
+  /// `snapname` - name of the snapshot, for example "snap0".
+  ///
+  /// `newname_option` - `Option` with name of the container to create. If `None`,
+  /// the original container is replaced by the snapshot.
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn snapshot_restore(&self, snapname: &str, newname_option: Option<&str>) -> bool {
+    unsafe {
+      let snapname_cstring = str_to_cstring(snapname);
+      let newname_cstring;
+      let newname_ptr = match newname_option {
+        Some(newname) => {
+                           newname_cstring = str_to_cstring(newname);
+                           newname_cstring.as_ptr()
+                         }
+        None => ptr::null()
+      };
+      ((*self.underlying).snapshot_restore)(self.underlying, snapname_cstring.as_ptr(), newname_ptr) != 0
+    }
+  }
+
+  /// Destroy the specified snapshot.
+  ///
+  /// # Parameters
+  /// `snapname` - name of the snapshot, for example "snap0".
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn snapshot_destroy(&self, snapname: &str) -> bool {
+    unsafe {
+      let snapname_cstring = str_to_cstring(snapname);
+      ((*self.underlying).snapshot_destroy)(self.underlying, snapname_cstring.as_ptr()) != 0
+    }
+  }
+
+  /// Destroy all snapshots of the container.
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  ///
+  /// ## Note
+  /// Always fails with liblxc older than 1.1, which lacks it.
+  pub fn snapshot_destroy_all(&self) -> bool {
+    if !features::has_slots_of(1, 1) {
+      return false;
+    }
+    unsafe {
+      ((*self.underlying).snapshot_destroy_all)(self.underlying) != 0
+    }
+  }
+
+}
+
+#[cfg(feature = "liblxc")]
+impl Drop for LxcContainer {
+  fn drop(&mut self) {
+    unsafe {
+      ffi::lxc_container_put(self.underlying);
+    }
+  }
+}
+
+/// Snapshot of a container.
+#[cfg(feature = "liblxc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LxcSnapshot {
+  /// Name of the snapshot, for example "snap0".
+  pub name: String,
+  /// Full path to the snapshot's comment file, if it has one.
+  pub comment_pathname: Option<String>,
+  /// Time the snapshot was created, formatted as "YYYY:MM:DD HH:MM:SS".
+  pub timestamp: String,
+  /// lxcpath of the snapshot, under which it is a container named `name`.
+  pub lxcpath: String,
+}
+
+#[cfg(feature = "liblxc")]
+impl LxcSnapshot {
+  fn from_raw(snapshot: &ffi::LxcSnapshot) -> LxcSnapshot {
+    let opt_str = |ptr: *mut libc::c_char| if ptr.is_null() { None } else { Some(ptr_to_str(ptr)) };
+    LxcSnapshot {
+      name: ptr_to_str(snapshot.name),
+      comment_pathname: opt_str(snapshot.comment_pathname),
+      timestamp: opt_str(snapshot.timestamp).unwrap_or(String::new()),
+      lxcpath: ptr_to_str(snapshot.lxcpath),
+    }
+  }
 }
 
 /// Specifications for how to create a new backing store.
+#[cfg(feature = "liblxc")]
 pub struct BDevSpecs {
     underlying: *mut ffi::attach_options::BDevSpecs
 }
 
+#[cfg(feature = "liblxc")]
 impl BDevSpecs {
   pub fn new() -> BDevSpecs {
     BDevSpecs {
       underlying: ptr::null_mut()
     }
   }  
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "liblxc")]
+impl Default for BDevSpecs {
+  fn default() -> BDevSpecs {
+    BDevSpecs::new()
+  }
+}
@@ -0,0 +1,192 @@
+use crate::ffi::lxclock;
+use crate::ffi::sys::flock;
+use crate::helper::*;
+use std::ffi::{CStr, OsStr};
+use std::fs::OpenOptions;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::IntoRawFd;
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+use super::LxcContainer;
+
+/// Lock created by liblxc, either a named file lock shared with other processes
+/// or an anonymous semaphore local to this process.
+///
+/// Named locks follow LXC's own convention, so they serialize against
+/// lxc-* tools and other liblxc users locking the same `(lxcpath, name)`.
+pub struct LxcLock {
+  underlying: *mut lxclock::LxcLock
+}
+
+impl LxcLock {
+
+  /// Creates a named lock.
+  ///
+  /// # Parameters
+  /// `lxcpath_option` - `Option` with lxcpath the lock belongs to. If `None`, the default lxcpath is used.
+  ///
+  /// `name` - name of the lock, usually a container name.
+  ///
+  /// # Return value
+  /// Returns `Ok(LxcLock)` on success, else `Err(&'static str)` with error description.
+  pub fn new(lxcpath_option: Option<&str>, name: &str) -> Result<LxcLock, &'static str> {
+    unsafe {
+      let lxcpath_cstring;
+      let lxcpath_ptr = match lxcpath_option {
+        Some(lxcpath) => {
+                           lxcpath_cstring = str_to_cstring(lxcpath);
+                           lxcpath_cstring.as_ptr()
+                         }
+        None => ptr::null()
+      };
+      let name_cstring = str_to_cstring(name);
+      LxcLock::parse_creation_result(lxclock::lxc_newlock(lxcpath_ptr, name_cstring.as_ptr()))
+    }
+  }
+
+  /// Creates an anonymous lock, only visible to this process.
+  ///
+  /// # Return value
+  /// Returns `Ok(LxcLock)` on success, else `Err(&'static str)` with error description.
+  pub fn anonymous() -> Result<LxcLock, &'static str> {
+    unsafe {
+      LxcLock::parse_creation_result(lxclock::lxc_newlock(ptr::null(), ptr::null()))
+    }
+  }
+
+  fn parse_creation_result(underlying: *mut lxclock::LxcLock) -> Result<LxcLock, &'static str> {
+    if underlying.is_null() {
+      Err("Cannot create LxcLock")
+    }
+    else {
+      Ok(LxcLock { underlying })
+    }
+  }
+
+  /// Acquire the lock.
+  ///
+  /// # Parameters
+  /// `timeout` - seconds to wait for the lock, `0` to wait forever.
+  ///
+  /// # Return value
+  /// Returns `Ok(LxcLockGuard)` holding the lock until dropped, else `Err` if the lock
+  /// couldn't be acquired within `timeout`.
+  ///
+  /// ## Note
+  /// liblxc only supports timeouts for anonymous locks, so a named lock with a
+  /// non-zero `timeout` is polled with a non-blocking `flock` on its lock file, as
+  /// liblxc locks it, until the time is up.
+  pub fn lock(&self, timeout: i32) -> Result<LxcLockGuard<'_>, &'static str> {
+    unsafe {
+      if (*self.underlying).type_ as i32 == lxclock::LXC_LOCK_FLOCK && timeout != 0 {
+        return self.poll_file_lock(Duration::from_secs(timeout.max(0) as u64));
+      }
+      if lxclock::lxclock(self.underlying, timeout) != 0 {
+        Err("Couldn't acquire lock")
+      }
+      else {
+        Ok(LxcLockGuard { lock: self })
+      }
+    }
+  }
+
+  /// Take a named lock within `timeout`, leaving the lock file open in the lock
+  /// like `lxclock` does, so that `lxcunlock` releases it.
+  unsafe fn poll_file_lock(&self, timeout: Duration) -> Result<LxcLockGuard<'_>, &'static str> {
+    let lock = &mut (*self.underlying).u;
+    if lock.fd == -1 {
+      let path = OsStr::from_bytes(CStr::from_ptr(lock.fname).to_bytes());
+      let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).mode(0o600)
+                   .open(path).map_err(|_| "Couldn't open lock file")?;
+      lock.fd = file.into_raw_fd();
+    }
+    let deadline = Instant::now() + timeout;
+    while flock(lock.fd, libc::LOCK_EX | libc::LOCK_NB) != 0 {
+      if Instant::now() >= deadline {
+        return Err("Couldn't acquire lock");
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+    Ok(LxcLockGuard { lock: self })
+  }
+}
+
+impl Drop for LxcLock {
+  fn drop(&mut self) {
+    unsafe {
+      lxclock::lxc_putlock(self.underlying);
+    }
+  }
+}
+
+/// Held `LxcLock`, released when dropped.
+pub struct LxcLockGuard<'a> {
+  lock: &'a LxcLock
+}
+
+impl<'a> Drop for LxcLockGuard<'a> {
+  fn drop(&mut self) {
+    unsafe {
+      lxclock::lxcunlock(self.lock.underlying);
+    }
+  }
+}
+
+enum ContainerLockKind {
+  Memory,
+  Disk,
+}
+
+/// Held memory or disk lock of a container, released when dropped.
+pub struct LxcContainerLockGuard<'a> {
+  container: &'a LxcContainer,
+  kind: ContainerLockKind
+}
+
+impl<'a> Drop for LxcContainerLockGuard<'a> {
+  fn drop(&mut self) {
+    unsafe {
+      match self.kind {
+        ContainerLockKind::Memory => lxclock::container_mem_unlock(self.container.underlying),
+        ContainerLockKind::Disk => lxclock::container_disk_unlock(self.container.underlying),
+      }
+    }
+  }
+}
+
+impl LxcContainer {
+
+  /// Lock the in-memory state of the container, as liblxc does around operations on
+  /// a shared `struct lxc_container`.
+  ///
+  /// # Return value
+  /// Returns `Ok(LxcContainerLockGuard)` holding the lock until dropped, else `Err`.
+  pub fn mem_lock(&self) -> Result<LxcContainerLockGuard<'_>, &'static str> {
+    unsafe {
+      if lxclock::container_mem_lock(self.underlying) != 0 {
+        Err("Couldn't acquire container memory lock")
+      }
+      else {
+        Ok(LxcContainerLockGuard { container: self, kind: ContainerLockKind::Memory })
+      }
+    }
+  }
+
+  /// Lock the on-disk state of the container (configuration, rootfs, snapshots).
+  /// This also takes the memory lock and is honoured by other processes.
+  ///
+  /// # Return value
+  /// Returns `Ok(LxcContainerLockGuard)` holding the lock until dropped, else `Err`.
+  pub fn disk_lock(&self) -> Result<LxcContainerLockGuard<'_>, &'static str> {
+    unsafe {
+      if lxclock::container_disk_lock(self.underlying) != 0 {
+        Err("Couldn't acquire container disk lock")
+      }
+      else {
+        Ok(LxcContainerLockGuard { container: self, kind: ContainerLockKind::Disk })
+      }
+    }
+  }
+}
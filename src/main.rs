@@ -1,61 +1,810 @@
 extern crate liblxc;
 use liblxc::*;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::process;
 
-fn main() {
-  println!("lxc version: {}", liblxc::version());
-  let container = LxcContainer::new("test", None);
-  match container {
-    Ok(c) => {
-      println!("Created lxc container object.");
-      let bdevtype = None;
-      let specs = BDevSpecs::new();
-      let argv = vec!["-d", "ubuntu", "-r", "trusty", "-a", "amd64"];
-      if c.create("download", bdevtype, specs, LxcCreateFlag::Verbose, Some(argv)) {
-        println!("Sucess!");
+const USAGE: &str = "\
+Usage: main-lxc [-P LXCPATH] COMMAND [ARGS]
+
+Commands:
+  list [--active|--defined] [--json]       List containers, with their info as JSON if --json
+  info NAME [--json]                       Show state, pid, addresses and config file
+  create NAME -t TEMPLATE [-B BDEV] [-q] [-- TEMPLATE ARGS]
+                                           Create a container from a template
+  create NAME -d DIST -r RELEASE -a ARCH [--variant VARIANT] [-B BDEV] [-q]
+                                           Create a container from a downloaded image
+  templates [-T DIR]                       List the installed templates
+  start NAME [-- INIT ARGS]                Start a container
+  stop NAME [-t TIMEOUT] [--no-kill] [-- PRE-STOP COMMAND]
+                                           Stop a container: run the pre-stop command inside it,
+                                           request a shutdown and wait up to TIMEOUT seconds if
+                                           given, then kill it unless --no-kill. Prints which
+                                           stage stopped it.
+  freeze NAME                              Freeze a running container
+  unfreeze NAME                            Thaw a frozen container
+  destroy NAME [-s]                        Destroy a container, with its snapshots if -s
+  clone NAME NEWNAME [-s] [-B BDEV] [-p NEWLXCPATH]
+                                           Copy a stopped container, as a snapshot if -s
+  rename NAME NEWNAME                      Rename a stopped container
+  config get NAME KEY                      Print a configuration item
+  config set NAME KEY VALUE                Set and save a configuration item
+  cgroup get NAME KEY                      Print a cgroup item of a running container
+  cgroup set NAME KEY VALUE                Set a cgroup item of a running container
+  exec NAME -- COMMAND [ARGS]              Run a command inside a running container
+  console NAME [-t TTY] [-e ESCAPE]        Attach to a console of a running container
+  ps NAME                                  List the processes of a running container
+  kill NAME [-s SIGNAL] (PID|--all)        Send SIGNAL (name or number, TERM by default) to a
+                                           process of a running container, by its pid inside
+                                           the container, or to all of them
+  snapshot create NAME [-c COMMENTFILE] [-T TAG]
+                                           Snapshot a stopped container, tagged TAG for pruning
+  snapshot list NAME                       List snapshots
+  snapshot restore NAME SNAP [NEWNAME]     Restore a snapshot, over NAME unless NEWNAME given
+  snapshot destroy NAME (SNAP|--all)       Destroy snapshots
+  snapshot prune NAME [--keep-last N] [--hourly N] [--daily N] [--weekly N] [--dry-run]
+                                           Destroy the tagged snapshots the rules don't keep, per
+                                           tag, printing each decision (or what it would be)
+  autostart [-L|-s|-r|-k] [-g GROUPS] [-a] [-A] [-t TIMEOUT]
+                                           Like lxc-autostart: start containers with lxc.start.auto
+                                           set, or list (-L), shut down (-s), reboot (-r) or kill (-k)
+                                           them. -g selects comma-separated groups in order (an empty
+                                           name means no group), -a all groups, -A ignores
+                                           lxc.start.auto. Failed containers are reported on stderr.
+  apply FILE [--dry-run]                   Create or update a container to match a TOML or YAML
+                                           spec, printing the operations performed (or needed)
+  stack up FILE                            Start a stack in dependency order and wait until healthy
+  stack down FILE                          Stop a stack in reverse order
+  stack order FILE                         Print the waves a stack starts in, one per line
+
+Exit status:
+  0  success
+  1  the operation failed
+  2  invalid usage
+  3  the container does not exist
+  4  the container is not in a suitable state (e.g. already running)
+  exec exits with the status of the command.
+";
+
+/// Error of a command, determining the exit status.
+enum CliError {
+  Usage(String),
+  Failed(String),
+  NotFound(String),
+  State(String),
+}
+
+impl CliError {
+  fn exit_code(&self) -> i32 {
+    match *self {
+      CliError::Failed(_) => 1,
+      CliError::Usage(_) => 2,
+      CliError::NotFound(_) => 3,
+      CliError::State(_) => 4,
+    }
+  }
+}
+
+impl fmt::Display for CliError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CliError::Usage(ref msg) => write!(f, "{}\nTry 'main-lxc help'.", msg),
+      CliError::Failed(ref msg) | CliError::NotFound(ref msg) | CliError::State(ref msg) => {
+        f.write_str(msg)
+      }
+    }
+  }
+}
+
+impl From<&'static str> for CliError {
+  fn from(msg: &'static str) -> CliError {
+    CliError::Failed(msg.to_string())
+  }
+}
+
+type CliResult = Result<i32, CliError>;
+
+fn usage<T>(msg: &str) -> Result<T, CliError> {
+  Err(CliError::Usage(msg.to_string()))
+}
+
+fn failed<T>(msg: String) -> Result<T, CliError> {
+  Err(CliError::Failed(msg))
+}
+
+fn check(ok: bool, what: &str, name: &str) -> CliResult {
+  if ok { Ok(0) } else { failed(format!("Failed to {} {}", what, name)) }
+}
+
+/// Command line arguments of one command, consumed as options and positionals are read.
+struct Opts {
+  args: Vec<String>,
+  rest: Vec<String>,
+  /// Index in `args` of the first positional argument once it was taken.
+  positionals: Option<usize>,
+}
+
+impl Opts {
+  fn new(mut args: Vec<String>) -> Opts {
+    let rest = match args.iter().position(|a| a == "--") {
+      Some(i) => { let rest = args.split_off(i + 1); args.pop(); rest }
+      None => Vec::new()
+    };
+    Opts { args, rest, positionals: None }
+  }
+
+  fn remove(&mut self, i: usize) -> String {
+    match self.positionals {
+      Some(ref mut first) if i < *first => *first -= 1,
+      _ => {}
+    }
+    self.args.remove(i)
+  }
+
+  fn flag(&mut self, short: &str, long: &str) -> bool {
+    let mut found = false;
+    while let Some(i) = self.args.iter().position(|a| a == short || a == long) {
+      self.remove(i);
+      found = true;
+    }
+    found
+  }
+
+  fn value(&mut self, short: &str, long: &str) -> Result<Option<String>, CliError> {
+    let prefix = format!("{}=", long);
+    if let Some(i) = self.args.iter().position(|a| a.starts_with(&prefix)) {
+      return Ok(Some(self.remove(i)[prefix.len()..].to_string()));
+    }
+    match self.args.iter().position(|a| a == short || a == long) {
+      Some(i) if i + 1 < self.args.len() => {
+        self.remove(i);
+        Ok(Some(self.remove(i)))
+      }
+      Some(_) => usage(&format!("Option {} requires a value", long)),
+      None => Ok(None),
+    }
+  }
+
+  fn number(&mut self, short: &str, long: &str) -> Result<Option<i32>, CliError> {
+    match self.value(short, long)? {
+      Some(value) => match value.parse() {
+        Ok(n) => Ok(Some(n)),
+        Err(_) => usage(&format!("Option {} requires a number", long)),
+      },
+      None => Ok(None),
+    }
+  }
+
+  fn positional(&mut self, what: &str) -> Result<String, CliError> {
+    match self.optional_positional() {
+      Some(arg) => Ok(arg),
+      None => usage(&format!("Missing {}", what)),
+    }
+  }
+
+  /// Takes the next positional argument. Options are taken before positionals, so
+  /// the first positional is the first argument not starting with `-`, and everything
+  /// after it is positional, like the value `-1`.
+  fn optional_positional(&mut self) -> Option<String> {
+    let i = match self.positionals {
+      Some(first) if first < self.args.len() => first,
+      Some(_) => return None,
+      None => self.args.iter().position(|a| !a.starts_with('-'))?,
+    };
+    self.positionals = Some(i);
+    Some(self.args.remove(i))
+  }
+
+  /// Checks everything was consumed and returns the arguments after `--`.
+  fn finish(self) -> Result<Vec<String>, CliError> {
+    match self.args.first() {
+      Some(arg) if arg.starts_with('-') => usage(&format!("Unknown option {}", arg)),
+      Some(arg) => usage(&format!("Unexpected argument {}", arg)),
+      None => Ok(self.rest),
+    }
+  }
+}
+
+struct Cli {
+  lxcpath: Option<String>,
+}
+
+impl Cli {
+  fn lxcpath(&self) -> Option<&str> {
+    self.lxcpath.as_ref().map(|p| &p[..])
+  }
+
+  fn open(&self, name: &str) -> Result<LxcContainer, CliError> {
+    LxcContainer::new(name, self.lxcpath()).map_err(|e| CliError::Failed(e.to_string()))
+  }
+
+  fn open_defined(&self, name: &str) -> Result<LxcContainer, CliError> {
+    let c = self.open(name)?;
+    if !c.is_defined() {
+      return Err(CliError::NotFound(format!("Container {} does not exist", name)));
+    }
+    Ok(c)
+  }
+
+  fn open_running(&self, name: &str) -> Result<LxcContainer, CliError> {
+    let c = self.open_defined(name)?;
+    if !c.is_running() {
+      return Err(CliError::State(format!("Container {} is not running", name)));
+    }
+    Ok(c)
+  }
+
+  fn open_stopped(&self, name: &str) -> Result<LxcContainer, CliError> {
+    let c = self.open_defined(name)?;
+    if c.is_running() {
+      return Err(CliError::State(format!("Container {} is running", name)));
+    }
+    Ok(c)
+  }
+
+  fn run(&self, command: &str, mut opts: Opts) -> CliResult {
+    match command {
+      "list" => self.list(opts),
+      "info" => self.info(opts),
+      "create" => self.create(opts),
+      "templates" => self.templates(opts),
+      "start" => self.start(opts),
+      "stop" => self.stop(opts),
+      "freeze" => self.freeze(opts),
+      "unfreeze" => self.unfreeze(opts),
+      "destroy" => self.destroy(opts),
+      "clone" => self.clone(opts),
+      "rename" => self.rename(opts),
+      "exec" => self.exec(opts),
+      "console" => self.console(opts),
+      "ps" => self.ps(opts),
+      "kill" => self.kill(opts),
+      "autostart" => self.autostart(opts),
+      "apply" => self.apply(opts),
+      "config" | "cgroup" | "snapshot" | "stack" => {
+        let action = opts.positional(&format!("{} action", command))?;
+        // Options of the action may come before its first positional.
+        opts.positionals = None;
+        match (command, &action[..]) {
+          ("config", "get") => self.config_get(opts),
+          ("config", "set") => self.config_set(opts),
+          ("cgroup", "get") => self.cgroup_get(opts),
+          ("cgroup", "set") => self.cgroup_set(opts),
+          ("snapshot", "create") => self.snapshot_create(opts),
+          ("snapshot", "list") => self.snapshot_list(opts),
+          ("snapshot", "restore") => self.snapshot_restore(opts),
+          ("snapshot", "destroy") => self.snapshot_destroy(opts),
+          ("snapshot", "prune") => self.snapshot_prune(opts),
+          ("stack", _) => self.stack(&action, opts),
+          _ => usage(&format!("Unknown {} action {}", command, action)),
+        }
+      }
+      "help" => { print!("{}", USAGE); Ok(0) }
+      _ => usage(&format!("Unknown command {}", command)),
+    }
+  }
+
+  fn list(&self, mut opts: Opts) -> CliResult {
+    let active = opts.flag("-a", "--active");
+    let defined = opts.flag("-d", "--defined");
+    let json = opts.flag("-j", "--json");
+    opts.finish()?;
+    let names = match (active, defined) {
+      (true, false) => list_active_containers(self.lxcpath())?,
+      (false, true) => list_defined_containers(self.lxcpath())?,
+      (false, false) => list_all_containers(self.lxcpath())?,
+      (true, true) => return usage("--active and --defined are mutually exclusive"),
+    };
+    if json {
+      let mut infos = Vec::new();
+      for name in names {
+        infos.push(self.open(&name)?.info());
+      }
+      return print_json(&infos);
+    }
+    for name in names {
+      println!("{}", name);
+    }
+    Ok(0)
+  }
+
+  fn info(&self, mut opts: Opts) -> CliResult {
+    let json = opts.flag("-j", "--json");
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_defined(&name)?;
+    if json {
+      return print_json(&c.info());
+    }
+    println!("{:<16}{}", "Name:", name);
+    println!("{:<16}{}", "State:", c.state());
+    if c.is_running() {
+      println!("{:<16}{}", "PID:", c.init_pid());
+      for ip in c.get_ips(None, None, 0) {
+        println!("{:<16}{}", "IP:", ip);
+      }
+    }
+    if let Ok(config) = c.config_file_name() {
+      println!("{:<16}{}", "Config:", config);
+    }
+    Ok(0)
+  }
+
+  fn create(&self, mut opts: Opts) -> CliResult {
+    let template = opts.value("-t", "--template")?;
+    let image = (opts.value("-d", "--dist")?, opts.value("-r", "--release")?, opts.value("-a", "--arch")?);
+    let variant = opts.value("--variant", "--variant")?;
+    let bdevtype = opts.value("-B", "--bdev")?;
+    let flags = if opts.flag("-q", "--quiet") { LxcCreateFlag::Quiet } else { LxcCreateFlag::Verbose };
+    let name = opts.positional("container name")?;
+    let argv = opts.finish()?;
+    let download = match image {
+      (None, None, None) if variant.is_none() => None,
+      (Some(dist), Some(release), Some(arch)) => {
+        let download = DownloadTemplate::new(&dist, &release, &arch);
+        Some(match variant { Some(variant) => download.variant(&variant), None => download })
+      }
+      _ => return usage("-d, -r and -a must be given together"),
+    };
+    let template = match (template, download.is_some()) {
+      (Some(_), true) => return usage("-t can't be combined with -d, -r and -a"),
+      (Some(template), false) => template,
+      (None, true) => "download".to_string(),
+      (None, false) => return usage("Missing template (-t)"),
+    };
+    if download.is_some() && !argv.is_empty() {
+      return usage("Template arguments can't be combined with -d, -r and -a");
+    }
+    let c = self.open(&name)?;
+    if c.is_defined() {
+      return Err(CliError::State(format!("Container {} already exists", name)));
+    }
+    if let Some(download) = download {
+      return match c.create_from(&download, bdevtype.as_ref().map(|b| &b[..]), BDevSpecs::new(), flags) {
+        Ok(()) => Ok(0),
+        Err(reason) => failed(format!("Failed to create {}: {}", name, reason)),
+      };
+    }
+    let argv_option = if argv.is_empty() { None } else { Some(argv.iter().map(|a| &a[..]).collect()) };
+    check(c.create(&template, bdevtype.as_ref().map(|b| &b[..]), BDevSpecs::new(), flags, argv_option),
+          "create", &name)
+  }
+
+  fn templates(&self, mut opts: Opts) -> CliResult {
+    let dir = opts.value("-T", "--dir")?;
+    opts.finish()?;
+    for template in list_templates(dir.as_ref().map(|d| &d[..]))? {
+      println!("{}", template);
+    }
+    Ok(0)
+  }
+
+  fn start(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let argv = opts.finish()?;
+    let c = self.open_stopped(&name)?;
+    let argv_option = if argv.is_empty() { None } else { Some(argv.iter().map(|a| &a[..]).collect()) };
+    check(c.start(0, argv_option), "start", &name)
+  }
+
+  fn stop(&self, mut opts: Opts) -> CliResult {
+    let timeout = opts.number("-t", "--timeout")?;
+    let kill = !opts.flag("-K", "--no-kill");
+    let name = opts.positional("container name")?;
+    let pre_stop = opts.finish()?;
+    let c = self.open_running(&name)?;
+    let policy = StopPolicy {
+      pre_stop: if pre_stop.is_empty() { None } else { Some(pre_stop) },
+      shutdown_timeout: timeout.unwrap_or(0),
+      kill,
+      ..StopPolicy::default()
+    };
+    let report = c.stop_gracefully(&policy);
+    if let Some(Err(e)) = report.pre_stop_status {
+      eprintln!("main-lxc: Failed to run pre-stop command in {}: {}", name, e);
+    }
+    match report.stopped_by {
+      Some(stage) => {
+        println!("{} stopped by {} after {:.1}s", name, stage.as_str(), report.elapsed.as_secs_f64());
+        Ok(0)
+      }
+      None => failed(format!("Failed to stop {}", name)),
+    }
+  }
+
+  fn freeze(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    if c.state() != LxcState::Running.as_str() {
+      return Err(CliError::State(format!("Container {} is {}", name, c.state())));
+    }
+    check(c.freeze(), "freeze", &name)
+  }
+
+  fn unfreeze(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    if c.state() != LxcState::Frozen.as_str() {
+      return Err(CliError::State(format!("Container {} is not frozen", name)));
+    }
+    check(c.unfreeze(), "unfreeze", &name)
+  }
+
+  fn destroy(&self, mut opts: Opts) -> CliResult {
+    let with_snapshots = opts.flag("-s", "--snapshots");
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_stopped(&name)?;
+    if with_snapshots {
+      check(c.destroy_with_snapshots(), "destroy", &name)
+    }
+    else {
+      check(c.destroy(), "destroy", &name)
+    }
+  }
+
+  fn clone(&self, mut opts: Opts) -> CliResult {
+    let flags = if opts.flag("-s", "--snapshot") { LxcCloneFlag::Snapshot } else { LxcCloneFlag::Void };
+    let bdevtype = opts.value("-B", "--bdev")?;
+    let newpath = opts.value("-p", "--newpath")?;
+    let name = opts.positional("container name")?;
+    let newname = opts.positional("new container name")?;
+    opts.finish()?;
+    let c = self.open_stopped(&name)?;
+    c.clone(Some(&newname), newpath.as_ref().map(|p| &p[..]), flags,
+            bdevtype.as_ref().map(|b| &b[..]), None, 0, None)
+     .map(|_| 0)
+     .map_err(|_| CliError::Failed(format!("Failed to clone {} to {}", name, newname)))
+  }
+
+  fn rename(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let newname = opts.positional("new container name")?;
+    opts.finish()?;
+    let c = self.open_stopped(&name)?;
+    check(c.rename(&newname), "rename", &name)
+  }
+
+  fn config_get(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let key = opts.positional("configuration key")?;
+    opts.finish()?;
+    let c = self.open_defined(&name)?;
+    match c.get_config_item(&key) {
+      Ok(value) => { println!("{}", value); Ok(0) }
+      Err(_) => failed(format!("Invalid configuration key {}", key)),
+    }
+  }
+
+  fn config_set(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let key = opts.positional("configuration key")?;
+    let value = opts.positional("value")?;
+    opts.finish()?;
+    let c = self.open_defined(&name)?;
+    if !c.set_config_item(&key, &value) {
+      return failed(format!("Failed to set {} for {}", key, name));
+    }
+    check(c.save_config(&c.config_file_name()?), "save configuration of", &name)
+  }
+
+  fn cgroup_get(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let key = opts.positional("cgroup key")?;
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    match c.get_cgroup_item(&key) {
+      Ok(value) => { println!("{}", value); Ok(0) }
+      Err(_) => failed(format!("Failed to get {} for {}", key, name)),
+    }
+  }
+
+  fn cgroup_set(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let key = opts.positional("cgroup key")?;
+    let value = opts.positional("value")?;
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    check(c.set_cgroup_item(&key, &value), &format!("set {} for", key), &name)
+  }
+
+  fn exec(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let argv = opts.finish()?;
+    if argv.is_empty() {
+      return usage("Missing command after --");
+    }
+    let c = self.open_running(&name)?;
+    c.attach_run_wait(&argv[0], argv.iter().map(|a| &a[..]).collect())
+     .map_err(|e| CliError::Failed(format!("Failed to run {} in {}: {}", argv[0], name, e)))
+  }
+
+  fn console(&self, mut opts: Opts) -> CliResult {
+    let ttynum = opts.number("-t", "--tty")?.unwrap_or(-1);
+    let escape = match opts.value("-e", "--escape")? {
+      Some(ref e) if e.len() == 1 && e.as_bytes()[0].is_ascii_alphabetic() => {
+        (e.to_ascii_lowercase().as_bytes()[0] - b'a' + 1) as i32
+      }
+      Some(_) => return usage("Escape must be a single letter, e.g. a for Ctrl-a"),
+      None => 1,
+    };
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    check(c.console(ttynum, 0, 1, 2, escape), "attach to console of", &name)
+  }
+
+  fn ps(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    let processes = c.processes().map_err(CliError::Failed)?;
+    println!("{:>7} {:>8} {:>6} {:<4} {:>8} COMMAND", "PID", "HOSTPID", "UID", "STAT", "RSS");
+    for p in processes {
+      let command = if p.cmdline.is_empty() { format!("[{}]", p.name) } else { p.cmdline.join(" ") };
+      println!("{:>7} {:>8} {:>6} {:<4} {:>8} {}", p.container_pid, p.pid, p.uid, p.state, p.rss / 1024, command);
+    }
+    Ok(0)
+  }
+
+  fn kill(&self, mut opts: Opts) -> CliResult {
+    let signal = match opts.value("-s", "--signal")? {
+      Some(signal) => match signal_number(&signal) {
+        Some(signal) => signal,
+        None => return usage(&format!("Unknown signal {}", signal)),
+      },
+      None => 15,
+    };
+    let all = opts.flag("-a", "--all");
+    let name = opts.positional("container name")?;
+    let target = match (all, opts.optional_positional()) {
+      (true, None) => SignalTarget::All,
+      (false, Some(pid)) => match pid.parse() {
+        Ok(pid) => SignalTarget::Pid(pid),
+        Err(_) => return usage(&format!("Invalid pid {}", pid)),
+      },
+      _ => return usage("Give either a pid or --all"),
+    };
+    opts.finish()?;
+    let c = self.open_running(&name)?;
+    c.signal(target, signal).map_err(CliError::Failed)?;
+    Ok(0)
+  }
+
+  fn autostart(&self, mut opts: Opts) -> CliResult {
+    let list = opts.flag("-L", "--list");
+    let mut actions = Vec::new();
+    if opts.flag("-s", "--shutdown") { actions.push(AutostartAction::Stop); }
+    if opts.flag("-r", "--reboot") { actions.push(AutostartAction::Reboot); }
+    if opts.flag("-k", "--kill") { actions.push(AutostartAction::Kill); }
+    let mut options = AutostartOptions {
+      lxcpath: self.lxcpath.clone(),
+      all_groups: opts.flag("-a", "--all"),
+      ignore_auto: opts.flag("-A", "--ignore-auto"),
+      ..AutostartOptions::default()
+    };
+    if let Some(timeout) = opts.number("-t", "--timeout")? {
+      options.timeout = timeout;
+    }
+    let mut groups = Vec::new();
+    while let Some(value) = opts.value("-g", "--groups")? {
+      groups.extend(value.split(',').map(|g| g.to_string()));
+    }
+    if !groups.is_empty() {
+      options.groups = groups;
+    }
+    opts.finish()?;
+    if actions.len() > 1 || (list && !actions.is_empty()) {
+      return usage("Only one of -L, -s, -r and -k may be given");
+    }
+
+    if list {
+      for container in list_autostart_containers(&options)? {
+        println!("{}", container.name());
+      }
+      return Ok(0);
+    }
+    let mut code = 0;
+    for outcome in autostart(actions.pop().unwrap_or(AutostartAction::Start), &options)? {
+      if let Err(reason) = outcome.result {
+        eprintln!("main-lxc: {}: {}", outcome.name, reason);
+        code = 1;
+      }
+    }
+    Ok(code)
+  }
+
+  fn snapshot_create(&self, mut opts: Opts) -> CliResult {
+    let commentfile = opts.value("-c", "--comment")?;
+    let tag = opts.value("-T", "--tag")?;
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_stopped(&name)?;
+    let result = match tag {
+      Some(tag) => {
+        let comment = match commentfile {
+          Some(ref file) => Some(fs::read_to_string(file).map_err(|e| CliError::Failed(format!("{}: {}", file, e)))?),
+          None => None,
+        };
+        c.snapshot_tagged(&tag, comment.as_ref().map(|c| &c[..]))
+      }
+      None => c.snapshot(commentfile.as_ref().map(|f| &f[..])),
+    };
+    let index = result.map_err(|e| CliError::Failed(format!("Failed to snapshot {}: {}", name, e)))?;
+    println!("snap{}", index);
+    Ok(0)
+  }
+
+  fn snapshot_list(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    let c = self.open_defined(&name)?;
+    for snapshot in c.snapshot_list()? {
+      println!("{} ({}) {}", snapshot.name, snapshot.lxcpath, snapshot.timestamp);
+    }
+    Ok(0)
+  }
+
+  fn snapshot_restore(&self, mut opts: Opts) -> CliResult {
+    let name = opts.positional("container name")?;
+    let snapname = opts.positional("snapshot name")?;
+    let newname = opts.optional_positional();
+    opts.finish()?;
+    let c = self.open_stopped(&name)?;
+    check(c.snapshot_restore(&snapname, newname.as_ref().map(|n| &n[..])),
+          &format!("restore {} of", snapname), &name)
+  }
+
+  fn snapshot_destroy(&self, mut opts: Opts) -> CliResult {
+    let all = opts.flag("-a", "--all");
+    let name = opts.positional("container name")?;
+    let snapname = if all { None } else { Some(opts.positional("snapshot name")?) };
+    opts.finish()?;
+    let c = self.open_defined(&name)?;
+    match snapname {
+      Some(snapname) => check(c.snapshot_destroy(&snapname), &format!("destroy {} of", snapname), &name),
+      None => check(c.snapshot_destroy_all(), "destroy snapshots of", &name),
+    }
+  }
+
+  fn snapshot_prune(&self, mut opts: Opts) -> CliResult {
+    let mut count = |long: &str| -> Result<u32, CliError> {
+      match opts.number(long, long)? {
+        Some(n) if n < 0 => usage(&format!("Option {} requires a non-negative number", long)),
+        Some(n) => Ok(n as u32),
+        None => Ok(0),
+      }
+    };
+    let retention = Retention {
+      keep_last: count("--keep-last")?,
+      hourly: count("--hourly")?,
+      daily: count("--daily")?,
+      weekly: count("--weekly")?,
+    };
+    let dry_run = opts.flag("-n", "--dry-run");
+    let name = opts.positional("container name")?;
+    opts.finish()?;
+    if retention.is_empty() {
+      return usage("At least one of --keep-last, --hourly, --daily and --weekly is required");
+    }
+    let c = self.open_defined(&name)?;
+    let policy = SnapshotPolicy { interval: None, retention, stop_running: false };
+    let report = policy.prune(&c, dry_run)?;
+    for decision in report.decisions.iter() {
+      let verdict = if decision.is_kept() {
+        format!("keep ({})", decision.reasons.join(", "))
+      }
+      else if dry_run {
+        "would prune".to_string()
       }
       else {
-        println!("Fail.");
-      }
-      if c.is_defined() { println!("Already defined"); } else { println!("Not yet defined"); }
-      // if c.rename("test_renamed") { println!("Renamed"); } else { println!("Rename failed"); }
-      println!("State: {}", c.state());
-      println!("Start: {}", c.start(0, None));
-      println!("PID: {}", c.init_pid());
-      println!("State: {}", c.state());
-      println!("Is running: {}", c.is_running());
-
-      let key = "lxc.hook.pre-start";
-      let value = "hi there";
-      println!("set: {}", c.set_config_item(key, value));
-      println!("Get config item (Ok): {} = {:?}", key, c.get_config_item(key));
-      println!("Get config item (Err): {} = {:?}", "err", c.get_config_item("err"));
-      let running_key = "lxc.utsname";
-      println!("Get running config item (Ok): {} = {:?}", running_key, c.get_running_config_item(running_key));
-      println!("Get running config item (Err): {} = {:?}", "err", c.get_running_config_item("err"));
-      let key_prefix = "lxc.network.0";
-      println!("Get keys list (Ok): {} = {:?}", key_prefix, c.get_keys(key_prefix));
-      println!("Get keys list (Err): {} = {:?}", "err", c.get_keys("err"));
-
-      println!("Get interfaces: {:?}", c.get_interfaces());
-      println!("Get IP addresses: {:?}", c.get_ips(None, None, 0));
-
-      println!("Freeze: {}", c.freeze());
-      println!("State: {}", c.state());
-      println!("Unfreeze: {}", c.unfreeze());
-      println!("State: {}", c.state());
-      println!("Stop: {}", c.stop());
-      println!("State: {}", c.state());
-
-      println!("#############################");
-      match c.clone(Some("test1"), None, LxcCloneFlag::Void, None, None, 0, None) {
-        Ok(c1) => {
-          println!("State: {}", c1.state());
-          println!("")
-        },
-        Err(e) => println!("{}", e)
-      }
-    },
-    Err(e) => println!("{}", e)
-  }
-  
-}
\ No newline at end of file
+        "pruned".to_string()
+      };
+      println!("{} {} {} {}", decision.name, decision.tag, decision.timestamp, verdict);
+    }
+    Ok(0)
+  }
+}
+
+#[cfg(feature = "serde")]
+fn print_json<T: serde::Serialize>(value: &T) -> CliResult {
+  let json = serde_json::to_string_pretty(value).map_err(|e| CliError::Failed(e.to_string()))?;
+  println!("{}", json);
+  Ok(0)
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json<T>(_value: &T) -> CliResult {
+  usage("--json requires main-lxc built with the serde feature")
+}
+
+#[cfg(feature = "spec")]
+impl Cli {
+  fn apply(&self, mut opts: Opts) -> CliResult {
+    let dry_run = opts.flag("-n", "--dry-run");
+    let file = opts.positional("spec file")?;
+    opts.finish()?;
+    let spec = spec::ContainerSpec::load(&file).map_err(CliError::Failed)?;
+    let c = self.open(&spec.name)?;
+    let actions = if dry_run {
+      spec::plan(&spec, &c)
+    }
+    else {
+      spec::apply(&spec, &c).map_err(|e| CliError::Failed(format!("Failed to apply {}: {}", file, e)))?
+    };
+    for action in actions {
+      println!("{}", action);
+    }
+    Ok(0)
+  }
+
+  fn stack(&self, action: &str, mut opts: Opts) -> CliResult {
+    let file = opts.positional("stack file")?;
+    opts.finish()?;
+    let stack = stack::StackSpec::load(&file).map_err(CliError::Failed)?;
+    let result = match action {
+      "up" => stack.up(self.lxcpath()),
+      "down" => stack.down(self.lxcpath()),
+      "order" => stack.start_order(self.lxcpath()).map(|waves| {
+        for wave in waves {
+          println!("{}", wave.join(" "));
+        }
+      }),
+      _ => return usage(&format!("Unknown stack action {}", action)),
+    };
+    match result {
+      Ok(()) => Ok(0),
+      Err(e @ stack::StackError::NotFound(_)) => Err(CliError::NotFound(e.to_string())),
+      Err(e) => failed(e.to_string()),
+    }
+  }
+}
+
+#[cfg(not(feature = "spec"))]
+impl Cli {
+  fn apply(&self, _opts: Opts) -> CliResult {
+    usage("apply requires main-lxc built with the spec feature")
+  }
+
+  fn stack(&self, _action: &str, _opts: Opts) -> CliResult {
+    usage("stack requires main-lxc built with the spec feature")
+  }
+}
+
+/// Splits off the global `-P LXCPATH` option, which may appear anywhere before `--`.
+fn parse_global(args: Vec<String>) -> Result<(Cli, Vec<String>), CliError> {
+  let mut opts = Opts::new(args);
+  let lxcpath = opts.value("-P", "--lxcpath")?;
+  let mut args = opts.args;
+  if !opts.rest.is_empty() {
+    args.push("--".to_string());
+    args.extend(opts.rest);
+  }
+  Ok((Cli { lxcpath }, args))
+}
+
+fn run(args: Vec<String>) -> CliResult {
+  let (cli, mut args) = parse_global(args)?;
+  if args.is_empty() {
+    return usage("Missing command");
+  }
+  let command = args.remove(0);
+  if command == "-h" || command == "--help" {
+    return cli.run("help", Opts::new(args));
+  }
+  cli.run(&command, Opts::new(args))
+}
+
+fn main() {
+  let code = match run(env::args().skip(1).collect()) {
+    Ok(code) => code,
+    Err(e) => {
+      eprintln!("main-lxc: {}", e);
+      e.exit_code()
+    }
+  };
+  process::exit(code);
+}
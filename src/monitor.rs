@@ -0,0 +1,212 @@
+//! State change events from lxc-monitord.
+//!
+//! liblxc reports every state change and exit code of containers in an lxcpath to
+//! lxc-monitord, which forwards them to clients of an abstract unix socket. The
+//! daemon is started by liblxc when a container starts (or by running
+//! `lxc-monitord <lxcpath>`); connecting fails while it isn't running.
+
+use std::io;
+use std::io::Read;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::time::Duration;
+use super::{get_global_config_item, LxcState};
+
+/// Size of `struct lxc_msg`: `int type; char name[NAME_MAX + 1]; int value;`.
+const MSG_SIZE: usize = 264;
+const MSG_NAME_SIZE: usize = 256;
+const MSG_STATE: i32 = 0;
+const MSG_PRIORITY: i32 = 1;
+const MSG_EXIT_CODE: i32 = 2;
+
+/// Longest abstract socket name liblxc produces: `sun_path` minus the leading
+/// NUL and the terminating NUL written by snprintf.
+const SOCK_NAME_MAX: usize = 105;
+
+/// Event reported by the monitor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitorEvent {
+  /// Container `name` changed to `state`.
+  State { name: String, state: LxcState },
+  /// Init of container `name` exited with `status`, as returned by `waitpid`.
+  ExitCode { name: String, status: i32 },
+  /// Log priority of container `name` changed to `priority`.
+  Priority { name: String, priority: i32 },
+}
+
+impl MonitorEvent {
+  /// Name of the container the event is about.
+  pub fn name(&self) -> &str {
+    match *self {
+      MonitorEvent::State { ref name, .. } => name,
+      MonitorEvent::ExitCode { ref name, .. } => name,
+      MonitorEvent::Priority { ref name, .. } => name,
+    }
+  }
+}
+
+/// Connection to lxc-monitord for one lxcpath.
+///
+/// Iterating yields events until the daemon closes the connection.
+pub struct Monitor {
+  stream: UnixStream
+}
+
+impl Monitor {
+
+  /// Connect to the monitor of an lxcpath.
+  ///
+  /// # Parameters
+  /// `lxcpath_option` - `Option` with lxcpath to monitor. If `None`, the default lxcpath is used.
+  ///
+  /// # Return value
+  /// Returns `Ok(Monitor)` on success, else `Err` with the connection error.
+  pub fn connect(lxcpath_option: Option<&str>) -> io::Result<Monitor> {
+    let name = socket_name(&lxcpath_or_default(lxcpath_option)?)?;
+    let addr = SocketAddr::from_abstract_name(&name)?;
+    Ok(Monitor { stream: UnixStream::connect_addr(&addr)? })
+  }
+
+  /// Limit how long `next_event` blocks. `None` blocks forever.
+  pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+    self.stream.set_read_timeout(timeout)
+  }
+
+  /// Wait for the next event.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the event, or `Err` on timeout, disconnection
+  /// (`UnexpectedEof`) or a malformed message (`InvalidData`).
+  pub fn next_event(&mut self) -> io::Result<MonitorEvent> {
+    let mut msg = [0u8; MSG_SIZE];
+    self.stream.read_exact(&mut msg)?;
+    decode_message(&msg)
+  }
+}
+
+impl Iterator for Monitor {
+  type Item = io::Result<MonitorEvent>;
+
+  fn next(&mut self) -> Option<io::Result<MonitorEvent>> {
+    match self.next_event() {
+      Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+      event => Some(event),
+    }
+  }
+}
+
+fn lxcpath_or_default(lxcpath_option: Option<&str>) -> io::Result<String> {
+  match lxcpath_option {
+    Some(lxcpath) => Ok(lxcpath.to_string()),
+    None => get_global_config_item("lxc.lxcpath")
+              .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e)),
+  }
+}
+
+/// Abstract socket name lxc-monitord listens on for `lxcpath`, without the
+/// leading NUL. Mirrors liblxc's `lxc_monitor_sock_name`.
+pub fn socket_name(lxcpath: &str) -> io::Result<Vec<u8>> {
+  let path = format!("lxc/{}/monitor-sock", lxcpath);
+  if path.len() >= SOCK_NAME_MAX + 2 {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "lxcpath too long for monitor socket"));
+  }
+  let mut name = format!("lxc/{:016x}/{}", fnv_64a(path.as_bytes()), lxcpath).into_bytes();
+  name.truncate(SOCK_NAME_MAX);
+  Ok(name)
+}
+
+fn fnv_64a(buf: &[u8]) -> u64 {
+  buf.iter().fold(0xcbf29ce484222325, |hash, b| {
+    (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+/// Decodes one `struct lxc_msg` in native byte order.
+pub fn decode_message(msg: &[u8]) -> io::Result<MonitorEvent> {
+  if msg.len() != MSG_SIZE {
+    return Err(invalid("monitor message has wrong size"));
+  }
+  let read_int = |offset: usize| {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&msg[offset..offset + 4]);
+    i32::from_ne_bytes(bytes)
+  };
+  let msg_type = read_int(0);
+  let name_bytes = &msg[4..4 + MSG_NAME_SIZE];
+  let name_len = name_bytes.iter().position(|b| *b == 0).unwrap_or(MSG_NAME_SIZE);
+  let name = String::from_utf8(name_bytes[..name_len].to_vec())
+               .map_err(|_| invalid("container name is not valid UTF-8"))?;
+  let value = read_int(4 + MSG_NAME_SIZE);
+  match msg_type {
+    MSG_STATE => {
+      let state = LxcState::from_index(value).ok_or_else(|| invalid("unknown container state"))?;
+      Ok(MonitorEvent::State { name, state })
+    }
+    MSG_EXIT_CODE => Ok(MonitorEvent::ExitCode { name, status: value }),
+    MSG_PRIORITY => Ok(MonitorEvent::Priority { name, priority: value }),
+    _ => Err(invalid("unknown monitor message type")),
+  }
+}
+
+/// Encodes an event as `struct lxc_msg`, the inverse of `decode_message`.
+pub fn encode_message(event: &MonitorEvent) -> Vec<u8> {
+  let (msg_type, value) = match *event {
+    MonitorEvent::State { state, .. } => (MSG_STATE, state as i32),
+    MonitorEvent::ExitCode { status, .. } => (MSG_EXIT_CODE, status),
+    MonitorEvent::Priority { priority, .. } => (MSG_PRIORITY, priority),
+  };
+  let mut msg = vec![0u8; MSG_SIZE];
+  msg[0..4].copy_from_slice(&msg_type.to_ne_bytes());
+  let name = event.name().as_bytes();
+  let name_len = if name.len() < MSG_NAME_SIZE { name.len() } else { MSG_NAME_SIZE - 1 };
+  msg[4..4 + name_len].copy_from_slice(&name[..name_len]);
+  msg[4 + MSG_NAME_SIZE..].copy_from_slice(&value.to_ne_bytes());
+  msg
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(feature = "async")]
+pub use self::async_monitor::AsyncMonitor;
+
+#[cfg(feature = "async")]
+mod async_monitor {
+  use std::io;
+  use std::os::unix::net::{SocketAddr, UnixStream as StdUnixStream};
+  use std::os::linux::net::SocketAddrExt;
+  use tokio::io::AsyncReadExt;
+  use tokio::net::UnixStream;
+  use super::{decode_message, lxcpath_or_default, socket_name, MonitorEvent, MSG_SIZE};
+
+  /// Asynchronous connection to lxc-monitord, see `Monitor`.
+  pub struct AsyncMonitor {
+    stream: UnixStream
+  }
+
+  impl AsyncMonitor {
+
+    /// Connect to the monitor of an lxcpath. Must be called within a tokio runtime.
+    pub fn connect(lxcpath_option: Option<&str>) -> io::Result<AsyncMonitor> {
+      let name = socket_name(&lxcpath_or_default(lxcpath_option)?)?;
+      let stream = StdUnixStream::connect_addr(&SocketAddr::from_abstract_name(&name)?)?;
+      stream.set_nonblocking(true)?;
+      Ok(AsyncMonitor { stream: UnixStream::from_std(stream)? })
+    }
+
+    /// Wait for the next event without blocking the executor.
+    ///
+    /// # Return value
+    /// Returns `Ok(Some(event))`, `Ok(None)` once the daemon closed the connection,
+    /// or `Err` on a malformed message or I/O error.
+    pub async fn next_event(&mut self) -> io::Result<Option<MonitorEvent>> {
+      let mut msg = [0u8; MSG_SIZE];
+      match self.stream.read_exact(&mut msg).await {
+        Ok(_) => decode_message(&msg).map(Some),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+      }
+    }
+  }
+}
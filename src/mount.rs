@@ -0,0 +1,393 @@
+//! Mount entries (`lxc.mount.entry`), automatic mounts (`lxc.mount.auto`) and bind
+//! mounts added to running containers.
+//!
+//! Only `MountEntry` is available without liblxc, for the `native` backend.
+//!
+//! ```no_run
+//! # #[cfg(feature = "liblxc")] {
+//! use liblxc::*;
+//! let c = LxcContainer::new("build", None).unwrap();
+//! let cache = MountEntry::bind("/var/cache/cargo", "root/.cargo/registry").create(MountCreate::Dir);
+//! c.add_mount_entry(&cache).unwrap();
+//! c.save_config(&c.config_file_name().unwrap());
+//! if c.is_running() {
+//!   c.mount_live(&cache).unwrap();
+//! }
+//! # }
+//! ```
+
+#[cfg(feature = "liblxc")]
+use crate::ffi::sys::*;
+#[cfg(feature = "liblxc")]
+use crate::helper::*;
+#[cfg(feature = "liblxc")]
+use libc;
+#[cfg(feature = "liblxc")]
+use libc::c_char;
+#[cfg(feature = "liblxc")]
+use std::ffi::CString;
+use std::fmt;
+#[cfg(feature = "liblxc")]
+use std::fs::{self, File};
+#[cfg(feature = "liblxc")]
+use std::io;
+#[cfg(feature = "liblxc")]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+#[cfg(feature = "liblxc")]
+use std::path::{Component, Path};
+#[cfg(feature = "liblxc")]
+use std::ptr;
+#[cfg(feature = "liblxc")]
+use super::{LxcContainer, Namespace};
+
+/// Values accepted in `lxc.mount.auto`.
+pub const MOUNT_AUTO_VALUES: [&str; 15] = [
+  "proc", "proc:mixed", "proc:rw",
+  "sys", "sys:ro", "sys:rw", "sys:mixed",
+  "cgroup", "cgroup:mixed", "cgroup:ro", "cgroup:rw",
+  "cgroup-full", "cgroup-full:mixed", "cgroup-full:ro", "cgroup-full:rw",
+];
+
+/// What liblxc creates at the mount point if it's missing (the `create=` option).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MountCreate {
+  Dir,
+  File,
+}
+
+/// An `lxc.mount.entry` line, in fstab format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MountEntry {
+  /// Device or, for bind mounts, host path to mount.
+  pub source: String,
+  /// Mount point, relative to the rootfs of the container, for example "srv/www".
+  pub target: String,
+  /// Filesystem type, "none" for bind mounts.
+  pub fstype: String,
+  /// Mount options except `create=`, for example "bind" and "ro".
+  pub options: Vec<String>,
+  /// What to create at the mount point if it's missing.
+  pub create: Option<MountCreate>,
+  /// The fifth fstab field, ignored by liblxc.
+  pub dump: u32,
+  /// The sixth fstab field, ignored by liblxc.
+  pub pass: u32,
+}
+
+impl MountEntry {
+  /// Bind mount of host path `source` at `target` in the container.
+  pub fn bind(source: &str, target: &str) -> MountEntry {
+    MountEntry {
+      source: source.to_string(),
+      target: target.trim_start_matches('/').to_string(),
+      fstype: "none".to_string(),
+      options: vec!["bind".to_string()],
+      create: None,
+      dump: 0,
+      pass: 0,
+    }
+  }
+
+  /// Mount read-only.
+  pub fn read_only(mut self) -> MountEntry {
+    if !self.is_read_only() {
+      self.options.retain(|o| o != "rw");
+      self.options.push("ro".to_string());
+    }
+    self
+  }
+
+  /// Mount recursively, with the mounts below `source` (`rbind`).
+  pub fn recursive(mut self) -> MountEntry {
+    for option in self.options.iter_mut() {
+      if option == "bind" {
+        *option = "rbind".to_string();
+      }
+    }
+    self
+  }
+
+  /// Create the mount point if it's missing.
+  pub fn create(mut self, create: MountCreate) -> MountEntry {
+    self.create = Some(create);
+    self
+  }
+
+  /// Determine whether this is a bind mount.
+  pub fn is_bind(&self) -> bool {
+    self.options.iter().any(|o| o == "bind" || o == "rbind")
+  }
+
+  /// Determine whether the mount is read-only.
+  pub fn is_read_only(&self) -> bool {
+    self.options.iter().any(|o| o == "ro")
+  }
+
+  #[cfg(feature = "liblxc")]
+  fn is_recursive(&self) -> bool {
+    self.options.iter().any(|o| o == "rbind")
+  }
+
+  /// Checks the entry and, for bind mounts, that `source` matches `create`.
+  #[cfg(feature = "liblxc")]
+  fn validate(&self) -> Result<(), String> {
+    if self.fstype.is_empty() || self.source.is_empty() {
+      return Err(format!("Mount entry at {} lacks a source or filesystem type", self.target));
+    }
+    let target = Path::new(&self.target);
+    if self.target.trim_matches('/').is_empty() || target.components().any(|c| c == Component::ParentDir) {
+      return Err(format!("Invalid mount point {}", self.target));
+    }
+    if !self.is_bind() {
+      return Ok(());
+    }
+    if !Path::new(&self.source).is_absolute() {
+      return Err(format!("Bind mount source {} isn't absolute", self.source));
+    }
+    let metadata = fs::metadata(&self.source).map_err(|e| format!("Invalid bind mount source {}: {}", self.source, e))?;
+    match self.create {
+      Some(MountCreate::Dir) if !metadata.is_dir() => Err(format!("{} isn't a directory", self.source)),
+      Some(MountCreate::File) if metadata.is_dir() => Err(format!("{} is a directory", self.source)),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Decodes the octal escapes of fstab fields, like `\040` for a space.
+fn unescape(field: &str) -> String {
+  let bytes = field.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let octal = bytes.get(i + 1..i + 4)
+                     .filter(|digits| bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+                     .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 8).ok());
+    match octal {
+      Some(byte) => {
+        decoded.push(byte);
+        i += 4;
+      }
+      None => {
+        decoded.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn escape(field: &str) -> String {
+  let mut escaped = String::with_capacity(field.len());
+  for c in field.chars() {
+    match c {
+      ' ' | '\t' | '\n' | '\\' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+impl std::str::FromStr for MountEntry {
+  type Err = String;
+
+  fn from_str(line: &str) -> Result<MountEntry, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 || fields.len() > 6 {
+      return Err(format!("Invalid mount entry: {}", line));
+    }
+    let number = |i: usize| -> Result<u32, String> {
+      fields.get(i).map_or(Ok(0), |n| n.parse().map_err(|_| format!("Invalid mount entry: {}", line)))
+    };
+    let mut options = Vec::new();
+    let mut create = None;
+    for option in fields[3].split(',') {
+      match option {
+        "create=dir" => create = Some(MountCreate::Dir),
+        "create=file" => create = Some(MountCreate::File),
+        "" | "defaults" => {}
+        option => options.push(option.to_string()),
+      }
+    }
+    Ok(MountEntry {
+      source: unescape(fields[0]),
+      target: unescape(fields[1]),
+      fstype: unescape(fields[2]),
+      options,
+      create,
+      dump: number(4)?,
+      pass: number(5)?,
+    })
+  }
+}
+
+impl fmt::Display for MountEntry {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut options = self.options.clone();
+    match self.create {
+      Some(MountCreate::Dir) => options.push("create=dir".to_string()),
+      Some(MountCreate::File) => options.push("create=file".to_string()),
+      None => {}
+    }
+    if options.is_empty() {
+      options.push("defaults".to_string());
+    }
+    write!(f, "{} {} {} {} {} {}", escape(&self.source), escape(&self.target), escape(&self.fstype),
+           options.join(","), self.dump, self.pass)
+  }
+}
+
+#[cfg(feature = "liblxc")]
+fn same_target(a: &str, b: &str) -> bool {
+  a.trim_matches('/') == b.trim_matches('/')
+}
+
+#[cfg(feature = "liblxc")]
+impl LxcContainer {
+
+  /// Get the mount entries of the container (`lxc.mount.entry`).
+  ///
+  /// # Return value
+  /// Returns `Ok` with the entries in configuration order, or `Err` if one can't be parsed.
+  pub fn mount_entries(&self) -> Result<Vec<MountEntry>, String> {
+    self.get_config_item("lxc.mount.entry")
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.parse())
+        .collect()
+  }
+
+  /// Add a mount entry (`lxc.mount.entry`), used from the next start of the container.
+  ///
+  /// # Return value
+  /// Returns `Ok` if the entry was added, else `Err` if it's invalid, another entry
+  /// has the same mount point, or the source of a bind mount doesn't exist on the host
+  /// or doesn't match `create`.
+  ///
+  /// ## Note
+  /// Like `set_config_item`, this only changes the loaded configuration; use
+  /// `save_config` to make it permanent. The same applies to the other setters here.
+  pub fn add_mount_entry(&self, entry: &MountEntry) -> Result<(), String> {
+    entry.validate()?;
+    if self.mount_entries()?.iter().any(|e| same_target(&e.target, &entry.target)) {
+      return Err(format!("Container {} already has a mount at {}", self.name(), entry.target));
+    }
+    if self.set_config_item("lxc.mount.entry", &entry.to_string()) { Ok(()) }
+    else { Err(format!("Couldn't add mount entry {}", entry)) }
+  }
+
+  /// Remove the mount entry with mount point `target`.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the removed entry, or `Err` if there is none.
+  pub fn remove_mount_entry(&self, target: &str) -> Result<MountEntry, String> {
+    let mut entries = self.mount_entries()?;
+    let index = entries.iter().position(|e| same_target(&e.target, target))
+                       .ok_or_else(|| format!("Container {} has no mount at {}", self.name(), target))?;
+    let removed = entries.remove(index);
+    let kept = self.clear_config_item("lxc.mount.entry") &&
+               entries.iter().all(|e| self.set_config_item("lxc.mount.entry", &e.to_string()));
+    if kept { Ok(removed) } else { Err("Couldn't update lxc.mount.entry".to_string()) }
+  }
+
+  /// Get the automatic mounts of the container (`lxc.mount.auto`), like "proc:mixed".
+  pub fn mount_auto(&self) -> Vec<String> {
+    self.get_config_item("lxc.mount.auto")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|v| v.to_string())
+        .collect()
+  }
+
+  /// Replace the automatic mounts of the container (`lxc.mount.auto`).
+  ///
+  /// # Return value
+  /// Returns `Ok` on success, else `Err` if a value isn't one of `MOUNT_AUTO_VALUES`
+  /// or the configuration couldn't be changed.
+  pub fn set_mount_auto(&self, values: &[&str]) -> Result<(), String> {
+    if let Some(value) = values.iter().find(|v| !MOUNT_AUTO_VALUES.contains(v)) {
+      return Err(format!("Invalid lxc.mount.auto value {}", value));
+    }
+    let set = self.clear_config_item("lxc.mount.auto") &&
+              (values.is_empty() || self.set_config_item("lxc.mount.auto", &values.join(" ")));
+    if set { Ok(()) } else { Err("Couldn't set lxc.mount.auto".to_string()) }
+  }
+
+  /// Bind-mount `entry` into the running container now, by entering its mount
+  /// namespace through `init_pid`. The mount point is created as `entry.create` says.
+  ///
+  /// # Return value
+  /// Returns `Ok` if the source is mounted, else `Err`.
+  ///
+  /// ## Note
+  /// This doesn't add `entry` to the configuration; see `add_mount_entry`. Needs
+  /// root and Linux 5.2 or newer, to carry the source into another mount namespace.
+  pub fn mount_live(&self, entry: &MountEntry) -> Result<(), String> {
+    if !entry.is_bind() {
+      return Err("Only bind mounts can be added to a running container".to_string());
+    }
+    entry.validate()?;
+    let pid = self.init_pid();
+    if pid <= 0 {
+      return Err(format!("Container {} isn't running", self.name()));
+    }
+    let target = format!("/{}", entry.target.trim_matches('/'));
+    let error = |e: io::Error| format!("Couldn't mount {} at {} in container {}: {}",
+                                       entry.source, target, self.name(), e);
+    let namespaces = self.namespaces(&[Namespace::Mount])?;
+    let source_cstring = str_to_cstring(&entry.source);
+    let recursive = if entry.is_recursive() { AT_RECURSIVE } else { 0 };
+    let tree = unsafe {
+      syscall(SYS_OPEN_TREE, AT_FDCWD, source_cstring.as_ptr(), OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC | recursive)
+    };
+    if tree < 0 {
+      return Err(error(io::Error::last_os_error()));
+    }
+    let tree = unsafe { File::from_raw_fd(tree as i32) };
+
+    // Everything the child needs is prepared here, so that it doesn't allocate.
+    let target_cstring = str_to_cstring(&target);
+    let mut dirs: Vec<CString> = Vec::new();
+    if entry.create.is_some() {
+      let mut prefix = String::new();
+      for name in entry.target.split('/').filter(|n| !n.is_empty()) {
+        prefix.push('/');
+        prefix.push_str(name);
+        dirs.push(str_to_cstring(&prefix));
+      }
+      if entry.create == Some(MountCreate::File) {
+        dirs.pop();
+      }
+    }
+    // The child exits with errno if something fails. It only makes system calls
+    // on what is prepared above, so it may run in the forked child.
+    let mount = || unsafe {
+      let errno = || io::Error::last_os_error().raw_os_error().unwrap_or(1);
+      for dir in &dirs {
+        if libc::mkdir(dir.as_ptr(), 0o755) != 0 && errno() != libc::EEXIST {
+          return errno();
+        }
+      }
+      if entry.create == Some(MountCreate::File) {
+        let fd = libc::open(target_cstring.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC, 0o644);
+        if fd < 0 {
+          return errno();
+        }
+        libc::close(fd);
+      }
+      if syscall(SYS_MOVE_MOUNT, tree.as_raw_fd(), b"\0".as_ptr() as *const c_char,
+                 AT_FDCWD, target_cstring.as_ptr(), MOVE_MOUNT_F_EMPTY_PATH) != 0 {
+        return errno();
+      }
+      if entry.is_read_only() &&
+         mount(ptr::null(), target_cstring.as_ptr(), ptr::null(), MS_BIND | MS_REMOUNT | MS_RDONLY, ptr::null()) != 0 {
+        return errno();
+      }
+      0
+    };
+    match unsafe { namespaces.run(&[Namespace::Mount], mount)? } {
+      0 => Ok(()),
+      errno => Err(error(io::Error::from_raw_os_error(errno))),
+    }
+  }
+}
@@ -0,0 +1,300 @@
+//! Namespaces of running containers, opened through `init_pid`.
+//!
+//! `LxcContainer::namespaces` opens the `/proc/PID/ns` files of the container's
+//! init process, and `ContainerNamespaces::run` runs a closure in a forked child
+//! after joining some of them. Unlike `attach`, nothing else of the container is
+//! applied: no cgroup, LSM profile, capabilities or environment, and programs come
+//! from the host unless the mount namespace is joined.
+//!
+//! ```no_run
+//! use liblxc::*;
+//! use std::ffi::CString;
+//! use std::ptr;
+//! let c = LxcContainer::new("web", None).unwrap();
+//! let namespaces = c.namespaces(&[Namespace::Net]).unwrap();
+//! // Everything the child needs is allocated before the fork.
+//! let program = CString::new("/bin/ss").unwrap();
+//! let flags = CString::new("-tlnp").unwrap();
+//! let argv = [program.as_ptr(), flags.as_ptr(), ptr::null()];
+//! // Safe, as the child only calls execv and returns.
+//! let status = unsafe {
+//!   namespaces.run(&[Namespace::Net], || {
+//!     libc::execv(program.as_ptr(), argv.as_ptr());
+//!     127
+//!   })
+//! };
+//! ```
+
+use crate::ffi::sys::*;
+use crate::helper::*;
+use libc;
+use libc::{c_int, c_void};
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use super::{fork_guard, LxcContainer};
+
+/// Exit code of a child whose closure unwound instead of returning, as for Rust
+/// programs that panic.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// A kind of Linux namespace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Namespace {
+  User,
+  Mount,
+  Pid,
+  Uts,
+  Ipc,
+  Net,
+  Cgroup,
+}
+
+impl Namespace {
+  /// All namespaces, in the order they are joined.
+  pub const ALL: [Namespace; 7] = [
+    Namespace::User, Namespace::Mount, Namespace::Pid, Namespace::Uts,
+    Namespace::Ipc, Namespace::Net, Namespace::Cgroup,
+  ];
+
+  /// Name of the namespace file in `/proc/PID/ns`, like "mnt".
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      Namespace::User => "user",
+      Namespace::Mount => "mnt",
+      Namespace::Pid => "pid",
+      Namespace::Uts => "uts",
+      Namespace::Ipc => "ipc",
+      Namespace::Net => "net",
+      Namespace::Cgroup => "cgroup",
+    }
+  }
+
+  fn clone_flag(&self) -> c_int {
+    match *self {
+      Namespace::User => CLONE_NEWUSER,
+      Namespace::Mount => CLONE_NEWNS,
+      Namespace::Pid => CLONE_NEWPID,
+      Namespace::Uts => CLONE_NEWUTS,
+      Namespace::Ipc => CLONE_NEWIPC,
+      Namespace::Net => CLONE_NEWNET,
+      Namespace::Cgroup => CLONE_NEWCGROUP,
+    }
+  }
+}
+
+impl std::str::FromStr for Namespace {
+  type Err = &'static str;
+
+  fn from_str(s: &str) -> Result<Namespace, &'static str> {
+    Namespace::ALL.iter().find(|ns| ns.as_str() == s).cloned().ok_or("Unknown namespace")
+  }
+}
+
+impl std::fmt::Display for Namespace {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Open namespace file of a process, closed when dropped.
+#[derive(Debug)]
+pub struct NamespaceFd {
+  namespace: Namespace,
+  file: File,
+}
+
+impl NamespaceFd {
+  /// Kind of the namespace.
+  pub fn namespace(&self) -> Namespace {
+    self.namespace
+  }
+
+  /// The open namespace file, for example to pass it to another process.
+  pub fn file(&self) -> &File {
+    &self.file
+  }
+}
+
+impl AsRawFd for NamespaceFd {
+  fn as_raw_fd(&self) -> RawFd {
+    self.file.as_raw_fd()
+  }
+}
+
+impl IntoRawFd for NamespaceFd {
+  fn into_raw_fd(self) -> RawFd {
+    self.file.into_raw_fd()
+  }
+}
+
+/// Ends a forked child with `PANIC_EXIT_CODE` if dropped while unwinding, so that
+/// it never returns into the stack it shares with the parent.
+struct ExitOnUnwind;
+
+impl Drop for ExitOnUnwind {
+  fn drop(&mut self) {
+    unsafe { libc::_exit(PANIC_EXIT_CODE) }
+  }
+}
+
+/// Namespaces of a container, opened by `LxcContainer::namespaces`. They stay
+/// valid even if the container stops.
+#[derive(Debug)]
+pub struct ContainerNamespaces {
+  name: String,
+  pid: i32,
+  fds: Vec<NamespaceFd>,
+}
+
+impl ContainerNamespaces {
+  /// Opens `namespaces` of process `pid`, the init of container `name`.
+  pub(crate) fn open(name: &str, pid: i32, namespaces: &[Namespace]) -> Result<ContainerNamespaces, String> {
+    if pid <= 0 {
+      return Err(format!("Container {} isn't running", name));
+    }
+    let mut fds = Vec::new();
+    for namespace in namespaces {
+      let path = format!("/proc/{}/ns/{}", pid, namespace);
+      let file = File::open(&path).map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+      fds.push(NamespaceFd { namespace: *namespace, file });
+    }
+    Ok(ContainerNamespaces { name: name.to_string(), pid, fds })
+  }
+
+  /// PID, in the host's PID namespace, of the process the namespaces were opened from.
+  pub fn pid(&self) -> i32 {
+    self.pid
+  }
+
+  /// Get the handle of `namespace`, if it was opened.
+  pub fn get(&self, namespace: Namespace) -> Option<&NamespaceFd> {
+    self.fds.iter().find(|fd| fd.namespace == namespace)
+  }
+
+  /// Take the handles, in the order they were requested.
+  pub fn into_fds(self) -> Vec<NamespaceFd> {
+    self.fds
+  }
+
+  /// Run `f` in a forked child after joining `namespaces` and wait for it.
+  ///
+  /// # Parameters
+  /// `namespaces` - namespaces to join, which have to be opened. They are joined in
+  /// the order of `Namespace::ALL`. If `Namespace::Pid` is among them, the child
+  /// forks once more, so that `f` runs in the PID namespace.
+  ///
+  /// `f` - closure returning the exit code of the child.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the exit code of the child, `PANIC_EXIT_CODE` if `f`
+  /// unwound, or `Err` if a namespace couldn't be joined or the child was killed
+  /// by a signal.
+  ///
+  /// # Safety
+  /// The child is a copy of a possibly multi-threaded process with only the calling
+  /// thread, so `f` may only make async-signal-safe calls. Allocating, taking a
+  /// lock, panicking or using `std::process::Command` can deadlock on a lock
+  /// another thread held at the fork. To run a program, prepare its path and argv
+  /// before calling `run` and `execve` it in `f`.
+  ///
+  /// ## Note
+  /// User and group ids are unchanged, so joining a user namespace usually needs
+  /// `setuid` and `setgid` in `f`.
+  pub unsafe fn run<F: FnOnce() -> i32>(&self, namespaces: &[Namespace], f: F) -> Result<i32, String> {
+    let mut joins = Vec::new();
+    for namespace in Namespace::ALL.iter().filter(|ns| namespaces.contains(ns)) {
+      let fd = self.get(*namespace)
+                   .ok_or_else(|| format!("The {} namespace of container {} isn't open", namespace, self.name))?;
+      joins.push((fd.as_raw_fd(), namespace.clone_flag()));
+    }
+    let fork_pid = namespaces.contains(&Namespace::Pid);
+    // Reports errno and the index of a namespace that couldn't be joined.
+    let (mut errors, error_writer) = io::pipe().map_err(|e| e.to_string())?;
+    let status = unsafe {
+      let fork = fork_guard();
+      let child = libc::fork();
+      drop(fork);
+      if child < 0 {
+        return Err(format!("Couldn't fork: {}", io::Error::last_os_error()));
+      }
+      if child == 0 {
+        let error_fd = error_writer.as_raw_fd();
+        for (index, &(fd, flag)) in joins.iter().enumerate() {
+          if setns(fd, flag) != 0 {
+            let errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+            let mut report = [0u8; 5];
+            report[..4].copy_from_slice(&errno.to_ne_bytes());
+            report[4] = index as u8;
+            libc::write(error_fd, report.as_ptr() as *const c_void, report.len() as libc::size_t);
+            libc::_exit(1);
+          }
+        }
+        if fork_pid {
+          let grandchild = libc::fork();
+          if grandchild < 0 {
+            libc::_exit(1);
+          }
+          if grandchild > 0 {
+            libc::close(error_fd);
+            let mut status = 0;
+            while waitpid(grandchild, &mut status, 0) < 0 {
+              if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                libc::_exit(1);
+              }
+            }
+            match wait_status_to_exit_code(status) {
+              Ok(code) => libc::_exit(code),
+              Err(_) => libc::_exit(128 + (status & 0x7f)),
+            }
+          }
+        }
+        libc::close(error_fd);
+        let exit_on_unwind = ExitOnUnwind;
+        let code = f();
+        mem::forget(exit_on_unwind);
+        libc::_exit(code);
+      }
+      drop(error_writer);
+      let mut status = 0;
+      while waitpid(child, &mut status, 0) < 0 {
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+          return Err(format!("Couldn't wait for child: {}", io::Error::last_os_error()));
+        }
+      }
+      status
+    };
+    let mut report = Vec::new();
+    let _ = errors.read_to_end(&mut report);
+    if report.len() == 5 {
+      let errno = i32::from_ne_bytes([report[0], report[1], report[2], report[3]]);
+      let namespace = Namespace::ALL.iter().filter(|ns| namespaces.contains(ns)).nth(report[4] as usize);
+      return Err(format!("Couldn't join the {} namespace of container {}: {}",
+                         namespace.map_or("", |ns| ns.as_str()), self.name, io::Error::from_raw_os_error(errno)));
+    }
+    wait_status_to_exit_code(status).map_err(|e| e.to_string())
+  }
+}
+
+impl LxcContainer {
+
+  /// Open `namespaces` of the running container, from its init process.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the open namespaces, or `Err` if the container isn't running
+  /// or a namespace file can't be opened, e.g. without privileges or kernel support.
+  #[cfg(feature = "liblxc")]
+  pub fn namespaces(&self, namespaces: &[Namespace]) -> Result<ContainerNamespaces, String> {
+    ContainerNamespaces::open(&self.name(), self.init_pid(), namespaces)
+  }
+
+  /// Run `f` in a forked child inside `namespaces` of the running container. See
+  /// `ContainerNamespaces::run`.
+  ///
+  /// # Safety
+  /// `f` may only make async-signal-safe calls, see `ContainerNamespaces::run`.
+  pub unsafe fn run_in_namespaces<F: FnOnce() -> i32>(&self, namespaces: &[Namespace], f: F) -> Result<i32, String> {
+    self.namespaces(namespaces)?.run(namespaces, f)
+  }
+}
@@ -0,0 +1,1153 @@
+//! Running containers without liblxc.
+//!
+//! `NativeContainer` starts a container of an lxcpath from its LXC configuration
+//! and a directory rootfs, setting up namespaces, mounts, `pivot_root` and cgroups
+//! itself, and has the lifecycle methods of `LxcContainer`: `start`, `stop`,
+//! `shutdown`, `freeze`, `unfreeze`, `state`, `init_pid`, `wait` and
+//! `attach_run_wait`. Each container gets a monitor process, which waits for its
+//! init, removes its cgroups once it exits, and meanwhile holds a lock on
+//! `STATE_FILE` in the container directory, so that other processes see the
+//! container running.
+//!
+//! Only part of the configuration is applied:
+//!
+//! - `lxc.rootfs.path` of type `dir`, `lxc.uts.name` and `lxc.include`,
+//! - `lxc.mount.entry`, `lxc.mount.fstab`, `lxc.mount.auto` and `lxc.autodev`,
+//! - `lxc.init.cmd`, `lxc.init.cwd`, `lxc.init.uid`, `lxc.init.gid` and `lxc.environment`,
+//! - `lxc.cgroup.*`, `lxc.cgroup2.*` and `lxc.signal.halt`,
+//! - networks of type `empty` or `none`.
+//!
+//! ID maps and other network types are refused. Capabilities, LSM profiles,
+//! seccomp, hooks, ttys and the console are ignored, so init runs with all the
+//! privileges of root in its namespaces.
+//!
+//! Built without the default `liblxc` feature, the crate doesn't link liblxc at
+//! all and `LxcContainer` is `NativeContainer`, so callers of the lifecycle
+//! methods, `namespaces`, `run_in_namespaces`, `processes` and `signal` keep
+//! their code. Everything else, like creating, cloning, snapshots or `attach`
+//! options, needs liblxc.
+//!
+//! ```no_run
+//! use liblxc::native::NativeContainer;
+//! let c = NativeContainer::new("web", None).unwrap();
+//! if !c.start(0, None) {
+//!   panic!("{}", c.error_string().unwrap_or_default());
+//! }
+//! c.attach_run_wait("/bin/hostname", vec!["hostname"]).unwrap();
+//! c.stop();
+//! ```
+
+use crate::ffi::sys::*;
+use crate::rootfs::resolve;
+use crate::helper::*;
+use crate::mount::{MountCreate, MountEntry};
+use crate::namespace::{ContainerNamespaces, Namespace};
+use crate::process::{cgroup_mount, signal_number};
+use libc;
+use libc::{c_char, c_int, c_uint, c_ulong, c_void};
+use std::cell::RefCell;
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+use super::{fork_guard, LxcState};
+
+/// lxcpath used when none is given.
+pub const DEFAULT_LXCPATH: &str = "/var/lib/lxc";
+/// File in the container directory with the PID of init and the cgroups of the
+/// container, locked by its monitor while it runs.
+pub const STATE_FILE: &str = "native.state";
+/// Prefix of the cgroups of containers, followed by their names.
+pub const CGROUP_PREFIX: &str = "lxc.payload.";
+
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// Devices bind-mounted from the host into `/dev` with `lxc.autodev`.
+const AUTODEV_DEVICES: [&str; 6] = ["null", "zero", "full", "random", "urandom", "tty"];
+/// Namespaces joined by `attach_run_wait`. The cgroup namespace is left out, as
+/// cgroups outside of it can't be joined from inside.
+const ATTACH_NAMESPACES: [Namespace; 5] = [
+  Namespace::Mount, Namespace::Pid, Namespace::Uts, Namespace::Ipc, Namespace::Net,
+];
+/// Time `stop`, `freeze` and `unfreeze` wait for the container to get there.
+const STATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Records sent to `start` by the monitor and init: a kind, a value and an index.
+const REPORT_SIZE: usize = 9;
+const REPORT_PID: u8 = b'p';
+const REPORT_ERROR: u8 = b'e';
+const MONITOR_UNSHARE: u32 = !0;
+const MONITOR_FORK: u32 = !0 - 1;
+
+/// The part of the configuration of a container that is applied.
+#[derive(Debug)]
+struct Config {
+  rootfs: Option<String>,
+  hostname: Option<String>,
+  mount_auto: Vec<String>,
+  mount_entries: Vec<MountEntry>,
+  autodev: bool,
+  init_cmd: Vec<String>,
+  init_cwd: String,
+  init_uid: u32,
+  init_gid: u32,
+  environment: Vec<String>,
+  new_net: bool,
+  /// v1 controller (`None` for cgroup2), file and value.
+  cgroup_items: Vec<(Option<String>, String, String)>,
+  halt_signal: i32,
+}
+
+impl Config {
+  fn load(path: &Path) -> Result<Config, String> {
+    let mut config = Config {
+      rootfs: None,
+      hostname: None,
+      mount_auto: Vec::new(),
+      mount_entries: Vec::new(),
+      autodev: true,
+      init_cmd: Vec::new(),
+      init_cwd: "/".to_string(),
+      init_uid: 0,
+      init_gid: 0,
+      environment: Vec::new(),
+      new_net: true,
+      cgroup_items: Vec::new(),
+      halt_signal: 30,
+    };
+    config.read(path, 0)?;
+    Ok(config)
+  }
+
+  fn read(&mut self, path: &Path, depth: usize) -> Result<(), String> {
+    if depth > MAX_INCLUDE_DEPTH {
+      return Err(format!("Too many nested includes in {}", path.display()));
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+    for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+      let (key, value) = match line.find('=') {
+        Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+        None => return Err(format!("Invalid line in {}: {}", path.display(), line)),
+      };
+      self.set(key, value, depth).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+  }
+
+  fn set(&mut self, key: &str, value: &str, depth: usize) -> Result<(), String> {
+    let invalid = || format!("Invalid value of {}: {}", key, value);
+    match key {
+      "lxc.include" => {
+        let path = Path::new(value);
+        if !path.is_dir() {
+          return self.read(path, depth + 1);
+        }
+        let mut files: Vec<PathBuf> = fs::read_dir(path)
+          .map_err(|e| format!("Couldn't read {}: {}", value, e))?
+          .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+          .filter(|file| file.extension().is_some_and(|extension| extension == "conf"))
+          .collect();
+        files.sort();
+        for file in files {
+          self.read(&file, depth + 1)?;
+        }
+      }
+      "lxc.rootfs.path" | "lxc.rootfs" => self.rootfs = Some(value.to_string()).filter(|v| !v.is_empty()),
+      "lxc.uts.name" | "lxc.utsname" => self.hostname = Some(value.to_string()).filter(|v| !v.is_empty()),
+      "lxc.mount.auto" if value.is_empty() => self.mount_auto.clear(),
+      "lxc.mount.auto" => self.mount_auto.extend(value.split_whitespace().map(|v| v.to_string())),
+      "lxc.mount.entry" => self.mount_entries.push(value.parse()?),
+      "lxc.mount.fstab" | "lxc.mount" => {
+        let fstab = fs::read_to_string(value).map_err(|e| format!("Couldn't read {}: {}", value, e))?;
+        for line in fstab.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+          self.mount_entries.push(line.parse()?);
+        }
+      }
+      "lxc.autodev" => self.autodev = match value {
+        "1" => true,
+        "0" => false,
+        _ => return Err(invalid()),
+      },
+      "lxc.init.cmd" | "lxc.init_cmd" => self.init_cmd = value.split_whitespace().map(|arg| arg.to_string()).collect(),
+      "lxc.init.cwd" => self.init_cwd = value.to_string(),
+      "lxc.init.uid" => self.init_uid = value.parse().map_err(|_| invalid())?,
+      "lxc.init.gid" => self.init_gid = value.parse().map_err(|_| invalid())?,
+      "lxc.environment" if value.is_empty() => self.environment.clear(),
+      "lxc.environment" => self.environment.push(value.to_string()),
+      "lxc.signal.halt" | "lxc.haltsignal" => self.halt_signal = signal_number(value).ok_or_else(invalid)?,
+      "lxc.idmap" | "lxc.id_map" => return Err("ID maps aren't supported".to_string()),
+      _ if key.starts_with("lxc.cgroup2.") => {
+        self.cgroup_items.push((None, key["lxc.cgroup2.".len()..].to_string(), value.to_string()));
+      }
+      _ if key.starts_with("lxc.cgroup.") => {
+        // Keys without a controller, like lxc.cgroup.relative, configure liblxc itself.
+        let item = &key["lxc.cgroup.".len()..];
+        if let Some(dot) = item.find('.') {
+          if &item[..dot] != "dir" {
+            self.cgroup_items.push((Some(item[..dot].to_string()), item.to_string(), value.to_string()));
+          }
+        }
+      }
+      _ if (key.starts_with("lxc.net.") || key.starts_with("lxc.network.")) && key.ends_with(".type") => {
+        self.new_net = match value {
+          "empty" => true,
+          "none" => false,
+          _ => return Err(format!("Network type {} isn't supported", value)),
+        };
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+}
+
+fn errno() -> c_int {
+  io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+fn path_to_cstring(path: &Path) -> Result<CString, String> {
+  CString::new(path.as_os_str().as_bytes()).map_err(|_| format!("Invalid path {}", path.display()))
+}
+
+/// Something init does before running its command.
+enum Step {
+  /// Moves the process into the cgroup with this `cgroup.procs` file.
+  JoinCgroup(CString),
+  Unshare(c_int),
+  Mkdir(CString),
+  CreateFile(CString),
+  /// Creates a symlink to the first path at the second.
+  Symlink(CString, CString),
+  /// Source, target, filesystem type, flags and data of `mount`.
+  Mount(Option<CString>, CString, Option<CString>, c_ulong, Option<CString>),
+  SetHostname(CString),
+  /// Makes this rootfs `/` and detaches the old one.
+  PivotRoot(CString),
+  Chdir(CString),
+  /// User and group id.
+  SetIds(u32, u32),
+}
+
+impl Step {
+  /// Performs the step, without allocating. Returns errno on failure.
+  unsafe fn run(&self) -> Result<(), c_int> {
+    let check = |result: c_int| if result == 0 { Ok(()) } else { Err(errno()) };
+    let opt_ptr = |s: &Option<CString>| s.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+    match *self {
+      Step::JoinCgroup(ref procs) => {
+        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC, 0);
+        if fd < 0 {
+          return Err(errno());
+        }
+        let written = libc::write(fd, b"0".as_ptr() as *const c_void, 1);
+        let error = errno();
+        libc::close(fd);
+        if written == 1 { Ok(()) } else { Err(error) }
+      }
+      Step::Unshare(flags) => check(unshare(flags)),
+      Step::Mkdir(ref path) => {
+        if libc::mkdir(path.as_ptr(), 0o755) == 0 || errno() == libc::EEXIST { Ok(()) } else { Err(errno()) }
+      }
+      Step::CreateFile(ref path) => {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC, 0o644);
+        if fd < 0 {
+          return Err(errno());
+        }
+        libc::close(fd);
+        Ok(())
+      }
+      Step::Symlink(ref target, ref path) => {
+        if libc::symlink(target.as_ptr(), path.as_ptr()) == 0 || errno() == libc::EEXIST { Ok(()) } else { Err(errno()) }
+      }
+      Step::Mount(ref source, ref target, ref fstype, flags, ref data) => {
+        check(mount(opt_ptr(source), target.as_ptr(), opt_ptr(fstype), flags, opt_ptr(data) as *const c_void))
+      }
+      Step::SetHostname(ref name) => check(sethostname(name.as_ptr(), name.as_bytes().len() as libc::size_t)),
+      Step::PivotRoot(ref rootfs) => {
+        let dot = b".\0".as_ptr() as *const c_char;
+        check(libc::chdir(rootfs.as_ptr()))?;
+        // The old root ends up under the new one, where it is detached.
+        if syscall(SYS_PIVOT_ROOT, dot, dot) != 0 {
+          return Err(errno());
+        }
+        check(umount2(dot, MNT_DETACH))?;
+        check(libc::chdir(b"/\0".as_ptr() as *const c_char))
+      }
+      Step::Chdir(ref dir) => check(libc::chdir(dir.as_ptr())),
+      Step::SetIds(uid, gid) => {
+        check(setgroups(0, ptr::null()))?;
+        check(libc::setgid(gid))?;
+        check(libc::setuid(uid))
+      }
+    }
+  }
+}
+
+/// Everything init of a container does, prepared before forking so that the
+/// children don't allocate.
+struct InitPlan {
+  /// Steps, whether they may fail, and what they do for error messages.
+  steps: Vec<(Step, bool, String)>,
+  /// Paths to try running the command from.
+  programs: Vec<CString>,
+  argv: Vec<CString>,
+  argv_ptrs: Vec<*const c_char>,
+  env: Vec<CString>,
+  env_ptrs: Vec<*const c_char>,
+}
+
+impl InitPlan {
+  fn new(name: &str, config: &Config, rootfs: &Path, cgroups: &[PathBuf], argv: Vec<String>) -> Result<InitPlan, String> {
+    let mut plan = InitPlan {
+      steps: Vec::new(),
+      programs: Vec::new(),
+      argv: Vec::new(),
+      argv_ptrs: Vec::new(),
+      env: Vec::new(),
+      env_ptrs: Vec::new(),
+    };
+    let rootfs_cstring = path_to_cstring(rootfs)?;
+    for dir in cgroups {
+      plan.push(Step::JoinCgroup(path_to_cstring(&dir.join("cgroup.procs"))?), false,
+                format!("join cgroup {}", dir.display()));
+    }
+    // The cgroup namespace is created once init is in the cgroups of the container.
+    let mut flags = CLONE_NEWNS | CLONE_NEWUTS | CLONE_NEWIPC | CLONE_NEWCGROUP;
+    if config.new_net {
+      flags |= CLONE_NEWNET;
+    }
+    plan.push(Step::Unshare(flags), false, "create the namespaces of the container".to_string());
+    plan.push(Step::Mount(None, str_to_cstring("/"), None, MS_REC | MS_PRIVATE, None), false,
+              "make the mounts private".to_string());
+    plan.push(Step::Mount(Some(rootfs_cstring.clone()), rootfs_cstring.clone(), None, MS_BIND | MS_REC, None), false,
+              format!("bind-mount the rootfs {}", rootfs.display()));
+    if config.autodev {
+      plan.mount(rootfs, "/dev", "tmpfs", MS_NOSUID | MS_NOEXEC, Some("size=500000,mode=755"), false)?;
+    }
+    for value in &config.mount_auto {
+      plan.mount_auto(rootfs, value)?;
+    }
+    for entry in &config.mount_entries {
+      plan.mount_entry(rootfs, entry)?;
+    }
+    if config.autodev {
+      plan.fill_dev(rootfs)?;
+    }
+    let hostname = config.hostname.as_ref().map_or(name, |hostname| &hostname[..]);
+    plan.push(Step::SetHostname(str_to_cstring(hostname)), false, format!("set the hostname to {}", hostname));
+    plan.push(Step::PivotRoot(rootfs_cstring), false, format!("change the root to {}", rootfs.display()));
+    plan.push(Step::Chdir(str_to_cstring(&config.init_cwd)), false,
+              format!("change the directory to {}", config.init_cwd));
+    if config.init_uid != 0 || config.init_gid != 0 {
+      plan.push(Step::SetIds(config.init_uid, config.init_gid), false,
+                format!("switch to user {} and group {}", config.init_uid, config.init_gid));
+    }
+
+    let argv = if !argv.is_empty() { argv }
+               else if !config.init_cmd.is_empty() { config.init_cmd.clone() }
+               else { vec!["/sbin/init".to_string()] };
+    // Commands without a slash are looked up once the mounts are in place, like execvp does.
+    plan.programs = if argv[0].contains('/') {
+      vec![str_to_cstring(&argv[0])]
+    }
+    else {
+      DEFAULT_PATH.split(':').map(|dir| str_to_cstring(&format!("{}/{}", dir, argv[0]))).collect()
+    };
+    let mut env = vec![format!("PATH={}", DEFAULT_PATH), "container=lxc".to_string()];
+    for var in &config.environment {
+      // Variables without a value are passed on from the caller, like liblxc does.
+      let var = match var.find('=') {
+        Some(_) => var.clone(),
+        None => match env::var(var) {
+          Ok(value) => format!("{}={}", var, value),
+          Err(_) => continue,
+        },
+      };
+      let prefix = var[..var.find('=').unwrap() + 1].to_string();
+      env.retain(|v| !v.starts_with(&prefix));
+      env.push(var);
+    }
+    plan.argv = argv.iter().map(|arg| str_to_cstring(arg)).collect();
+    plan.argv_ptrs = vec_cstring_to_ptr(&plan.argv);
+    plan.env = env.iter().map(|var| str_to_cstring(var)).collect();
+    plan.env_ptrs = vec_cstring_to_ptr(&plan.env);
+    Ok(plan)
+  }
+
+  fn push(&mut self, step: Step, optional: bool, what: String) {
+    self.steps.push((step, optional, what));
+  }
+
+  /// Creates the directories from `rootfs` down to `dir`, which is inside it.
+  fn mkdirs(&mut self, rootfs: &Path, dir: &Path) -> Result<(), String> {
+    let mut path = rootfs.to_path_buf();
+    for component in dir.strip_prefix(rootfs).unwrap_or(dir).components() {
+      path.push(component);
+      self.push(Step::Mkdir(path_to_cstring(&path)?), false, format!("create {}", path.display()));
+    }
+    Ok(())
+  }
+
+  /// Mounts a filesystem of type `fstype` at `target` in the container.
+  fn mount(&mut self, rootfs: &Path, target: &str, fstype: &str, flags: c_ulong,
+           data: Option<&str>, optional: bool) -> Result<(), String> {
+    let host_target = resolve(rootfs, target, true)?;
+    self.mkdirs(rootfs, &host_target)?;
+    self.push(Step::Mount(Some(str_to_cstring(fstype)), path_to_cstring(&host_target)?, Some(str_to_cstring(fstype)),
+                          flags, data.map(str_to_cstring)),
+              optional, format!("mount {} at {}", fstype, target));
+    Ok(())
+  }
+
+  /// Adds the mounts of `lxc.mount.auto` value `value`.
+  fn mount_auto(&mut self, rootfs: &Path, value: &str) -> Result<(), String> {
+    let flags = MS_NOSUID | MS_NODEV | MS_NOEXEC;
+    let (kind, mode) = match value.find(':') {
+      Some(i) => (&value[..i], &value[i + 1..]),
+      None => (value, ""),
+    };
+    let read_only = if mode == "rw" { 0 } else { MS_RDONLY };
+    match kind {
+      "proc" => {
+        self.mount(rootfs, "/proc", "proc", flags, None, false)?;
+        if mode != "rw" {
+          let sys = path_to_cstring(&resolve(rootfs, "/proc/sys", true)?)?;
+          self.push(Step::Mount(Some(sys.clone()), sys.clone(), None, MS_BIND, None), false,
+                    "bind-mount /proc/sys".to_string());
+          self.push(Step::Mount(None, sys, None, MS_BIND | MS_REMOUNT | MS_RDONLY | flags, None), false,
+                    "make /proc/sys read-only".to_string());
+        }
+      }
+      "sys" => self.mount(rootfs, "/sys", "sysfs", flags | read_only, None, false)?,
+      "cgroup" | "cgroup-full" => {
+        // Only a single cgroup2 hierarchy can be mounted as it is seen in the cgroup namespace.
+        let unified = cgroup_mount("").map_err(|e| e.to_string())?;
+        if unified.is_some_and(|(mount, _)| mount == Path::new("/sys/fs/cgroup")) {
+          self.mount(rootfs, "/sys/fs/cgroup", "cgroup2", flags | read_only, None, false)?;
+        }
+      }
+      "shmounts" => {}
+      _ => return Err(format!("lxc.mount.auto value {} isn't supported", value)),
+    }
+    Ok(())
+  }
+
+  fn mount_entry(&mut self, rootfs: &Path, entry: &MountEntry) -> Result<(), String> {
+    // Mount points may be given as host paths inside the rootfs.
+    let target = match Path::new(&entry.target).strip_prefix(rootfs) {
+      Ok(relative) => relative.to_str().unwrap_or(&entry.target).to_string(),
+      Err(_) => entry.target.clone(),
+    };
+    let target = format!("/{}", target.trim_matches('/'));
+    let mut flags = 0;
+    let mut data = Vec::new();
+    let mut optional = false;
+    for option in &entry.options {
+      match &option[..] {
+        "ro" => flags |= MS_RDONLY,
+        "rw" => flags &= !MS_RDONLY,
+        "nosuid" => flags |= MS_NOSUID,
+        "nodev" => flags |= MS_NODEV,
+        "noexec" => flags |= MS_NOEXEC,
+        "sync" => flags |= MS_SYNCHRONOUS,
+        "noatime" => flags |= MS_NOATIME,
+        "nodiratime" => flags |= MS_NODIRATIME,
+        "relatime" => flags |= MS_RELATIME,
+        "strictatime" => flags |= MS_STRICTATIME,
+        "bind" => flags |= MS_BIND,
+        "rbind" => flags |= MS_BIND | MS_REC,
+        "optional" => optional = true,
+        "suid" | "dev" | "exec" | "async" | "auto" | "noauto" | "nofail" => {}
+        option => data.push(option),
+      }
+    }
+    let host_target = resolve(rootfs, &target, true)?;
+    match entry.create {
+      Some(MountCreate::Dir) => self.mkdirs(rootfs, &host_target)?,
+      Some(MountCreate::File) => {
+        if let Some(parent) = host_target.parent() {
+          self.mkdirs(rootfs, parent)?;
+        }
+        self.push(Step::CreateFile(path_to_cstring(&host_target)?), false, format!("create {}", target));
+      }
+      None => {}
+    }
+    let what = format!("mount {} at {}", entry.source, target);
+    let target_cstring = path_to_cstring(&host_target)?;
+    if flags & MS_BIND == 0 {
+      let data = if data.is_empty() { None } else { Some(str_to_cstring(&data.join(","))) };
+      self.push(Step::Mount(Some(str_to_cstring(&entry.source)), target_cstring, Some(str_to_cstring(&entry.fstype)),
+                            flags, data),
+                optional, what);
+      return Ok(());
+    }
+    self.push(Step::Mount(Some(str_to_cstring(&entry.source)), target_cstring.clone(), None, flags & (MS_BIND | MS_REC), None),
+              optional, what.clone());
+    // Flags of bind mounts only apply when remounting.
+    let remount = flags & !(MS_BIND | MS_REC);
+    if remount != 0 {
+      self.push(Step::Mount(None, target_cstring, None, MS_BIND | MS_REMOUNT | remount, None), optional, what);
+    }
+    Ok(())
+  }
+
+  /// Fills the `/dev` tmpfs of `lxc.autodev` with devices, `/dev/pts` and symlinks.
+  fn fill_dev(&mut self, rootfs: &Path) -> Result<(), String> {
+    let dev = resolve(rootfs, "/dev", true)?;
+    for device in AUTODEV_DEVICES.iter() {
+      let host_device = format!("/dev/{}", device);
+      let path = path_to_cstring(&dev.join(device))?;
+      self.push(Step::CreateFile(path.clone()), false, format!("create {}", host_device));
+      self.push(Step::Mount(Some(str_to_cstring(&host_device)), path, None, MS_BIND, None), false,
+                format!("bind-mount {}", host_device));
+    }
+    self.mount(rootfs, "/dev/pts", "devpts", MS_NOSUID | MS_NOEXEC,
+               Some("newinstance,ptmxmode=0666,mode=0620,gid=5"), true)?;
+    self.mkdirs(rootfs, &dev.join("shm"))?;
+    let links = [("pts/ptmx", "ptmx"), ("/proc/self/fd", "fd"), ("/proc/self/fd/0", "stdin"),
+                 ("/proc/self/fd/1", "stdout"), ("/proc/self/fd/2", "stderr")];
+    for &(target, name) in links.iter() {
+      self.push(Step::Symlink(str_to_cstring(target), path_to_cstring(&dev.join(name))?), false,
+                format!("create /dev/{}", name));
+    }
+    Ok(())
+  }
+
+  /// What failed, for error `index` of a report.
+  fn describe(&self, index: u32) -> String {
+    match index {
+      MONITOR_UNSHARE => "create the PID namespace".to_string(),
+      MONITOR_FORK => "fork init".to_string(),
+      index if (index as usize) < self.steps.len() => self.steps[index as usize].2.clone(),
+      _ => format!("run {}", self.argv[0].to_string_lossy()),
+    }
+  }
+
+  /// Runs the steps and executes the command in the child that becomes init,
+  /// reporting a failure through `report`.
+  unsafe fn exec(&self, report: c_int) -> ! {
+    for (index, &(ref step, optional, _)) in self.steps.iter().enumerate() {
+      if let Err(error) = step.run() {
+        if !optional {
+          send(report, REPORT_ERROR, error, index as u32);
+          libc::_exit(1);
+        }
+      }
+    }
+    let mut error = libc::ENOENT;
+    for program in &self.programs {
+      libc::execve(program.as_ptr(), self.argv_ptrs.as_ptr(), self.env_ptrs.as_ptr());
+      if errno() != libc::ENOENT {
+        error = errno();
+      }
+    }
+    send(report, REPORT_ERROR, error, self.steps.len() as u32);
+    libc::_exit(1)
+  }
+}
+
+/// Writes a record of kind `kind` to pipe `fd`.
+unsafe fn send(fd: c_int, kind: u8, value: i32, index: u32) {
+  let mut record = [0u8; REPORT_SIZE];
+  record[0] = kind;
+  record[1..5].copy_from_slice(&value.to_ne_bytes());
+  record[5..9].copy_from_slice(&index.to_ne_bytes());
+  libc::write(fd, record.as_ptr() as *const c_void, record.len() as libc::size_t);
+}
+
+/// Closes the file descriptors above 2 except those in `keep`, which is sorted.
+unsafe fn close_fds_except(keep: &[c_int]) {
+  let mut first: c_uint = 3;
+  for &fd in keep {
+    let fd = fd as c_uint;
+    if fd > first {
+      syscall(SYS_CLOSE_RANGE, first, fd - 1, 0 as c_uint);
+    }
+    if fd >= first {
+      first = fd + 1;
+    }
+  }
+  syscall(SYS_CLOSE_RANGE, first, !0 as c_uint, 0 as c_uint);
+}
+
+/// Body of the monitor process: starts init in a new PID namespace, reports its
+/// PID, waits for it and removes the cgroups in `cleanup`. Keeps `lock` open
+/// until then.
+unsafe fn monitor(plan: &InitPlan, report: c_int, lock: c_int, devnull: c_int, cleanup: &[CString]) -> ! {
+  for fd in 0..3 {
+    libc::dup2(devnull, fd);
+  }
+  let mut keep = [report, lock];
+  keep.sort();
+  close_fds_except(&keep);
+  if unshare(CLONE_NEWPID) != 0 {
+    send(report, REPORT_ERROR, errno(), MONITOR_UNSHARE);
+    libc::_exit(1);
+  }
+  let init = libc::fork();
+  if init < 0 {
+    send(report, REPORT_ERROR, errno(), MONITOR_FORK);
+    libc::_exit(1);
+  }
+  if init == 0 {
+    libc::close(lock);
+    plan.exec(report);
+  }
+  send(report, REPORT_PID, init, 0);
+  libc::close(report);
+  let mut status = 0;
+  while waitpid(init, &mut status, 0) < 0 && errno() == libc::EINTR {}
+  // The other processes of the container are killed with init, but may take a
+  // moment to leave its cgroups.
+  for dir in cleanup {
+    for _ in 0..500 {
+      if libc::rmdir(dir.as_ptr()) == 0 || errno() != libc::EBUSY {
+        break;
+      }
+      libc::usleep(10000);
+    }
+  }
+  libc::_exit(0)
+}
+
+/// Creates the cgroups of container `name`, in the cgroup2 hierarchy, the cgroup
+/// v1 freezer and the hierarchies of the v1 controllers in `lxc.cgroup.*`, and
+/// sets the items of the configuration.
+fn create_cgroups(name: &str, config: &Config) -> Result<Vec<PathBuf>, String> {
+  let leaf = format!("{}{}", CGROUP_PREFIX, name);
+  let unified = cgroup_mount("").map_err(|e| e.to_string())?.map(|(mount, _)| mount);
+  let mut controllers = vec!["freezer"];
+  controllers.extend(config.cgroup_items.iter().filter_map(|item| item.0.as_ref().map(|c| &c[..])));
+  let mut v1 = Vec::new();
+  for controller in controllers {
+    if let Some((mount, _)) = cgroup_mount(controller).map_err(|e| e.to_string())? {
+      v1.push((controller, mount));
+    }
+  }
+  let mut mounts: Vec<&PathBuf> = Vec::new();
+  for mount in unified.iter().chain(v1.iter().map(|(_, mount)| mount)) {
+    if !mounts.contains(&mount) {
+      mounts.push(mount);
+    }
+  }
+  if mounts.is_empty() {
+    return Err("No cgroup hierarchy is mounted".to_string());
+  }
+  let mut dirs = Vec::new();
+  for mount in mounts {
+    let dir = mount.join(&leaf);
+    match fs::create_dir(&dir) {
+      Err(ref e) if e.kind() != io::ErrorKind::AlreadyExists => {
+        remove_cgroups(&dirs);
+        return Err(format!("Couldn't create cgroup {}: {}", dir.display(), e));
+      }
+      _ => dirs.push(dir),
+    }
+  }
+  for (controller, file, value) in &config.cgroup_items {
+    let mount = match *controller {
+      Some(ref controller) => v1.iter().find(|&&(c, _)| c == controller).map(|(_, mount)| mount),
+      None => unified.as_ref(),
+    };
+    let result = match mount {
+      Some(mount) => {
+        if controller.is_none() {
+          // Controllers have to be enabled for the children of the hierarchy's root.
+          let enable = format!("+{}", file.split('.').next().unwrap_or(""));
+          let _ = fs::write(mount.join("cgroup.subtree_control"), enable);
+        }
+        fs::write(mount.join(&leaf).join(file), value).map_err(|e| e.to_string())
+      }
+      None => Err("the hierarchy isn't mounted".to_string()),
+    };
+    if let Err(e) = result {
+      remove_cgroups(&dirs);
+      return Err(format!("Couldn't set cgroup item {} to {}: {}", file, value, e));
+    }
+  }
+  Ok(dirs)
+}
+
+fn remove_cgroups(dirs: &[PathBuf]) {
+  for dir in dirs {
+    let _ = fs::remove_dir(dir);
+  }
+}
+
+/// Determine whether process `pid` exists and isn't a zombie.
+fn alive(pid: i32) -> bool {
+  match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+    Ok(stat) => stat.rfind(')').and_then(|i| stat[i + 1..].trim_start().chars().next())
+                    .is_some_and(|state| state != 'Z' && state != 'X'),
+    Err(_) => false,
+  }
+}
+
+/// Freezer file of the first cgroup in `cgroups` that has one, with the values
+/// that freeze and thaw it. cgroup v1 freezers come first, as on hosts with both
+/// they are the ones freezing the tasks.
+fn freezer(cgroups: &[PathBuf]) -> Option<(PathBuf, &'static str, &'static str)> {
+  cgroups.iter().map(|dir| dir.join("freezer.state")).find(|file| file.exists())
+         .map(|file| (file, "FROZEN", "THAWED"))
+         .or_else(|| cgroups.iter().map(|dir| dir.join("cgroup.freeze")).find(|file| file.exists())
+                            .map(|file| (file, "1", "0")))
+}
+
+fn freezer_state(cgroups: &[PathBuf]) -> LxcState {
+  let (file, frozen, _) = match freezer(cgroups) {
+    Some(freezer) => freezer,
+    None => return LxcState::Running,
+  };
+  let value = fs::read_to_string(&file).unwrap_or_default();
+  match value.trim() {
+    "FROZEN" => LxcState::Frozen,
+    "FREEZING" => LxcState::Freezing,
+    value if value == frozen => {
+      let events = fs::read_to_string(file.with_file_name("cgroup.events")).unwrap_or_default();
+      if events.lines().any(|line| line == "frozen 1") { LxcState::Frozen } else { LxcState::Freezing }
+    }
+    _ => LxcState::Running,
+  }
+}
+
+/// Init and cgroups of a running container.
+struct Running {
+  pid: i32,
+  cgroups: Vec<PathBuf>,
+}
+
+/// A container run by this crate instead of liblxc. See the module documentation.
+#[derive(Debug)]
+pub struct NativeContainer {
+  name: String,
+  lxcpath: String,
+  error: RefCell<Option<String>>,
+}
+
+impl NativeContainer {
+
+  /// Creates a container object, like `LxcContainer::new`.
+  ///
+  /// # Parameters
+  /// `name` - name of the container.
+  ///
+  /// `config_path_option` - `Option` with the lxcpath of the container, or `None`
+  /// for `DEFAULT_LXCPATH`.
+  ///
+  /// # Return value
+  /// Returns `Ok(NativeContainer)`, or `Err` if `name` is invalid.
+  pub fn new(name: &str, config_path_option: Option<&str>) -> Result<NativeContainer, &'static str> {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+      return Err("Invalid container name");
+    }
+    Ok(NativeContainer {
+      name: name.to_string(),
+      lxcpath: config_path_option.unwrap_or(DEFAULT_LXCPATH).to_string(),
+      error: RefCell::new(None),
+    })
+  }
+
+  /// Name of the container.
+  pub fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  /// The lxcpath of the container.
+  pub fn get_config_path(&self) -> String {
+    self.lxcpath.clone()
+  }
+
+  /// Return the config file name, `LXCPATH/NAME/config`.
+  pub fn config_file_name(&self) -> Result<String, &'static str> {
+    Ok(format!("{}/{}/config", self.lxcpath, self.name))
+  }
+
+  /// Determine if the config file exists.
+  pub fn is_defined(&self) -> bool {
+    self.dir().join("config").is_file()
+  }
+
+  /// Why the last `start`, `stop`, `shutdown`, `freeze`, `unfreeze` or
+  /// `attach_run_wait` failed, if it did.
+  pub fn error_string(&self) -> Option<String> {
+    self.error.borrow().clone()
+  }
+
+  /// Determine state of container.
+  ///
+  /// # Return value
+  /// Returns "STOPPED", "STARTING" while `start` runs, "RUNNING", "FREEZING",
+  /// "FROZEN", or "STOPPING" between the exit of init and the removal of its cgroups.
+  pub fn state(&self) -> String {
+    self.runtime().0.as_str().to_string()
+  }
+
+  /// Determine if container is running, in any state but "STOPPED".
+  pub fn is_running(&self) -> bool {
+    self.runtime().0 != LxcState::Stopped
+  }
+
+  /// Determine process ID of the containers init process.
+  ///
+  /// # Return value
+  /// Returns pid of init process as seen from outside the container, or -1 if it
+  /// isn't running.
+  pub fn init_pid(&self) -> i32 {
+    match self.runtime() {
+      (LxcState::Running, Some(running)) | (LxcState::Freezing, Some(running)) |
+      (LxcState::Frozen, Some(running)) => running.pid,
+      _ => -1,
+    }
+  }
+
+  /// Start the container, in the background.
+  ///
+  /// # Parameters
+  /// `use_init` - must be 0, as there is no lxc-init to run.
+  ///
+  /// `argv_option` - `Option` with the command to run as init instead of
+  /// `lxc.init.cmd`, or `/sbin/init` if that isn't set either. Commands without a
+  /// slash are looked up in the usual directories of `PATH` in the container.
+  ///
+  /// # Return value
+  /// Returns `true` once the command runs, else `false`; see `error_string`.
+  ///
+  /// ## Note
+  /// Needs root. A command which exits at once still counts as started.
+  pub fn start(&self, use_init: i32, argv_option: Option<Vec<&str>>) -> bool {
+    let argv = argv_option.unwrap_or_default().iter().map(|arg| arg.to_string()).collect();
+    let result = self.try_start(use_init, argv);
+    self.record(result)
+  }
+
+  /// Kill the container, waiting until it stopped.
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn stop(&self) -> bool {
+    let result = self.try_stop();
+    self.record(result)
+  }
+
+  /// Ask the container to shut down, by sending `lxc.signal.halt` (SIGPWR by
+  /// default) to its init.
+  ///
+  /// # Parameters
+  /// `timeout` - seconds to wait for it to stop, -1 to wait forever or 0 not to wait.
+  ///
+  /// # Return value
+  /// Returns `true` if it stopped in time, or the signal was sent with `timeout` 0,
+  /// else `false`.
+  pub fn shutdown(&self, timeout: i32) -> bool {
+    let result = self.try_shutdown(timeout);
+    self.record(result)
+  }
+
+  /// Freeze running container, waiting until all its processes are frozen.
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn freeze(&self) -> bool {
+    let result = self.set_frozen(true);
+    self.record(result)
+  }
+
+  /// Thaw a frozen container.
+  ///
+  /// # Return value
+  /// Returns `true` on success, else `false`.
+  pub fn unfreeze(&self) -> bool {
+    let result = self.set_frozen(false);
+    self.record(result)
+  }
+
+  /// Wait for container to reach a particular state.
+  ///
+  /// # Parameters
+  /// `state` - state to wait for.
+  ///
+  /// `timeout` - timeout in seconds, -1 to wait forever.
+  ///
+  /// # Return value
+  /// Returns `true` if state reached within timeout, else `false`.
+  pub fn wait(&self, state: &str, timeout: i32) -> bool {
+    let state: LxcState = match state.parse() {
+      Ok(state) => state,
+      Err(_) => return false,
+    };
+    let timeout = if timeout < 0 { None } else { Some(Duration::from_secs(timeout as u64)) };
+    self.wait_for(|s| s == state, timeout)
+  }
+
+  /// Run a program inside the container and wait for it to finish.
+  ///
+  /// # Parameters
+  /// `program` - full path inside the container of the program to run.
+  ///
+  /// `argv` - vector of arguments to pass to `program`, starting with the program name itself.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the exit code of `program`, or `Err` if it couldn't be run
+  /// or was terminated by a signal; see `error_string`.
+  ///
+  /// ## Note
+  /// The program joins the cgroups and namespaces of the container, except its
+  /// cgroup namespace, with the environment of the caller and `/` as directory.
+  pub fn attach_run_wait(&self, program: &str, argv: Vec<&str>) -> Result<i32, &'static str> {
+    match self.try_attach(program, argv) {
+      Ok(code) => {
+        *self.error.borrow_mut() = None;
+        Ok(code)
+      }
+      Err(e) => {
+        *self.error.borrow_mut() = Some(e);
+        Err("Couldn't attach to container")
+      }
+    }
+  }
+
+  /// Open `namespaces` of the running container, like `LxcContainer::namespaces`.
+  pub fn namespaces(&self, namespaces: &[Namespace]) -> Result<ContainerNamespaces, String> {
+    ContainerNamespaces::open(&self.name, self.init_pid(), namespaces)
+  }
+
+  fn dir(&self) -> PathBuf {
+    Path::new(&self.lxcpath).join(&self.name)
+  }
+
+  fn record(&self, result: Result<(), String>) -> bool {
+    let ok = result.is_ok();
+    *self.error.borrow_mut() = result.err();
+    ok
+  }
+
+  /// State of the container, with its init and cgroups once they are known.
+  fn runtime(&self) -> (LxcState, Option<Running>) {
+    let mut file = match File::open(self.dir().join(STATE_FILE)) {
+      Ok(file) => file,
+      Err(_) => return (LxcState::Stopped, None),
+    };
+    // Nobody holds the lock without a monitor, and the lock goes with the file.
+    if unsafe { flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } == 0 {
+      return (LxcState::Stopped, None);
+    }
+    let mut text = String::new();
+    let _ = file.read_to_string(&mut text);
+    let mut lines = text.lines();
+    let pid = match lines.next().and_then(|line| line.parse().ok()) {
+      Some(pid) => pid,
+      None => return (LxcState::Starting, None),
+    };
+    let running = Running { pid, cgroups: lines.map(PathBuf::from).collect() };
+    if !alive(pid) {
+      return (LxcState::Stopping, Some(running));
+    }
+    (freezer_state(&running.cgroups), Some(running))
+  }
+
+  fn running(&self) -> Result<Running, String> {
+    match self.runtime() {
+      (LxcState::Stopped, _) | (LxcState::Stopping, _) => Err(format!("Container {} isn't running", self.name)),
+      (_, Some(running)) => Ok(running),
+      (state, None) => Err(format!("Container {} is {}", self.name, state)),
+    }
+  }
+
+  /// Polls the state until `reached` accepts it or `timeout` passes.
+  fn wait_for<F: Fn(LxcState) -> bool>(&self, reached: F, timeout: Option<Duration>) -> bool {
+    let start = Instant::now();
+    loop {
+      if reached(self.runtime().0) {
+        return true;
+      }
+      if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+        return false;
+      }
+      thread::sleep(Duration::from_millis(20));
+    }
+  }
+
+  fn try_start(&self, use_init: i32, argv: Vec<String>) -> Result<(), String> {
+    if use_init != 0 {
+      return Err("lxc-init isn't available without liblxc".to_string());
+    }
+    if !self.is_defined() {
+      return Err(format!("Container {} isn't defined", self.name));
+    }
+    let config = Config::load(&self.dir().join("config"))?;
+    let rootfs = self.rootfs(&config)?;
+    let path = self.dir().join(STATE_FILE);
+    let mut lock = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)
+                     .map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?;
+    // Readers of the state take the lock for a moment, too.
+    let mut attempts = 0;
+    while unsafe { flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+      attempts += 1;
+      if attempts == 10 {
+        return Err(format!("Container {} is already running", self.name));
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+    let cgroups = create_cgroups(&self.name, &config)?;
+    let pid = InitPlan::new(&self.name, &config, &rootfs, &cgroups, argv)
+                .and_then(|plan| self.spawn(&plan, &lock, &cgroups));
+    let pid = match pid {
+      Ok(pid) => pid,
+      Err(e) => {
+        remove_cgroups(&cgroups);
+        return Err(e);
+      }
+    };
+    let mut state = format!("{}\n", pid);
+    for dir in &cgroups {
+      state.push_str(&format!("{}\n", dir.display()));
+    }
+    lock.set_len(0).and_then(|_| lock.seek(io::SeekFrom::Start(0))).and_then(|_| lock.write_all(state.as_bytes()))
+        .map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+  }
+
+  /// Directory of the rootfs in `config`, `LXCPATH/NAME/rootfs` by default.
+  fn rootfs(&self, config: &Config) -> Result<PathBuf, String> {
+    let rootfs = match config.rootfs {
+      Some(ref rootfs) => rootfs.clone(),
+      None => self.dir().join("rootfs").to_string_lossy().into_owned(),
+    };
+    let dir = rootfs.strip_prefix("dir:").unwrap_or(&rootfs);
+    if !dir.starts_with('/') {
+      return Err(format!("Rootfs {} isn't supported, only directories are", rootfs));
+    }
+    let dir = fs::canonicalize(dir).map_err(|e| format!("Invalid rootfs {}: {}", dir, e))?;
+    if !dir.is_dir() {
+      return Err(format!("Rootfs {} isn't a directory", dir.display()));
+    }
+    Ok(dir)
+  }
+
+  /// Forks the monitor, which forks init, and returns the PID of init once it runs
+  /// its command.
+  fn spawn(&self, plan: &InitPlan, lock: &File, cgroups: &[PathBuf]) -> Result<i32, String> {
+    let devnull = OpenOptions::new().read(true).write(true).open("/dev/null").map_err(|e| e.to_string())?;
+    let cleanup = cgroups.iter().map(|dir| path_to_cstring(dir)).collect::<Result<Vec<CString>, String>>()?;
+    // Closed by init when it executes its command, as it's close-on-exec.
+    let (mut reports, report_writer) = io::pipe().map_err(|e| e.to_string())?;
+    let status = unsafe {
+      let fork = fork_guard();
+      let child = libc::fork();
+      drop(fork);
+      if child < 0 {
+        return Err(format!("Couldn't fork: {}", io::Error::last_os_error()));
+      }
+      if child == 0 {
+        // The monitor is orphaned, so that the caller needn't wait for it.
+        libc::setsid();
+        match libc::fork() {
+          0 => monitor(plan, report_writer.as_raw_fd(), lock.as_raw_fd(), devnull.as_raw_fd(), &cleanup),
+          pid => libc::_exit(if pid < 0 { 1 } else { 0 }),
+        }
+      }
+      drop(report_writer);
+      let mut status = 0;
+      while waitpid(child, &mut status, 0) < 0 && errno() == libc::EINTR {}
+      status
+    };
+    if wait_status_to_exit_code(status) != Ok(0) {
+      return Err("Couldn't fork the monitor".to_string());
+    }
+    let mut report = Vec::new();
+    reports.read_to_end(&mut report).map_err(|e| e.to_string())?;
+    let mut pid = None;
+    for record in report.chunks(REPORT_SIZE).filter(|record| record.len() == REPORT_SIZE) {
+      let value = i32::from_ne_bytes([record[1], record[2], record[3], record[4]]);
+      let index = u32::from_ne_bytes([record[5], record[6], record[7], record[8]]);
+      match record[0] {
+        REPORT_PID => pid = Some(value),
+        _ => return Err(format!("Couldn't {}: {}", plan.describe(index), io::Error::from_raw_os_error(value))),
+      }
+    }
+    pid.ok_or_else(|| "The monitor exited before starting init".to_string())
+  }
+
+  fn signal_init(&self, pid: i32, signal: i32) -> Result<(), String> {
+    if unsafe { kill(pid, signal) } != 0 && errno() != libc::ESRCH {
+      return Err(format!("Couldn't signal init of container {}: {}", self.name, io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  fn try_stop(&self) -> Result<(), String> {
+    let running = self.running()?;
+    self.signal_init(running.pid, 9)?;
+    // Frozen processes only die once thawed.
+    if let Some((file, _, thawed)) = freezer(&running.cgroups) {
+      let _ = fs::write(file, thawed);
+    }
+    if !self.wait_for(|state| state == LxcState::Stopped, Some(STATE_TIMEOUT)) {
+      return Err(format!("Container {} didn't stop", self.name));
+    }
+    Ok(())
+  }
+
+  fn try_shutdown(&self, timeout: i32) -> Result<(), String> {
+    let running = self.running()?;
+    let config = Config::load(&self.dir().join("config"))?;
+    self.signal_init(running.pid, config.halt_signal)?;
+    let timeout = if timeout < 0 { None } else { Some(Duration::from_secs(timeout as u64)) };
+    if timeout != Some(Duration::from_secs(0)) && !self.wait_for(|state| state == LxcState::Stopped, timeout) {
+      return Err(format!("Container {} didn't shut down in time", self.name));
+    }
+    Ok(())
+  }
+
+  fn set_frozen(&self, frozen: bool) -> Result<(), String> {
+    let running = self.running()?;
+    let (file, freeze, thaw) = freezer(&running.cgroups)
+                                 .ok_or_else(|| format!("Container {} has no freezer", self.name))?;
+    let wanted = if frozen { LxcState::Frozen } else { LxcState::Running };
+    let start = Instant::now();
+    // The cgroup v1 freezer gives up on tasks that don't stop at once, so it's asked again.
+    loop {
+      fs::write(&file, if frozen { freeze } else { thaw })
+        .map_err(|e| format!("Couldn't write {}: {}", file.display(), e))?;
+      if self.wait_for(|state| state == wanted, Some(Duration::from_millis(100))) {
+        return Ok(());
+      }
+      if start.elapsed() >= STATE_TIMEOUT {
+        return Err(format!("Container {} didn't get {}", self.name, wanted));
+      }
+    }
+  }
+
+  fn try_attach(&self, program: &str, argv: Vec<&str>) -> Result<i32, String> {
+    let running = self.running()?;
+    let namespaces = ContainerNamespaces::open(&self.name, running.pid, &ATTACH_NAMESPACES)?;
+    let mut procs = Vec::new();
+    for dir in &running.cgroups {
+      let path = dir.join("cgroup.procs");
+      procs.push(OpenOptions::new().write(true).open(&path)
+                   .map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?);
+    }
+    let program_cstring = str_to_cstring(program);
+    let argv_cstring = vec_str_to_cstring(argv);
+    let argv_ptrs = vec_cstring_to_ptr(&argv_cstring);
+    // The child only writes, changes directory and executes what is prepared above.
+    let code = unsafe {
+      namespaces.run(&ATTACH_NAMESPACES, || {
+        for file in &procs {
+          if libc::write(file.as_raw_fd(), b"0".as_ptr() as *const c_void, 1) != 1 {
+            return 126;
+          }
+        }
+        libc::chdir(b"/\0".as_ptr() as *const c_char);
+        libc::execv(program_cstring.as_ptr(), argv_ptrs.as_ptr());
+        127
+      })?
+    };
+    Ok(code)
+  }
+}
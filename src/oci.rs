@@ -0,0 +1,510 @@
+//! OCI images run as LXC containers, enabled with the `oci` feature.
+//!
+//! `LxcContainer::create_from_oci` creates a container from a local image, without
+//! a registry: an OCI image layout directory, a tarball of one, or a `docker save`
+//! archive. The layers are applied in order into the rootfs, honouring whiteouts,
+//! and the image configuration becomes configuration items:
+//!
+//! * `Entrypoint` followed by `Cmd` becomes `lxc.init.cmd`,
+//! * each `Env` entry becomes an `lxc.environment` item,
+//! * `User`, resolved against the image's `/etc/passwd` and `/etc/group`, becomes
+//!   `lxc.init.uid` and `lxc.init.gid`,
+//! * `WorkingDir` becomes `lxc.init.cwd`,
+//! * `architecture` becomes `lxc.arch`.
+//!
+//! Before liblxc 2.1 the old `lxc.init_cmd`, `lxc.init_uid` and `lxc.init_gid` keys
+//! are used, and there is no `lxc.init.cwd`. liblxc splits `lxc.init.cmd` at
+//! whitespace, so images with an argument that is empty or contains whitespace are
+//! refused. The process is also saved next to the configuration as `OciProcess`,
+//! which `LxcContainer::run_oci_process` runs inside the container along with the
+//! image's environment, `WorkingDir` and user.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use crate::features::{config_key, has_key};
+use crate::image::{io_error, open_tarball, sha256_file, unpack_tarball};
+use super::{AttachOptions, LxcContainer};
+
+/// Annotation naming a manifest in the index of an image layout.
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// File in the container directory holding the `OciProcess`.
+const PROCESS_FILE: &str = "oci-process.json";
+
+/// `PATH` of images that don't set one, as Docker does.
+const DEFAULT_PATH: &str = "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Which image `LxcContainer::create_from_oci` picks from an archive or layout.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OciOptions {
+  /// Image to pick: the `org.opencontainers.image.ref.name` annotation of an image
+  /// layout, for example "v1", or a repository tag of a docker archive, for example
+  /// "app:v1". If `None`, the only image is picked.
+  pub reference: Option<String>,
+  /// Architecture to pick from a multi-platform image, in OCI terms such as "arm64".
+  /// If `None`, the architecture of the host.
+  pub arch: Option<String>,
+}
+
+/// Process an OCI image runs, saved when a container is created from it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OciProcess {
+  /// Command line: the entrypoint followed by the command.
+  pub args: Vec<String>,
+  /// Environment, as `NAME=VALUE`.
+  pub env: Vec<String>,
+  /// Working directory.
+  pub cwd: String,
+  /// User id.
+  pub uid: u32,
+  /// Group id.
+  pub gid: u32,
+}
+
+impl OciProcess {
+  /// Options attaching with exactly the environment, directory and user of the process.
+  pub fn attach_options(&self) -> AttachOptions {
+    AttachOptions {
+      cwd: Some(self.cwd.clone()),
+      uid: Some(self.uid),
+      gid: Some(self.gid),
+      clear_env: true,
+      env: self.env.clone(),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct Platform {
+  architecture: String,
+  os: String,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+  #[serde(rename = "mediaType", default)]
+  media_type: String,
+  digest: String,
+  #[serde(default)]
+  annotations: BTreeMap<String, String>,
+  #[serde(default)]
+  platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+struct Index {
+  manifests: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+  config: Descriptor,
+  layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerManifest {
+  config: String,
+  #[serde(default)]
+  repo_tags: Option<Vec<String>>,
+  layers: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct ImageConfig {
+  #[serde(default)]
+  architecture: String,
+  #[serde(default)]
+  config: Option<ProcessConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessConfig {
+  user: Option<String>,
+  env: Option<Vec<String>>,
+  entrypoint: Option<Vec<String>>,
+  cmd: Option<Vec<String>>,
+  working_dir: Option<String>,
+}
+
+/// Removes a directory when dropped.
+struct Staging(PathBuf);
+
+impl Drop for Staging {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.0);
+  }
+}
+
+impl LxcContainer {
+
+  /// Create the container from a local OCI image, with the dir backing store.
+  ///
+  /// # Parameters
+  /// `image` - OCI image layout directory, or tarball of one, or `docker save` archive.
+  ///
+  /// `options` - which image of the layout or archive to use.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the process of the image, else `Err` with a description of the
+  /// error. On failure the unpacked rootfs is removed again.
+  pub fn create_from_oci<P: AsRef<Path>>(&self, image: P, options: &OciOptions) -> Result<OciProcess, String> {
+    let image = image.as_ref();
+    let dir = Path::new(&self.get_config_path()).join(self.name());
+    let mut process = OciProcess::default();
+    self.create_with_rootfs(|rootfs| {
+      let _staging;
+      let layout = if image.is_dir() {
+        image.to_path_buf()
+      }
+      else {
+        _staging = Staging(dir.join("oci-image"));
+        unpack_tarball(image, &_staging.0)?;
+        _staging.0.clone()
+      };
+      let (config, layers) = read_image(&layout, options)?;
+      for layer in layers.iter() {
+        apply_layer(layer, rootfs)?;
+      }
+      process = image_process(&config, rootfs)?;
+      config_items(&config, &process)
+    })?;
+    let path = dir.join(PROCESS_FILE);
+    let json = ::serde_json::to_string_pretty(&process).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| io_error(&path, e))?;
+    Ok(process)
+  }
+
+  /// Process of the OCI image the container was created from.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the process, else `Err` if the container wasn't created by
+  /// `create_from_oci` or its process can't be read.
+  pub fn oci_process(&self) -> Result<OciProcess, String> {
+    let path = Path::new(&self.get_config_path()).join(self.name()).join(PROCESS_FILE);
+    let json = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    ::serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+  }
+
+  /// Run the process of the OCI image inside the running container and wait for it,
+  /// as its default attach command.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the exit code of the process, or `Err` if it couldn't be run
+  /// or was terminated by a signal.
+  pub fn run_oci_process(&self) -> Result<i32, &'static str> {
+    let process = self.oci_process().map_err(|_| "Container has no OCI process")?;
+    let program = match process.args.first() {
+      Some(program) => program,
+      None => return Err("OCI image has no command"),
+    };
+    self.attach_run_wait_with(&process.attach_options(), program,
+                              process.args.iter().map(|a| &a[..]).collect())
+  }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+  let json = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+  ::serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Path of blob `digest` in `layout`, after checking its content matches.
+fn blob(layout: &Path, digest: &str) -> Result<PathBuf, String> {
+  let hex = match digest.find(':') {
+    Some(i) if &digest[..i] == "sha256" => &digest[i + 1..],
+    _ => return Err(format!("Unsupported digest {}", digest)),
+  };
+  if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+    return Err(format!("Invalid digest {}", digest));
+  }
+  let path = layout.join("blobs").join("sha256").join(hex);
+  if sha256_file(&path)? != hex {
+    return Err(format!("Blob {} does not match its digest", digest));
+  }
+  Ok(path)
+}
+
+fn host_arch() -> &'static str {
+  match ::std::env::consts::ARCH {
+    "x86_64" => "amd64",
+    "x86" => "386",
+    "aarch64" => "arm64",
+    "powerpc64" => "ppc64le",
+    arch => arch,
+  }
+}
+
+fn is_index(media_type: &str) -> bool {
+  media_type == "application/vnd.oci.image.index.v1+json" ||
+  media_type == "application/vnd.docker.distribution.manifest.list.v2+json"
+}
+
+fn pick_platform<'a>(candidates: &[&'a Descriptor], arch: &str) -> Result<&'a Descriptor, String> {
+  if candidates.len() == 1 {
+    return Ok(candidates[0]);
+  }
+  if candidates.iter().all(|d| d.platform.is_none()) {
+    return Err("The image holds several manifests, choose one by reference".to_string());
+  }
+  candidates.iter()
+            .find(|d| d.platform.as_ref().is_some_and(|p| p.architecture == arch && p.os == "linux"))
+            .cloned()
+            .ok_or_else(|| format!("The image has no manifest for linux/{}", arch))
+}
+
+fn resolve_manifest(layout: &Path, descriptor: &Descriptor, arch: &str) -> Result<Manifest, String> {
+  if !is_index(&descriptor.media_type) {
+    return read_json(&blob(layout, &descriptor.digest)?);
+  }
+  let index: Index = read_json(&blob(layout, &descriptor.digest)?)?;
+  let candidates: Vec<&Descriptor> = index.manifests.iter().collect();
+  resolve_manifest(layout, pick_platform(&candidates, arch)?, arch)
+}
+
+/// Relative path from an archive, refusing anything leaving the archive.
+fn relative(dir: &Path, path: &str) -> Result<PathBuf, String> {
+  if Path::new(path).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+    Ok(dir.join(path))
+  }
+  else {
+    Err(format!("Invalid path {} in the image", path))
+  }
+}
+
+/// Configuration and layers, bottom first, of the image picked by `options`.
+fn read_image(layout: &Path, options: &OciOptions) -> Result<(ImageConfig, Vec<PathBuf>), String> {
+  let arch = options.arch.as_ref().map(|a| &a[..]).unwrap_or(host_arch());
+  if layout.join("index.json").exists() {
+    let index: Index = read_json(&layout.join("index.json"))?;
+    let candidates: Vec<&Descriptor> = index.manifests.iter().filter(|d| match options.reference {
+      Some(ref reference) => d.annotations.get(REF_NAME) == Some(reference),
+      None => true,
+    }).collect();
+    if candidates.is_empty() {
+      return Err(match options.reference {
+        Some(ref reference) => format!("The image has no manifest named {}", reference),
+        None => "The image has no manifest".to_string(),
+      });
+    }
+    let manifest = resolve_manifest(layout, pick_platform(&candidates, arch)?, arch)?;
+    let config = read_json(&blob(layout, &manifest.config.digest)?)?;
+    let layers = manifest.layers.iter().map(|l| blob(layout, &l.digest)).collect::<Result<_, _>>()?;
+    return Ok((config, layers));
+  }
+  if layout.join("manifest.json").exists() {
+    let manifests: Vec<DockerManifest> = read_json(&layout.join("manifest.json"))?;
+    let manifest = match options.reference {
+      Some(ref reference) => manifests.iter().find(|m| {
+        m.repo_tags.as_ref().is_some_and(|tags| tags.contains(reference))
+      }).ok_or_else(|| format!("The archive has no image tagged {}", reference))?,
+      None if manifests.len() == 1 => &manifests[0],
+      None => return Err("The archive holds several images, choose one by reference".to_string()),
+    };
+    let config = read_json(&relative(layout, &manifest.config)?)?;
+    let layers = manifest.layers.iter().map(|l| relative(layout, l)).collect::<Result<_, _>>()?;
+    return Ok((config, layers));
+  }
+  Err(format!("{} is neither an OCI image layout nor a docker archive", layout.display()))
+}
+
+enum Whiteout {
+  /// Hide everything lower layers put in this directory.
+  Opaque(PathBuf),
+  /// Hide this path of lower layers.
+  Remove(PathBuf),
+}
+
+fn whiteout(path: &Path) -> Option<Whiteout> {
+  let name = path.file_name()?.to_str()?;
+  let parent = path.parent().unwrap_or(Path::new(""));
+  if name == ".wh..wh..opq" {
+    Some(Whiteout::Opaque(parent.to_path_buf()))
+  }
+  else {
+    name.strip_prefix(".wh.").map(|removed| Whiteout::Remove(parent.join(removed)))
+  }
+}
+
+/// `path` of a layer inside `rootfs`, or `None` if it leaves the rootfs, or goes
+/// through a symlink before its last component.
+fn inside(rootfs: &Path, path: &Path) -> Option<PathBuf> {
+  let components: Vec<_> = path.components().filter(|c| *c != Component::CurDir && *c != Component::RootDir)
+                               .collect();
+  let mut result = rootfs.to_path_buf();
+  for (i, component) in components.iter().enumerate() {
+    match *component {
+      Component::Normal(name) => result.push(name),
+      _ => return None,
+    }
+    let last = i + 1 == components.len();
+    if !last && fs::symlink_metadata(&result).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+      return None;
+    }
+  }
+  Some(result)
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+  match fs::symlink_metadata(path) {
+    Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+    Ok(_) => fs::remove_file(path),
+    Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+/// Apply a layer: first its whiteouts, which only hide what lower layers added,
+/// then its content.
+fn apply_layer(layer: &Path, rootfs: &Path) -> Result<(), String> {
+  let error = |e: io::Error| io_error(layer, e);
+  let mut archive = open_tarball(layer)?;
+  for entry in archive.entries().map_err(error)? {
+    let entry = entry.map_err(error)?;
+    let path = entry.path().map_err(error)?.into_owned();
+    match whiteout(&path) {
+      Some(Whiteout::Opaque(dir)) => {
+        let dir = match inside(rootfs, &dir) {
+          Some(ref dir) if !fs::symlink_metadata(dir).map(|m| m.file_type().is_symlink()).unwrap_or(false) => dir.clone(),
+          _ => continue,
+        };
+        if let Ok(children) = fs::read_dir(&dir) {
+          for child in children {
+            remove_path(&child.map_err(error)?.path()).map_err(error)?;
+          }
+        }
+      }
+      Some(Whiteout::Remove(target)) => {
+        if let Some(target) = inside(rootfs, &target) {
+          remove_path(&target).map_err(error)?;
+        }
+      }
+      None => (),
+    }
+  }
+
+  let mut archive = open_tarball(layer)?;
+  for entry in archive.entries().map_err(error)? {
+    let mut entry = entry.map_err(error)?;
+    let path = entry.path().map_err(error)?.into_owned();
+    if whiteout(&path).is_some() {
+      continue;
+    }
+    // An entry replaces whatever lower layers have at its path, unless both are directories.
+    if let Some(target) = inside(rootfs, &path) {
+      let is_dir = entry.header().entry_type().is_dir();
+      match fs::symlink_metadata(&target) {
+        Ok(ref metadata) if metadata.is_dir() != is_dir || metadata.file_type().is_symlink() => {
+          remove_path(&target).map_err(error)?;
+        }
+        _ => (),
+      }
+    }
+    entry.unpack_in(rootfs).map_err(error)?;
+  }
+  Ok(())
+}
+
+/// Look `name` up in the first column of a passwd or group file of the rootfs.
+fn lookup(rootfs: &Path, file: &str, name: &str) -> Option<Vec<String>> {
+  let path = rootfs.join("etc").join(file);
+  // Don't follow links out of the rootfs.
+  if !fs::symlink_metadata(&path).map(|m| m.is_file()).unwrap_or(false) {
+    return None;
+  }
+  let content = fs::read_to_string(&path).ok()?;
+  content.lines()
+         .map(|line| line.split(':').map(|f| f.to_string()).collect::<Vec<_>>())
+         .find(|fields| fields.len() >= 4 && fields[0] == name)
+}
+
+fn passwd_by_uid(rootfs: &Path, uid: u32) -> Option<u32> {
+  let path = rootfs.join("etc/passwd");
+  if !fs::symlink_metadata(&path).map(|m| m.is_file()).unwrap_or(false) {
+    return None;
+  }
+  fs::read_to_string(&path).ok()?.lines()
+    .map(|line| line.split(':').collect::<Vec<_>>())
+    .find(|fields| fields.len() >= 4 && fields[2].parse() == Ok(uid))
+    .and_then(|fields| fields[3].parse().ok())
+}
+
+/// User and group ids of an image `User`: "user", "uid", "user:group" or "uid:gid".
+fn resolve_user(rootfs: &Path, user: &str) -> Result<(u32, u32), String> {
+  if user.is_empty() {
+    return Ok((0, 0));
+  }
+  let (user, group) = match user.find(':') {
+    Some(i) => (&user[..i], Some(&user[i + 1..])),
+    None => (user, None),
+  };
+  let (uid, primary_gid) = match user.parse::<u32>() {
+    Ok(uid) => (uid, passwd_by_uid(rootfs, uid).unwrap_or(0)),
+    Err(_) => {
+      let fields = lookup(rootfs, "passwd", user).ok_or_else(|| format!("User {} not found in the image", user))?;
+      match (fields[2].parse(), fields[3].parse()) {
+        (Ok(uid), Ok(gid)) => (uid, gid),
+        _ => return Err(format!("Invalid passwd entry for {}", user)),
+      }
+    }
+  };
+  let gid = match group {
+    None => primary_gid,
+    Some(group) => match group.parse::<u32>() {
+      Ok(gid) => gid,
+      Err(_) => {
+        let fields = lookup(rootfs, "group", group).ok_or_else(|| format!("Group {} not found in the image", group))?;
+        fields[2].parse().map_err(|_| format!("Invalid group entry for {}", group))?
+      }
+    },
+  };
+  Ok((uid, gid))
+}
+
+fn image_process(config: &ImageConfig, rootfs: &Path) -> Result<OciProcess, String> {
+  let empty = ProcessConfig::default();
+  let process = config.config.as_ref().unwrap_or(&empty);
+  let mut args = process.entrypoint.clone().unwrap_or_default();
+  args.extend(process.cmd.clone().unwrap_or_default());
+  let mut env = process.env.clone().unwrap_or_default();
+  if !env.iter().any(|var| var.starts_with("PATH=")) {
+    env.insert(0, DEFAULT_PATH.to_string());
+  }
+  let cwd = match process.working_dir {
+    Some(ref dir) if !dir.is_empty() => dir.clone(),
+    _ => "/".to_string(),
+  };
+  let (uid, gid) = resolve_user(rootfs, process.user.as_ref().map(|u| &u[..]).unwrap_or(""))?;
+  Ok(OciProcess { args, env, cwd, uid, gid })
+}
+
+fn config_items(config: &ImageConfig, process: &OciProcess) -> Result<Vec<(String, String)>, String> {
+  let mut items = Vec::new();
+  if !config.architecture.is_empty() {
+    items.push(("lxc.arch".to_string(), config.architecture.clone()));
+  }
+  for var in process.env.iter() {
+    items.push(("lxc.environment".to_string(), var.clone()));
+  }
+  if let Some(arg) = process.args.iter().find(|arg| arg.is_empty() || arg.contains(char::is_whitespace)) {
+    return Err(format!("Argument {:?} of the image's command can't be passed to lxc.init.cmd", arg));
+  }
+  if !process.args.is_empty() {
+    items.push((config_key("lxc.init.cmd").to_string(), process.args.join(" ")));
+  }
+  if has_key("lxc.init.cwd") {
+    items.push(("lxc.init.cwd".to_string(), process.cwd.clone()));
+  }
+  if process.uid != 0 {
+    items.push((config_key("lxc.init.uid").to_string(), process.uid.to_string()));
+  }
+  if process.gid != 0 {
+    items.push((config_key("lxc.init.gid").to_string(), process.gid.to_string()));
+  }
+  Ok(items)
+}
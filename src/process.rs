@@ -0,0 +1,272 @@
+//! Processes of running containers, found through the cgroup of their init.
+//!
+//! `LxcContainer::processes` lists every process in the cgroup of the container's
+//! init process and the cgroups below it, with details read from `/proc`, like
+//! `ps` inside the container would. `LxcContainer::signal` sends signals to them.
+//!
+//! ```no_run
+//! use liblxc::*;
+//! let c = LxcContainer::new("web", None).unwrap();
+//! for p in c.processes().unwrap() {
+//!   println!("{:>6} {:>6} {} {:>8} {}", p.container_pid, p.uid, p.state, p.rss / 1024, p.cmdline.join(" "));
+//! }
+//! c.signal(SignalTarget::Pid(42), 15).unwrap();
+//! ```
+
+use crate::ffi::sys::kill;
+use libc;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::LxcContainer;
+
+/// A process of a container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerProcess {
+  /// PID in the host's PID namespace.
+  pub pid: i32,
+  /// PID in the container's PID namespace.
+  pub container_pid: i32,
+  /// Real user id in the container, mapped back through its user namespace.
+  pub uid: u32,
+  /// Real user id on the host.
+  pub host_uid: u32,
+  /// Name of the executable, as in `/proc/PID/comm`.
+  pub name: String,
+  /// Arguments, empty for zombies.
+  pub cmdline: Vec<String>,
+  /// State as shown by `ps`, like 'R' for running, 'S' for sleeping or 'Z' for zombie.
+  pub state: char,
+  /// Resident memory in bytes.
+  pub rss: u64,
+}
+
+/// Processes a signal is sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignalTarget {
+  /// Every process of the container.
+  All,
+  /// The process with this PID in the container's PID namespace.
+  Pid(i32),
+}
+
+/// Signals known by name to `signal_number`, without the SIG prefix.
+const SIGNALS: [(&str, i32); 13] = [
+  ("HUP", 1), ("INT", 2), ("QUIT", 3), ("KILL", 9), ("USR1", 10), ("USR2", 12), ("PIPE", 13),
+  ("ALRM", 14), ("TERM", 15), ("CHLD", 17), ("CONT", 18), ("STOP", 19), ("PWR", 30),
+];
+
+/// Number of signal `signal`, given as a number or a name like TERM or SIGTERM.
+///
+/// # Return value
+/// Returns `Some` with the signal number, or `None` if `signal` isn't known.
+pub fn signal_number(signal: &str) -> Option<i32> {
+  if let Ok(number) = signal.parse() {
+    return if number > 0 && number < 65 { Some(number) } else { None };
+  }
+  let name = signal.trim_start_matches("SIG");
+  SIGNALS.iter().find(|&&(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, number)| number)
+}
+
+/// Mount point of the cgroup hierarchy holding `controllers` ("" for cgroup2),
+/// with the cgroup path it shows as its root.
+pub(crate) fn cgroup_mount(controllers: &str) -> io::Result<Option<(PathBuf, String)>> {
+  let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+  for line in mountinfo.lines() {
+    // ID PARENT MAJOR:MINOR ROOT MOUNTPOINT OPTIONS [OPTIONAL...] - FSTYPE SOURCE SUPEROPTIONS
+    let fields: Vec<&str> = line.split(' ').collect();
+    let separator = match fields.iter().position(|f| *f == "-") {
+      Some(separator) if fields.len() > separator + 3 && fields.len() > 5 => separator,
+      _ => continue,
+    };
+    let fstype = fields[separator + 1];
+    let matches = if controllers.is_empty() {
+      fstype == "cgroup2"
+    }
+    else {
+      let options: Vec<&str> = fields[separator + 3].split(',').collect();
+      fstype == "cgroup" && controllers.split(',').all(|c| options.contains(&c))
+    };
+    if matches {
+      return Ok(Some((PathBuf::from(unescape_mountinfo(fields[4])), unescape_mountinfo(fields[3]))));
+    }
+  }
+  Ok(None)
+}
+
+/// Decodes the octal escapes of mountinfo fields, like `\040` for a space.
+fn unescape_mountinfo(field: &str) -> String {
+  let mut decoded = String::new();
+  let mut rest = field;
+  while let Some(i) = rest.find('\\') {
+    decoded.push_str(&rest[..i]);
+    match rest.get(i + 1..i + 4).and_then(|o| u8::from_str_radix(o, 8).ok()) {
+      Some(byte) => {
+        decoded.push(byte as char);
+        rest = &rest[i + 4..];
+      }
+      None => {
+        decoded.push('\\');
+        rest = &rest[i + 1..];
+      }
+    }
+  }
+  decoded.push_str(rest);
+  decoded
+}
+
+/// Directory of the cgroup of process `pid`, in the first hierarchy that is mounted
+/// and lists it: cgroup2 first, then the cgroup v1 hierarchies.
+fn cgroup_dir(pid: i32) -> Result<PathBuf, String> {
+  let cgroups = fs::read_to_string(format!("/proc/{}/cgroup", pid))
+                  .map_err(|e| format!("Couldn't read the cgroups of process {}: {}", pid, e))?;
+  // ID:CONTROLLERS:PATH, with ID 0 and no controllers for cgroup2.
+  let mut entries: Vec<(&str, &str)> = cgroups.lines().filter_map(|line| {
+    let mut fields = line.splitn(3, ':');
+    match (fields.next(), fields.next(), fields.next()) {
+      (Some(_), Some(controllers), Some(path)) => Some((controllers, path)),
+      _ => None,
+    }
+  }).collect();
+  entries.sort_by_key(|&(controllers, _)| !controllers.is_empty());
+  for (controllers, path) in entries {
+    let (mount_point, root) = match cgroup_mount(controllers).map_err(|e| e.to_string())? {
+      Some(mount) => mount,
+      None => continue,
+    };
+    let relative = if root == "/" { path } else { path.strip_prefix(&root[..]).unwrap_or(path) };
+    let dir = mount_point.join(relative.trim_start_matches('/'));
+    if read_procs(&dir).map(|procs| procs.contains(&pid)).unwrap_or(false) {
+      return Ok(dir);
+    }
+  }
+  Err(format!("Couldn't find the cgroup of process {}", pid))
+}
+
+fn read_procs(dir: &Path) -> io::Result<Vec<i32>> {
+  Ok(fs::read_to_string(dir.join("cgroup.procs"))?.lines().filter_map(|pid| pid.trim().parse().ok()).collect())
+}
+
+/// Adds the processes of cgroup `dir` and its descendants to `pids`.
+fn collect_procs(dir: &Path, pids: &mut BTreeSet<i32>) -> io::Result<()> {
+  pids.extend(read_procs(dir)?);
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    if entry.file_type()?.is_dir() {
+      // Cgroups may disappear while they are walked.
+      match collect_procs(&entry.path(), pids) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        result => result?,
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Reads process `pid` from `/proc`, or `None` if it's gone or not in the PID
+/// namespace at depth `level` (0 for the host's) or below.
+fn read_process(pid: i32, level: usize) -> Option<ContainerProcess> {
+  let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+  let field = |name: &str| {
+    status.lines()
+          .find(|line| line.starts_with(name) && line[name.len()..].starts_with(':'))
+          .map(|line| line[name.len() + 1..].trim())
+  };
+  let host_uid: u32 = field("Uid")?.split_whitespace().next()?.parse().ok()?;
+  let container_pid = match field("NSpid") {
+    Some(pids) => pids.split_whitespace().nth(level)?.parse().ok()?,
+    None => pid,
+  };
+  let rss = field("VmRSS").and_then(|rss| rss.split_whitespace().next())
+                          .and_then(|kb| kb.parse::<u64>().ok()).unwrap_or(0) * 1024;
+  let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+  let cmdline = cmdline.split(|b| *b == 0).filter(|arg| !arg.is_empty())
+                       .map(|arg| String::from_utf8_lossy(arg).into_owned()).collect();
+  Some(ContainerProcess {
+    pid,
+    container_pid,
+    uid: container_uid(pid, host_uid),
+    host_uid,
+    name: field("Name").unwrap_or("").to_string(),
+    cmdline,
+    state: field("State").and_then(|state| state.chars().next()).unwrap_or('?'),
+    rss,
+  })
+}
+
+/// Maps host user id `uid` into the user namespace of process `pid`, like the kernel
+/// shows it there; unmapped ids become the overflow id 65534.
+fn container_uid(pid: i32, uid: u32) -> u32 {
+  let map = match fs::read_to_string(format!("/proc/{}/uid_map", pid)) {
+    Ok(map) => map,
+    Err(_) => return uid,
+  };
+  for line in map.lines() {
+    // INSIDE OUTSIDE COUNT
+    let range: Vec<u64> = line.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+    if range.len() == 3 && uid as u64 >= range[1] && (uid as u64) < range[1] + range[2] {
+      return (range[0] + (uid as u64 - range[1])) as u32;
+    }
+  }
+  65534
+}
+
+impl LxcContainer {
+
+  /// List the processes of the running container.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the processes ordered by host PID, or `Err` if the container
+  /// isn't running or its cgroup can't be found. Processes of the cgroup outside the
+  /// PID namespace of the container are left out.
+  ///
+  /// ## Note
+  /// Processes starting or exiting while the list is read may be missing.
+  pub fn processes(&self) -> Result<Vec<ContainerProcess>, String> {
+    let pid = self.init_pid();
+    if pid <= 0 {
+      return Err(format!("Container {} isn't running", self.name()));
+    }
+    let dir = cgroup_dir(pid)?;
+    let mut pids = BTreeSet::new();
+    collect_procs(&dir, &mut pids).map_err(|e| format!("Couldn't read {}: {}", dir.display(), e))?;
+    // Processes outside the PID namespace of init, if any, aren't visible in the container.
+    let level = fs::read_to_string(format!("/proc/{}/status", pid)).ok()
+                  .and_then(|status| status.lines().find(|line| line.starts_with("NSpid:"))
+                                               .map(|line| line.split_whitespace().count() - 2))
+                  .unwrap_or(0);
+    Ok(pids.into_iter().filter_map(|pid| read_process(pid, level)).collect())
+  }
+
+  /// Send signal `signal`, like 15 for SIGTERM, to processes of the running container.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the number of processes signalled, or `Err` if the container
+  /// isn't running, has no process `target`, or a signal couldn't be sent.
+  ///
+  /// ## Note
+  /// A process could exit and its PID be reused between listing and signalling.
+  pub fn signal(&self, target: SignalTarget, signal: i32) -> Result<usize, String> {
+    let processes = self.processes()?;
+    let targets: Vec<&ContainerProcess> = match target {
+      SignalTarget::All => processes.iter().collect(),
+      SignalTarget::Pid(pid) => processes.iter().filter(|p| p.container_pid == pid).collect(),
+    };
+    if let SignalTarget::Pid(pid) = target {
+      if targets.is_empty() {
+        return Err(format!("Container {} has no process {}", self.name(), pid));
+      }
+    }
+    for process in &targets {
+      if unsafe { kill(process.pid, signal) } != 0 {
+        let error = io::Error::last_os_error();
+        // It may have exited in the meantime.
+        if error.raw_os_error() != Some(libc::ESRCH) {
+          return Err(format!("Couldn't signal process {}: {}", process.container_pid, error));
+        }
+      }
+    }
+    Ok(targets.len())
+  }
+}
@@ -0,0 +1,52 @@
+//! Paths inside the root filesystems of containers, seen from the host.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Maximum number of symlinks followed when resolving a path in a rootfs, as in Linux.
+const MAX_SYMLINKS: u32 = 40;
+
+/// Resolves absolute container path `path` to a host path inside `rootfs`, following
+/// symlinks as if `rootfs` was `/`. The last component is followed only if `follow_last`.
+pub(crate) fn resolve(rootfs: &Path, path: &str, follow_last: bool) -> Result<PathBuf, String> {
+  if !path.starts_with('/') {
+    return Err(format!("Path {} in the container isn't absolute", path));
+  }
+  let mut resolved = PathBuf::new();
+  let mut pending: Vec<OsString> = components(Path::new(path));
+  let mut symlinks = 0;
+  while let Some(name) = pending.pop() {
+    if name == ".." {
+      resolved.pop();
+      continue;
+    }
+    let candidate = resolved.join(&name);
+    let host_path = rootfs.join(&candidate);
+    let is_symlink = fs::symlink_metadata(&host_path).map(|m| m.file_type().is_symlink()).unwrap_or(false);
+    if is_symlink && (follow_last || !pending.is_empty()) {
+      symlinks += 1;
+      if symlinks > MAX_SYMLINKS {
+        return Err(format!("Too many levels of symbolic links in {}", path));
+      }
+      let target = fs::read_link(&host_path).map_err(|e| format!("Couldn't resolve {}: {}", path, e))?;
+      if target.is_absolute() {
+        resolved = PathBuf::new();
+      }
+      pending.extend(components(&target));
+    }
+    else {
+      resolved = candidate;
+    }
+  }
+  Ok(rootfs.join(resolved))
+}
+
+/// Names in `path`, last first, with ".." kept and "." dropped.
+fn components(path: &Path) -> Vec<OsString> {
+  path.components().rev().filter_map(|component| match component {
+    Component::Normal(name) => Some(name.to_os_string()),
+    Component::ParentDir => Some(OsString::from("..")),
+    _ => None,
+  }).collect()
+}
@@ -0,0 +1,109 @@
+//! Thread-safety model.
+//!
+//! `LxcContainer` is `Send` but not `Sync`: a handle may be moved to another thread,
+//! but it must not be used from two threads at the same time. liblxc reference counts
+//! `struct lxc_container` under its own locks, so moving and dropping handles is safe.
+//!
+//! liblxc guards the in-memory state of a container with `container_mem_lock` and
+//! its on-disk state, also against other processes, with `container_disk_lock`. It
+//! takes them itself in most calls; `config_file_name`, `get_config_path` and
+//! `clear_config` touch the struct without them, so the wrappers of those take the
+//! memory lock. The locks aren't recursive, so callers must not hold
+//! `LxcContainer::mem_lock` or `LxcContainer::disk_lock` across calls on the same handle.
+//!
+//! To share one container between threads wrap it in `SharedLxcContainer`, which
+//! serializes calls per container. All `SharedLxcContainer`s of a container in the
+//! process use one `struct lxc_container`, so its liblxc locks cover every thread.
+//!
+//! liblxc's `process_lock` is taken by liblxc around each `fork` in the process
+//! through `pthread_atfork`, so it can't be held across calls that fork. Instead,
+//! daemonized `start` and the fork of `attach` run under a process-wide fork lock:
+//! descriptors opened by one of them can't leak into the other one's child. `create`
+//! and `clone` only fork to `exec`, which closes descriptors opened close-on-exec,
+//! so they don't take it.
+
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "liblxc")]
+use std::sync::{Arc, Weak};
+#[cfg(feature = "liblxc")]
+use super::{BDevSpecs, LxcContainer};
+
+#[cfg(feature = "liblxc")]
+unsafe impl Send for LxcContainer {}
+#[cfg(feature = "liblxc")]
+unsafe impl Send for BDevSpecs {}
+
+static FORK_LOCK: Mutex<()> = Mutex::new(());
+
+/// Live `SharedLxcContainer` of an lxcpath and name.
+#[cfg(feature = "liblxc")]
+type SharedEntry = (String, String, Weak<Mutex<LxcContainer>>);
+
+/// Live `SharedLxcContainer`s by lxcpath and name.
+#[cfg(feature = "liblxc")]
+static SHARED: Mutex<Vec<SharedEntry>> = Mutex::new(Vec::new());
+
+/// Held process-wide fork lock, see `fork_guard`.
+pub struct ForkGuard {
+  _guard: MutexGuard<'static, ()>
+}
+
+/// Acquire the process-wide lock taken by liblxc operations that fork.
+///
+/// Hold it around your own `fork` calls if they must not interleave with
+/// container startup or attaching.
+pub fn fork_guard() -> ForkGuard {
+  ForkGuard { _guard: FORK_LOCK.lock().unwrap_or_else(|e| e.into_inner()) }
+}
+
+/// Container handle that can be cloned and used from several threads.
+///
+/// Calls through `lock` are serialized, so at most one thread uses the underlying
+/// `LxcContainer` at a time. Clones, and other `SharedLxcContainer`s made for the
+/// same container, refer to the same handle and the same lock.
+#[cfg(feature = "liblxc")]
+pub struct SharedLxcContainer {
+  inner: Arc<Mutex<LxcContainer>>
+}
+
+#[cfg(feature = "liblxc")]
+impl SharedLxcContainer {
+
+  /// Wraps `container` for use from several threads.
+  ///
+  /// ## Note
+  /// If a `SharedLxcContainer` of the container with the same name and lxcpath
+  /// exists, it is returned and `container` is dropped, along with any
+  /// configuration it loaded.
+  pub fn new(container: LxcContainer) -> SharedLxcContainer {
+    let config_path = container.get_config_path();
+    let name = container.name();
+    let mut shared = SHARED.lock().unwrap_or_else(|e| e.into_inner());
+    shared.retain(|(_, _, inner)| inner.strong_count() > 0);
+    let existing = shared.iter()
+                         .find(|(p, n, _)| *p == config_path && *n == name)
+                         .and_then(|(_, _, inner)| inner.upgrade());
+    if let Some(inner) = existing {
+      return SharedLxcContainer { inner };
+    }
+    let inner = Arc::new(Mutex::new(container));
+    shared.push((config_path, name, Arc::downgrade(&inner)));
+    SharedLxcContainer { inner }
+  }
+
+  /// Obtain exclusive access to the container until the guard is dropped.
+  ///
+  /// ## Note
+  /// A panic in another thread holding the guard does not poison the container:
+  /// liblxc keeps its own state consistent and the handle stays usable.
+  pub fn lock(&self) -> MutexGuard<'_, LxcContainer> {
+    self.inner.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+#[cfg(feature = "liblxc")]
+impl Clone for SharedLxcContainer {
+  fn clone(&self) -> SharedLxcContainer {
+    SharedLxcContainer { inner: self.inner.clone() }
+  }
+}
@@ -0,0 +1,363 @@
+//! Snapshots taken on a schedule or before operations, pruned by retention rules.
+//!
+//! Snapshots taken through this module are tagged in their comment file, whose
+//! first line reads `snapshot-tag: TAG`: "scheduled" for the ones a
+//! `SnapshotScheduler` takes, `before:OPERATION` for the ones
+//! `SnapshotPolicy::before` takes. Retention rules apply to each tag separately,
+//! and snapshots without a tag, such as the ones taken by hand, are never pruned.
+//!
+//! The rules work like those of most backup tools: a snapshot is kept if it is one
+//! of the last `keep_last` snapshots, or the newest snapshot of one of the last
+//! `hourly` hours, `daily` days or `weekly` weeks that have snapshots. Hours, days
+//! and weeks (starting on Monday) are those of the snapshot timestamps, in the
+//! host's local time.
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+use super::{LxcContainer, LxcSnapshot};
+
+/// Prefix of the first line of the comment file of a tagged snapshot.
+pub const TAG_PREFIX: &str = "snapshot-tag: ";
+
+/// Tag of the snapshots taken by a `SnapshotScheduler`.
+pub const SCHEDULED_TAG: &str = "scheduled";
+
+/// Which snapshots of a tag to keep. A retention without rules keeps everything.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+  /// Number of most recent snapshots to keep.
+  pub keep_last: u32,
+  /// Number of most recent hours to keep the newest snapshot of.
+  pub hourly: u32,
+  /// Number of most recent days to keep the newest snapshot of.
+  pub daily: u32,
+  /// Number of most recent weeks to keep the newest snapshot of.
+  pub weekly: u32,
+}
+
+impl Retention {
+  /// Whether the retention has no rules.
+  pub fn is_empty(&self) -> bool {
+    self.keep_last == 0 && self.hourly == 0 && self.daily == 0 && self.weekly == 0
+  }
+}
+
+/// What pruning decided for one snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PruneDecision {
+  /// Name of the snapshot, for example "snap0".
+  pub name: String,
+  /// Tag of the snapshot.
+  pub tag: String,
+  /// Time the snapshot was created, formatted as "YYYY:MM:DD HH:MM:SS".
+  pub timestamp: String,
+  /// Rules keeping the snapshot: "last", "hourly", "daily", "weekly", "timestamp"
+  /// if its timestamp can't be read, or "retention" if the retention has no rules.
+  /// Empty if it is pruned.
+  pub reasons: Vec<&'static str>,
+}
+
+impl PruneDecision {
+  /// Whether the snapshot is kept.
+  pub fn is_kept(&self) -> bool {
+    !self.reasons.is_empty()
+  }
+}
+
+/// Outcome of pruning the snapshots of a container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PruneReport {
+  /// Decisions for the tagged snapshots, newest first within each tag.
+  pub decisions: Vec<PruneDecision>,
+  /// Whether this was a dry run, which destroyed nothing.
+  pub dry_run: bool,
+}
+
+impl PruneReport {
+  /// Names of the snapshots destroyed, or that would be in a dry run.
+  pub fn pruned(&self) -> Vec<&str> {
+    self.decisions.iter().filter(|d| !d.is_kept()).map(|d| &d.name[..]).collect()
+  }
+
+  /// Names of the tagged snapshots kept.
+  pub fn kept(&self) -> Vec<&str> {
+    self.decisions.iter().filter(|d| d.is_kept()).map(|d| &d.name[..]).collect()
+  }
+}
+
+/// A snapshot taken by a policy, and the pruning that followed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicySnapshot {
+  /// Name of the new snapshot, for example "snap3".
+  pub name: String,
+  /// Outcome of pruning after the snapshot.
+  pub prune: PruneReport,
+}
+
+/// Tag of `snapshot`, read from its comment file.
+pub fn snapshot_tag(snapshot: &LxcSnapshot) -> Option<String> {
+  let content = fs::read_to_string(snapshot.comment_pathname.as_ref()?).ok()?;
+  let first = content.lines().next()?;
+  if first.starts_with(TAG_PREFIX) && first.len() > TAG_PREFIX.len() {
+    Some(first[TAG_PREFIX.len()..].to_string())
+  }
+  else {
+    None
+  }
+}
+
+fn check_tag(tag: &str) -> Result<(), &'static str> {
+  if tag.is_empty() || tag.chars().any(|c| c.is_whitespace() || c.is_control()) {
+    return Err("Invalid snapshot tag");
+  }
+  Ok(())
+}
+
+impl LxcContainer {
+
+  /// Snapshot the container, tagging the snapshot in its comment file.
+  ///
+  /// # Parameters
+  /// `tag` - tag of the snapshot, without whitespace.
+  ///
+  /// `comment_option` - `Option` with a description added below the tag.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the number of the snapshot (snapshot `N` is named `snapN`) or
+  /// `Err` in case of error.
+  pub fn snapshot_tagged(&self, tag: &str, comment_option: Option<&str>) -> Result<i32, &'static str> {
+    check_tag(tag)?;
+    let commentfile = env::temp_dir().join(format!("rust-lxc-snapshot-{}-{}", self.name(), process::id()));
+    let mut content = format!("{}{}\n", TAG_PREFIX, tag);
+    if let Some(comment) = comment_option {
+      content.push_str(comment);
+      if !comment.ends_with('\n') {
+        content.push('\n');
+      }
+    }
+    fs::write(&commentfile, content).map_err(|_| "Failed to write the snapshot comment")?;
+    let result = self.snapshot(Some(&commentfile.to_string_lossy()));
+    let _ = fs::remove_file(&commentfile);
+    result
+  }
+}
+
+/// How a container is snapshotted and how many snapshots are kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotPolicy {
+  /// Time between scheduled snapshots, `None` to only snapshot before operations.
+  pub interval: Option<Duration>,
+  /// Which snapshots to keep of each tag.
+  pub retention: Retention,
+  /// Stop a running container for the snapshot and start it again, as liblxc
+  /// only snapshots stopped containers.
+  pub stop_running: bool,
+}
+
+impl Default for SnapshotPolicy {
+  /// A snapshot a day, keeping a week of daily snapshots and four weekly ones.
+  fn default() -> SnapshotPolicy {
+    SnapshotPolicy {
+      interval: Some(Duration::from_secs(24 * 3600)),
+      retention: Retention { keep_last: 1, hourly: 0, daily: 7, weekly: 4 },
+      stop_running: false,
+    }
+  }
+}
+
+impl SnapshotPolicy {
+
+  /// Snapshot `container` before operation `operation`, tagged `before:OPERATION`,
+  /// then prune the snapshots of that tag.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the snapshot taken, else `Err` if it couldn't be taken or the
+  /// pruning failed.
+  pub fn before(&self, container: &LxcContainer, operation: &str) -> Result<PolicySnapshot, &'static str> {
+    self.take(container, &format!("before:{}", operation))
+  }
+
+  /// Snapshot `container` with tag `tag`, then prune the snapshots of that tag.
+  fn take(&self, container: &LxcContainer, tag: &str) -> Result<PolicySnapshot, &'static str> {
+    check_tag(tag)?;
+    let running = container.is_running();
+    if running {
+      if !self.stop_running {
+        return Err("Container is running");
+      }
+      if !container.stop() {
+        return Err("Failed to stop the container");
+      }
+    }
+    let result = container.snapshot_tagged(tag, None);
+    if running && !container.start(0, None) {
+      return Err("Failed to start the container again");
+    }
+    let index = result?;
+    let prune = self.prune_tag(container, Some(tag), false)?;
+    Ok(PolicySnapshot { name: format!("snap{}", index), prune })
+  }
+
+  /// Destroy the tagged snapshots of `container` the retention doesn't keep.
+  ///
+  /// # Parameters
+  /// `dry_run` - only report what would be destroyed.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the decision for each tagged snapshot, else `Err` if the
+  /// snapshots couldn't be listed or destroyed.
+  pub fn prune(&self, container: &LxcContainer, dry_run: bool) -> Result<PruneReport, &'static str> {
+    self.prune_tag(container, None, dry_run)
+  }
+
+  fn prune_tag(&self, container: &LxcContainer, tag_option: Option<&str>, dry_run: bool) -> Result<PruneReport, &'static str> {
+    let mut tagged: Vec<(String, LxcSnapshot)> = Vec::new();
+    for snapshot in container.snapshot_list()? {
+      if let Some(tag) = snapshot_tag(&snapshot) {
+        if tag_option.is_none_or(|t| t == tag) {
+          tagged.push((tag, snapshot));
+        }
+      }
+    }
+    let mut tags: Vec<String> = tagged.iter().map(|t| t.0.clone()).collect();
+    tags.sort();
+    tags.dedup();
+
+    let mut decisions = Vec::new();
+    for tag in tags.iter() {
+      let snapshots: Vec<&LxcSnapshot> = tagged.iter().filter(|t| t.0 == *tag).map(|t| &t.1).collect();
+      decisions.extend(decide(tag, &snapshots, &self.retention));
+    }
+    if !dry_run {
+      for decision in decisions.iter().filter(|d| !d.is_kept()) {
+        if !container.snapshot_destroy(&decision.name) {
+          return Err("Failed to destroy a snapshot");
+        }
+      }
+    }
+    Ok(PruneReport { decisions, dry_run })
+  }
+}
+
+/// Days since 1970-01-01 and seconds since midnight of a "YYYY:MM:DD HH:MM:SS" timestamp.
+fn parse_timestamp(timestamp: &str) -> Option<(i64, i64)> {
+  let numbers: Vec<i64> = timestamp.split([':', ' ']).map(|n| n.parse().ok()).collect::<Option<_>>()?;
+  if numbers.len() != 6 {
+    return None;
+  }
+  let (year, month, day) = (numbers[0], numbers[1], numbers[2]);
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+    return None;
+  }
+  // Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+  let y = if month <= 2 { year - 1 } else { year };
+  let era = if y >= 0 { y } else { y - 399 } / 400;
+  let yoe = y - era * 400;
+  let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  Some((era * 146097 + doe - 719468, numbers[3] * 3600 + numbers[4] * 60 + numbers[5]))
+}
+
+fn snapshot_index(snapshot: &LxcSnapshot) -> i64 {
+  if snapshot.name.starts_with("snap") { snapshot.name[4..].parse().unwrap_or(-1) } else { -1 }
+}
+
+/// Retention bucket: its name, how many to keep, and the period of a timestamp.
+type Bucket = (&'static str, u32, fn(i64, i64) -> i64);
+
+/// Which of the snapshots of one tag `retention` keeps.
+fn decide(tag: &str, snapshots: &[&LxcSnapshot], retention: &Retention) -> Vec<PruneDecision> {
+  let mut snapshots: Vec<(Option<(i64, i64)>, &LxcSnapshot)> =
+    snapshots.iter().map(|s| (parse_timestamp(&s.timestamp), *s)).collect();
+  // Newest first; snapshots taken within the same second by number.
+  snapshots.sort_by(|a, b| b.0.cmp(&a.0).then(snapshot_index(b.1).cmp(&snapshot_index(a.1))));
+
+  let mut reasons: Vec<Vec<&'static str>> = vec![Vec::new(); snapshots.len()];
+  for i in 0..snapshots.len() {
+    if retention.is_empty() {
+      reasons[i].push("retention");
+    }
+    else if snapshots[i].0.is_none() {
+      reasons[i].push("timestamp");
+    }
+    else if (i as u32) < retention.keep_last {
+      reasons[i].push("last");
+    }
+  }
+  let buckets: [Bucket; 3] = [
+    ("hourly", retention.hourly, |days, secs| days * 24 + secs / 3600),
+    ("daily", retention.daily, |days, _| days),
+    // 1970-01-01 was a Thursday, so weeks start on Monday.
+    ("weekly", retention.weekly, |days, _| (days + 3).div_euclid(7)),
+  ];
+  for &(reason, count, bucket) in buckets.iter() {
+    let mut last_bucket = None;
+    let mut used = 0;
+    for (i, &(time, _)) in snapshots.iter().enumerate() {
+      if used >= count {
+        break;
+      }
+      if let Some((days, secs)) = time {
+        let current = bucket(days, secs);
+        if last_bucket != Some(current) {
+          last_bucket = Some(current);
+          used += 1;
+          reasons[i].push(reason);
+        }
+      }
+    }
+  }
+
+  snapshots.iter().zip(reasons).map(|(&(_, snapshot), reasons)| PruneDecision {
+    name: snapshot.name.clone(),
+    tag: tag.to_string(),
+    timestamp: snapshot.timestamp.clone(),
+    reasons,
+  }).collect()
+}
+
+/// Takes the scheduled snapshots of one container according to a `SnapshotPolicy`.
+pub struct SnapshotScheduler {
+  policy: SnapshotPolicy,
+  next_snapshot: Option<Instant>,
+}
+
+impl SnapshotScheduler {
+
+  /// Creates a scheduler for `policy`, which snapshots at its first poll.
+  pub fn new(policy: SnapshotPolicy) -> SnapshotScheduler {
+    SnapshotScheduler { policy, next_snapshot: None }
+  }
+
+  /// The policy this scheduler follows.
+  pub fn policy(&self) -> &SnapshotPolicy {
+    &self.policy
+  }
+
+  /// When the next scheduled snapshot is due, `None` before the first poll or if
+  /// the policy has no interval.
+  pub fn next_snapshot(&self) -> Option<Instant> {
+    self.next_snapshot
+  }
+
+  /// Snapshot `container` if a scheduled snapshot is due, then prune the
+  /// scheduled snapshots.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the snapshot taken, `Ok(None)` if none was due, or `Err` if
+  /// it failed. A failed snapshot is retried at the next interval.
+  pub fn poll(&mut self, container: &LxcContainer) -> Result<Option<PolicySnapshot>, &'static str> {
+    let interval = match self.policy.interval {
+      Some(interval) => interval,
+      None => return Ok(None),
+    };
+    let now = Instant::now();
+    if self.next_snapshot.is_some_and(|due| due > now) {
+      return Ok(None);
+    }
+    self.next_snapshot = Some(now + interval);
+    self.policy.take(container, SCHEDULED_TAG).map(Some)
+  }
+}
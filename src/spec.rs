@@ -0,0 +1,365 @@
+//! Declarative container specifications, enabled with the `spec` feature.
+//!
+//! A `ContainerSpec`, usually loaded from a TOML or YAML file, describes how a
+//! container should look. `plan` compares it with an existing container and lists
+//! the operations needed to get there; `apply` performs them.
+//!
+//! Only what a spec mentions is managed: keys missing from `limits` and `config`
+//! are left alone, and `network`, `mounts` and `id_maps` replace the container's
+//! entries only when present. `template`, `template_args` and `bdev` are used when
+//! the container has to be created and ignored afterwards.
+//!
+//! ```toml
+//! name = "web"
+//! template = "download"
+//! template_args = ["-d", "alpine", "-r", "3.4", "-a", "amd64"]
+//! autostart = true
+//! mounts = ["/srv/www srv/www none bind,create=dir 0 0"]
+//!
+//! [[network]]
+//! type = "veth"
+//! link = "lxcbr0"
+//! flags = "up"
+//!
+//! [limits]
+//! "memory.limit_in_bytes" = "536870912"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use super::{BDevSpecs, LxcContainer, LxcCreateFlag};
+
+/// Seconds a container gets to shut down cleanly when a change requires a restart.
+pub const RESTART_TIMEOUT: i32 = 30;
+
+/// Keys holding several values, which are cleared before being set again, like
+/// the `lxc.hook.` keys.
+const LIST_KEYS: [&str; 7] = ["lxc.mount.entry", "lxc.id_map", "lxc.idmap", "lxc.cap.drop", "lxc.cap.keep",
+                              "lxc.group", "lxc.environment"];
+
+/// Whether setting `key` adds a value instead of replacing it.
+fn is_list_key(key: &str) -> bool {
+  LIST_KEYS.contains(&key) || key.starts_with("lxc.hook.")
+}
+
+/// Desired state of a container.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContainerSpec {
+  /// Name of the container.
+  pub name: String,
+  /// Template to create the container from, for example "download".
+  pub template: String,
+  /// Arguments passed to the template.
+  #[serde(default)]
+  pub template_args: Vec<String>,
+  /// Backing store type to create the container with, for example "dir" or "btrfs".
+  pub bdev: Option<String>,
+  /// Value of `lxc.start.auto`.
+  pub autostart: Option<bool>,
+  /// Network interfaces, replacing the `lxc.network` entries.
+  pub network: Option<Vec<NetworkSpec>>,
+  /// cgroup limits, kept as `lxc.cgroup.<key>` and applied to the running container.
+  /// Values are compared as strings, so write them the way the kernel reports them.
+  #[serde(default)]
+  pub limits: BTreeMap<String, String>,
+  /// `lxc.mount.entry` lines, in fstab format.
+  pub mounts: Option<Vec<String>>,
+  /// `lxc.id_map` lines, for example "u 0 100000 65536".
+  pub id_maps: Option<Vec<String>>,
+  /// Any other configuration items.
+  #[serde(default)]
+  pub config: BTreeMap<String, String>,
+}
+
+/// Network interface of a container, one `lxc.network` entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkSpec {
+  /// Network type, for example "veth", "macvlan" or "empty".
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// Host interface to attach to.
+  pub link: Option<String>,
+  /// "up" to bring the interface up.
+  pub flags: Option<String>,
+  /// MAC address.
+  pub hwaddr: Option<String>,
+  /// Interface name inside the container.
+  pub name: Option<String>,
+  /// IPv4 addresses with prefix length, for example "10.0.3.10/24".
+  #[serde(default)]
+  pub ipv4: Vec<String>,
+  /// IPv6 addresses with prefix length.
+  #[serde(default)]
+  pub ipv6: Vec<String>,
+}
+
+impl NetworkSpec {
+  /// Configuration items of the interface, `type` first as liblxc requires.
+  fn items(&self) -> Vec<(&'static str, Vec<String>)> {
+    let opt = |value: &Option<String>| value.iter().cloned().collect();
+    vec![("type", vec![self.kind.clone()]),
+         ("link", opt(&self.link)),
+         ("flags", opt(&self.flags)),
+         ("hwaddr", opt(&self.hwaddr)),
+         ("name", opt(&self.name)),
+         ("ipv4", self.ipv4.clone()),
+         ("ipv6", self.ipv6.clone())]
+  }
+}
+
+impl ContainerSpec {
+
+  /// Parse a spec in TOML format.
+  pub fn from_toml(spec: &str) -> Result<ContainerSpec, String> {
+    parse_toml(spec)
+  }
+
+  /// Parse a spec in YAML format.
+  pub fn from_yaml(spec: &str) -> Result<ContainerSpec, String> {
+    parse_yaml(spec)
+  }
+
+  /// Load a spec from a file, in YAML format if its extension is `.yaml` or `.yml`,
+  /// else in TOML format.
+  ///
+  /// # Return value
+  /// Returns `Ok(ContainerSpec)` on success, else `Err` with a description of the
+  /// read or parse error.
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<ContainerSpec, String> {
+    load_file(path.as_ref())
+  }
+
+  /// Configuration items managed by the spec, except networks.
+  fn desired_config(&self) -> Vec<(String, Vec<String>)> {
+    let mut items = Vec::new();
+    if let Some(autostart) = self.autostart {
+      items.push(("lxc.start.auto".to_string(), vec![if autostart { "1" } else { "0" }.to_string()]));
+    }
+    for (key, value) in self.limits.iter() {
+      items.push((format!("lxc.cgroup.{}", key), vec![value.clone()]));
+    }
+    if let Some(ref mounts) = self.mounts {
+      items.push(("lxc.mount.entry".to_string(), mounts.clone()));
+    }
+    if let Some(ref id_maps) = self.id_maps {
+      items.push(("lxc.id_map".to_string(), id_maps.clone()));
+    }
+    for (key, value) in self.config.iter() {
+      items.push((key.clone(), vec![value.clone()]));
+    }
+    items
+  }
+}
+
+pub(crate) fn parse_toml<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+  toml::from_str(text).map_err(|e| e.to_string())
+}
+
+pub(crate) fn parse_yaml<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+  serde_yaml::from_str(text).map_err(|e| e.to_string())
+}
+
+/// Reads `path` as YAML if its extension is `.yaml` or `.yml`, else as TOML.
+pub(crate) fn load_file<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+  let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  let parsed = match path.extension().and_then(|e| e.to_str()) {
+    Some("yaml") | Some("yml") => parse_yaml(&text),
+    _ => parse_toml(&text),
+  };
+  parsed.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Single operation needed to make a container match its spec.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+  /// Create the container.
+  Create { template: String, bdevtype: Option<String>, args: Vec<String> },
+  /// Remove all values of a configuration item.
+  ClearConfig(String),
+  /// Set (or, for list items, add) a configuration item.
+  SetConfig(String, String),
+  /// Change a cgroup item of the running container.
+  SetCgroup(String, String),
+  /// Restart the running container so configuration changes take effect.
+  Restart,
+}
+
+impl fmt::Display for Action {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Action::Create { ref template, ref bdevtype, ref args } => {
+        write!(f, "create -t {}", template)?;
+        if let Some(ref bdevtype) = *bdevtype {
+          write!(f, " -B {}", bdevtype)?;
+        }
+        if !args.is_empty() {
+          write!(f, " -- {}", args.join(" "))?;
+        }
+        Ok(())
+      }
+      Action::ClearConfig(ref key) => write!(f, "clear {}", key),
+      Action::SetConfig(ref key, ref value) => write!(f, "set {} = {}", key, value),
+      Action::SetCgroup(ref key, ref value) => write!(f, "set cgroup {} = {}", key, value),
+      Action::Restart => f.write_str("restart"),
+    }
+  }
+}
+
+/// Values of a configuration item, empty if it is unset or unknown.
+fn config_values(container: &LxcContainer, key: &str) -> Vec<String> {
+  match container.get_config_item(key) {
+    Ok(value) => value.lines().filter(|l| !l.is_empty()).map(|l| l.to_string()).collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+/// Whether a changed item only takes effect when the container restarts.
+fn needs_restart(key: &str) -> bool {
+  !key.starts_with("lxc.start.") && !key.starts_with("lxc.cgroup.") && key != "lxc.group"
+}
+
+/// Compute the operations needed to make `container` match `spec`, without changing it.
+///
+/// # Return value
+/// Returns the operations in the order `apply` performs them; empty if the container
+/// already matches. For a container that doesn't exist yet, the configuration is
+/// compared against an empty one.
+pub fn plan(spec: &ContainerSpec, container: &LxcContainer) -> Vec<Action> {
+  let mut actions = Vec::new();
+  let defined = container.is_defined();
+  if !defined {
+    actions.push(create_action(spec));
+  }
+  let current = |key: &str| if defined { config_values(container, key) } else { Vec::new() };
+  let mut restart = false;
+
+  for (key, values) in spec.desired_config() {
+    if current(&key) == values {
+      continue;
+    }
+    if is_list_key(&key) || values.is_empty() {
+      actions.push(Action::ClearConfig(key.clone()));
+    }
+    for value in values {
+      actions.push(Action::SetConfig(key.clone(), value));
+    }
+    restart |= needs_restart(&key);
+  }
+
+  if let Some(ref networks) = spec.network {
+    let mut count = 0;
+    while !current(&format!("lxc.network.{}.type", count)).is_empty() {
+      count += 1;
+    }
+    let matches = count == networks.len() && networks.iter().enumerate().all(|(i, network)| {
+      network.items().into_iter().all(|(item, values)| {
+        current(&format!("lxc.network.{}.{}", i, item)) == values
+      })
+    });
+    if !matches {
+      actions.push(Action::ClearConfig("lxc.network".to_string()));
+      for network in networks {
+        for (item, values) in network.items() {
+          for value in values {
+            actions.push(Action::SetConfig(format!("lxc.network.{}", item), value));
+          }
+        }
+      }
+      restart = true;
+    }
+  }
+
+  if defined && container.is_running() {
+    if restart {
+      actions.push(Action::Restart);
+    }
+    else {
+      for (key, value) in spec.limits.iter() {
+        let current = container.get_cgroup_item(key).map(|v| v.trim_end().to_string());
+        if current.as_ref() != Ok(value) {
+          actions.push(Action::SetCgroup(key.clone(), value.clone()));
+        }
+      }
+    }
+  }
+  actions
+}
+
+fn create_action(spec: &ContainerSpec) -> Action {
+  Action::Create {
+    template: spec.template.clone(),
+    bdevtype: spec.bdev.clone(),
+    args: spec.template_args.clone(),
+  }
+}
+
+/// Make `container` match `spec`, creating it if needed.
+///
+/// Configuration changes are saved to the container's configuration file. If the
+/// container is running, changed cgroup limits are applied to it directly, while
+/// other changes restart it.
+///
+/// # Return value
+/// Returns `Ok` with the operations performed, else `Err` with a description of the
+/// operation that failed. Operations before it have been performed.
+pub fn apply(spec: &ContainerSpec, container: &LxcContainer) -> Result<Vec<Action>, &'static str> {
+  let mut done = Vec::new();
+  if !container.is_defined() {
+    let create = create_action(spec);
+    execute(&create, container)?;
+    done.push(create);
+  }
+  // Planned again after creation, to compare against what the template wrote.
+  let mut unsaved = false;
+  for action in plan(spec, container) {
+    match action {
+      Action::ClearConfig(_) | Action::SetConfig(..) => unsaved = true,
+      _ => if unsaved {
+        save(container)?;
+        unsaved = false;
+      }
+    }
+    execute(&action, container)?;
+    done.push(action);
+  }
+  if unsaved {
+    save(container)?;
+  }
+  Ok(done)
+}
+
+fn save(container: &LxcContainer) -> Result<(), &'static str> {
+  if container.save_config(&container.config_file_name()?) { Ok(()) } else { Err("Couldn't save configuration") }
+}
+
+fn execute(action: &Action, container: &LxcContainer) -> Result<(), &'static str> {
+  let ok = match *action {
+    Action::Create { ref template, ref bdevtype, ref args } => {
+      let argv_option = if args.is_empty() { None } else { Some(args.iter().map(|a| &a[..]).collect()) };
+      container.create(template, bdevtype.as_ref().map(|b| &b[..]), BDevSpecs::new(),
+                       LxcCreateFlag::Quiet, argv_option)
+    }
+    Action::ClearConfig(ref key) => container.clear_config_item(key),
+    Action::SetConfig(ref key, ref value) => container.set_config_item(key, value),
+    Action::SetCgroup(ref key, ref value) => container.set_cgroup_item(key, value),
+    Action::Restart => (container.shutdown(RESTART_TIMEOUT) || container.stop()) && container.start(0, None),
+  };
+  if ok {
+    Ok(())
+  }
+  else {
+    Err(match *action {
+      Action::Create { .. } => "Couldn't create container",
+      Action::ClearConfig(_) => "Couldn't clear configuration item",
+      Action::SetConfig(..) => "Couldn't set configuration item",
+      Action::SetCgroup(..) => "Couldn't set cgroup item",
+      Action::Restart => "Couldn't restart container",
+    })
+  }
+}
@@ -0,0 +1,326 @@
+//! Stacks of cooperating containers, enabled with the `spec` feature.
+//!
+//! A `StackSpec`, usually loaded from a TOML or YAML file, lists existing
+//! containers, what each depends on and when it counts as healthy. `up` starts them
+//! in waves: a container starts once everything it depends on is healthy, and the
+//! containers of a wave become healthy in parallel. `down` stops them in reverse.
+//!
+//! Within a wave containers start in `lxc.start.order` (highest first, then by name),
+//! each followed by its `lxc.start.delay`. If the stack lists `groups`, containers
+//! whose `lxc.group` contains an earlier group are started before those in later ones.
+//!
+//! A member's `health.check` is a `HealthCheck` with durations in seconds: the
+//! member is healthy once it passes, and `up` fails as soon as it turns unhealthy.
+//!
+//! ```toml
+//! groups = ["infra"]
+//!
+//! [[containers]]
+//! name = "db"
+//! health = { command = ["pg_isready"] }
+//!
+//! [[containers]]
+//! name = "web"
+//! depends_on = ["db"]
+//! health = { ip = true }
+//! timeout = 120
+//!
+//! [[containers]]
+//! name = "worker"
+//! depends_on = ["web"]
+//! health.check = { command = ["/bin/check"], interval = 2, retries = 5 }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::spec::{load_file, parse_toml, parse_yaml};
+use super::{HealthCheck, HealthRunner, HealthStatus, LxcContainer};
+
+/// Interval between health checks.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+fn default_timeout() -> u64 {
+  60
+}
+
+/// Containers of a stack.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StackSpec {
+  /// `lxc.group` values, in the order their members start.
+  #[serde(default)]
+  pub groups: Vec<String>,
+  /// Members of the stack.
+  #[serde(default)]
+  pub containers: Vec<StackMember>,
+}
+
+/// Container in a stack.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StackMember {
+  /// Name of an existing container.
+  pub name: String,
+  /// Members that must be healthy before this one starts.
+  #[serde(default)]
+  pub depends_on: Vec<String>,
+  /// When the container counts as healthy, in addition to being RUNNING.
+  #[serde(default)]
+  pub health: HealthCondition,
+  /// Seconds to wait for the container to become healthy or to shut down.
+  #[serde(default = "default_timeout")]
+  pub timeout: u64,
+}
+
+/// Conditions a running container has to meet to be healthy.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCondition {
+  /// Wait until the container has an IP address.
+  #[serde(default)]
+  pub ip: bool,
+  /// Wait until this command, run inside the container, exits with status 0.
+  #[serde(default)]
+  pub command: Vec<String>,
+  /// Wait until this health check passes, failing if it turns unhealthy.
+  #[serde(default)]
+  pub check: Option<HealthCheck>,
+}
+
+/// Error starting or stopping a stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StackError {
+  /// The stack lists a container twice or depends on a container it doesn't list.
+  Invalid(String),
+  /// Dependencies between these containers form a cycle.
+  Cycle(Vec<String>),
+  /// Container with this name doesn't exist.
+  NotFound(String),
+  /// Container `name` couldn't be started or stopped, or didn't become healthy in time.
+  Container { name: String, reason: &'static str },
+}
+
+impl fmt::Display for StackError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      StackError::Invalid(ref msg) => f.write_str(msg),
+      StackError::Cycle(ref names) => write!(f, "Dependency cycle between {}", names.join(", ")),
+      StackError::NotFound(ref name) => write!(f, "Container {} does not exist", name),
+      StackError::Container { ref name, reason } => write!(f, "{}: {}", name, reason),
+    }
+  }
+}
+
+fn container_error(name: &str, reason: &'static str) -> StackError {
+  StackError::Container { name: name.to_string(), reason }
+}
+
+/// Start settings read from a container's configuration.
+struct StartConfig {
+  order: i32,
+  groups: Vec<String>,
+}
+
+impl StartConfig {
+  fn read(container: &LxcContainer) -> StartConfig {
+    StartConfig { order: container.start_order(), groups: container.groups() }
+  }
+}
+
+impl StackSpec {
+
+  /// Parse a stack in TOML format.
+  pub fn from_toml(stack: &str) -> Result<StackSpec, String> {
+    parse_toml(stack)
+  }
+
+  /// Parse a stack in YAML format.
+  pub fn from_yaml(stack: &str) -> Result<StackSpec, String> {
+    parse_yaml(stack)
+  }
+
+  /// Load a stack from a file, in YAML format if its extension is `.yaml` or `.yml`,
+  /// else in TOML format.
+  ///
+  /// # Return value
+  /// Returns `Ok(StackSpec)` on success, else `Err` with a description of the
+  /// read or parse error.
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<StackSpec, String> {
+    load_file(path.as_ref())
+  }
+
+  fn member(&self, name: &str) -> &StackMember {
+    self.containers.iter().find(|m| m.name == name).expect("member of the stack")
+  }
+
+  /// Compute the waves `up` starts the containers in.
+  ///
+  /// # Parameters
+  /// `lxcpath_option` - `Option` with lxcpath of the containers. If `None`, the default lxcpath is used.
+  ///
+  /// # Return value
+  /// Returns `Ok` with the names of the containers of each wave in start order, else
+  /// `Err` if the stack is inconsistent or a container doesn't exist.
+  pub fn start_order(&self, lxcpath_option: Option<&str>) -> Result<Vec<Vec<String>>, StackError> {
+    let mut configs = BTreeMap::new();
+    for member in self.containers.iter() {
+      if configs.contains_key(&member.name[..]) {
+        return Err(StackError::Invalid(format!("{} is listed twice", member.name)));
+      }
+      for dependency in member.depends_on.iter() {
+        if !self.containers.iter().any(|m| m.name == *dependency) {
+          return Err(StackError::Invalid(format!("{} depends on {}, which is not in the stack",
+                                                 member.name, dependency)));
+        }
+      }
+      let container = open(&member.name, lxcpath_option)?;
+      configs.insert(&member.name[..], StartConfig::read(&container));
+    }
+
+    // Position in `groups` of the first group each container belongs to.
+    let group_rank = |name: &str| {
+      self.groups.iter().position(|g| configs[name].groups.contains(g))
+    };
+    let mut dependencies: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for member in self.containers.iter() {
+      let mut deps: BTreeSet<&str> = member.depends_on.iter().map(|d| &d[..]).collect();
+      if let Some(rank) = group_rank(&member.name) {
+        for other in self.containers.iter() {
+          if group_rank(&other.name).is_some_and(|r| r < rank) {
+            deps.insert(&other.name[..]);
+          }
+        }
+      }
+      dependencies.insert(&member.name[..], deps);
+    }
+
+    let mut waves: Vec<Vec<String>> = Vec::new();
+    let mut placed = BTreeSet::new();
+    while placed.len() < dependencies.len() {
+      let mut wave: Vec<&str> = dependencies.iter()
+        .filter(|&(name, deps)| !placed.contains(name) && deps.iter().all(|d| placed.contains(d)))
+        .map(|(name, _)| *name)
+        .collect();
+      if wave.is_empty() {
+        let stuck = dependencies.keys().filter(|n| !placed.contains(*n)).map(|n| n.to_string()).collect();
+        return Err(StackError::Cycle(stuck));
+      }
+      wave.sort_by(|a, b| configs[b].order.cmp(&configs[a].order).then(a.cmp(b)));
+      placed.extend(wave.iter().cloned());
+      waves.push(wave.iter().map(|n| n.to_string()).collect());
+    }
+    Ok(waves)
+  }
+
+  /// Start the stack and wait until every container is healthy.
+  ///
+  /// Containers that are already running are not restarted, but still have to
+  /// become healthy.
+  ///
+  /// # Parameters
+  /// `lxcpath_option` - `Option` with lxcpath of the containers. If `None`, the default lxcpath is used.
+  ///
+  /// # Return value
+  /// Returns `Ok(())` once all containers are healthy, else `Err` naming the container
+  /// that failed. Later waves are not started then; earlier ones keep running.
+  pub fn up(&self, lxcpath_option: Option<&str>) -> Result<(), StackError> {
+    for wave in self.start_order(lxcpath_option)? {
+      let results: Vec<Result<(), StackError>> = thread::scope(|scope| {
+        let mut checks = Vec::new();
+        for name in wave.iter() {
+          let member = self.member(name);
+          let container = open(name, lxcpath_option)?;
+          if !container.is_running() && !container.start(0, None) {
+            return Err(container_error(name, "failed to start"));
+          }
+          let delay = container.start_delay().max(0) as u64;
+          checks.push(scope.spawn(move || wait_healthy(&container, member)));
+          thread::sleep(Duration::from_secs(delay));
+        }
+        Ok(checks.into_iter().map(|check| check.join().unwrap()).collect())
+      })?;
+      results.into_iter().collect::<Result<(), StackError>>()?;
+    }
+    Ok(())
+  }
+
+  /// Stop the stack, in the reverse of the order `up` starts it.
+  ///
+  /// Each container gets its `timeout` to shut down cleanly before it is killed.
+  /// Containers of a wave are stopped in parallel.
+  ///
+  /// # Parameters
+  /// `lxcpath_option` - `Option` with lxcpath of the containers. If `None`, the default lxcpath is used.
+  ///
+  /// # Return value
+  /// Returns `Ok(())` once all containers are stopped, else `Err` naming the container
+  /// that failed. Earlier waves are not stopped then.
+  pub fn down(&self, lxcpath_option: Option<&str>) -> Result<(), StackError> {
+    for wave in self.start_order(lxcpath_option)?.into_iter().rev() {
+      let results: Vec<Result<(), StackError>> = thread::scope(|scope| {
+        let stops: Vec<_> = wave.iter().rev().map(|name| {
+          let timeout = self.member(name).timeout as i32;
+          scope.spawn(move || {
+            let container = open(name, lxcpath_option)?;
+            if container.is_running() && !(container.shutdown(timeout) || container.stop()) {
+              return Err(container_error(name, "failed to stop"));
+            }
+            Ok(())
+          })
+        }).collect();
+        stops.into_iter().map(|stop| stop.join().unwrap()).collect()
+      });
+      results.into_iter().collect::<Result<(), StackError>>()?;
+    }
+    Ok(())
+  }
+}
+
+fn open(name: &str, lxcpath_option: Option<&str>) -> Result<LxcContainer, StackError> {
+  let container = LxcContainer::new(name, lxcpath_option).map_err(|e| container_error(name, e))?;
+  if !container.is_defined() {
+    return Err(StackError::NotFound(name.to_string()));
+  }
+  Ok(container)
+}
+
+fn check_health(container: &LxcContainer, health: &HealthCondition) -> Result<(), &'static str> {
+  if container.state() != "RUNNING" {
+    return Err("not running");
+  }
+  if health.ip && container.get_ips(None, None, 0).is_empty() {
+    return Err("no IP address");
+  }
+  if !health.command.is_empty() {
+    let argv = health.command.iter().map(|a| &a[..]).collect();
+    if container.attach_run_wait(&health.command[0], argv) != Ok(0) {
+      return Err("health command failed");
+    }
+  }
+  Ok(())
+}
+
+fn wait_healthy(container: &LxcContainer, member: &StackMember) -> Result<(), StackError> {
+  let deadline = Instant::now() + Duration::from_secs(member.timeout);
+  let mut runner = member.health.check.clone().map(HealthRunner::new);
+  loop {
+    let reason = match (check_health(container, &member.health), runner.as_mut()) {
+      (Ok(()), None) => return Ok(()),
+      (Ok(()), Some(runner)) => match runner.poll(container) {
+        HealthStatus::Healthy => return Ok(()),
+        HealthStatus::Unhealthy => return Err(container_error(&member.name, "unhealthy")),
+        HealthStatus::Starting => "health check not passed yet",
+      },
+      (Err(reason), _) => reason,
+    };
+    let now = Instant::now();
+    if now >= deadline {
+      return Err(container_error(&member.name, reason));
+    }
+    thread::sleep(if deadline - now < POLL_INTERVAL { deadline - now } else { POLL_INTERVAL });
+  }
+}
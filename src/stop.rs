@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+use crate::attach::wait_attached;
+use super::{AttachOptions, LxcContainer};
+
+/// How `LxcContainer::stop_gracefully` stops a container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopPolicy {
+  /// Command run inside the container before it is asked to shut down, for example
+  /// to drain connections. Its exit status doesn't affect the following stages.
+  pub pre_stop: Option<Vec<String>>,
+  /// Time after which the pre-stop command is killed and the next stage begins.
+  pub pre_stop_timeout: Duration,
+  /// Seconds to wait for the container to stop after the shutdown request; `0`
+  /// skips the request and a negative value waits forever, as in liblxc.
+  pub shutdown_timeout: i32,
+  /// Whether to kill the container if it is still running afterwards.
+  pub kill: bool,
+}
+
+impl Default for StopPolicy {
+  /// Shut down within 30 seconds, then kill. A pre-stop command gets 30 seconds too.
+  fn default() -> StopPolicy {
+    StopPolicy { pre_stop: None, pre_stop_timeout: Duration::from_secs(30), shutdown_timeout: 30, kill: true }
+  }
+}
+
+/// Stage of `LxcContainer::stop_gracefully` after which the container was stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopStage {
+  /// The container wasn't running to begin with.
+  NotRunning,
+  /// The container stopped while or after the pre-stop command ran.
+  PreStop,
+  /// The container shut down cleanly after the shutdown request.
+  Shutdown,
+  /// The container was killed.
+  Kill,
+}
+
+impl StopStage {
+  /// Lower-case name of the stage, for example "shutdown".
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      StopStage::NotRunning => "not running",
+      StopStage::PreStop => "pre-stop",
+      StopStage::Shutdown => "shutdown",
+      StopStage::Kill => "kill",
+    }
+  }
+}
+
+/// What `LxcContainer::stop_gracefully` did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopReport {
+  /// Stage that stopped the container, `None` if it is still running.
+  pub stopped_by: Option<StopStage>,
+  /// Exit status of the pre-stop command, or `Err` if it couldn't be run or timed
+  /// out; `None` if the policy has none or the container wasn't running.
+  pub pre_stop_status: Option<Result<i32, &'static str>>,
+  /// Time taken by all stages.
+  pub elapsed: Duration,
+}
+
+impl StopReport {
+  /// Whether the container is stopped.
+  pub fn is_stopped(&self) -> bool {
+    self.stopped_by.is_some()
+  }
+}
+
+impl LxcContainer {
+
+  /// Stop the container in stages: run the pre-stop command, request a shutdown and
+  /// wait for it, then kill the container, each stage only if it is still running.
+  ///
+  /// # Parameters
+  /// `policy` - which stages to go through and how long to wait for the shutdown.
+  ///
+  /// # Return value
+  /// Returns a `StopReport` telling which stage stopped the container, if any. A
+  /// container that stopped on its own is credited to the last stage run.
+  pub fn stop_gracefully(&self, policy: &StopPolicy) -> StopReport {
+    let started = Instant::now();
+    let mut report = StopReport { stopped_by: None, pre_stop_status: None, elapsed: Duration::from_secs(0) };
+    report.stopped_by = self.stop_stages(policy, &mut report.pre_stop_status);
+    report.elapsed = started.elapsed();
+    report
+  }
+
+  fn stop_stages(&self, policy: &StopPolicy,
+                 pre_stop_status: &mut Option<Result<i32, &'static str>>) -> Option<StopStage> {
+    if !self.is_running() {
+      return Some(StopStage::NotRunning);
+    }
+    let mut last_stage = None;
+    if let Some(ref command) = policy.pre_stop {
+      *pre_stop_status = Some(self.run_pre_stop(command, policy.pre_stop_timeout));
+      if !self.is_running() {
+        return Some(StopStage::PreStop);
+      }
+      last_stage = Some(StopStage::PreStop);
+    }
+    if policy.shutdown_timeout != 0 {
+      // A zero timeout only sends the request, the wait happens here.
+      self.shutdown(0);
+      if self.wait("STOPPED", policy.shutdown_timeout) {
+        return Some(StopStage::Shutdown);
+      }
+      last_stage = Some(StopStage::Shutdown);
+    }
+    if policy.kill {
+      if self.stop() {
+        return Some(StopStage::Kill);
+      }
+      last_stage = last_stage.or(Some(StopStage::Kill));
+    }
+    // stop() and wait() also fail for a container that stopped in the meantime.
+    if self.is_running() { None } else { last_stage }
+  }
+
+  fn run_pre_stop(&self, command: &[String], timeout: Duration) -> Result<i32, &'static str> {
+    let program = command.first().ok_or("Empty pre-stop command")?;
+    let pid = self.attach_stdio(&AttachOptions::default(), [0, 1, 2], program,
+                                command.iter().map(|a| &a[..]).collect())?;
+    wait_attached(pid, Some(timeout))
+  }
+}
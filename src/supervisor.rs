@@ -0,0 +1,329 @@
+//! Restarting containers that stop, like a service manager.
+//!
+//! A `Supervisor` watches containers by polling their state. When a watched
+//! container that was running stops, it is started again according to its
+//! `RestartPolicy`, after an exponentially growing delay if it keeps failing.
+//!
+//! Polling can't tell why a container stopped, so every stop counts as a failure
+//! unless its exit status was passed in through `handle_event` beforehand, for
+//! example from a `Monitor` running in another thread. Containers that are stopped
+//! when they start being watched are left alone until they run; `unwatch` a
+//! container before stopping it on purpose.
+//!
+//! Containers can also be given a `HealthCheck`: a running container that becomes
+//! unhealthy is killed and then restarted like one that failed.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::health::{HealthCheck, HealthRunner, HealthStatus};
+use super::{LxcContainer, MonitorEvent};
+
+/// When a watched container is restarted after it stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+  /// Never restart; only record the exit.
+  Never,
+  /// Restart after failures, giving up after `max_retries` consecutive ones.
+  OnFailure { max_retries: u32 },
+  /// Always restart.
+  Always,
+}
+
+/// Delay before restarting a container that keeps failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+  /// Delay before the first restart.
+  pub initial: Duration,
+  /// Upper bound of the delay.
+  pub max: Duration,
+  /// Factor the delay grows by with each consecutive failure.
+  pub factor: u32,
+  /// Time a container has to keep running for its consecutive failures to be forgotten.
+  pub reset_after: Duration,
+}
+
+impl Default for Backoff {
+  /// 1 second, doubling up to 5 minutes, forgotten after a minute of running.
+  fn default() -> Backoff {
+    Backoff {
+      initial: Duration::from_secs(1),
+      max: Duration::from_secs(300),
+      factor: 2,
+      reset_after: Duration::from_secs(60),
+    }
+  }
+}
+
+impl Backoff {
+  /// Delay before the restart following `failures` consecutive failures.
+  pub fn delay(&self, failures: u32) -> Duration {
+    let mut delay = self.initial;
+    for _ in 1..failures {
+      delay = delay.saturating_mul(self.factor);
+      if delay >= self.max {
+        return self.max;
+      }
+    }
+    if delay > self.max { self.max } else { delay }
+  }
+}
+
+/// Why a watched container last stopped running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+  /// Init exited with this status, as returned by `waitpid`.
+  Exited(i32),
+  /// The container stopped for an unknown reason.
+  Stopped,
+  /// The supervisor killed the container because its health check failed.
+  Unhealthy,
+  /// The supervisor couldn't start the container again.
+  StartFailed,
+}
+
+impl ExitReason {
+  fn is_failure(&self) -> bool {
+    *self != ExitReason::Exited(0)
+  }
+}
+
+/// What the supervisor knows about a watched container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SupervisedState {
+  /// Name of the container.
+  pub name: String,
+  /// Restart policy of the container.
+  pub policy: RestartPolicy,
+  /// Number of times the supervisor started the container.
+  pub restarts: u32,
+  /// Failures since the container last ran for `Backoff::reset_after`.
+  pub consecutive_failures: u32,
+  /// Why the container last stopped, `None` if it didn't since being watched.
+  pub last_exit: Option<ExitReason>,
+  /// When the next restart is due, if one is scheduled.
+  pub next_restart: Option<Instant>,
+  /// Whether the supervisor gave up restarting the container.
+  pub gave_up: bool,
+  /// Health of the container, `None` if it has no health check.
+  pub health: Option<HealthStatus>,
+}
+
+struct Watched {
+  state: SupervisedState,
+  backoff: Backoff,
+  running_since: Option<Instant>,
+  exit_status: Option<i32>,
+  health: Option<HealthRunner>,
+}
+
+/// Watches containers and restarts them according to their policies.
+pub struct Supervisor {
+  lxcpath: Option<String>,
+  watched: Vec<Watched>,
+}
+
+impl Supervisor {
+
+  /// Creates a supervisor for containers in `lxcpath_option`, or in the default
+  /// lxcpath if `None`.
+  pub fn new(lxcpath_option: Option<&str>) -> Supervisor {
+    Supervisor { lxcpath: lxcpath_option.map(|p| p.to_string()), watched: Vec::new() }
+  }
+
+  /// Start watching container `name`, replacing its previous policy if it is watched already.
+  pub fn watch(&mut self, name: &str, policy: RestartPolicy, backoff: Backoff) {
+    self.unwatch(name);
+    self.watched.push(Watched {
+      state: SupervisedState {
+        name: name.to_string(),
+        policy,
+        restarts: 0,
+        consecutive_failures: 0,
+        last_exit: None,
+        next_restart: None,
+        gave_up: false,
+        health: None,
+      },
+      backoff,
+      running_since: None,
+      exit_status: None,
+      health: None,
+    });
+  }
+
+  /// Stop watching container `name`.
+  pub fn unwatch(&mut self, name: &str) {
+    self.watched.retain(|w| w.state.name != name);
+  }
+
+  /// Set or remove the health check of watched container `name`.
+  ///
+  /// # Return value
+  /// Returns `true` if the container is watched, else `false`.
+  pub fn set_health_check(&mut self, name: &str, check_option: Option<HealthCheck>) -> bool {
+    match self.watched.iter_mut().find(|w| w.state.name == name) {
+      Some(watched) => {
+        watched.health = check_option.map(HealthRunner::new);
+        watched.state.health = watched.health.as_ref().map(|h| h.status());
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Health check runner of watched container `name`, for its probe history.
+  pub fn health(&self, name: &str) -> Option<&HealthRunner> {
+    self.watched.iter().find(|w| w.state.name == name).and_then(|w| w.health.as_ref())
+  }
+
+  /// State of container `name`, `None` if it isn't watched.
+  pub fn state(&self, name: &str) -> Option<&SupervisedState> {
+    self.watched.iter().find(|w| w.state.name == name).map(|w| &w.state)
+  }
+
+  /// States of all watched containers, in the order they were watched.
+  pub fn states(&self) -> Vec<SupervisedState> {
+    self.watched.iter().map(|w| w.state.clone()).collect()
+  }
+
+  /// Take note of an event from the monitor of the supervised lxcpath. Exit codes
+  /// tell the next poll why the container stopped.
+  pub fn handle_event(&mut self, event: &MonitorEvent) {
+    if let MonitorEvent::ExitCode { ref name, status } = *event {
+      if let Some(watched) = self.watched.iter_mut().find(|w| w.state.name == *name) {
+        watched.exit_status = Some(status);
+      }
+    }
+  }
+
+  /// Check every watched container once, recording stops, running due health checks
+  /// and performing restarts that are due.
+  pub fn poll(&mut self) {
+    let lxcpath = self.lxcpath.as_ref().map(|p| &p[..]);
+    for watched in self.watched.iter_mut() {
+      if let Ok(container) = LxcContainer::new(&watched.state.name, lxcpath) {
+        watched.poll(&container, Instant::now());
+      }
+    }
+  }
+
+  /// Poll every `interval` on a background thread.
+  ///
+  /// # Return value
+  /// Returns a handle to inspect and change the supervisor while it runs, and to stop it.
+  pub fn spawn(self, interval: Duration) -> SupervisorHandle {
+    let supervisor = Arc::new(Mutex::new(self));
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = {
+      let supervisor = supervisor.clone();
+      let stop = stop.clone();
+      thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+          supervisor.lock().unwrap_or_else(|e| e.into_inner()).poll();
+          thread::park_timeout(interval);
+        }
+      })
+    };
+    SupervisorHandle { supervisor, stop, thread }
+  }
+}
+
+impl Watched {
+  fn poll(&mut self, container: &LxcContainer, now: Instant) {
+    if container.is_running() {
+      let since = *self.running_since.get_or_insert(now);
+      if now - since >= self.backoff.reset_after {
+        self.state.consecutive_failures = 0;
+      }
+      self.state.next_restart = None;
+      self.exit_status = None;
+      if !self.check_health(container) {
+        return;
+      }
+      self.running_since = None;
+      self.exited(ExitReason::Unhealthy, now);
+    }
+    if let Some(ref mut health) = self.health {
+      health.reset();
+      self.state.health = Some(health.status());
+    }
+    if self.running_since.take().is_some() {
+      let reason = match self.exit_status.take() {
+        Some(status) => ExitReason::Exited(status),
+        None => ExitReason::Stopped,
+      };
+      self.exited(reason, now);
+    }
+    match self.state.next_restart {
+      Some(due) if due <= now => {
+        self.state.next_restart = None;
+        if container.start(0, None) {
+          self.state.restarts += 1;
+          self.running_since = Some(now);
+        }
+        else {
+          self.exited(ExitReason::StartFailed, now);
+        }
+      }
+      _ => (),
+    }
+  }
+
+  /// Run the health check if due; returns `true` if it killed the unhealthy container.
+  fn check_health(&mut self, container: &LxcContainer) -> bool {
+    let status = match self.health {
+      Some(ref mut health) => health.poll(container),
+      None => return false,
+    };
+    self.state.health = Some(status);
+    status == HealthStatus::Unhealthy && container.stop()
+  }
+
+  fn exited(&mut self, reason: ExitReason, now: Instant) {
+    self.state.last_exit = Some(reason);
+    if reason.is_failure() {
+      self.state.consecutive_failures += 1;
+    }
+    let restart = match self.state.policy {
+      RestartPolicy::Never => false,
+      RestartPolicy::Always => true,
+      RestartPolicy::OnFailure { max_retries } => {
+        if self.state.consecutive_failures > max_retries {
+          self.state.gave_up = true;
+        }
+        reason.is_failure() && !self.state.gave_up
+      }
+    };
+    if restart {
+      self.state.next_restart = Some(now + self.backoff.delay(self.state.consecutive_failures));
+    }
+  }
+}
+
+/// Supervisor running on a background thread, see `Supervisor::spawn`.
+pub struct SupervisorHandle {
+  supervisor: Arc<Mutex<Supervisor>>,
+  stop: Arc<AtomicBool>,
+  thread: thread::JoinHandle<()>,
+}
+
+impl SupervisorHandle {
+
+  /// Obtain exclusive access to the supervisor, pausing it until the guard is dropped.
+  pub fn lock(&self) -> MutexGuard<'_, Supervisor> {
+    self.supervisor.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  /// Stop the background thread and return the supervisor.
+  pub fn stop(self) -> Supervisor {
+    self.stop.store(true, Ordering::SeqCst);
+    self.thread.thread().unpark();
+    let _ = self.thread.join();
+    match Arc::try_unwrap(self.supervisor) {
+      Ok(supervisor) => supervisor.into_inner().unwrap_or_else(|e| e.into_inner()),
+      Err(_) => unreachable!("the supervisor thread has exited"),
+    }
+  }
+}
@@ -0,0 +1,363 @@
+//! Installed templates and typed arguments for the common ones.
+//!
+//! Template scripts are installed as `lxc-NAME` in the templates directory.
+//! The builders here produce the argv of a template, so that a misspelt option is
+//! caught by the compiler instead of by a template that fails halfway through a
+//! download:
+//!
+//! ```no_run
+//! use liblxc::*;
+//! let c = LxcContainer::new("web", None).unwrap();
+//! let template = DownloadTemplate::new("ubuntu", "trusty", "amd64").no_validate(true);
+//! c.create_from(&template, None, BDevSpecs::new(), LxcCreateFlag::Quiet).unwrap();
+//! ```
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use super::{BDevSpecs, LxcContainer, LxcCreateFlag};
+
+/// Directory liblxc looks for templates in, as installed by most distributions.
+pub const TEMPLATES_DIR: &str = "/usr/share/lxc/templates";
+
+/// List the templates installed in `dir_option`, or in `TEMPLATES_DIR` if `None`.
+///
+/// # Return value
+/// Returns `Ok` with the sorted names of the executable `lxc-*` files, without the
+/// prefix, else `Err` if the directory can't be read.
+pub fn list_templates(dir_option: Option<&str>) -> Result<Vec<String>, &'static str> {
+  let entries = fs::read_dir(dir_option.unwrap_or(TEMPLATES_DIR))
+                  .map_err(|_| "Failed to read the templates directory")?;
+  let mut templates = Vec::new();
+  for entry in entries {
+    let entry = entry.map_err(|_| "Failed to read the templates directory")?;
+    let executable = match entry.metadata() {
+      Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+      Err(_) => false,
+    };
+    let file_name = entry.file_name().to_string_lossy().into_owned();
+    if executable && file_name.starts_with("lxc-") && file_name.len() > 4 {
+      templates.push(file_name[4..].to_string());
+    }
+  }
+  templates.sort();
+  Ok(templates)
+}
+
+/// Template with its arguments, for `LxcContainer::create_from`.
+pub trait Template {
+  /// Name of the template, as passed to `create`.
+  fn name(&self) -> &str;
+
+  /// Arguments passed to the template.
+  fn args(&self) -> Vec<String>;
+
+  /// Check the arguments before the template runs.
+  ///
+  /// # Return value
+  /// Returns `Ok(())` if they look usable, else `Err` with what is wrong.
+  fn validate(&self) -> Result<(), &'static str> {
+    Ok(())
+  }
+}
+
+fn check_word(value: &str, what: &'static str) -> Result<(), &'static str> {
+  if value.is_empty() || value.chars().any(char::is_whitespace) {
+    return Err(what);
+  }
+  Ok(())
+}
+
+fn push_option(args: &mut Vec<String>, option: &str, value: &Option<String>) {
+  if let Some(ref value) = *value {
+    args.push(option.to_string());
+    args.push(value.clone());
+  }
+}
+
+fn push_flag(args: &mut Vec<String>, option: &str, set: bool) {
+  if set {
+    args.push(option.to_string());
+  }
+}
+
+/// Arguments of the `download` template, which fetches prebuilt images.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownloadTemplate {
+  dist: String,
+  release: String,
+  arch: String,
+  variant: Option<String>,
+  server: Option<String>,
+  keyserver: Option<String>,
+  no_validate: bool,
+  flush_cache: bool,
+}
+
+impl DownloadTemplate {
+
+  /// Image of distribution `dist`, release `release` and architecture `arch`,
+  /// for example `("ubuntu", "trusty", "amd64")`.
+  pub fn new(dist: &str, release: &str, arch: &str) -> DownloadTemplate {
+    DownloadTemplate {
+      dist: dist.to_string(),
+      release: release.to_string(),
+      arch: arch.to_string(),
+      variant: None,
+      server: None,
+      keyserver: None,
+      no_validate: false,
+      flush_cache: false,
+    }
+  }
+
+  /// Image variant (`--variant`), usually "default".
+  pub fn variant(mut self, variant: &str) -> DownloadTemplate {
+    self.variant = Some(variant.to_string());
+    self
+  }
+
+  /// Image server (`--server`).
+  pub fn server(mut self, server: &str) -> DownloadTemplate {
+    self.server = Some(server.to_string());
+    self
+  }
+
+  /// GPG keyserver used to validate images (`--keyserver`).
+  pub fn keyserver(mut self, keyserver: &str) -> DownloadTemplate {
+    self.keyserver = Some(keyserver.to_string());
+    self
+  }
+
+  /// Skip the GPG validation of the image (`--no-validate`).
+  pub fn no_validate(mut self, no_validate: bool) -> DownloadTemplate {
+    self.no_validate = no_validate;
+    self
+  }
+
+  /// Download the image again instead of using the local cache (`--flush-cache`).
+  pub fn flush_cache(mut self, flush_cache: bool) -> DownloadTemplate {
+    self.flush_cache = flush_cache;
+    self
+  }
+}
+
+impl Template for DownloadTemplate {
+  fn name(&self) -> &str {
+    "download"
+  }
+
+  fn args(&self) -> Vec<String> {
+    let mut args = vec!["--dist".to_string(), self.dist.clone(),
+                        "--release".to_string(), self.release.clone(),
+                        "--arch".to_string(), self.arch.clone()];
+    push_option(&mut args, "--variant", &self.variant);
+    push_option(&mut args, "--server", &self.server);
+    push_option(&mut args, "--keyserver", &self.keyserver);
+    push_flag(&mut args, "--no-validate", self.no_validate);
+    push_flag(&mut args, "--flush-cache", self.flush_cache);
+    args
+  }
+
+  fn validate(&self) -> Result<(), &'static str> {
+    check_word(&self.dist, "Invalid distribution")?;
+    check_word(&self.release, "Invalid release")?;
+    check_word(&self.arch, "Invalid architecture")?;
+    match self.variant {
+      Some(ref variant) => check_word(variant, "Invalid variant"),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Arguments of the `debian` template, which bootstraps with debootstrap.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebianTemplate {
+  release: Option<String>,
+  arch: Option<String>,
+  mirror: Option<String>,
+  security_mirror: Option<String>,
+  packages: Vec<String>,
+}
+
+impl DebianTemplate {
+
+  /// Template defaults: the current stable release for the host architecture.
+  pub fn new() -> DebianTemplate {
+    DebianTemplate::default()
+  }
+
+  /// Release to install (`--release`), for example "jessie".
+  pub fn release(mut self, release: &str) -> DebianTemplate {
+    self.release = Some(release.to_string());
+    self
+  }
+
+  /// Architecture to install (`--arch`).
+  pub fn arch(mut self, arch: &str) -> DebianTemplate {
+    self.arch = Some(arch.to_string());
+    self
+  }
+
+  /// Package mirror (`--mirror`).
+  pub fn mirror(mut self, mirror: &str) -> DebianTemplate {
+    self.mirror = Some(mirror.to_string());
+    self
+  }
+
+  /// Security updates mirror (`--security-mirror`).
+  pub fn security_mirror(mut self, mirror: &str) -> DebianTemplate {
+    self.security_mirror = Some(mirror.to_string());
+    self
+  }
+
+  /// Install an additional package (`--packages`).
+  pub fn package(mut self, package: &str) -> DebianTemplate {
+    self.packages.push(package.to_string());
+    self
+  }
+}
+
+impl Template for DebianTemplate {
+  fn name(&self) -> &str {
+    "debian"
+  }
+
+  fn args(&self) -> Vec<String> {
+    let mut args = Vec::new();
+    push_option(&mut args, "--release", &self.release);
+    push_option(&mut args, "--arch", &self.arch);
+    push_option(&mut args, "--mirror", &self.mirror);
+    push_option(&mut args, "--security-mirror", &self.security_mirror);
+    if !self.packages.is_empty() {
+      args.push("--packages".to_string());
+      args.push(self.packages.join(","));
+    }
+    args
+  }
+
+  fn validate(&self) -> Result<(), &'static str> {
+    for package in self.packages.iter() {
+      if package.is_empty() || package.contains(',') || package.chars().any(char::is_whitespace) {
+        return Err("Invalid package name");
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Arguments of the `ubuntu` template, which bootstraps with debootstrap.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UbuntuTemplate {
+  debian: DebianTemplate,
+  user: Option<String>,
+  auth_key: Option<String>,
+  flush_cache: bool,
+}
+
+impl UbuntuTemplate {
+
+  /// Template defaults: the latest LTS release for the host architecture.
+  pub fn new() -> UbuntuTemplate {
+    UbuntuTemplate::default()
+  }
+
+  /// Release to install (`--release`), for example "trusty".
+  pub fn release(mut self, release: &str) -> UbuntuTemplate {
+    self.debian = self.debian.release(release);
+    self
+  }
+
+  /// Architecture to install (`--arch`).
+  pub fn arch(mut self, arch: &str) -> UbuntuTemplate {
+    self.debian = self.debian.arch(arch);
+    self
+  }
+
+  /// Package mirror (`--mirror`).
+  pub fn mirror(mut self, mirror: &str) -> UbuntuTemplate {
+    self.debian = self.debian.mirror(mirror);
+    self
+  }
+
+  /// Security updates mirror (`--security-mirror`).
+  pub fn security_mirror(mut self, mirror: &str) -> UbuntuTemplate {
+    self.debian = self.debian.security_mirror(mirror);
+    self
+  }
+
+  /// Install an additional package (`--packages`).
+  pub fn package(mut self, package: &str) -> UbuntuTemplate {
+    self.debian = self.debian.package(package);
+    self
+  }
+
+  /// User created in the container instead of "ubuntu" (`--user`).
+  pub fn user(mut self, user: &str) -> UbuntuTemplate {
+    self.user = Some(user.to_string());
+    self
+  }
+
+  /// Public key file authorized for the user (`--auth-key`).
+  pub fn auth_key(mut self, path: &str) -> UbuntuTemplate {
+    self.auth_key = Some(path.to_string());
+    self
+  }
+
+  /// Bootstrap again instead of using the local cache (`--flush-cache`).
+  pub fn flush_cache(mut self, flush_cache: bool) -> UbuntuTemplate {
+    self.flush_cache = flush_cache;
+    self
+  }
+}
+
+impl Template for UbuntuTemplate {
+  fn name(&self) -> &str {
+    "ubuntu"
+  }
+
+  fn args(&self) -> Vec<String> {
+    let mut args = self.debian.args();
+    push_option(&mut args, "--user", &self.user);
+    push_option(&mut args, "--auth-key", &self.auth_key);
+    push_flag(&mut args, "--flush-cache", self.flush_cache);
+    args
+  }
+
+  fn validate(&self) -> Result<(), &'static str> {
+    self.debian.validate()?;
+    match self.user {
+      Some(ref user) => check_word(user, "Invalid user name"),
+      None => Ok(()),
+    }
+  }
+}
+
+impl LxcContainer {
+
+  /// Create a container from a template and its typed arguments.
+  ///
+  /// # Parameters
+  /// `template` - template to execute, with its arguments.
+  ///
+  /// `bdevtype_option` - `Option` with backing store type to use. If `None`, dir will be used.
+  ///
+  /// `bdev_specs` - additional parameters for the backing store.
+  ///
+  /// `flags` - `LxcCreateFlag` options
+  ///
+  /// # Return value
+  /// Returns `Ok(())` on success, else `Err` if the arguments are invalid or the
+  /// creation failed.
+  pub fn create_from<T: Template + ?Sized>(&self, template: &T,
+                                           bdevtype_option: Option<&str>,
+                                           bdev_specs: BDevSpecs,
+                                           flags: LxcCreateFlag) -> Result<(), &'static str> {
+    template.validate()?;
+    let args = template.args();
+    let argv_option = if args.is_empty() { None } else { Some(args.iter().map(|a| &a[..]).collect()) };
+    if !self.create(template.name(), bdevtype_option, bdev_specs, flags, argv_option) {
+      return Err("Failed to create the container");
+    }
+    Ok(())
+  }
+}
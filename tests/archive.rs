@@ -0,0 +1,203 @@
+//! Containers exported to archives and imported again.
+#![cfg(all(feature = "lxc-stub", feature = "archive"))]
+
+extern crate liblxc;
+extern crate tar;
+
+mod support;
+
+use liblxc::*;
+use liblxc::archive::{ExportOptions, ARCHIVE_VERSION};
+use std::fs::{self, File};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+
+/// Creates container "web" in `lxcpath` with a small rootfs, a network interface
+/// and a bind mount from its directory.
+fn web(lxcpath: &Path) -> LxcContainer {
+  let c = LxcContainer::new("web", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let dir = lxcpath.join("web");
+  let rootfs = dir.join("rootfs");
+  fs::create_dir_all(rootfs.join("etc")).unwrap();
+  fs::create_dir_all(rootfs.join("bin")).unwrap();
+  fs::write(rootfs.join("etc/hostname"), "web\n").unwrap();
+  fs::write(rootfs.join("bin/busybox"), "#!/bin/true\n").unwrap();
+  fs::set_permissions(rootfs.join("bin/busybox"), fs::Permissions::from_mode(0o755)).unwrap();
+  symlink("busybox", rootfs.join("bin/sh")).unwrap();
+  assert!(c.set_config_item("lxc.network.type", "veth"));
+  assert!(c.set_config_item("lxc.network.hwaddr", "00:16:3e:12:34:56"));
+  let entry = format!("{} srv none bind,create=dir 0 0", dir.join("data").display());
+  assert!(c.set_config_item("lxc.mount.entry", &entry));
+  assert!(c.save_config(&c.config_file_name().unwrap()));
+  c
+}
+
+fn entries(archive: &Path) -> Vec<String> {
+  let mut archive = tar::Archive::new(File::open(archive).unwrap());
+  archive.entries().unwrap().map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned()).collect()
+}
+
+#[test]
+fn export_and_import() {
+  let _stub = support::stub();
+  let work = support::temp_dir("archive-roundtrip");
+  let lxcpath = work.join("lxc");
+  let c = web(&lxcpath);
+  let archive = work.join("web.tar");
+
+  assert!(c.start(0, None));
+  let manifest = c.export(&archive, &ExportOptions::default()).unwrap();
+  assert!(c.is_running());
+  assert_eq!(manifest.version, ARCHIVE_VERSION);
+  assert_eq!(manifest.name, "web");
+  assert_eq!(manifest.lxcpath, lxcpath.to_str().unwrap());
+  assert_eq!(manifest.snapshots, Vec::new());
+  let names = entries(&archive);
+  assert_eq!(names[..2], ["manifest.json".to_string(), "config".to_string()]);
+  assert!(names.contains(&"rootfs/etc/hostname".to_string()));
+  assert!(!names.iter().any(|n| n.starts_with("snaps")));
+  assert!(!work.join("web.config.tmp").exists());
+
+  let other = work.join("other");
+  let copy = LxcContainer::import(&archive, "copy", Some(other.to_str().unwrap())).unwrap();
+  assert!(copy.is_defined());
+  assert_eq!(copy.state(), "STOPPED");
+  let dir = other.join("copy");
+  let rootfs = dir.join("rootfs");
+  assert_eq!(copy.get_config_item("lxc.rootfs"), Ok(rootfs.to_str().unwrap().to_string()));
+  assert_eq!(copy.get_config_item("lxc.utsname"), Ok("copy".to_string()));
+  assert_eq!(copy.get_config_item("lxc.network.0.type"), Ok("veth".to_string()));
+  let hwaddr = copy.get_config_item("lxc.network.0.hwaddr").unwrap();
+  assert!(hwaddr.starts_with("00:16:3e:") && hwaddr.len() == 17, "{}", hwaddr);
+  assert_ne!(hwaddr, "00:16:3e:12:34:56");
+  assert_eq!(copy.get_config_item("lxc.mount.entry"),
+             Ok(format!("{} srv none bind,create=dir 0 0", dir.join("data").display())));
+  assert_eq!(fs::read_to_string(rootfs.join("etc/hostname")).unwrap(), "copy\n");
+  assert_eq!(fs::metadata(rootfs.join("bin/busybox")).unwrap().permissions().mode() & 0o777, 0o755);
+  assert_eq!(fs::read_link(rootfs.join("bin/sh")).unwrap(), Path::new("busybox"));
+  let config = fs::read_to_string(dir.join("config")).unwrap();
+  assert!(config.contains("lxc.utsname = copy\n"), "{}", config);
+
+  let error = LxcContainer::import(&archive, "copy", Some(other.to_str().unwrap())).err().unwrap();
+  assert!(error.contains("already exists"), "{}", error);
+  assert!(c.stop());
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn snapshots_and_freezing() {
+  // liblxc 3.0 only knows lxc.rootfs.path and lxc.uts.name.
+  let stub = support::stub();
+  stub.set_version("3.0.0");
+  let work = support::temp_dir("archive-snapshots");
+  let lxcpath = work.join("lxc");
+  let c = web(&lxcpath);
+  let comment = work.join("comment");
+  fs::write(&comment, "before upgrade\n").unwrap();
+  assert_eq!(c.snapshot(Some(comment.to_str().unwrap())), Ok(0));
+  // liblxc copies the rootfs into the snapshot; the stub only records it.
+  let snap_rootfs = lxcpath.join("web/snaps/snap0/rootfs");
+  fs::create_dir_all(snap_rootfs.join("etc")).unwrap();
+  fs::write(snap_rootfs.join("etc/hostname"), "web\n").unwrap();
+  fs::write(snap_rootfs.join("version"), "1\n").unwrap();
+
+  assert!(c.start(0, None));
+  let archive = work.join("web.tar.gz");
+  let options = ExportOptions { snapshots: true, freeze: true, gzip: true };
+  let manifest = c.export(&archive, &options).unwrap();
+  assert_eq!(c.state(), "RUNNING");
+  assert_eq!(manifest.snapshots.len(), 1);
+  assert_eq!(manifest.snapshots[0].name, "snap0");
+  assert!(manifest.snapshots[0].comment);
+
+  let copy = LxcContainer::import(&archive, "copy", Some(lxcpath.to_str().unwrap())).unwrap();
+  let snapshots = copy.snapshot_list().unwrap();
+  assert_eq!(snapshots.len(), 1);
+  assert_eq!(snapshots[0].name, "snap0");
+  let dir = lxcpath.join("copy/snaps/snap0");
+  assert_eq!(fs::read_to_string(dir.join("comment")).unwrap(), "before upgrade\n");
+  assert_eq!(fs::read_to_string(dir.join("rootfs/version")).unwrap(), "1\n");
+  assert_eq!(fs::read_to_string(dir.join("rootfs/etc/hostname")).unwrap(), "copy\n");
+  assert_eq!(fs::read_to_string(dir.join("ts")).unwrap(), manifest.snapshots[0].timestamp);
+  let snapshot = LxcContainer::new("snap0", Some(&snapshots[0].lxcpath)).unwrap();
+  assert_eq!(snapshot.get_config_item("lxc.rootfs.path"), Ok(dir.join("rootfs").to_str().unwrap().to_string()));
+  assert_eq!(snapshot.get_config_item("lxc.uts.name"), Ok("copy".to_string()));
+  assert_eq!(copy.get_config_item("lxc.uts.name"), Ok("copy".to_string()));
+  assert!(c.stop());
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn errors() {
+  let stub = support::stub();
+  let work = support::temp_dir("archive-errors");
+  let lxcpath = work.join("lxc");
+  let lxcpath_str = lxcpath.to_str().unwrap();
+
+  let missing = LxcContainer::new("missing", Some(lxcpath_str)).unwrap();
+  assert!(missing.export(work.join("missing.tar"), &ExportOptions::default()).is_err());
+  assert!(!work.join("missing.tar").exists());
+
+  // A container without a rootfs directory can't be exported.
+  let c = LxcContainer::new("empty", Some(lxcpath_str)).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.export(work.join("empty.tar"), &ExportOptions::default()).unwrap_err().contains("rootfs"));
+  assert!(!work.join("empty.tar").exists());
+
+  let c = web(&lxcpath);
+
+  // Plain tarballs aren't container archives.
+  let tarball = work.join("plain.tar");
+  let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+  builder.append_dir_all("rootfs", lxcpath.join("web/rootfs")).unwrap();
+  builder.finish().unwrap();
+  let error = LxcContainer::import(&tarball, "copy", Some(lxcpath_str)).err().unwrap();
+  assert!(error.contains("not a container archive"), "{}", error);
+  assert!(!lxcpath.join("copy").exists());
+  assert!(!LxcContainer::new("copy", Some(lxcpath_str)).unwrap().is_defined());
+
+  // A running container that can't be stopped isn't exported.
+  assert!(c.start(0, None));
+  stub.fail("web", "stop");
+  assert!(c.export(work.join("web.tar"), &ExportOptions::default()).is_err());
+  assert!(c.is_running());
+  assert!(!work.join("web.tar").exists());
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn untrusted_manifests() {
+  let _stub = support::stub();
+  let work = support::temp_dir("archive-untrusted");
+  let lxcpath = work.join("lxc");
+  let lxcpath_str = lxcpath.to_str().unwrap();
+  let c = web(&lxcpath);
+
+  // Only whole path prefixes are moved, not those of the neighbouring "web2".
+  let neighbour = format!("{}2/hooks/start", lxcpath.join("web").display());
+  assert!(c.set_config_item("lxc.hook.pre-start", &neighbour));
+  assert!(c.save_config(&c.config_file_name().unwrap()));
+  let archive = work.join("web.tar");
+  c.export(&archive, &ExportOptions::default()).unwrap();
+  let copy = LxcContainer::import(&archive, "copy", Some(lxcpath_str)).unwrap();
+  assert_eq!(copy.get_config_item("lxc.hook.pre-start"), Ok(neighbour));
+
+  // Snapshot names are paths below snaps/, so they can't leave it.
+  let tampered = work.join("tampered.tar");
+  let manifest = format!(r#"{{"version":{},"name":"web","lxcpath":"{}","lxc_version":"1.1.5","created":0,
+                            "snapshots":[{{"name":"../../other","timestamp":"","comment":false}}]}}"#,
+                         ARCHIVE_VERSION, lxcpath_str);
+  let mut builder = tar::Builder::new(File::create(&tampered).unwrap());
+  let mut header = tar::Header::new_gnu();
+  header.set_size(manifest.len() as u64);
+  header.set_mode(0o644);
+  header.set_cksum();
+  builder.append_data(&mut header, "manifest.json", manifest.as_bytes()).unwrap();
+  builder.append_path_with_name(lxcpath.join("web/config"), "config").unwrap();
+  builder.finish().unwrap();
+  let error = LxcContainer::import(&tampered, "evil", Some(lxcpath_str)).err().unwrap();
+  assert!(error.contains("invalid snapshot name"), "{}", error);
+  assert!(!lxcpath.join("evil").exists());
+  fs::remove_dir_all(&work).unwrap();
+}
@@ -0,0 +1,65 @@
+//! Asynchronous API against the liblxc stub.
+#![cfg(all(feature = "lxc-stub", feature = "async"))]
+
+extern crate liblxc;
+extern crate tokio;
+
+mod support;
+
+use liblxc::*;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+fn runtime() -> Runtime {
+  Builder::new_current_thread().enable_time().build().unwrap()
+}
+
+#[test]
+fn lifecycle() {
+  let stub = support::stub();
+  runtime().block_on(async {
+    let c = AsyncLxcContainer::new(LxcContainer::new("async", None).unwrap());
+    assert!(c.create("download", None, BDevSpecs::new(), LxcCreateFlag::Quiet,
+                     Some(vec!["-d", "alpine"])).await);
+    assert!(c.start(0, None).await);
+    assert!(c.wait("RUNNING", 1).await);
+    assert_eq!(c.attach_run_wait("sh", vec!["sh", "-c", "exit 3"]).await, Ok(3));
+    assert!(c.shutdown(5).await);
+    assert!(!c.stop().await);
+    let copy = c.clone(Some("async-copy"), None, LxcCloneFlag::Void, None, None, 0, None).await;
+    assert_eq!(copy.unwrap().state().await, "STOPPED");
+  });
+  assert_eq!(stub.argv(None, "async", "create"), vec!["-d", "alpine"]);
+  assert_eq!(stub.live_containers(), 0);
+}
+
+#[test]
+fn wait_for_state_polls_without_blocking() {
+  let stub = support::stub();
+  let c = LxcContainer::new("polled", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let c = AsyncLxcContainer::new(c);
+  let rt = runtime();
+  assert!(!rt.block_on(c.wait_for_state("RUNNING", Duration::from_millis(50),
+                                        Duration::from_millis(10))));
+  std::thread::scope(|s| {
+    s.spawn(|| {
+      std::thread::sleep(Duration::from_millis(30));
+      stub.set_state(None, "polled", "RUNNING");
+    });
+    assert!(rt.block_on(c.wait_for_state("RUNNING", Duration::from_secs(5),
+                                         Duration::from_millis(10))));
+  });
+}
+
+#[test]
+fn failures_are_reported() {
+  let stub = support::stub();
+  stub.fail("doomed", "start");
+  runtime().block_on(async {
+    let c = AsyncLxcContainer::new(LxcContainer::new("doomed", None).unwrap());
+    assert!(!c.start(0, None).await);
+    assert!(c.attach_run_wait("true", vec!["true"]).await.is_err());
+    assert!(c.clone(Some("nope"), None, LxcCloneFlag::Void, None, None, 0, None).await.is_err());
+  });
+}
@@ -0,0 +1,93 @@
+//! Autostart settings and group operations against the liblxc stub.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+
+fn created(name: &str, auto: bool, order: i32, groups: &[&str]) -> LxcContainer {
+  let c = LxcContainer::new(name, None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.set_start_auto(auto));
+  assert!(c.set_start_order(order));
+  assert!(c.set_groups(groups));
+  c
+}
+
+fn names(containers: Vec<LxcContainer>) -> Vec<String> {
+  containers.iter().map(|c| c.name()).collect()
+}
+
+#[test]
+fn settings_round_trip() {
+  let _stub = support::stub();
+  let c = created("box", false, 0, &[]);
+  assert!(!c.start_auto());
+  assert_eq!(c.start_delay(), 0);
+  assert!(c.groups().is_empty());
+
+  assert!(c.set_start_auto(true));
+  assert!(c.set_start_order(-3));
+  assert!(c.set_start_delay(7));
+  assert!(c.set_groups(&["onboot", "web"]));
+  assert!(c.add_group("web"));
+  assert!(c.add_group("db"));
+  assert!(c.start_auto());
+  assert_eq!(c.start_order(), -3);
+  assert_eq!(c.start_delay(), 7);
+  assert_eq!(c.groups(), vec!["onboot", "web", "db"]);
+  assert_eq!(c.get_config_item("lxc.start.auto"), Ok("1".to_string()));
+
+  assert!(c.set_groups(&["web"]));
+  assert_eq!(c.groups(), vec!["web"]);
+}
+
+#[test]
+fn selection_and_order() {
+  let _stub = support::stub();
+  created("a", true, 0, &[]);
+  created("b", true, 5, &[]);
+  created("c", true, 0, &["web"]);
+  created("d", false, 9, &[]);
+  created("e", true, 1, &["db", "web"]);
+
+  let mut options = AutostartOptions::default();
+  assert_eq!(names(list_autostart_containers(&options).unwrap()), vec!["b", "a"]);
+
+  options.groups = vec!["web".to_string(), String::new()];
+  assert_eq!(names(list_autostart_containers(&options).unwrap()), vec!["e", "c", "b", "a"]);
+
+  options.groups = vec!["db".to_string(), "web".to_string()];
+  assert_eq!(names(list_autostart_containers(&options).unwrap()), vec!["e", "c"]);
+
+  options.all_groups = true;
+  options.ignore_auto = true;
+  assert_eq!(names(list_autostart_containers(&options).unwrap()), vec!["d", "b", "e", "a", "c"]);
+}
+
+#[test]
+fn actions_report_each_container() {
+  let stub = support::stub();
+  created("a", true, 0, &[]);
+  created("b", true, 5, &[]);
+  created("c", true, 1, &[]);
+  stub.fail("c", "start");
+  let options = AutostartOptions::default();
+
+  let outcomes = autostart(AutostartAction::Start, &options).unwrap();
+  let results: Vec<(&str, Result<(), &str>)> =
+    outcomes.iter().map(|o| (&o.name[..], o.result)).collect();
+  assert_eq!(results, vec![("b", Ok(())), ("c", Err("failed to start")), ("a", Ok(()))]);
+  assert!(stub.event(None, "b", "start") < stub.event(None, "a", "start"));
+
+  let outcomes = autostart(AutostartAction::Stop, &options).unwrap();
+  assert_eq!(outcomes.iter().map(|o| &o.name[..]).collect::<Vec<_>>(), vec!["a", "c", "b"]);
+  assert!(outcomes.iter().all(|o| o.result.is_ok()));
+  assert!(stub.event(None, "a", "stop") < stub.event(None, "b", "stop"));
+
+  assert!(autostart(AutostartAction::Reboot, &options).unwrap().iter().all(|o| o.result.is_ok()));
+  stub.fail("*", "list_containers");
+  assert!(autostart(AutostartAction::Kill, &options).is_err());
+}
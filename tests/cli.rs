@@ -0,0 +1,262 @@
+//! Runs the main-lxc binary against the liblxc stub. Each test keeps its host
+//! in its own state file (`LXC_STUB_STATE`), shared by the processes it spawns.
+#![cfg(feature = "lxc-stub")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+struct Host {
+  state: PathBuf,
+}
+
+impl Host {
+  fn new(test: &str) -> Host {
+    let state = env::temp_dir().join(format!("rust-lxc-cli-{}-{}", test, std::process::id()));
+    let _ = fs::remove_file(&state);
+    Host { state }
+  }
+
+  fn run(&self, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_main-lxc"))
+      .args(args)
+      .env("LXC_STUB_STATE", &self.state)
+      .output()
+      .unwrap()
+  }
+
+  /// Runs `args` and returns stdout, asserting the exit status.
+  fn expect(&self, args: &[&str], code: i32) -> String {
+    let output = self.run(args);
+    assert_eq!(output.status.code(), Some(code), "main-lxc {:?}: {}",
+               args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+  }
+}
+
+impl Drop for Host {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.state);
+  }
+}
+
+#[test]
+fn lifecycle() {
+  let host = Host::new("lifecycle");
+  host.expect(&["create", "web", "-t", "busybox", "-q"], 0);
+  host.expect(&["create", "db", "-t", "download", "--", "-d", "alpine"], 0);
+  host.expect(&["create", "web", "-t", "busybox"], 4);
+  host.expect(&["create", "img", "-d", "alpine", "-r", "3.4", "-a", "amd64", "--variant", "default"], 0);
+  assert_eq!(host.expect(&["list"], 0), "db\nimg\nweb\n");
+
+  host.expect(&["start", "web"], 0);
+  host.expect(&["start", "web"], 4);
+  assert_eq!(host.expect(&["list", "--active"], 0), "web\n");
+  let info = host.expect(&["info", "web"], 0);
+  assert!(info.starts_with("Name:           web\nState:          RUNNING\nPID:"), "{}", info);
+  assert!(info.contains("IP:"));
+
+  host.expect(&["freeze", "web"], 0);
+  host.expect(&["freeze", "web"], 4);
+  host.expect(&["unfreeze", "web"], 0);
+  assert!(host.expect(&["stop", "web", "-t", "5", "--", "true"], 0).starts_with("web stopped by shutdown after "));
+  host.expect(&["stop", "web"], 4);
+
+  host.expect(&["clone", "web", "web2"], 0);
+  host.expect(&["rename", "web2", "www"], 0);
+  assert_eq!(host.expect(&["list", "--defined"], 0), "db\nimg\nweb\nwww\n");
+  host.expect(&["destroy", "www"], 0);
+  host.expect(&["destroy", "www"], 3);
+  host.expect(&["info", "nope"], 3);
+}
+
+#[test]
+fn config_and_cgroups() {
+  let host = Host::new("config");
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-cli-lxcpath-config-{}", std::process::id()));
+  fs::create_dir_all(lxcpath.join("c1")).unwrap();
+  let lxcpath = lxcpath.to_str().unwrap();
+  host.expect(&["-P", lxcpath, "create", "c1", "-t", "busybox"], 0);
+  host.expect(&["-P", lxcpath, "config", "set", "c1", "lxc.start.auto", "1"], 0);
+  assert_eq!(host.expect(&["-P", lxcpath, "config", "get", "c1", "lxc.start.auto"], 0), "1\n");
+  let saved = fs::read_to_string(format!("{}/c1/config", lxcpath)).unwrap();
+  assert!(saved.contains("lxc.start.auto = 1\n"));
+  host.expect(&["-P", lxcpath, "config", "get", "c1", "bogus"], 1);
+  host.expect(&["-P", lxcpath, "cgroup", "get", "c1", "memory.limit_in_bytes"], 4);
+  host.expect(&["-P", lxcpath, "start", "c1"], 0);
+  host.expect(&["-P", lxcpath, "cgroup", "set", "c1", "memory.limit_in_bytes", "1024"], 0);
+  assert_eq!(host.expect(&["-P", lxcpath, "cgroup", "get", "c1", "memory.limit_in_bytes"], 0), "1024\n");
+  // Values may start with a dash once the positionals began.
+  host.expect(&["-P", lxcpath, "cgroup", "set", "c1", "memory.limit_in_bytes", "-1"], 0);
+  assert_eq!(host.expect(&["-P", lxcpath, "cgroup", "get", "c1", "memory.limit_in_bytes"], 0), "-1\n");
+  host.expect(&["-P", lxcpath, "cgroup", "set", "--bogus", "c1", "memory.limit_in_bytes", "1"], 2);
+  fs::remove_dir_all(lxcpath).unwrap();
+}
+
+#[test]
+fn exec_passes_exit_status() {
+  let host = Host::new("exec");
+  host.expect(&["create", "c1", "-t", "busybox"], 0);
+  host.expect(&["exec", "c1", "--", "true"], 4);
+  host.expect(&["start", "c1"], 0);
+  assert_eq!(host.expect(&["exec", "c1", "--", "echo", "hi"], 0), "hi\n");
+  host.expect(&["exec", "c1", "--", "sh", "-c", "exit 7"], 7);
+}
+
+#[test]
+fn ps_and_kill_arguments() {
+  let host = Host::new("kill");
+  host.expect(&["create", "c1", "-t", "busybox"], 0);
+  host.expect(&["ps", "c1"], 4);
+  host.expect(&["kill", "c1", "1"], 4);
+  host.expect(&["kill", "c1", "-s", "SIGKILL", "--all"], 4);
+  host.expect(&["kill", "c1"], 2);
+  host.expect(&["kill", "c1", "1", "--all"], 2);
+  host.expect(&["kill", "c1", "-s", "BOGUS", "1"], 2);
+  host.expect(&["kill", "c1", "x"], 2);
+}
+
+#[test]
+fn snapshots() {
+  let host = Host::new("snapshots");
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-cli-lxcpath-snapshots-{}", std::process::id()));
+  let lxcpath = lxcpath.to_str().unwrap();
+  host.expect(&["-P", lxcpath, "create", "c1", "-t", "busybox"], 0);
+  assert_eq!(host.expect(&["-P", lxcpath, "snapshot", "create", "c1"], 0), "snap0\n");
+  assert_eq!(host.expect(&["snapshot", "create", "c1", "--lxcpath", lxcpath], 0), "snap1\n");
+  let list = host.expect(&["-P", lxcpath, "snapshot", "list", "c1"], 0);
+  assert_eq!(list.lines().count(), 2);
+  assert!(list.starts_with("snap0 "));
+  host.expect(&["-P", lxcpath, "snapshot", "restore", "c1", "snap0", "c2"], 0);
+  host.expect(&["-P", lxcpath, "destroy", "c1"], 1);
+  host.expect(&["-P", lxcpath, "snapshot", "destroy", "c1", "snap1"], 0);
+  host.expect(&["-P", lxcpath, "destroy", "c1", "-s"], 0);
+  assert_eq!(host.expect(&["-P", lxcpath, "list"], 0), "c2\n");
+
+  assert_eq!(host.expect(&["-P", lxcpath, "snapshot", "create", "c2", "-T", "nightly"], 0), "snap0\n");
+  assert_eq!(host.expect(&["-P", lxcpath, "snapshot", "create", "c2", "--tag", "nightly"], 0), "snap1\n");
+  host.expect(&["-P", lxcpath, "snapshot", "create", "c2", "-T", "night ly"], 1);
+  host.expect(&["-P", lxcpath, "snapshot", "prune", "c2"], 2);
+  host.expect(&["-P", lxcpath, "snapshot", "prune", "c2", "--daily", "-1"], 2);
+  let prune = host.expect(&["-P", lxcpath, "snapshot", "prune", "c2", "--keep-last", "1", "--dry-run"], 0);
+  let lines: Vec<&str> = prune.lines().collect();
+  assert_eq!(lines.len(), 2, "{}", prune);
+  assert!(lines[0].starts_with("snap1 nightly ") && lines[0].ends_with(" keep (last)"), "{}", prune);
+  assert!(lines[1].starts_with("snap0 nightly ") && lines[1].ends_with(" would prune"), "{}", prune);
+  let prune = host.expect(&["-P", lxcpath, "snapshot", "prune", "c2", "--keep-last", "1"], 0);
+  assert!(prune.lines().nth(1).unwrap().ends_with(" pruned"), "{}", prune);
+  let list = host.expect(&["-P", lxcpath, "snapshot", "list", "c2"], 0);
+  assert!(list.starts_with("snap1 ") && list.lines().count() == 1, "{}", list);
+  let _ = fs::remove_dir_all(lxcpath);
+}
+
+#[test]
+fn usage_errors() {
+  let host = Host::new("usage");
+  assert!(host.expect(&["help"], 0).starts_with("Usage: main-lxc"));
+  host.expect(&[], 2);
+  host.expect(&["frobnicate"], 2);
+  host.expect(&["create", "c1"], 2);
+  host.expect(&["create", "c1", "-d", "alpine", "-r", "3.4"], 2);
+  host.expect(&["create", "c1", "-t", "busybox", "-d", "alpine", "-r", "3.4", "-a", "amd64"], 2);
+  host.expect(&["create", "c1", "-d", "alpine", "-r", "3.4", "-a", "amd64", "--", "--no-validate"], 2);
+  host.expect(&["create", "c1", "-d", "alpine", "-r", "", "-a", "amd64"], 1);
+  host.expect(&["templates", "-T", "/nonexistent"], 1);
+  host.expect(&["stop", "c1", "-t"], 2);
+  host.expect(&["list", "--bogus"], 2);
+  host.expect(&["config", "frob", "c1"], 2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_output() {
+  let host = Host::new("json");
+  host.expect(&["create", "c1", "-t", "busybox"], 0);
+  host.expect(&["create", "c2", "-t", "busybox"], 0);
+  host.expect(&["start", "c2"], 0);
+
+  let info: liblxc::ContainerInfo = serde_json::from_str(&host.expect(&["info", "c2", "--json"], 0)).unwrap();
+  assert_eq!(info.name, "c2");
+  assert_eq!(info.state, "RUNNING");
+  assert!(info.pid.is_some());
+  assert!(!info.ips.is_empty());
+
+  let list: serde_json::Value = serde_json::from_str(&host.expect(&["list", "--json"], 0)).unwrap();
+  let states: Vec<(&str, &str)> = list.as_array().unwrap().iter()
+    .map(|i| (i["name"].as_str().unwrap(), i["state"].as_str().unwrap()))
+    .collect();
+  assert_eq!(states, vec![("c1", "STOPPED"), ("c2", "RUNNING")]);
+  assert!(list[0]["pid"].is_null());
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn json_output_needs_serde() {
+  let host = Host::new("json");
+  host.expect(&["create", "c1", "-t", "busybox"], 0);
+  host.expect(&["info", "c1", "--json"], 2);
+}
+
+#[cfg(feature = "spec")]
+#[test]
+fn apply_spec() {
+  let host = Host::new("apply");
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-cli-lxcpath-apply-{}", std::process::id()));
+  fs::create_dir_all(lxcpath.join("web")).unwrap();
+  let file = lxcpath.join("web.yaml");
+  fs::write(&file, "name: web\ntemplate: busybox\nautostart: true\n").unwrap();
+  let lxcpath = lxcpath.to_str().unwrap();
+  let file = file.to_str().unwrap();
+
+  assert_eq!(host.expect(&["-P", lxcpath, "apply", file, "--dry-run"], 0),
+             "create -t busybox\nset lxc.start.auto = 1\n");
+  host.expect(&["-P", lxcpath, "info", "web"], 3);
+  assert_eq!(host.expect(&["-P", lxcpath, "apply", file], 0), "create -t busybox\nset lxc.start.auto = 1\n");
+  assert_eq!(host.expect(&["-P", lxcpath, "apply", file], 0), "");
+  host.expect(&["-P", lxcpath, "apply", "/nonexistent.toml"], 1);
+  fs::remove_dir_all(lxcpath).unwrap();
+}
+
+#[cfg(feature = "spec")]
+#[test]
+fn stack_up_and_down() {
+  let host = Host::new("stack");
+  let file = env::temp_dir().join(format!("rust-lxc-cli-stack-{}.toml", std::process::id()));
+  fs::write(&file, "[[containers]]\nname = \"db\"\n\n[[containers]]\nname = \"web\"\ndepends_on = [\"db\"]\n").unwrap();
+  let file = file.to_str().unwrap();
+  host.expect(&["stack", "up", file], 3);
+  host.expect(&["create", "db", "-t", "busybox"], 0);
+  host.expect(&["create", "web", "-t", "busybox"], 0);
+  assert_eq!(host.expect(&["stack", "order", file], 0), "db\nweb\n");
+  host.expect(&["stack", "up", file], 0);
+  assert_eq!(host.expect(&["list", "--active"], 0), "db\nweb\n");
+  host.expect(&["stack", "down", file], 0);
+  assert_eq!(host.expect(&["list", "--active"], 0), "");
+  host.expect(&["stack", "sideways", file], 2);
+  fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn autostart_groups() {
+  let host = Host::new("autostart");
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-cli-lxcpath-autostart-{}", std::process::id()));
+  let lxcpath_str = lxcpath.to_str().unwrap();
+  for name in ["a", "b", "c"].iter() {
+    fs::create_dir_all(lxcpath.join(name)).unwrap();
+    host.expect(&["-P", lxcpath_str, "create", name, "-t", "busybox"], 0);
+  }
+  host.expect(&["-P", lxcpath_str, "config", "set", "b", "lxc.start.auto", "1"], 0);
+  host.expect(&["-P", lxcpath_str, "config", "set", "c", "lxc.start.auto", "1"], 0);
+  host.expect(&["-P", lxcpath_str, "config", "set", "c", "lxc.group", "web"], 0);
+
+  assert_eq!(host.expect(&["-P", lxcpath_str, "autostart", "-L"], 0), "b\n");
+  assert_eq!(host.expect(&["-P", lxcpath_str, "autostart", "-L", "-g", "web,"], 0), "c\nb\n");
+  assert_eq!(host.expect(&["-P", lxcpath_str, "autostart", "-L", "-A", "-a"], 0), "a\nb\nc\n");
+  host.expect(&["-P", lxcpath_str, "autostart", "-g", "web", "-g", ""], 0);
+  assert_eq!(host.expect(&["-P", lxcpath_str, "list", "--active"], 0), "b\nc\n");
+  host.expect(&["-P", lxcpath_str, "autostart", "-a", "-s", "-t", "5"], 0);
+  assert_eq!(host.expect(&["-P", lxcpath_str, "list", "--active"], 0), "");
+  host.expect(&["-P", lxcpath_str, "autostart", "-s", "-k"], 2);
+  fs::remove_dir_all(&lxcpath).unwrap();
+}
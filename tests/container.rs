@@ -0,0 +1,359 @@
+//! Exercises the safe wrapper against the liblxc stub.
+//! Run with `cargo test --features lxc-stub`.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+
+fn created(name: &str) -> LxcContainer {
+  let c = LxcContainer::new(name, None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  c
+}
+
+#[test]
+fn version_comes_from_liblxc() {
+  let stub = support::stub();
+  stub.set_version("1.0.7");
+  assert_eq!(version(), "1.0.7");
+}
+
+#[test]
+fn new_reports_allocation_failure() {
+  let stub = support::stub();
+  stub.fail("broken", "lxc_container_new");
+  assert!(LxcContainer::new("broken", None).is_err());
+  assert!(LxcContainer::new("fine", None).is_ok());
+}
+
+#[test]
+fn handles_are_released_on_drop() {
+  let stub = support::stub();
+  {
+    let c = created("owned");
+    let _clone = c.clone(Some("owned-copy"), None, LxcCloneFlag::Void, None, None, 0, None).unwrap();
+    assert_eq!(stub.live_containers(), 2);
+  }
+  assert_eq!(stub.live_containers(), 0);
+}
+
+#[test]
+fn create_passes_template_and_argv() {
+  let stub = support::stub();
+  let c = LxcContainer::new("created", None).unwrap();
+  assert!(!c.is_defined());
+  let argv = vec!["-d", "ubuntu", "-r", "trusty", "-a", "amd64"];
+  assert!(c.create("download", None, BDevSpecs::new(), LxcCreateFlag::Verbose, Some(argv)));
+  assert!(c.is_defined());
+  assert_eq!(stub.template(None, "created").unwrap(), "download");
+  assert_eq!(stub.argv(None, "created", "create"),
+             vec!["-d", "ubuntu", "-r", "trusty", "-a", "amd64"]);
+  assert!(!c.create("download", None, BDevSpecs::new(), LxcCreateFlag::Verbose, None));
+}
+
+#[test]
+fn lifecycle() {
+  let _stub = support::stub();
+  let c = created("cycle");
+  assert_eq!(c.state(), "STOPPED");
+  assert!(!c.is_running());
+  assert_eq!(c.init_pid(), -1);
+
+  assert!(c.start(0, None));
+  assert!(c.is_running());
+  assert!(c.wait("RUNNING", 1));
+  assert!(c.init_pid() > 0);
+  assert!(!c.start(0, None));
+
+  assert!(c.freeze());
+  assert_eq!(c.state(), "FROZEN");
+  assert!(c.unfreeze());
+  assert_eq!(c.state(), "RUNNING");
+
+  assert!(!c.destroy());
+  assert!(c.stop());
+  assert!(!c.stop());
+  assert!(c.wait("STOPPED", 1));
+  assert!(c.destroy());
+  assert!(!c.is_defined());
+}
+
+#[test]
+fn start_passes_null_terminated_argv() {
+  let stub = support::stub();
+  let c = created("argv");
+  assert!(c.start(1, Some(vec!["/bin/sh", "-c", "true"])));
+  assert_eq!(stub.argv(None, "argv", "start"), vec!["/bin/sh", "-c", "true"]);
+}
+
+#[test]
+fn shutdown_and_reboot() {
+  let _stub = support::stub();
+  let c = created("power");
+  assert!(!c.reboot());
+  assert!(!c.shutdown(0));
+  assert!(c.start(0, None));
+  assert!(c.reboot());
+  assert!(c.shutdown(10));
+  assert_eq!(c.state(), "STOPPED");
+}
+
+#[test]
+fn config_items() {
+  let _stub = support::stub();
+  let c = created("config");
+  assert!(c.set_config_item("lxc.utsname", "box"));
+  assert_eq!(c.get_config_item("lxc.utsname"), Ok("box".to_string()));
+  assert_eq!(c.get_config_item("lxc.start.auto"), Ok("".to_string()));
+  assert!(c.get_config_item("err").is_err());
+  assert!(!c.set_config_item("err", "value"));
+
+  assert!(c.set_config_item("lxc.network.0.type", "veth"));
+  assert!(c.set_config_item("lxc.network.0.link", "lxcbr0"));
+  assert_eq!(c.get_keys("lxc.network.0"),
+             Ok(vec!["lxc.network.0.type".to_string(), "lxc.network.0.link".to_string()]));
+  assert!(c.get_keys("err").is_err());
+
+  assert!(c.clear_config_item("lxc.network"));
+  assert_eq!(c.get_config_item("lxc.network.0.type"), Ok("".to_string()));
+  c.clear_config();
+  assert_eq!(c.get_config_item("lxc.utsname"), Ok("".to_string()));
+}
+
+#[test]
+fn running_config_items() {
+  let _stub = support::stub();
+  let c = created("running-config");
+  assert!(c.get_running_config_item("lxc.utsname").is_err());
+  assert!(c.start(0, None));
+  assert_eq!(c.get_running_config_item("lxc.utsname"), Ok("running-config".to_string()));
+  assert!(c.get_running_config_item("lxc.missing").is_err());
+}
+
+#[test]
+fn config_paths() {
+  let _stub = support::stub();
+  let c = LxcContainer::new("paths", Some("/tmp/lxc")).unwrap();
+  assert_eq!(c.get_config_path(), "/tmp/lxc");
+  assert_eq!(c.config_file_name(), Ok("/tmp/lxc/paths/config".to_string()));
+  assert!(c.set_config_path(Some("/srv/lxc")));
+  assert_eq!(c.config_file_name(), Ok("/srv/lxc/paths/config".to_string()));
+  assert!(!c.set_config_path(None));
+}
+
+#[test]
+fn config_file_name_error() {
+  let stub = support::stub();
+  stub.fail("nameless", "config_file_name");
+  let c = LxcContainer::new("nameless", None).unwrap();
+  assert!(c.config_file_name().is_err());
+}
+
+#[test]
+fn network_queries() {
+  let _stub = support::stub();
+  let c = created("net");
+  assert!(c.get_interfaces().is_empty());
+  assert!(c.get_ips(None, None, 0).is_empty());
+  assert!(c.start(0, None));
+  assert_eq!(c.get_interfaces(), vec!["eth0", "lo"]);
+  assert_eq!(c.get_ips(None, Some("inet"), 0).len(), 1);
+  assert_eq!(c.get_ips(Some("eth0"), None, 0).len(), 2);
+  assert!(c.get_ips(Some("lo"), None, 0).is_empty());
+}
+
+#[test]
+fn cgroup_items() {
+  let _stub = support::stub();
+  let c = created("cgroup");
+  assert!(!c.set_cgroup_item("memory.limit_in_bytes", "1G"));
+  assert!(c.start(0, None));
+  assert!(c.get_cgroup_item("memory.limit_in_bytes").is_err());
+  assert!(c.set_cgroup_item("memory.limit_in_bytes", "1073741824"));
+  assert_eq!(c.get_cgroup_item("memory.limit_in_bytes"), Ok("1073741824".to_string()));
+}
+
+#[test]
+fn clone_and_rename() {
+  let _stub = support::stub();
+  let c = created("origin");
+  let copy = c.clone(Some("copy"), None, LxcCloneFlag::Snapshot, None, None, 0,
+                     Some(vec!["--hook-arg"])).unwrap();
+  assert!(copy.is_defined());
+  assert_eq!(copy.get_config_item("lxc.utsname"), Ok("copy".to_string()));
+  assert!(c.clone(Some("copy"), None, LxcCloneFlag::Void, None, None, 0, None).is_err());
+
+  assert!(copy.rename("renamed"));
+  assert_eq!(copy.config_file_name(), Ok("/var/lib/lxc/renamed/config".to_string()));
+  assert!(!c.rename("renamed"));
+
+  assert!(c.start(0, None));
+  assert!(c.clone(Some("running-copy"), None, LxcCloneFlag::Void, None, None, 0, None).is_err());
+}
+
+#[test]
+fn scripted_failures() {
+  let stub = support::stub();
+  let c = created("flaky");
+  stub.fail("flaky", "start");
+  assert!(!c.start(0, None));
+  assert_eq!(c.state(), "STOPPED");
+  stub.fail("*", "get_config_item");
+  assert!(c.get_config_item("lxc.utsname").is_err());
+}
+
+#[test]
+fn external_state_changes_are_visible() {
+  let stub = support::stub();
+  let c = created("crashy");
+  assert!(c.start(0, None));
+  stub.set_state(None, "crashy", "STOPPED");
+  assert!(!c.is_running());
+  assert_eq!(c.init_pid(), -1);
+}
+
+#[test]
+fn save_config_writes_file() {
+  let _stub = support::stub();
+  let c = created("saved");
+  assert!(c.set_config_item("lxc.cap.drop", "sys_module"));
+  assert!(c.set_config_item("lxc.cap.drop", "mac_admin"));
+  let path = std::env::temp_dir().join(format!("rust-lxc-stub-{}.conf", std::process::id()));
+  assert!(c.save_config(path.to_str().unwrap()));
+  let saved = std::fs::read_to_string(&path).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  assert!(saved.contains("lxc.utsname = saved\n"));
+  assert!(saved.contains("lxc.cap.drop = sys_module\nlxc.cap.drop = mac_admin\n"));
+  assert!(!c.save_config("/nonexistent/dir/config"));
+}
+
+#[test]
+fn attach_run_wait_reports_exit_code() {
+  let _stub = support::stub();
+  let c = created("attached");
+  assert!(c.attach_run_wait("true", vec!["true"]).is_err());
+  assert!(c.start(0, None));
+  assert_eq!(c.attach_run_wait("true", vec!["true"]), Ok(0));
+  assert_eq!(c.attach_run_wait("sh", vec!["sh", "-c", "exit 42"]), Ok(42));
+  assert!(c.attach_run_wait("sh", vec!["sh", "-c", "kill -9 $$"]).is_err());
+}
+
+#[test]
+fn attach_run_wait_releases_fork_guard_while_waiting() {
+  let _stub = support::stub();
+  let c = created("attached");
+  assert!(c.start(0, None));
+  let waiter = std::thread::spawn(move || c.attach_run_wait("sleep", vec!["sleep", "1"]));
+  std::thread::sleep(std::time::Duration::from_millis(200));
+  let started = std::time::Instant::now();
+  drop(fork_guard());
+  assert!(started.elapsed() < std::time::Duration::from_millis(500));
+  assert_eq!(waiter.join().unwrap(), Ok(0));
+}
+
+#[test]
+fn attach_run_wait_with_options() {
+  let _stub = support::stub();
+  let c = created("attached");
+  assert!(c.start(0, None));
+  let check = |options: &AttachOptions, script: &str| {
+    c.attach_run_wait_with(options, "sh", vec!["sh", "-c", script])
+  };
+  assert_eq!(check(&AttachOptions::default(), "test -z \"$LXC_STUB_UID$GREETING\""), Ok(0));
+  let options = AttachOptions {
+    cwd: Some("/".to_string()),
+    uid: Some(1000),
+    gid: Some(100),
+    env: vec!["GREETING=hello".to_string()],
+    ..AttachOptions::default()
+  };
+  assert_eq!(check(&options, "test \"$(pwd) $GREETING $LXC_STUB_UID $LXC_STUB_GID\" = \"/ hello 1000 100\""), Ok(0));
+  assert_eq!(check(&options, "test -n \"$CARGO\""), Ok(0));
+  let options = AttachOptions { clear_env: true, ..options };
+  assert_eq!(check(&options, "test -z \"$CARGO\" && test -n \"$GREETING\""), Ok(0));
+}
+
+#[test]
+fn snapshots() {
+  let stub = support::stub();
+  stub.set_time(86400);
+  let lxcpath = std::env::temp_dir().join(format!("rust-lxc-snapshots-{}", std::process::id()));
+  let lxcpath = lxcpath.to_str().unwrap();
+  let c = LxcContainer::new("snapped", Some(lxcpath)).unwrap();
+  assert!(c.snapshot(None).is_err());
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+
+  let comment = format!("{}.comment", lxcpath);
+  std::fs::write(&comment, "before upgrade\n").unwrap();
+  assert_eq!(c.snapshot(Some(&comment)), Ok(0));
+  assert_eq!(c.snapshot(None), Ok(1));
+  std::fs::remove_file(&comment).unwrap();
+
+  let snapshots = c.snapshot_list().unwrap();
+  assert_eq!(snapshots.len(), 2);
+  assert_eq!(snapshots[0].name, "snap0");
+  assert_eq!(snapshots[0].timestamp, "1970:01:02 00:00:00");
+  assert_eq!(snapshots[0].lxcpath, format!("{}/snapped/snaps", lxcpath));
+  let comment_path = snapshots[0].comment_pathname.clone().unwrap();
+  assert_eq!(std::fs::read_to_string(&comment_path).unwrap(), "before upgrade\n");
+  assert_eq!(snapshots[1].comment_pathname, None);
+
+  assert!(c.snapshot_restore("snap0", Some("restored")));
+  assert!(LxcContainer::new("restored", Some(lxcpath)).unwrap().is_defined());
+  assert!(!c.destroy());
+  assert!(c.snapshot_destroy("snap1"));
+  assert!(!c.snapshot_destroy("snap1"));
+  assert_eq!(c.snapshot_list().unwrap().len(), 1);
+  assert!(c.snapshot_destroy_all());
+  assert!(c.snapshot_list().unwrap().is_empty());
+  assert!(c.destroy());
+
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  assert!(c.snapshot(None).is_err());
+  std::fs::remove_dir_all(lxcpath).unwrap();
+}
+
+#[test]
+fn info_gathers_everything() {
+  let stub = support::stub();
+  let c = created("inventoried");
+  let info = c.info();
+  assert_eq!(info.name, "inventoried");
+  assert_eq!(info.state, "STOPPED");
+  assert_eq!(info.pid, None);
+  assert!(info.ips.is_empty());
+  assert_eq!(info.config_path, "/var/lib/lxc");
+  assert_eq!(info.config_file, Some("/var/lib/lxc/inventoried/config".to_string()));
+  assert!(info.cgroup.is_empty());
+
+  assert!(c.start(0, None));
+  assert!(c.set_cgroup_item("memory.usage_in_bytes", "4096\n"));
+  let info = c.info();
+  assert_eq!(info.state, "RUNNING");
+  assert_eq!(info.pid, Some(c.init_pid()));
+  assert_eq!(info.interfaces, c.get_interfaces());
+  assert_eq!(info.ips, c.get_ips(None, None, 0));
+  assert_eq!(info.cgroup.get("memory.usage_in_bytes").map(|v| &v[..]), Some("4096"));
+  assert_eq!(info.cgroup.len(), 1);
+
+  // The cgroup2 items stand in for those the unified hierarchy lacks.
+  assert!(c.stop());
+  assert!(c.start(0, None));
+  assert!(c.set_cgroup_item("memory.current", "8192\n"));
+  assert!(c.set_cgroup_item("memory.max", "max\n"));
+  assert!(c.set_cgroup_item("cpu.stat", "usage_usec 1000\nuser_usec 600\n"));
+  assert!(c.set_cgroup_item("pids.current", "3\n"));
+  let cgroup = c.info().cgroup;
+  assert_eq!(cgroup.get("memory.current").map(|v| &v[..]), Some("8192"));
+  assert_eq!(cgroup.get("memory.max").map(|v| &v[..]), Some("max"));
+  assert_eq!(cgroup.get("cpu.stat").map(|v| &v[..]), Some("usage_usec 1000\nuser_usec 600"));
+  assert_eq!(cgroup.get("pids.current").map(|v| &v[..]), Some("3"));
+  assert_eq!(cgroup.len(), 4);
+
+  stub.fail("*", "get_ips");
+  assert!(c.info().ips.is_empty());
+}
@@ -0,0 +1,136 @@
+//! Differences between the rootfs of a container and its snapshots.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use liblxc::diff::FileType;
+use std::env;
+use std::fs::{self, File};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn write(root: &Path, path: &str, content: &str) {
+  let path = root.join(path);
+  fs::create_dir_all(path.parent().unwrap()).unwrap();
+  fs::write(&path, content).unwrap();
+  fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+  // The same modification time everywhere, so that only content tells files apart.
+  File::options().write(true).open(&path).unwrap()
+    .set_modified(UNIX_EPOCH + Duration::from_secs(86400)).unwrap();
+}
+
+/// Writes the rootfs the snapshots are taken of.
+fn rootfs(root: &Path) {
+  write(root, "bin/busybox", "bb");
+  write(root, "bin/busybox2", "bb2");
+  symlink("busybox", root.join("bin/sh")).unwrap();
+  write(root, "etc/hostname", "web\n");
+  write(root, "etc/motd", "hello\n");
+  write(root, "etc/passwd", "root\n");
+  write(root, "opt/app/run", "x");
+  write(root, "var/log", "log");
+}
+
+/// Changes the rootfs written by `rootfs` the way a provisioning run could.
+fn provision(root: &Path) {
+  fs::remove_file(root.join("bin/sh")).unwrap();
+  symlink("busybox2", root.join("bin/sh")).unwrap();
+  write(root, "etc/hostname", "wab\n");
+  fs::set_permissions(root.join("etc/motd"), fs::Permissions::from_mode(0o600)).unwrap();
+  fs::remove_file(root.join("etc/passwd")).unwrap();
+  write(root, "etc/shadow", "root:*\n");
+  fs::remove_dir_all(root.join("opt/app")).unwrap();
+  write(root, "srv/www", "<html>");
+  fs::remove_file(root.join("var/log")).unwrap();
+  write(root, "var/log/syslog", "");
+}
+
+fn changes(diff: RootfsDiff) -> Vec<String> {
+  diff.map(|c| {
+    let c = c.unwrap();
+    format!("{} {}", c.kind.as_char(), c.path.display())
+  }).collect()
+}
+
+#[test]
+fn container_and_snapshots() {
+  let _stub = support::stub();
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-diff-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&lxcpath);
+  let c = LxcContainer::new("web", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let current = lxcpath.join("web/rootfs");
+  rootfs(&current);
+  assert_eq!(c.snapshot(None), Ok(0));
+  // liblxc copies the rootfs into the snapshot; the stub only records it.
+  rootfs(&lxcpath.join("web/snaps/snap0/rootfs"));
+  provision(&current);
+
+  let expected = vec!["M bin/sh", "M etc/hostname", "M etc/motd", "D etc/passwd", "A etc/shadow",
+                      "D opt/app", "D opt/app/run", "A srv", "A srv/www", "M var/log", "A var/log/syslog"];
+  assert_eq!(changes(c.diff_snapshot("snap0", &DiffOptions::default()).unwrap()), expected);
+
+  let all: Vec<FsChange> = c.diff_snapshot("snap0", &DiffOptions::default()).unwrap().map(|c| c.unwrap()).collect();
+  assert!(all[0].content_changed);
+  assert_eq!(all[0].new.as_ref().unwrap().link_target.as_ref().unwrap(), Path::new("busybox2"));
+  assert!(all[1].content_changed && !all[1].mode_changed());
+  assert!(all[2].mode_changed() && !all[2].content_changed);
+  assert_eq!((all[2].old.as_ref().unwrap().mode, all[2].new.as_ref().unwrap().mode), (0o644, 0o600));
+  assert_eq!(all[3].new, None);
+  assert_eq!(all[4].old, None);
+  assert!(all[9].type_changed());
+  assert_eq!(all[9].new.as_ref().unwrap().file_type, FileType::Dir);
+
+  // Without content checks, a file of the same size and time looks unchanged.
+  let quick = DiffOptions { check_content: false, include_mtime: false };
+  let quick_changes = changes(c.diff_snapshot("snap0", &quick).unwrap());
+  assert!(!quick_changes.contains(&"M etc/hostname".to_string()));
+  assert_eq!(quick_changes.len(), expected.len() - 1);
+
+  // Two snapshots, the second taken after provisioning.
+  assert_eq!(c.snapshot(None), Ok(1));
+  let snap1 = lxcpath.join("web/snaps/snap1/rootfs");
+  rootfs(&snap1);
+  provision(&snap1);
+  assert_eq!(changes(c.diff_snapshots("snap0", "snap1", &DiffOptions::default()).unwrap()), expected);
+  assert_eq!(changes(c.diff_snapshot("snap1", &DiffOptions::default()).unwrap()), Vec::<String>::new());
+  let reverse = changes(c.diff_snapshots("snap1", "snap0", &DiffOptions::default()).unwrap());
+  assert_eq!(reverse[3..5], ["A etc/passwd".to_string(), "D etc/shadow".to_string()]);
+
+  // Modification times are only reported if asked for.
+  File::options().write(true).open(current.join("bin/busybox")).unwrap()
+    .set_modified(UNIX_EPOCH + Duration::from_secs(2 * 86400)).unwrap();
+  assert_eq!(changes(c.diff_snapshot("snap1", &DiffOptions::default()).unwrap()), Vec::<String>::new());
+  let with_mtime = DiffOptions { check_content: true, include_mtime: true };
+  let changed: Vec<FsChange> = c.diff_snapshot("snap1", &with_mtime).unwrap().map(|c| c.unwrap()).collect();
+  assert_eq!(changed.len(), 1);
+  assert!(changed[0].mtime_changed() && !changed[0].content_changed);
+
+  assert!(c.diff_snapshot("snap7", &DiffOptions::default()).err().unwrap().contains("no snapshot snap7"));
+  fs::remove_dir_all(lxcpath.join("web/snaps/snap1/rootfs")).unwrap();
+  assert!(c.diff_snapshot("snap1", &DiffOptions::default()).is_err());
+  assert!(c.snapshot_destroy_all());
+  let _ = fs::remove_dir_all(&lxcpath);
+}
+
+#[test]
+fn renamed_rootfs_key() {
+  // liblxc 3.0 only knows lxc.rootfs.path.
+  let stub = support::stub();
+  stub.set_version("3.0.0");
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-diff-renamed-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&lxcpath);
+  let c = LxcContainer::new("web", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  rootfs(&lxcpath.join("web/rootfs"));
+  assert_eq!(c.snapshot(None), Ok(0));
+  rootfs(&lxcpath.join("web/snaps/snap0/rootfs"));
+  write(&lxcpath.join("web/rootfs"), "etc/motd", "hi\n");
+  assert_eq!(changes(c.diff_snapshot("snap0", &DiffOptions::default()).unwrap()), ["M etc/motd"]);
+  assert!(c.snapshot_destroy_all());
+  let _ = fs::remove_dir_all(&lxcpath);
+}
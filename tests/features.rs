@@ -0,0 +1,142 @@
+//! liblxc versions and the features depending on them.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+
+#[test]
+fn versions() {
+  let v: LxcVersion = "4.0.12".parse().unwrap();
+  assert_eq!(v, LxcVersion::new(4, 0, 12));
+  assert_eq!("2.1".parse::<LxcVersion>().unwrap(), LxcVersion::new(2, 1, 0));
+  let rc: LxcVersion = "3.0.0.rc1".parse().unwrap();
+  assert_eq!(rc.suffix, ".rc1");
+  assert_eq!(rc.to_string(), "3.0.0.rc1");
+  assert_eq!(" 5.0.3-devel\n".parse::<LxcVersion>().unwrap().suffix, "-devel");
+  for invalid in &["", "4", "x.1", "4.x", "-1.0"] {
+    assert!(invalid.parse::<LxcVersion>().is_err(), "{}", invalid);
+  }
+
+  assert!(LxcVersion::new(1, 0, 7) < LxcVersion::new(1, 1, 0));
+  assert!(LxcVersion::new(2, 0, 11) < LxcVersion::new(2, 1, 0));
+  assert!(LxcVersion::new(10, 0, 0) > LxcVersion::new(9, 9, 9));
+  assert!(rc < LxcVersion::new(3, 0, 0) && rc > LxcVersion::new(2, 1, 1));
+  assert_eq!(LxcVersion::new(2, 0, 11).features(), vec![LxcFeature::CheckpointRestore, LxcFeature::AttachInterface]);
+  assert!(LxcVersion::new(2, 1, 1).supports(LxcFeature::NewConfigKeys));
+  assert!(!rc.supports(LxcFeature::ConsoleLog));
+  assert_eq!(LxcVersion::new(4, 0, 0).features(), LxcFeature::ALL.to_vec());
+
+  for feature in &LxcFeature::ALL {
+    assert_eq!(feature.to_string().parse::<LxcFeature>().unwrap(), *feature);
+  }
+  assert!("criu".parse::<LxcFeature>().is_err());
+}
+
+#[test]
+fn current_version() {
+  let stub = support::stub();
+  assert_eq!(LxcVersion::current().unwrap(), LxcVersion::new(1, 1, 5));
+  assert!(has_feature(LxcFeature::CheckpointRestore));
+  assert!(!has_feature(LxcFeature::Cgroup2));
+  stub.set_version("4.0.12");
+  assert!(has_feature(LxcFeature::Cgroup2));
+  stub.set_version("devel");
+  assert!(LxcVersion::current().is_err());
+  assert!(!has_feature(LxcFeature::CheckpointRestore));
+}
+
+#[test]
+fn unsupported() {
+  let stub = support::stub();
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  stub.set_version("1.0.7");
+  // A failing slot would give "Couldn't ...", so these never reach it.
+  stub.fail("web", "checkpoint");
+  stub.fail("web", "attach_interface");
+  assert_eq!(c.checkpoint("/tmp/checkpoint", true, false),
+             Err("checkpoint needs liblxc 1.1.0 or newer, not 1.0.7".to_string()));
+  assert!(c.restore("/tmp/checkpoint", false).is_err());
+  assert_eq!(c.attach_interface("eth1", None),
+             Err("attach_interface needs liblxc 1.1.0 or newer, not 1.0.7".to_string()));
+  assert!(c.detach_interface("eth1", None).is_err());
+  // Nothing was called, the container still runs.
+  assert_eq!(c.state(), "RUNNING");
+
+  assert!(c.stop());
+  assert_eq!(c.snapshot(None), Ok(0));
+  assert!(!c.snapshot_destroy_all());
+  assert_eq!(c.snapshot_list().unwrap().len(), 1);
+  assert!(!c.destroy_with_snapshots());
+  assert!(c.is_defined());
+  stub.set_version("1.1.0");
+  assert!(c.snapshot_destroy_all());
+  assert_eq!(c.snapshot_list().unwrap().len(), 0);
+}
+
+#[test]
+fn configuration_keys() {
+  let stub = support::stub();
+  assert_eq!(key_feature("lxc.cgroup2.memory.max"), Some(LxcFeature::Cgroup2));
+  assert_eq!(key_feature("lxc.console.buffer.size"), Some(LxcFeature::ConsoleLog));
+  assert_eq!(key_feature("lxc.cgroup.memory.limit_in_bytes"), None);
+
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(!c.set_config_item("lxc.cgroup2.memory.max", "1G"));
+  assert!(!c.set_config_item("lxc.console.buffer.size", "auto"));
+  assert!(c.get_config_item("lxc.cgroup2.memory.max").is_err());
+  assert!(c.set_config_item("lxc.cgroup.memory.limit_in_bytes", "1G"));
+  assert_eq!(c.get_config_item("lxc.cgroup.memory.limit_in_bytes"), Ok("1G".to_string()));
+
+  stub.set_version("3.0.0");
+  assert!(c.set_config_item("lxc.console.buffer.size", "auto"));
+  assert!(!c.set_config_item("lxc.cgroup2.memory.max", "1G"));
+  stub.set_version("4.0.12");
+  assert!(c.set_config_item("lxc.cgroup2.memory.max", "1G"));
+  assert_eq!(c.get_config_item("lxc.cgroup2.memory.max"), Ok("1G".to_string()));
+  assert!(c.clear_config_item("lxc.cgroup2.memory.max"));
+  assert_eq!(c.get_config_item("lxc.cgroup2.memory.max"), Ok(String::new()));
+}
+
+#[test]
+fn renamed_keys() {
+  let stub = support::stub();
+  assert_eq!(key_feature("lxc.rootfs.path"), Some(LxcFeature::NewConfigKeys));
+  assert_eq!(key_feature("lxc.rootfs"), None);
+
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(!c.set_config_item("lxc.uts.name", "www"));
+  assert!(c.set_config_item("lxc.utsname", "www"));
+  stub.set_version("2.1.1");
+  assert!(c.set_config_item("lxc.uts.name", "www"));
+  assert!(c.set_config_item("lxc.utsname", "www"));
+  stub.set_version("3.0.0");
+  assert!(c.set_config_item("lxc.uts.name", "www"));
+  assert!(!c.set_config_item("lxc.utsname", "www"));
+  assert!(c.get_config_item("lxc.rootfs").is_err());
+}
+
+#[test]
+fn supported() {
+  let stub = support::stub();
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert_eq!(c.attach_interface("eth1", None), Err("Couldn't attach interface".to_string()));
+  assert!(c.start(0, None));
+  assert_eq!(c.attach_interface("eth1", Some("eth0")), Ok(()));
+  assert_eq!(c.detach_interface("eth0", Some("eth1")), Ok(()));
+
+  assert!(c.restore("/tmp/checkpoint", false).is_err());
+  assert_eq!(c.checkpoint("/tmp/checkpoint", true, false), Ok(()));
+  assert_eq!(c.state(), "STOPPED");
+  assert_eq!(c.restore("/tmp/checkpoint", true), Ok(()));
+  assert_eq!(c.state(), "RUNNING");
+  stub.fail("web", "checkpoint");
+  assert_eq!(c.checkpoint("/tmp/checkpoint", false, false), Err("Couldn't checkpoint container".to_string()));
+}
@@ -0,0 +1,175 @@
+//! Files pushed into containers and pulled out of them.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::fs;
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+fn mode(path: &Path) -> u32 {
+  fs::symlink_metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+/// Writes a small tree to push: a file, a script in a subdirectory and a symlink.
+fn tree(dir: &Path) {
+  fs::create_dir_all(dir.join("conf.d")).unwrap();
+  fs::write(dir.join("app.conf"), "port = 80\n").unwrap();
+  fs::set_permissions(dir.join("app.conf"), fs::Permissions::from_mode(0o640)).unwrap();
+  fs::write(dir.join("conf.d/run"), "#!/bin/sh\n").unwrap();
+  fs::set_permissions(dir.join("conf.d/run"), fs::Permissions::from_mode(0o755)).unwrap();
+  fs::set_permissions(dir.join("conf.d"), fs::Permissions::from_mode(0o750)).unwrap();
+  symlink("app.conf", dir.join("current")).unwrap();
+}
+
+fn check_tree(dir: &Path) {
+  assert_eq!(fs::read_to_string(dir.join("app.conf")).unwrap(), "port = 80\n");
+  assert_eq!(mode(&dir.join("app.conf")), 0o640);
+  assert_eq!(fs::read_to_string(dir.join("conf.d/run")).unwrap(), "#!/bin/sh\n");
+  assert_eq!(mode(&dir.join("conf.d/run")), 0o755);
+  assert_eq!(mode(&dir.join("conf.d")), 0o750);
+  assert_eq!(fs::read_link(dir.join("current")).unwrap(), Path::new("app.conf"));
+}
+
+#[test]
+fn stopped_container() {
+  let _stub = support::stub();
+  let work = support::temp_dir("files-stopped");
+  let lxcpath = work.join("lxc");
+  let c = LxcContainer::new("web", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let rootfs = lxcpath.join("web/rootfs");
+  fs::create_dir_all(rootfs.join("etc")).unwrap();
+  // Container root is the caller, so that owners can be changed without privileges.
+  let uid = fs::metadata(&work).unwrap().uid();
+  let gid = fs::metadata(&work).unwrap().gid();
+  assert!(c.set_config_item("lxc.id_map", &format!("u 0 {} 1", uid)));
+  assert!(c.set_config_item("lxc.id_map", &format!("g 0 {} 1", gid)));
+  assert!(c.set_config_item("lxc.id_map", "u 1 100000 65535"));
+
+  let host = work.join("host");
+  tree(&host);
+  c.push_file(host.join("app.conf"), "/etc/app.conf", 0o600, 0, 0).unwrap();
+  let pushed = rootfs.join("etc/app.conf");
+  assert_eq!(fs::read_to_string(&pushed).unwrap(), "port = 80\n");
+  assert_eq!(mode(&pushed), 0o600);
+  assert_eq!((fs::metadata(&pushed).unwrap().uid(), fs::metadata(&pushed).unwrap().gid()), (uid, gid));
+  assert_eq!(fs::read_dir(rootfs.join("etc")).unwrap().count(), 1);
+
+  // Symlinks are resolved inside the rootfs, even absolute ones and those going up.
+  symlink("/etc", rootfs.join("config")).unwrap();
+  symlink("../../../etc/app.conf", rootfs.join("etc/link")).unwrap();
+  fs::write(host.join("motd"), "hi\n").unwrap();
+  c.push_file(host.join("motd"), "/config/motd", 0o644, 0, 0).unwrap();
+  assert_eq!(fs::read_to_string(rootfs.join("etc/motd")).unwrap(), "hi\n");
+  let pulled = work.join("pulled.conf");
+  c.pull_file("/etc/link", &pulled).unwrap();
+  assert_eq!(fs::read_to_string(&pulled).unwrap(), "port = 80\n");
+  assert_eq!(mode(&pulled), 0o600);
+
+  c.push_dir(&host, "/srv/app", 0, 0).unwrap();
+  check_tree(&rootfs.join("srv/app"));
+  c.pull_dir("/srv/app", work.join("copy")).unwrap();
+  check_tree(&work.join("copy"));
+
+  // A symlink planted where the temporary copy goes isn't followed out of the rootfs.
+  let host_file = work.join("host-file");
+  fs::write(&host_file, "host\n").unwrap();
+  fs::set_permissions(&host_file, fs::Permissions::from_mode(0o600)).unwrap();
+  symlink(&host_file, rootfs.join("etc/.motd.lxc-copy")).unwrap();
+  assert!(c.push_file(host.join("motd"), "/etc/motd", 0o644, 0, 0).is_err());
+  assert_eq!(fs::read_to_string(&host_file).unwrap(), "host\n");
+  assert_eq!(mode(&host_file), 0o600);
+  // The failed push removed the symlink.
+  c.push_file(host.join("motd"), "/etc/motd", 0o644, 0, 0).unwrap();
+
+  // Owners which aren't mapped can't be written.
+  let error = c.push_file(host.join("motd"), "/etc/motd", 0o644, 70000, 0).unwrap_err();
+  assert!(error.contains("70000"), "{}", error);
+  assert!(c.push_file(host.join("motd"), "etc/motd", 0o644, 0, 0).is_err());
+  assert!(c.push_file(host.join("motd"), "/missing/motd", 0o644, 0, 0).is_err());
+  assert!(c.pull_file("/etc/missing", work.join("missing")).is_err());
+  assert!(!work.join("missing").exists());
+  assert!(c.pull_file("/etc", work.join("etc")).is_err());
+
+  // Other backing stores can only be reached while the container runs.
+  assert!(c.set_config_item("lxc.rootfs", "overlayfs:/var/lib/lxc/base/rootfs:/var/lib/lxc/web/delta0"));
+  assert!(c.push_file(host.join("motd"), "/etc/motd", 0o644, 0, 0).unwrap_err().contains("rootfs"));
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn renamed_id_maps() {
+  let stub = support::stub();
+  stub.set_version("3.0.0");
+  let work = support::temp_dir("files-idmap");
+  let lxcpath = work.join("lxc");
+  let c = LxcContainer::new("web", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let rootfs = lxcpath.join("web/rootfs");
+  fs::create_dir_all(rootfs.join("etc")).unwrap();
+  let uid = fs::metadata(&work).unwrap().uid();
+  let gid = fs::metadata(&work).unwrap().gid();
+  assert!(!c.set_config_item("lxc.id_map", &format!("u 0 {} 1", uid)));
+  assert!(c.set_config_item("lxc.idmap", &format!("u 1000 {} 1", uid)));
+  assert!(c.set_config_item("lxc.idmap", &format!("g 1000 {} 1", gid)));
+
+  fs::write(work.join("motd"), "hi\n").unwrap();
+  c.push_file(work.join("motd"), "/etc/motd", 0o644, 1000, 1000).unwrap();
+  let pushed = rootfs.join("etc/motd");
+  assert_eq!((fs::metadata(&pushed).unwrap().uid(), fs::metadata(&pushed).unwrap().gid()), (uid, gid));
+  let error = c.push_file(work.join("motd"), "/etc/motd", 0o644, 0, 0).unwrap_err();
+  assert!(error.contains("User 0"), "{}", error);
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn running_container() {
+  let _stub = support::stub();
+  let work = support::temp_dir("files-running");
+  let c = LxcContainer::new("web", Some(work.join("lxc").to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  // The stub runs attached programs on the host, so container paths are host paths.
+  let uid = fs::metadata(&work).unwrap().uid();
+  let gid = fs::metadata(&work).unwrap().gid();
+  let host = work.join("host");
+  tree(&host);
+  let inside = work.join("inside");
+  fs::create_dir(&inside).unwrap();
+
+  let conf = inside.join("app.conf");
+  c.push_file(host.join("app.conf"), conf.to_str().unwrap(), 0o600, uid, gid).unwrap();
+  assert_eq!(fs::read_to_string(&conf).unwrap(), "port = 80\n");
+  assert_eq!(mode(&conf), 0o600);
+  fs::write(host.join("app.conf"), "port = 8080\n").unwrap();
+  c.push_file(host.join("app.conf"), conf.to_str().unwrap(), 0o640, uid, gid).unwrap();
+  assert_eq!(fs::read_to_string(&conf).unwrap(), "port = 8080\n");
+  assert_eq!(fs::read_dir(&inside).unwrap().count(), 1);
+  c.pull_file(conf.to_str().unwrap(), work.join("pulled")).unwrap();
+  assert_eq!(fs::read_to_string(work.join("pulled")).unwrap(), "port = 8080\n");
+  assert_eq!(mode(&work.join("pulled")), 0o640);
+
+  fs::write(host.join("app.conf"), "port = 80\n").unwrap();
+  let app = inside.join("srv/app");
+  c.push_dir(&host, app.to_str().unwrap(), uid, gid).unwrap();
+  check_tree(&app);
+  c.pull_dir(app.to_str().unwrap(), work.join("copy")).unwrap();
+  check_tree(&work.join("copy"));
+
+  let missing = inside.join("missing");
+  assert!(c.pull_file(missing.to_str().unwrap(), work.join("missing")).is_err());
+  assert!(!work.join("missing").exists());
+  assert!(c.push_file(host.join("app.conf"), missing.join("app.conf").to_str().unwrap(), 0o644, uid, gid).is_err());
+  assert_eq!(fs::read_dir(&inside).unwrap().count(), 2);
+
+  // A frozen container can't run anything.
+  assert!(c.freeze());
+  assert!(c.push_file(host.join("app.conf"), conf.to_str().unwrap(), 0o644, uid, gid).unwrap_err().contains("FROZEN"));
+  assert!(c.unfreeze());
+  assert!(c.stop());
+  fs::remove_dir_all(&work).unwrap();
+}
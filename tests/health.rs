@@ -0,0 +1,142 @@
+//! Health checks against the liblxc stub, which runs attached commands on the host.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use liblxc::supervisor::{Backoff, ExitReason};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn running(name: &str) -> LxcContainer {
+  let c = LxcContainer::new(name, None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  c
+}
+
+/// Health check passing while `flag` exists, probed on every poll.
+fn flag_check(flag: &Path, retries: u32) -> HealthCheck {
+  HealthCheck {
+    command: vec!["test".to_string(), "-e".to_string(), flag.to_str().unwrap().to_string()],
+    interval: Duration::from_secs(0),
+    retries,
+    ..HealthCheck::default()
+  }
+}
+
+fn flag(test: &str) -> PathBuf {
+  let flag = env::temp_dir().join(format!("rust-lxc-health-{}-{}", test, std::process::id()));
+  let _ = fs::remove_file(&flag);
+  flag
+}
+
+#[test]
+fn status_follows_probes() {
+  let _stub = support::stub();
+  let c = running("probed");
+  let flag = flag("status");
+  let mut runner = HealthRunner::new(flag_check(&flag, 2));
+  assert_eq!(runner.status(), HealthStatus::Starting);
+
+  assert_eq!(runner.poll(&c), HealthStatus::Starting);
+  assert_eq!(runner.poll(&c), HealthStatus::Unhealthy);
+  assert_eq!(runner.failing_streak(), 2);
+  fs::write(&flag, "").unwrap();
+  assert_eq!(runner.poll(&c), HealthStatus::Healthy);
+  assert_eq!(runner.failing_streak(), 0);
+  fs::remove_file(&flag).unwrap();
+  assert_eq!(runner.poll(&c), HealthStatus::Healthy);
+  assert_eq!(runner.poll(&c), HealthStatus::Unhealthy);
+
+  let history: Vec<bool> = runner.history().iter().map(|p| p.is_success()).collect();
+  assert_eq!(history, vec![false, false, true, false, false]);
+  assert_eq!(runner.poll(&c), HealthStatus::Unhealthy);
+  assert_eq!(runner.history().len(), HEALTH_HISTORY);
+  assert_eq!(runner.history()[0].result, Ok(1));
+
+  assert!(c.stop());
+  assert_eq!(runner.poll(&c), HealthStatus::Starting);
+  assert_eq!(runner.failing_streak(), 0);
+  assert_eq!(runner.history().len(), HEALTH_HISTORY);
+}
+
+#[test]
+fn start_period_and_interval() {
+  let _stub = support::stub();
+  let c = running("slow-start");
+  let flag = flag("start-period");
+  let mut runner = HealthRunner::new(HealthCheck { start_period: Duration::from_secs(60),
+                                                   ..flag_check(&flag, 1) });
+  for _ in 0..3 {
+    assert_eq!(runner.poll(&c), HealthStatus::Starting);
+  }
+  assert_eq!(runner.failing_streak(), 0);
+  fs::write(&flag, "").unwrap();
+  assert_eq!(runner.poll(&c), HealthStatus::Healthy);
+  fs::remove_file(&flag).unwrap();
+  assert_eq!(runner.poll(&c), HealthStatus::Unhealthy);
+
+  let mut runner = HealthRunner::new(HealthCheck { interval: Duration::from_secs(60), ..flag_check(&flag, 1) });
+  assert_eq!(runner.poll(&c), HealthStatus::Unhealthy);
+  fs::write(&flag, "").unwrap();
+  assert_eq!(runner.poll(&c), HealthStatus::Unhealthy);
+  assert_eq!(runner.history().len(), 1);
+  assert_eq!(runner.probe(&c), HealthStatus::Healthy);
+  fs::remove_file(&flag).unwrap();
+}
+
+#[test]
+fn timeouts_and_errors() {
+  let _stub = support::stub();
+  let c = running("hung");
+  let mut runner = HealthRunner::new(HealthCheck {
+    command: vec!["sleep".to_string(), "1".to_string()],
+    timeout: Duration::from_millis(50),
+    retries: 1,
+    ..HealthCheck::default()
+  });
+  assert_eq!(runner.probe(&c), HealthStatus::Unhealthy);
+  assert_eq!(runner.history()[0].result, Err("Health check timed out"));
+  // The hung command was killed and reaped.
+  let sleeping = fs::read_dir("/proc").unwrap().filter_map(|entry| {
+    fs::read_to_string(entry.unwrap().path().join("stat")).ok()
+  }).filter(|stat| {
+    let fields: Vec<&str> = stat.rsplit(") ").next().unwrap().split(' ').collect();
+    stat.contains("(sleep)") && fields[1] == std::process::id().to_string()
+  }).count();
+  assert_eq!(sleeping, 0);
+
+  let mut runner = HealthRunner::new(HealthCheck { retries: 1, ..HealthCheck::default() });
+  assert_eq!(runner.probe(&c), HealthStatus::Unhealthy);
+  assert_eq!(runner.history()[0].result, Err("Empty health check command"));
+}
+
+#[test]
+fn supervisor_restarts_unhealthy_containers() {
+  let stub = support::stub();
+  let c = running("sick");
+  let flag = flag("supervisor");
+  let mut supervisor = Supervisor::new(None);
+  let backoff = Backoff { initial: Duration::from_secs(0), ..Backoff::default() };
+  supervisor.watch("sick", RestartPolicy::Always, backoff);
+  assert!(supervisor.set_health_check("sick", Some(flag_check(&flag, 1))));
+  assert!(!supervisor.set_health_check("unknown", None));
+
+  fs::write(&flag, "").unwrap();
+  supervisor.poll();
+  assert_eq!(supervisor.state("sick").unwrap().health, Some(HealthStatus::Healthy));
+  fs::remove_file(&flag).unwrap();
+  supervisor.poll();
+  let state = supervisor.state("sick").unwrap();
+  assert_eq!(state.last_exit, Some(ExitReason::Unhealthy));
+  assert_eq!(state.restarts, 1);
+  assert_eq!(state.health, Some(HealthStatus::Starting));
+  assert!(c.is_running());
+  assert!(stub.event(None, "sick", "stop") < stub.event(None, "sick", "start"));
+  assert_eq!(supervisor.health("sick").unwrap().history().len(), 2);
+}
@@ -0,0 +1,195 @@
+//! Containers created from local root filesystems, and the image cache.
+#![cfg(all(feature = "lxc-stub", feature = "image"))]
+
+extern crate flate2;
+extern crate liblxc;
+extern crate tar;
+
+mod support;
+
+use liblxc::*;
+use liblxc::image::{sha256_file, ImageCache, RootfsSource};
+use std::fs::{self, File};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Writes a small rootfs to `dir`.
+fn rootfs(dir: &Path) {
+  fs::create_dir_all(dir.join("etc")).unwrap();
+  fs::create_dir_all(dir.join("bin")).unwrap();
+  fs::create_dir_all(dir.join("root")).unwrap();
+  fs::set_permissions(dir.join("root"), fs::Permissions::from_mode(0o700)).unwrap();
+  fs::write(dir.join("etc/hostname"), "image\n").unwrap();
+  fs::write(dir.join("bin/busybox"), "#!/bin/true\n").unwrap();
+  fs::set_permissions(dir.join("bin/busybox"), fs::Permissions::from_mode(0o755)).unwrap();
+  symlink("busybox", dir.join("bin/sh")).unwrap();
+}
+
+/// Packs the rootfs written by `rootfs` into `tarball`, gzip-compressed if `gzip`.
+fn tarball(work: &Path, tarball: &Path, gzip: bool) {
+  let source = work.join("source");
+  rootfs(&source);
+  let file = File::create(tarball).unwrap();
+  if gzip {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", &source).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+  }
+  else {
+    let mut builder = tar::Builder::new(file);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", &source).unwrap();
+    builder.finish().unwrap();
+  }
+  fs::remove_dir_all(&source).unwrap();
+}
+
+fn check_rootfs(rootfs: &Path) {
+  assert_eq!(fs::read_to_string(rootfs.join("etc/hostname")).unwrap(), "image\n");
+  let mode = |p: &str| fs::metadata(rootfs.join(p)).unwrap().permissions().mode() & 0o7777;
+  assert_eq!(mode("bin/busybox"), 0o755);
+  assert_eq!(mode("root"), 0o700);
+  assert_eq!(fs::read_link(rootfs.join("bin/sh")).unwrap(), Path::new("busybox"));
+}
+
+#[test]
+fn from_tarball() {
+  let _stub = support::stub();
+  let work = support::temp_dir("image-tarball");
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+  let gz = work.join("rootfs.tar.gz");
+  tarball(&work, &gz, true);
+
+  let c = LxcContainer::new("web", Some(lxcpath)).unwrap();
+  assert_eq!(c.create_from_rootfs(&RootfsSource::Tarball(gz.clone()), &[("lxc.arch", "amd64")]), Ok(()));
+  let rootfs = Path::new(lxcpath).join("web/rootfs");
+  check_rootfs(&rootfs);
+  assert!(c.is_defined());
+  assert_eq!(c.get_config_item("lxc.rootfs"), Ok(rootfs.to_str().unwrap().to_string()));
+  assert_eq!(c.get_config_item("lxc.utsname"), Ok("web".to_string()));
+  let config = fs::read_to_string(Path::new(lxcpath).join("web/config")).unwrap();
+  assert!(config.contains("lxc.arch = amd64\n"), "{}", config);
+  assert_eq!(c.create_from_rootfs(&RootfsSource::Tarball(gz), &[]),
+             Err("Container web already exists".to_string()));
+
+  let plain = work.join("rootfs.tar");
+  tarball(&work, &plain, false);
+  let c = LxcContainer::new("db", Some(lxcpath)).unwrap();
+  assert_eq!(c.create_from_rootfs(&RootfsSource::Tarball(plain), &[]), Ok(()));
+  check_rootfs(&Path::new(lxcpath).join("db/rootfs"));
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn from_directory() {
+  // liblxc 3.0 only knows lxc.rootfs.path and lxc.uts.name.
+  let stub = support::stub();
+  stub.set_version("3.0.0");
+  let work = support::temp_dir("image-directory");
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+  let source = work.join("source");
+  rootfs(&source);
+  fs::set_permissions(&source, fs::Permissions::from_mode(0o751)).unwrap();
+
+  let c = LxcContainer::new("web", Some(lxcpath)).unwrap();
+  assert_eq!(c.create_from_rootfs(&RootfsSource::Directory(source.clone()), &[]), Ok(()));
+  let rootfs = Path::new(lxcpath).join("web/rootfs");
+  check_rootfs(&rootfs);
+  assert_eq!(fs::metadata(&rootfs).unwrap().permissions().mode() & 0o777, 0o751);
+  assert!(c.is_defined());
+  assert_eq!(c.get_config_item("lxc.rootfs.path"), Ok(rootfs.to_str().unwrap().to_string()));
+  assert_eq!(c.get_config_item("lxc.uts.name"), Ok("web".to_string()));
+
+  let c = LxcContainer::new("file", Some(lxcpath)).unwrap();
+  let file = source.join("etc/hostname");
+  assert_eq!(c.create_from_rootfs(&RootfsSource::Directory(file.clone()), &[]),
+             Err(format!("{} is not a directory", file.display())));
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn failures_clean_up() {
+  let stub = support::stub();
+  let work = support::temp_dir("image-failures");
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+  let c = LxcContainer::new("web", Some(lxcpath)).unwrap();
+  assert!(c.create_from_rootfs(&RootfsSource::Tarball(work.join("missing.tar")), &[]).is_err());
+  assert!(!Path::new(lxcpath).join("web").exists());
+
+  // A container directory that existed before is kept.
+  let gz = work.join("rootfs.tar.gz");
+  tarball(&work, &gz, true);
+  fs::create_dir_all(Path::new(lxcpath).join("web")).unwrap();
+  stub.fail("web", "save_config");
+  assert_eq!(c.create_from_rootfs(&RootfsSource::Tarball(gz), &[]),
+             Err(format!("Failed to save {}/web/config", lxcpath)));
+  assert!(Path::new(lxcpath).join("web").exists());
+  assert!(!Path::new(lxcpath).join("web/rootfs").exists());
+  assert!(!c.is_defined());
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn image_cache() {
+  let _stub = support::stub();
+  let work = support::temp_dir("image-cache");
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+  let gz = work.join("rootfs.tar.gz");
+  tarball(&work, &gz, true);
+  let hash = sha256_file(&gz).unwrap();
+  assert_eq!(hash.len(), 64);
+
+  let cache = ImageCache::new(work.join("images"));
+  assert_eq!(cache.list(), Ok(vec![]));
+  let image = cache.import(&gz).unwrap();
+  assert_eq!(image.hash, hash);
+  assert_eq!(image.path, work.join("images").join(&hash));
+  assert_eq!(image.size, fs::metadata(&gz).unwrap().len());
+  assert_eq!(cache.import(&gz), Ok(image.clone()));
+  fs::write(work.join("images/notes.txt"), "not an image").unwrap();
+  assert_eq!(cache.list(), Ok(vec![image.clone()]));
+  assert_eq!(cache.get(&format!("sha256:{}", hash)), Some(image.clone()));
+  assert_eq!(cache.get("notes.txt"), None);
+  assert_eq!(cache.verify(&hash), Ok(true));
+
+  let c = LxcContainer::new("web", Some(lxcpath)).unwrap();
+  assert_eq!(cache.create_container(&hash, &c, &[]), Ok(()));
+  check_rootfs(&Path::new(lxcpath).join("web/rootfs"));
+  let unknown = "0".repeat(64);
+  let c = LxcContainer::new("db", Some(lxcpath)).unwrap();
+  assert_eq!(cache.create_container(&unknown, &c, &[]), Err(format!("Image {} is not in the cache", unknown)));
+
+  fs::write(&image.path, "tampered").unwrap();
+  assert_eq!(cache.verify(&hash), Ok(false));
+  assert_eq!(cache.remove(&hash), Ok(true));
+  assert_eq!(cache.remove(&hash), Ok(false));
+  assert_eq!(cache.list(), Ok(vec![]));
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn concurrent_imports() {
+  let work = support::temp_dir("image-concurrent");
+  let cache = ImageCache::new(work.join("images"));
+  let tarballs: Vec<PathBuf> = (0..8).map(|i| {
+    let tarball = work.join(format!("{}.tar", i));
+    fs::write(&tarball, format!("image {}", i).repeat(10000)).unwrap();
+    tarball
+  }).collect();
+  let imports: Vec<_> = tarballs.iter().map(|tarball| {
+    let (cache, tarball) = (cache.clone(), tarball.clone());
+    std::thread::spawn(move || cache.import(&tarball).unwrap())
+  }).collect();
+  for (import, tarball) in imports.into_iter().zip(&tarballs) {
+    let image = import.join().unwrap();
+    assert_eq!(image.hash, sha256_file(tarball).unwrap());
+    assert_eq!(cache.verify(&image.hash), Ok(true));
+  }
+  assert_eq!(cache.list().unwrap().len(), 8);
+  fs::remove_dir_all(&work).unwrap();
+}
@@ -0,0 +1,85 @@
+//! Locking primitives against the liblxc stub.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn anonymous_lock_times_out_while_held() {
+  let _stub = support::stub();
+  let lock = LxcLock::anonymous().unwrap();
+  {
+    let _guard = lock.lock(0).unwrap();
+    assert!(lock.lock(1).is_err());
+  }
+  assert!(lock.lock(1).is_ok());
+}
+
+#[test]
+fn named_lock_times_out_while_held() {
+  let _stub = support::stub();
+  let name = format!("timeout-{}", std::process::id());
+  let first = LxcLock::new(Some("/var/lib/lxc"), &name).unwrap();
+  let second = LxcLock::new(Some("/var/lib/lxc"), &name).unwrap();
+  {
+    let _guard = first.lock(1).unwrap();
+    let started = Instant::now();
+    assert!(second.lock(1).is_err());
+    assert!(started.elapsed() >= Duration::from_secs(1));
+  }
+  let _guard = second.lock(1).unwrap();
+  assert!(first.lock(1).is_err());
+}
+
+#[test]
+fn named_locks_serialize_holders() {
+  let _stub = support::stub();
+  let name = format!("serialized-{}", std::process::id());
+  let first = LxcLock::new(None, &name).unwrap();
+  let guard = first.lock(0).unwrap();
+
+  let (tx, rx) = mpsc::channel();
+  let waiter_name = name.clone();
+  let waiter = thread::spawn(move || {
+    let second = LxcLock::new(None, &waiter_name).unwrap();
+    let _guard = second.lock(0).unwrap();
+    tx.send(()).unwrap();
+  });
+
+  assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+  drop(guard);
+  assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+  waiter.join().unwrap();
+}
+
+#[test]
+fn container_locks_are_scoped() {
+  let _stub = support::stub();
+  let c = LxcContainer::new(&format!("locked-{}", std::process::id()), None).unwrap();
+  {
+    let _mem = c.mem_lock().unwrap();
+  }
+  {
+    let _disk = c.disk_lock().unwrap();
+  }
+  let _mem = c.mem_lock().unwrap();
+}
+
+#[test]
+fn container_lock_failure_fails_calls() {
+  let stub = support::stub();
+  let name = format!("unlockable-{}", std::process::id());
+  let c = LxcContainer::new(&name, None).unwrap();
+  stub.fail(&name, "mem_lock");
+  assert!(c.mem_lock().is_err());
+  assert!(c.config_file_name().is_err());
+  assert!(!c.clear_config());
+  assert!(panic::catch_unwind(AssertUnwindSafe(|| c.get_config_path())).is_err());
+}
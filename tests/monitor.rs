@@ -0,0 +1,105 @@
+//! Monitor protocol and a fake lxc-monitord.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::monitor::{decode_message, encode_message, socket_name};
+use liblxc::*;
+use std::io::Write;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener};
+use std::thread;
+
+/// Listens where lxc-monitord would for `lxcpath` and sends `events` to the first client.
+fn fake_monitord(lxcpath: &str, events: Vec<MonitorEvent>) -> thread::JoinHandle<()> {
+  let addr = SocketAddr::from_abstract_name(socket_name(lxcpath).unwrap()).unwrap();
+  let listener = UnixListener::bind_addr(&addr).unwrap();
+  thread::spawn(move || {
+    let (mut client, _) = listener.accept().unwrap();
+    for event in events {
+      client.write_all(&encode_message(&event)).unwrap();
+    }
+  })
+}
+
+fn events() -> Vec<MonitorEvent> {
+  vec![MonitorEvent::State { name: "web".to_string(), state: LxcState::Starting },
+       MonitorEvent::State { name: "web".to_string(), state: LxcState::Running },
+       MonitorEvent::ExitCode { name: "db".to_string(), status: 256 },
+       MonitorEvent::State { name: "db".to_string(), state: LxcState::Stopped }]
+}
+
+#[test]
+fn socket_name_matches_liblxc() {
+  assert_eq!(socket_name("/var/lib/lxc").unwrap(),
+             b"lxc/ad055575fe28ddd5//var/lib/lxc".to_vec());
+  let long = "/home/someone/.local/share/lxc/with/a/very/long/path/that/gets/truncated/at/the/end/x";
+  assert_eq!(socket_name(long).unwrap(),
+             b"lxc/75891638b7956aa9//home/someone/.local/share/lxc/with/a/very/long/path/that/gets/truncated/at/the/end/".to_vec());
+  assert!(socket_name(&"x".repeat(100)).is_err());
+}
+
+#[test]
+fn messages_round_trip() {
+  for event in events() {
+    let msg = encode_message(&event);
+    assert_eq!(msg.len(), 264);
+    assert_eq!(decode_message(&msg).unwrap(), event);
+  }
+  let mut bad_state = encode_message(&events()[0]);
+  bad_state[260..].copy_from_slice(&42i32.to_ne_bytes());
+  assert!(decode_message(&bad_state).is_err());
+  let mut bad_type = encode_message(&events()[0]);
+  bad_type[0..4].copy_from_slice(&7i32.to_ne_bytes());
+  assert!(decode_message(&bad_type).is_err());
+  assert!(decode_message(&[0u8; 10]).is_err());
+}
+
+#[test]
+fn monitor_yields_events_until_disconnect() {
+  let lxcpath = format!("/tmp/rust-lxc-monitor-{}", std::process::id());
+  let daemon = fake_monitord(&lxcpath, events());
+  let monitor = Monitor::connect(Some(&lxcpath)).unwrap();
+  let received = monitor.collect::<Result<Vec<_>, _>>().unwrap();
+  daemon.join().unwrap();
+  assert_eq!(received, events());
+}
+
+#[test]
+fn monitor_uses_default_lxcpath() {
+  let stub = support::stub();
+  let lxcpath = format!("/tmp/rust-lxc-default-monitor-{}", std::process::id());
+  stub.set_lxcpath(&lxcpath);
+  let daemon = fake_monitord(&lxcpath, events());
+  let monitor = Monitor::connect(None).unwrap();
+  assert_eq!(monitor.count(), events().len());
+  daemon.join().unwrap();
+}
+
+#[test]
+fn connect_fails_without_daemon() {
+  assert!(Monitor::connect(Some("/nonexistent/lxcpath")).is_err());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_monitor() {
+  extern crate tokio;
+  use liblxc::monitor::AsyncMonitor;
+
+  let lxcpath = format!("/tmp/rust-lxc-async-monitor-{}", std::process::id());
+  let daemon = fake_monitord(&lxcpath, events());
+  let rt = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+  let received = rt.block_on(async {
+    let mut monitor = AsyncMonitor::connect(Some(&lxcpath)).unwrap();
+    let mut received = Vec::new();
+    while let Some(event) = monitor.next_event().await.unwrap() {
+      received.push(event);
+    }
+    received
+  });
+  daemon.join().unwrap();
+  assert_eq!(received, events());
+}
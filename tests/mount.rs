@@ -0,0 +1,128 @@
+//! Mount entries and automatic mounts.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use liblxc::mount::MOUNT_AUTO_VALUES;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn entries() {
+  let line = "/srv/my\\040files srv/files none bind,ro,create=dir 0 0";
+  let entry: MountEntry = line.parse().unwrap();
+  assert_eq!(entry.source, "/srv/my files");
+  assert_eq!(entry.target, "srv/files");
+  assert_eq!(entry.fstype, "none");
+  assert_eq!(entry.options, vec!["bind", "ro"]);
+  assert_eq!(entry.create, Some(MountCreate::Dir));
+  assert!(entry.is_bind() && entry.is_read_only());
+  assert_eq!(entry.to_string(), line);
+  assert_eq!("proc proc proc defaults".parse::<MountEntry>().unwrap().to_string(), "proc proc proc defaults 0 0");
+  assert!("/srv srv none".parse::<MountEntry>().is_err());
+
+  let bind = MountEntry::bind("/var/cache", "/var/cache/").read_only().recursive().create(MountCreate::Dir);
+  assert_eq!(bind.to_string(), "/var/cache var/cache/ none rbind,ro,create=dir 0 0");
+  assert_eq!(bind.clone().read_only(), bind);
+}
+
+#[test]
+fn container_entries() {
+  let _stub = support::stub();
+  let work = env::temp_dir().join(format!("rust-lxc-mount-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&work);
+  fs::create_dir_all(work.join("src")).unwrap();
+  fs::write(work.join("token"), "secret\n").unwrap();
+  let c = LxcContainer::new("build", Some(work.join("lxc").to_str().unwrap())).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert_eq!(c.mount_entries(), Ok(Vec::new()));
+
+  let src = MountEntry::bind(work.join("src").to_str().unwrap(), "usr/src").create(MountCreate::Dir);
+  let token = MountEntry::bind(work.join("token").to_str().unwrap(), "run/token").read_only().create(MountCreate::File);
+  let tmp: MountEntry = "tmpfs tmp tmpfs size=64m 0 0".parse().unwrap();
+  c.add_mount_entry(&src).unwrap();
+  c.add_mount_entry(&token).unwrap();
+  c.add_mount_entry(&tmp).unwrap();
+  assert_eq!(c.mount_entries(), Ok(vec![src.clone(), token.clone(), tmp.clone()]));
+  assert_eq!(c.get_config_item("lxc.mount.entry").unwrap().lines().nth(1),
+             Some(&format!("{} run/token none bind,ro,create=file 0 0", work.join("token").display())[..]));
+
+  // Bind mount sources have to exist and match what is created.
+  assert!(c.add_mount_entry(&MountEntry::bind(work.join("src").to_str().unwrap(), "/usr/src")).unwrap_err().contains("already"));
+  assert!(c.add_mount_entry(&MountEntry::bind(work.join("missing").to_str().unwrap(), "mnt")).is_err());
+  assert!(c.add_mount_entry(&MountEntry::bind("relative", "mnt")).is_err());
+  assert!(c.add_mount_entry(&MountEntry::bind(work.join("token").to_str().unwrap(), "mnt").create(MountCreate::Dir)).is_err());
+  assert!(c.add_mount_entry(&MountEntry::bind(work.join("src").to_str().unwrap(), "mnt").create(MountCreate::File)).is_err());
+  assert!(c.add_mount_entry(&MountEntry::bind(work.join("src").to_str().unwrap(), "../mnt")).is_err());
+  assert!(c.add_mount_entry(&MountEntry::bind(work.join("src").to_str().unwrap(), "/")).is_err());
+  assert_eq!(c.mount_entries().unwrap().len(), 3);
+
+  assert_eq!(c.remove_mount_entry("/run/token"), Ok(token));
+  assert!(c.remove_mount_entry("run/token").is_err());
+  assert_eq!(c.mount_entries(), Ok(vec![src.clone(), tmp]));
+
+  assert_eq!(c.mount_auto(), Vec::<String>::new());
+  c.set_mount_auto(&["proc:mixed", "sys:ro", "cgroup"]).unwrap();
+  assert_eq!(c.mount_auto(), vec!["proc:mixed", "sys:ro", "cgroup"]);
+  assert!(c.set_mount_auto(&["proc:ro"]).is_err());
+  assert_eq!(c.mount_auto().len(), 3);
+  assert!(MOUNT_AUTO_VALUES.contains(&"cgroup-full:ro"));
+  c.set_mount_auto(&[]).unwrap();
+  assert_eq!(c.mount_auto(), Vec::<String>::new());
+
+  // Live mounts need a running container and a bind mount.
+  assert!(c.mount_live(&src).unwrap_err().contains("isn't running"));
+  assert!(c.mount_live(&"tmpfs tmp tmpfs defaults".parse().unwrap()).is_err());
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn live_mount() {
+  let stub = support::stub();
+  let work = env::temp_dir().join(format!("rust-lxc-mount-live-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&work);
+  fs::create_dir_all(work.join("cache")).unwrap();
+  fs::write(work.join("cache/index"), "1\n").unwrap();
+  // A process in its own mount namespace stands in for the container.
+  let mut init = match Command::new("unshare").args(["--mount", "--propagation", "private", "sleep", "30"]).spawn() {
+    Ok(child) => child,
+    Err(_) => return,
+  };
+  let in_namespace = (0..500).any(|_| {
+    thread::sleep(Duration::from_millis(10));
+    fs::read_to_string(format!("/proc/{}/comm", init.id())).map(|c| c == "sleep\n").unwrap_or(false)
+  });
+  if !in_namespace {
+    // Creating mount namespaces needs privileges.
+    let _ = init.kill();
+    return;
+  }
+  let c = LxcContainer::new("build", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  stub.set_init_pid(None, "build", init.id());
+
+  let target = work.join("mnt");
+  let entry = MountEntry::bind(work.join("cache").to_str().unwrap(), target.to_str().unwrap())
+                .read_only().create(MountCreate::Dir);
+  c.mount_live(&entry).unwrap();
+  let inside = Path::new(&format!("/proc/{}/root", init.id())).join(target.strip_prefix("/").unwrap());
+  assert_eq!(fs::read_to_string(inside.join("index")).unwrap(), "1\n");
+  assert!(fs::write(inside.join("other"), "").is_err());
+  // The host only sees the created mount point.
+  assert!(target.is_dir() && !target.join("index").exists());
+
+  let missing = MountEntry::bind(work.join("cache").to_str().unwrap(), work.join("missing/mnt").to_str().unwrap());
+  assert!(c.mount_live(&missing).is_err());
+  init.kill().unwrap();
+  init.wait().unwrap();
+  assert!(c.stop());
+  fs::remove_dir_all(&work).unwrap();
+}
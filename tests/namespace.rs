@@ -0,0 +1,111 @@
+//! Namespaces of running containers.
+#![cfg(feature = "lxc-stub")]
+
+extern crate libc;
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::ffi::CString;
+use std::fs;
+use std::mem;
+use std::os::unix::fs::MetadataExt;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Starts a process standing in for the init of a container, in its own network
+/// and UTS namespaces if the test may create them.
+fn init() -> Child {
+  let mut child = Command::new("unshare").args(["--net", "--uts", "sleep", "30"]).spawn().unwrap();
+  for _ in 0..500 {
+    if let Ok(Some(_)) = child.try_wait() {
+      break;
+    }
+    if fs::read_to_string(format!("/proc/{}/comm", child.id())).map(|c| c == "sleep\n").unwrap_or(false) {
+      return child;
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  Command::new("sleep").arg("30").spawn().unwrap()
+}
+
+/// Inode of namespace `ns` of process `pid`, which identifies the namespace.
+fn namespace_inode(ns: &str, pid: u32) -> u64 {
+  fs::metadata(format!("/proc/{}/ns/{}", pid, ns)).unwrap().ino()
+}
+
+/// Namespace file `ns` of the calling process, for `in_namespace`.
+fn own_namespace(ns: &str) -> CString {
+  CString::new(format!("/proc/self/ns/{}", ns)).unwrap()
+}
+
+/// Determine whether the calling process is in the namespace with `inode`, with
+/// only a `stat` call, so that children of `run` may call it.
+fn in_namespace(own: &CString, inode: u64) -> bool {
+  unsafe {
+    let mut stat: libc::stat = mem::zeroed();
+    libc::stat(own.as_ptr(), &mut stat) == 0 && stat.st_ino as u64 == inode
+  }
+}
+
+#[test]
+fn namespaces() {
+  assert_eq!("net".parse(), Ok(Namespace::Net));
+  assert_eq!(Namespace::Mount.to_string(), "mnt");
+  assert!("mount".parse::<Namespace>().is_err());
+
+  let stub = support::stub();
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.namespaces(&[Namespace::Net]).unwrap_err().contains("isn't running"));
+  assert!(c.start(0, None));
+  let mut init = init();
+  let pid = init.id();
+  stub.set_init_pid(None, "web", pid);
+
+  let namespaces = c.namespaces(&[Namespace::Net, Namespace::Uts, Namespace::Pid]).unwrap();
+  assert_eq!(namespaces.pid(), pid as i32);
+  assert_eq!(namespaces.get(Namespace::Net).unwrap().namespace(), Namespace::Net);
+  assert!(namespaces.get(Namespace::Mount).is_none());
+
+  // Only the joined namespaces change. Everything the children check is prepared
+  // before the fork.
+  let (own_net, own_uts, own_mnt) = (own_namespace("net"), own_namespace("uts"), own_namespace("mnt"));
+  let (net, uts) = (namespace_inode("net", pid), namespace_inode("uts", pid));
+  let mnt = namespace_inode("mnt", std::process::id());
+  unsafe {
+    let joined = namespaces.run(&[Namespace::Net], || {
+      if in_namespace(&own_net, net) && in_namespace(&own_mnt, mnt) { 0 } else { 1 }
+    });
+    assert_eq!(joined, Ok(0));
+    assert_eq!(namespaces.run(&[Namespace::Uts, Namespace::Net], || {
+      if in_namespace(&own_uts, uts) && in_namespace(&own_net, net) { 0 } else { 1 }
+    }), Ok(0));
+    // The closure runs in a grandchild to be inside the PID namespace.
+    assert_eq!(namespaces.run(&[Namespace::Pid], || 7), Ok(7));
+    assert!(namespaces.run(&[Namespace::Mount], || 0).unwrap_err().contains("mnt namespace"));
+    assert_eq!(c.run_in_namespaces(&[Namespace::Net], || 3), Ok(3));
+  }
+
+  // The fork guard is only held for the fork, not while the child runs.
+  thread::scope(|scope| {
+    let waiter = scope.spawn(|| unsafe { namespaces.run(&[], || { libc::sleep(1); 0 }) });
+    thread::sleep(Duration::from_millis(200));
+    let started = Instant::now();
+    drop(fork_guard());
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(waiter.join().unwrap(), Ok(0));
+  });
+
+  // Handles keep the namespaces after the process is gone.
+  init.kill().unwrap();
+  init.wait().unwrap();
+  assert_eq!(unsafe { namespaces.run(&[Namespace::Net], || 0) }, Ok(0));
+  assert!(c.namespaces(&[Namespace::Net]).is_err());
+  let fds = namespaces.into_fds();
+  assert_eq!(fds.iter().map(|fd| fd.namespace()).collect::<Vec<_>>(),
+             vec![Namespace::Net, Namespace::Uts, Namespace::Pid]);
+  assert!(c.stop());
+}
@@ -0,0 +1,147 @@
+//! Containers run without liblxc.
+#![cfg(all(feature = "native", any(feature = "lxc-stub", not(feature = "liblxc"))))]
+
+extern crate liblxc;
+
+use liblxc::native::{NativeContainer, CGROUP_PREFIX};
+use std::env;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// Defines container `name` in a new lxcpath, with a rootfs borrowing `/usr` from
+/// the host and `config` appended to its configuration.
+fn define(name: &str, config: &str) -> (PathBuf, NativeContainer) {
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-native-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&lxcpath);
+  let rootfs = lxcpath.join(name).join("rootfs");
+  for dir in &["usr", "proc", "sys", "dev", "tmp", "etc"] {
+    fs::create_dir_all(rootfs.join(dir)).unwrap();
+  }
+  for link in &["bin", "sbin", "lib", "lib64"] {
+    let target = fs::read_link(Path::new("/").join(link)).unwrap_or(Path::new("usr").join(link));
+    symlink(target, rootfs.join(link)).unwrap();
+  }
+  fs::write(lxcpath.join(name).join("config"), format!(
+    "lxc.rootfs.path = dir:{}\nlxc.uts.name = native-test\nlxc.net.0.type = empty\n\
+     lxc.mount.auto = proc:mixed sys:ro\nlxc.mount.entry = /usr usr none bind,ro 0 0\n\
+     lxc.environment = GREETING=hello\n{}",
+    rootfs.display(), config)).unwrap();
+  let c = NativeContainer::new(name, Some(lxcpath.to_str().unwrap())).unwrap();
+  (lxcpath, c)
+}
+
+/// Determine whether the test may create namespaces and mounts.
+fn privileged() -> bool {
+  Command::new("unshare").args(["--mount", "--pid", "--fork", "true"])
+                         .status().map(|status| status.success()).unwrap_or(false)
+}
+
+fn sh(c: &NativeContainer, script: &str) -> i32 {
+  c.attach_run_wait("/bin/sh", vec!["sh", "-c", script]).unwrap_or_else(|e| panic!("{}: {:?}", e, c.error_string()))
+}
+
+#[test]
+fn configuration_errors() {
+  assert!(NativeContainer::new("a/b", None).is_err());
+  let (lxcpath, c) = define("config", "lxc.idmap = u 0 100000 65536\n");
+  assert!(c.is_defined());
+  assert_eq!(c.state(), "STOPPED");
+  assert_eq!(c.init_pid(), -1);
+  assert!(!c.start(0, None));
+  assert!(c.error_string().unwrap().contains("ID maps aren't supported"));
+
+  fs::write(lxcpath.join("config/config"), "lxc.net.0.type = veth\n").unwrap();
+  assert!(!c.start(0, None));
+  assert!(c.error_string().unwrap().contains("Network type veth isn't supported"));
+  fs::write(lxcpath.join("config/config"), "lxc.rootfs.path = overlay:/a:/b\n").unwrap();
+  assert!(!c.start(0, None));
+  assert!(c.error_string().unwrap().contains("only directories"));
+
+  let missing = NativeContainer::new("missing", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(!missing.is_defined());
+  assert!(!missing.start(0, None));
+  assert!(!missing.stop());
+  assert!(missing.error_string().unwrap().contains("isn't running"));
+  fs::remove_dir_all(lxcpath).unwrap();
+}
+
+#[test]
+fn lifecycle() {
+  if !privileged() {
+    return;
+  }
+  let (lxcpath, c) = define("lifecycle", "lxc.init.cmd = sleep 300\n");
+  assert!(c.start(0, None), "{:?}", c.error_string());
+  assert_eq!(c.state(), "RUNNING");
+  let pid = c.init_pid();
+  assert!(pid > 0);
+  assert_ne!(fs::read_link(format!("/proc/{}/ns/pid", pid)).unwrap(), fs::read_link("/proc/self/ns/pid").unwrap());
+  let environ = fs::read(format!("/proc/{}/environ", pid)).unwrap();
+  let environ: Vec<&[u8]> = environ.split(|b| *b == 0).collect();
+  assert!(environ.contains(&&b"GREETING=hello"[..]) && environ.contains(&&b"container=lxc"[..]));
+
+  assert_eq!(sh(&c, "[ \"$(cat /proc/1/comm)\" = sleep ]"), 0);
+  assert_eq!(sh(&c, "[ \"$(cat /proc/sys/kernel/hostname)\" = native-test ]"), 0);
+  assert_eq!(sh(&c, "echo x > /dev/null && [ -c /dev/urandom ]"), 0);
+  assert_ne!(sh(&c, "touch /usr/native-test 2>/dev/null"), 0);
+  assert_ne!(sh(&c, "echo 1 2>/dev/null > /proc/sys/kernel/hostname"), 0);
+  assert_eq!(sh(&c, "exit 3"), 3);
+
+  assert!(!c.start(0, None));
+  assert!(c.error_string().unwrap().contains("already running"));
+  assert!(c.freeze(), "{:?}", c.error_string());
+  assert_eq!(c.state(), "FROZEN");
+  assert!(c.unfreeze(), "{:?}", c.error_string());
+  assert_eq!(c.state(), "RUNNING");
+
+  assert!(c.stop(), "{:?}", c.error_string());
+  assert_eq!(c.state(), "STOPPED");
+  assert_eq!(c.init_pid(), -1);
+  assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+  assert!(!Path::new("/sys/fs/cgroup/freezer").join(format!("{}lifecycle", CGROUP_PREFIX)).exists());
+  fs::remove_dir_all(lxcpath).unwrap();
+}
+
+#[test]
+fn shutdown() {
+  if !privileged() {
+    return;
+  }
+  let (lxcpath, c) = define("shutdown", "lxc.signal.halt = SIGTERM\n");
+  assert!(!c.start(0, Some(vec!["/nonexistent"])));
+  assert!(c.error_string().unwrap().contains("Couldn't run /nonexistent"), "{:?}", c.error_string());
+  assert!(c.wait("STOPPED", 5));
+
+  let script = "trap 'exit 0' TERM; touch /tmp/ready; while :; do sleep 1; done";
+  assert!(c.start(0, Some(vec!["sh", "-c", script])), "{:?}", c.error_string());
+  assert!(c.wait("RUNNING", 5));
+  // Init of a PID namespace only gets the signals it handles.
+  let ready = lxcpath.join("shutdown/rootfs/tmp/ready");
+  for _ in 0..500 {
+    if ready.exists() {
+      break;
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  assert!(c.shutdown(10), "{:?}", c.error_string());
+  assert_eq!(c.state(), "STOPPED");
+  assert!(!c.shutdown(0));
+  fs::remove_dir_all(lxcpath).unwrap();
+}
+
+/// Without liblxc, `LxcContainer` is the native backend.
+#[cfg(not(feature = "liblxc"))]
+#[test]
+fn lxc_container_is_native() {
+  let (lxcpath, _) = define("dispatch", "lxc.idmap = u 0 100000 65536\n");
+  let c = liblxc::LxcContainer::new("dispatch", Some(lxcpath.to_str().unwrap())).unwrap();
+  assert!(c.is_defined());
+  assert_eq!(c.state(), "STOPPED");
+  assert!(!c.start(0, None));
+  assert!(c.error_string().unwrap().contains("ID maps aren't supported"));
+  fs::remove_dir_all(lxcpath).unwrap();
+}
@@ -0,0 +1,279 @@
+//! Containers created from OCI image layouts and docker archives.
+#![cfg(all(feature = "lxc-stub", feature = "oci"))]
+
+extern crate flate2;
+extern crate liblxc;
+extern crate tar;
+
+mod support;
+
+use liblxc::*;
+use liblxc::image::sha256_file;
+use liblxc::oci::{OciOptions, OciProcess};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+enum Entry<'a> {
+  Dir(&'a str),
+  File(&'a str, &'a str),
+}
+
+/// Writes a layer tarball holding `entries`, gzip-compressed if `gzip`.
+fn layer(path: &Path, entries: &[Entry], gzip: bool) {
+  let mut data = tar::Builder::new(Vec::new());
+  for entry in entries {
+    let mut header = tar::Header::new_gnu();
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    match *entry {
+      Entry::Dir(name) => {
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        header.set_cksum();
+        data.append_data(&mut header, name, &[][..]).unwrap();
+      }
+      Entry::File(name, content) => {
+        header.set_mode(0o644);
+        header.set_size(content.len() as u64);
+        header.set_cksum();
+        data.append_data(&mut header, name, content.as_bytes()).unwrap();
+      }
+    }
+  }
+  let data = data.into_inner().unwrap();
+  if gzip {
+    let mut encoder = flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, &data).unwrap();
+    encoder.finish().unwrap();
+  }
+  else {
+    fs::write(path, data).unwrap();
+  }
+}
+
+/// Moves `path` into the blobs of `layout`, returning its digest.
+fn blob(layout: &Path, path: &Path) -> String {
+  let hex = sha256_file(path).unwrap();
+  let blobs = layout.join("blobs/sha256");
+  fs::create_dir_all(&blobs).unwrap();
+  fs::rename(path, blobs.join(&hex)).unwrap();
+  format!("sha256:{}", hex)
+}
+
+fn write_layers(work: &Path) -> (PathBuf, PathBuf) {
+  let base = work.join("base.tar");
+  layer(&base, &[
+    Entry::Dir("etc/"),
+    Entry::File("etc/passwd", "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1000::/home/app:/bin/sh\n"),
+    Entry::File("etc/group", "root:x:0:\napp:x:1000:\nstaff:x:50:app\n"),
+    Entry::Dir("data/"),
+    Entry::File("data/old", "old\n"),
+    Entry::File("data/kept", "kept\n"),
+    Entry::Dir("cache/"),
+    Entry::File("cache/stale", "stale\n"),
+    Entry::File("conf", "file\n"),
+  ], false);
+  let top = work.join("top.tar.gz");
+  layer(&top, &[
+    Entry::File("data/.wh.old", ""),
+    Entry::File("cache/.wh..wh..opq", ""),
+    Entry::File("cache/fresh", "fresh\n"),
+    Entry::Dir("conf/"),
+    Entry::File("conf/app.conf", "port=80\n"),
+    Entry::File("data/new", "new\n"),
+  ], true);
+  (base, top)
+}
+
+/// Writes a script checking the environment, working directory and ids it runs with.
+fn check_script(work: &Path) -> String {
+  let path = work.join("check.sh");
+  fs::write(&path, "test \"$(pwd) $GREETING $LXC_STUB_UID $LXC_STUB_GID\" = \"/tmp hello world 1000 50\"\n").unwrap();
+  path.to_str().unwrap().to_string()
+}
+
+fn image_config(work: &Path, cmd: &[&str]) -> PathBuf {
+  let path = work.join("config.json");
+  fs::write(&path, format!(r#"{{
+    "architecture": "amd64",
+    "os": "linux",
+    "config": {{
+      "User": "app:staff",
+      "Env": ["GREETING=hello world"],
+      "Entrypoint": ["sh"],
+      "Cmd": {:?},
+      "WorkingDir": "/tmp"
+    }}
+  }}"#, cmd)).unwrap();
+  path
+}
+
+/// Writes an OCI image layout with one manifest named "v1" to `layout`, whose image
+/// runs `sh` with `cmd`.
+fn oci_layout(work: &Path, layout: &Path, cmd: &[&str]) {
+  let (base, top) = write_layers(work);
+  let config = image_config(work, cmd);
+  let config = blob(layout, &config);
+  let base = blob(layout, &base);
+  let top = blob(layout, &top);
+  let manifest = work.join("manifest.json");
+  fs::write(&manifest, format!(r#"{{
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    "config": {{ "mediaType": "application/vnd.oci.image.config.v1+json", "digest": "{}", "size": 0 }},
+    "layers": [
+      {{ "mediaType": "application/vnd.oci.image.layer.v1.tar", "digest": "{}", "size": 0 }},
+      {{ "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip", "digest": "{}", "size": 0 }}
+    ]
+  }}"#, config, base, top)).unwrap();
+  let manifest = blob(layout, &manifest);
+  fs::write(layout.join("oci-layout"), r#"{"imageLayoutVersion": "1.0.0"}"#).unwrap();
+  fs::write(layout.join("index.json"), format!(r#"{{
+    "schemaVersion": 2,
+    "manifests": [{{
+      "mediaType": "application/vnd.oci.image.manifest.v1+json",
+      "digest": "{}",
+      "size": 0,
+      "annotations": {{ "org.opencontainers.image.ref.name": "v1" }}
+    }}]
+  }}"#, manifest)).unwrap();
+}
+
+fn check_container(c: &LxcContainer, lxcpath: &str, process: &OciProcess) {
+  let rootfs = Path::new(lxcpath).join(c.name()).join("rootfs");
+  let read = |p: &str| fs::read_to_string(rootfs.join(p)).unwrap();
+  assert!(!rootfs.join("data/old").exists());
+  assert!(!rootfs.join("data/.wh.old").exists());
+  assert_eq!(read("data/kept"), "kept\n");
+  assert_eq!(read("data/new"), "new\n");
+  assert!(!rootfs.join("cache/stale").exists());
+  assert!(!rootfs.join("cache/.wh..wh..opq").exists());
+  assert_eq!(read("cache/fresh"), "fresh\n");
+  assert_eq!(read("conf/app.conf"), "port=80\n");
+
+  assert_eq!(process.args[0], "sh");
+  assert_eq!(process.args.len(), 2);
+  assert_eq!(process.env[1], "GREETING=hello world");
+  assert!(process.env[0].starts_with("PATH="));
+  assert_eq!((&process.cwd[..], process.uid, process.gid), ("/tmp", 1000, 50));
+
+  assert_eq!(c.get_config_item("lxc.arch"), Ok("amd64".to_string()));
+  assert_eq!(c.get_config_item("lxc.environment"), Ok(process.env.join("\n")));
+  assert_eq!(c.get_config_item("lxc.init_uid"), Ok("1000".to_string()));
+  assert_eq!(c.get_config_item("lxc.init_gid"), Ok("50".to_string()));
+  assert_eq!(c.get_config_item("lxc.init_cmd"), Ok(process.args.join(" ")));
+  assert!(c.get_config_item("lxc.init.cwd").is_err());
+  assert_eq!(c.oci_process().as_ref(), Ok(process));
+}
+
+#[test]
+fn from_layout() {
+  let stub = support::stub();
+  let work = support::temp_dir("oci-layout");
+  let layout = work.join("layout");
+  oci_layout(&work, &layout, &[&check_script(&work)]);
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+
+  let c = LxcContainer::new("app", Some(lxcpath)).unwrap();
+  let options = OciOptions { reference: Some("v1".to_string()), arch: None };
+  let process = c.create_from_oci(&layout, &options).unwrap();
+  assert!(c.is_defined());
+  check_container(&c, lxcpath, &process);
+
+  assert!(c.run_oci_process().is_err());
+  assert!(c.start(0, None));
+  assert_eq!(c.run_oci_process(), Ok(0));
+  assert!(c.stop());
+
+  // A layout packed into a tarball, picked without reference as it is the only image.
+  let tarball = work.join("layout.tar");
+  let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+  builder.append_dir_all(".", &layout).unwrap();
+  builder.finish().unwrap();
+  let c = LxcContainer::new("packed", Some(lxcpath)).unwrap();
+  let process = c.create_from_oci(&tarball, &OciOptions::default()).unwrap();
+  check_container(&c, lxcpath, &process);
+  assert!(!Path::new(lxcpath).join("packed/oci-image").exists());
+
+  // liblxc 3.0 only knows the new names, and takes the working directory.
+  stub.set_version("3.0.0");
+  let c = LxcContainer::new("current", Some(lxcpath)).unwrap();
+  let process = c.create_from_oci(&layout, &OciOptions::default()).unwrap();
+  assert_eq!(c.get_config_item("lxc.init.cmd"), Ok(process.args.join(" ")));
+  assert_eq!(c.get_config_item("lxc.init.cwd"), Ok("/tmp".to_string()));
+  assert_eq!(c.get_config_item("lxc.init.uid"), Ok("1000".to_string()));
+  assert_eq!(c.get_config_item("lxc.init.gid"), Ok("50".to_string()));
+  assert!(c.get_config_item("lxc.init_cmd").is_err());
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn from_docker_archive() {
+  let _stub = support::stub();
+  let work = support::temp_dir("oci-docker");
+  let archive = work.join("archive");
+  fs::create_dir_all(archive.join("base")).unwrap();
+  fs::create_dir_all(archive.join("top")).unwrap();
+  let (base, top) = write_layers(&work);
+  fs::rename(&base, archive.join("base/layer.tar")).unwrap();
+  fs::rename(&top, archive.join("top/layer.tar")).unwrap();
+  fs::rename(image_config(&work, &[&check_script(&work)]), archive.join("config.json")).unwrap();
+  fs::write(archive.join("manifest.json"), r#"[{
+    "Config": "config.json",
+    "RepoTags": ["app:v1", "app:latest"],
+    "Layers": ["base/layer.tar", "top/layer.tar"]
+  }]"#).unwrap();
+  let tarball = work.join("app.tar");
+  let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+  builder.append_dir_all(".", &archive).unwrap();
+  builder.finish().unwrap();
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+
+  let c = LxcContainer::new("app", Some(lxcpath)).unwrap();
+  let options = OciOptions { reference: Some("app:v2".to_string()), arch: None };
+  assert!(c.create_from_oci(&tarball, &options).unwrap_err().contains("app:v2"));
+  assert!(!c.is_defined());
+  assert!(!Path::new(lxcpath).join("app/rootfs").exists());
+
+  let options = OciOptions { reference: Some("app:latest".to_string()), arch: None };
+  let process = c.create_from_oci(&tarball, &options).unwrap();
+  check_container(&c, lxcpath, &process);
+  fs::remove_dir_all(&work).unwrap();
+}
+
+#[test]
+fn errors() {
+  let _stub = support::stub();
+  let work = support::temp_dir("oci-errors");
+  let layout = work.join("layout");
+  oci_layout(&work, &layout, &["-c", "exit 0"]);
+  let lxcpath = work.join("lxc");
+  let lxcpath = lxcpath.to_str().unwrap();
+  let c = LxcContainer::new("app", Some(lxcpath)).unwrap();
+
+  // liblxc would split the argument with a space in lxc.init.cmd.
+  assert!(c.create_from_oci(&layout, &OciOptions::default()).unwrap_err().contains("exit 0"));
+  assert!(!c.is_defined());
+  assert!(!Path::new(lxcpath).join("app").exists());
+
+  let options = OciOptions { reference: Some("v2".to_string()), arch: None };
+  assert!(c.create_from_oci(&layout, &options).unwrap_err().contains("v2"));
+  assert!(c.create_from_oci(&work, &OciOptions::default()).is_err());
+  assert!(c.oci_process().is_err());
+  assert_eq!(c.run_oci_process(), Err("Container has no OCI process"));
+
+  // A blob whose content doesn't match its digest.
+  for entry in fs::read_dir(layout.join("blobs/sha256")).unwrap() {
+    let path = entry.unwrap().path();
+    if fs::read_to_string(&path).map(|s| s.contains("\"architecture\"")).unwrap_or(false) {
+      fs::write(&path, "{}").unwrap();
+    }
+  }
+  assert!(c.create_from_oci(&layout, &OciOptions::default()).unwrap_err().contains("does not match"));
+  assert!(!c.is_defined());
+  fs::remove_dir_all(&work).unwrap();
+}
@@ -0,0 +1,87 @@
+//! Processes of containers.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// PID of the first child of process `pid`, waiting for it to appear.
+fn first_child(pid: u32) -> Option<i32> {
+  for _ in 0..500 {
+    let children = fs::read_to_string(format!("/proc/{}/task/{}/children", pid, pid)).unwrap_or_default();
+    if let Some(child) = children.split_whitespace().next() {
+      return child.parse().ok();
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  None
+}
+
+#[test]
+fn list_and_signal() {
+  let stub = support::stub();
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.processes().unwrap_err().contains("isn't running"));
+  assert!(c.start(0, None));
+  // The container shares the cgroup of the test, whose processes stand in for its own.
+  let mut init = Command::new("sleep").arg("30").spawn().unwrap();
+  let mut worker = Command::new("sleep").arg("31").spawn().unwrap();
+  stub.set_init_pid(None, "web", init.id());
+
+  let processes = c.processes().unwrap();
+  let uid = fs::metadata("/proc/self").unwrap().uid();
+  let init_process = processes.iter().find(|p| p.pid == init.id() as i32).unwrap();
+  assert_eq!(init_process.container_pid, init.id() as i32);
+  assert_eq!((init_process.uid, init_process.host_uid), (uid, uid));
+  assert_eq!(init_process.name, "sleep");
+  assert_eq!(init_process.cmdline, vec!["sleep", "30"]);
+  assert!(init_process.state == 'S' || init_process.state == 'R', "{}", init_process.state);
+  assert!(init_process.rss > 0);
+  assert!(processes.iter().any(|p| p.pid == std::process::id() as i32));
+  assert!(processes.windows(2).all(|w| w[0].pid < w[1].pid));
+
+  assert_eq!(c.signal(SignalTarget::Pid(worker.id() as i32), 15), Ok(1));
+  assert_eq!(worker.wait().unwrap().signal(), Some(15));
+  assert!(c.signal(SignalTarget::Pid(-5), 15).unwrap_err().contains("no process -5"));
+  init.kill().unwrap();
+  init.wait().unwrap();
+  assert!(c.processes().is_err());
+  assert!(c.stop());
+  assert!(c.signal(SignalTarget::All, 15).is_err());
+}
+
+#[test]
+fn pid_namespace() {
+  let stub = support::stub();
+  let c = LxcContainer::new("web", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  // Killed init makes unshare fail to re-raise SIGKILL on itself, which it reports
+  // on stderr.
+  let mut unshare = match Command::new("unshare").args(["--pid", "--fork", "sleep", "30"])
+                                                 .stderr(Stdio::null()).spawn() {
+    Ok(unshare) => unshare,
+    Err(_) => return,
+  };
+  let init = match first_child(unshare.id()) {
+    Some(init) => init,
+    // Creating PID namespaces needs privileges.
+    None => return,
+  };
+  stub.set_init_pid(None, "web", init as u32);
+  let processes = c.processes().unwrap();
+  let init_process = processes.iter().find(|p| p.pid == init).unwrap();
+  assert_eq!(init_process.container_pid, 1);
+  assert_eq!(c.signal(SignalTarget::Pid(1), 9), Ok(1));
+  assert!(!unshare.wait().unwrap().success());
+  assert!(c.stop());
+}
@@ -0,0 +1,147 @@
+//! Tagged snapshots, retention and the snapshot scheduler.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use liblxc::snapshot_policy::{snapshot_tag, PruneDecision};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+/// 2024-01-01 00:00:00 UTC, a Monday.
+const MONDAY: i64 = 1704067200;
+const HOUR: i64 = 3600;
+
+fn container(name: &str) -> (LxcContainer, String) {
+  // Snapshot timestamps are in local time.
+  env::set_var("TZ", "UTC");
+  let lxcpath = env::temp_dir().join(format!("rust-lxc-snapshot-policy-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&lxcpath);
+  let lxcpath = lxcpath.to_str().unwrap().to_string();
+  let c = LxcContainer::new(name, Some(&lxcpath)).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  (c, lxcpath)
+}
+
+fn names(c: &LxcContainer) -> Vec<String> {
+  c.snapshot_list().unwrap().into_iter().map(|s| s.name).collect()
+}
+
+#[test]
+fn retention() {
+  let stub = support::stub();
+  let (c, lxcpath) = container("db");
+  let at = |time: i64, tag: Option<&str>| {
+    stub.set_time(time);
+    match tag {
+      Some(tag) => c.snapshot_tagged(tag, Some("nightly")).unwrap(),
+      None => c.snapshot(None).unwrap(),
+    }
+  };
+  at(MONDAY - HOUR, Some("scheduled"));                // snap0, Sunday of the week before
+  at(MONDAY + HOUR / 6, Some("scheduled"));            // snap1
+  at(MONDAY + 2 * HOUR / 3, Some("scheduled"));        // snap2
+  at(MONDAY + 5 * HOUR / 6, None);                     // snap3, untagged
+  at(MONDAY + HOUR + HOUR / 6, Some("scheduled"));     // snap4
+  at(MONDAY + 29 * HOUR, Some("scheduled"));           // snap5, Tuesday
+  at(MONDAY + 30 * HOUR, Some("scheduled"));           // snap6
+  at(MONDAY + 58 * HOUR, Some("scheduled"));           // snap7, Wednesday
+  at(MONDAY + 59 * HOUR, Some("before:upgrade"));      // snap8
+  at(MONDAY + 7 * 24 * HOUR + 10 * HOUR, Some("scheduled")); // snap9, next Monday
+
+  let snapshots = c.snapshot_list().unwrap();
+  assert_eq!(snapshot_tag(&snapshots[0]), Some("scheduled".to_string()));
+  assert_eq!(fs::read_to_string(snapshots[0].comment_pathname.as_ref().unwrap()).unwrap(),
+             "snapshot-tag: scheduled\nnightly\n");
+  assert_eq!(snapshot_tag(&snapshots[3]), None);
+  assert_eq!(snapshot_tag(&snapshots[8]), Some("before:upgrade".to_string()));
+
+  let policy = SnapshotPolicy {
+    interval: None,
+    retention: Retention { keep_last: 1, hourly: 2, daily: 4, weekly: 3 },
+    stop_running: false,
+  };
+  let report = policy.prune(&c, true).unwrap();
+  assert!(report.dry_run);
+  assert_eq!(names(&c).len(), 10);
+  let decisions: Vec<(&str, &str, Vec<&str>)> = report.decisions.iter()
+    .map(|d: &PruneDecision| (&d.name[..], &d.tag[..], d.reasons.clone())).collect();
+  assert_eq!(decisions, vec![
+    ("snap8", "before:upgrade", vec!["last", "hourly", "daily", "weekly"]),
+    ("snap9", "scheduled", vec!["last", "hourly", "daily", "weekly"]),
+    ("snap7", "scheduled", vec!["hourly", "daily", "weekly"]),
+    ("snap6", "scheduled", vec!["daily"]),
+    ("snap5", "scheduled", vec![]),
+    ("snap4", "scheduled", vec!["daily"]),
+    ("snap2", "scheduled", vec![]),
+    ("snap1", "scheduled", vec![]),
+    ("snap0", "scheduled", vec!["weekly"]),
+  ]);
+  assert_eq!(report.pruned(), vec!["snap5", "snap2", "snap1"]);
+  assert_eq!(report.decisions[0].timestamp, "2024:01:03 11:00:00");
+
+  let report = policy.prune(&c, false).unwrap();
+  assert!(!report.dry_run);
+  assert_eq!(report.pruned(), vec!["snap5", "snap2", "snap1"]);
+  assert_eq!(names(&c), vec!["snap0", "snap3", "snap4", "snap6", "snap7", "snap8", "snap9"]);
+
+  // Without rules everything is kept.
+  let keep_all = SnapshotPolicy { retention: Retention::default(), ..policy };
+  assert_eq!(keep_all.prune(&c, false).unwrap().pruned(), Vec::<&str>::new());
+  assert_eq!(names(&c).len(), 7);
+  assert!(c.destroy_with_snapshots());
+  let _ = fs::remove_dir_all(&lxcpath);
+}
+
+#[test]
+fn scheduler_and_operations() {
+  let stub = support::stub();
+  stub.set_time(MONDAY);
+  let (c, lxcpath) = container("app");
+  assert!(c.start(0, None));
+
+  let policy = SnapshotPolicy {
+    interval: Some(Duration::from_secs(3600)),
+    retention: Retention { keep_last: 2, ..Retention::default() },
+    stop_running: false,
+  };
+  let mut scheduler = SnapshotScheduler::new(policy);
+  assert_eq!(scheduler.poll(&c).err(), Some("Container is running"));
+  assert!(scheduler.next_snapshot().is_some());
+  assert_eq!(scheduler.poll(&c).unwrap(), None);
+  assert!(c.is_running());
+
+  let policy = SnapshotPolicy { stop_running: true, ..policy };
+  let mut scheduler = SnapshotScheduler::new(policy);
+  let taken = scheduler.poll(&c).unwrap().unwrap();
+  assert_eq!(taken.name, "snap0");
+  assert_eq!(taken.prune.kept(), vec!["snap0"]);
+  assert!(c.is_running());
+  assert_eq!(scheduler.poll(&c).unwrap(), None);
+  assert_eq!(snapshot_tag(&c.snapshot_list().unwrap()[0]), Some("scheduled".to_string()));
+
+  for i in 1..4 {
+    stub.set_time(MONDAY + i * 60);
+    policy.before(&c, "upgrade").unwrap();
+  }
+  assert_eq!(names(&c), vec!["snap0", "snap2", "snap3"]);
+  // liblxc reuses the lowest free number, so the newest is now snap1.
+  stub.set_time(MONDAY + 4 * 60);
+  let taken = policy.before(&c, "upgrade").unwrap();
+  assert_eq!(taken.name, "snap1");
+  assert_eq!(taken.prune.kept(), vec!["snap1", "snap3"]);
+  assert_eq!(taken.prune.pruned(), vec!["snap2"]);
+  assert_eq!(names(&c), vec!["snap0", "snap1", "snap3"]);
+  assert!(c.is_running());
+
+  assert_eq!(policy.before(&c, "up grade").err(), Some("Invalid snapshot tag"));
+  let manual = SnapshotPolicy { interval: None, ..policy };
+  assert_eq!(SnapshotScheduler::new(manual).poll(&c).unwrap(), None);
+  assert_eq!(names(&c).len(), 3);
+  assert!(c.stop());
+  assert!(c.destroy_with_snapshots());
+  let _ = fs::remove_dir_all(&lxcpath);
+}
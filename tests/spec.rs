@@ -0,0 +1,177 @@
+//! Reconciles containers against specs on the liblxc stub.
+#![cfg(all(feature = "lxc-stub", feature = "spec"))]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::spec::{self, Action, ContainerSpec};
+use liblxc::*;
+
+const WEB: &str = r#"
+name = "web"
+template = "download"
+template_args = ["-d", "alpine"]
+autostart = true
+mounts = ["/srv srv none bind,create=dir 0 0"]
+
+[[network]]
+type = "veth"
+link = "lxcbr0"
+flags = "up"
+ipv4 = ["10.0.3.10/24"]
+
+[limits]
+"memory.limit_in_bytes" = "536870912"
+
+[config]
+"lxc.cap.drop" = "sys_module"
+"#;
+
+/// Fresh lxcpath with directories for `names`, so configurations can be saved.
+fn lxcpath(test: &str, names: &[&str]) -> String {
+  let lxcpath = std::env::temp_dir().join(format!("rust-lxc-spec-{}-{}", test, std::process::id()));
+  let _ = std::fs::remove_dir_all(&lxcpath);
+  for name in names {
+    std::fs::create_dir_all(lxcpath.join(name)).unwrap();
+  }
+  lxcpath.to_str().unwrap().to_string()
+}
+
+fn set(key: &str, value: &str) -> Action {
+  Action::SetConfig(key.to_string(), value.to_string())
+}
+
+#[test]
+fn toml_and_yaml_agree() {
+  let yaml = "
+name: web
+template: download
+template_args: [-d, alpine]
+autostart: true
+mounts: ['/srv srv none bind,create=dir 0 0']
+network:
+  - type: veth
+    link: lxcbr0
+    flags: up
+    ipv4: [10.0.3.10/24]
+limits:
+  memory.limit_in_bytes: '536870912'
+config:
+  lxc.cap.drop: sys_module
+";
+  let spec = ContainerSpec::from_toml(WEB).unwrap();
+  assert_eq!(ContainerSpec::from_yaml(yaml).unwrap(), spec);
+  assert_eq!(spec.network.as_ref().unwrap()[0].kind, "veth");
+  assert!(ContainerSpec::from_toml("name = \"x\"\ntemplate = \"t\"\nautostrat = true\n").is_err());
+  assert!(ContainerSpec::from_toml("template = \"t\"\n").is_err());
+}
+
+#[test]
+fn apply_creates_and_converges() {
+  let stub = support::stub();
+  let spec = ContainerSpec::from_toml(WEB).unwrap();
+  let lxcpath = lxcpath("converge", &["web", "broken"]);
+  let c = LxcContainer::new("web", Some(&lxcpath)).unwrap();
+  let planned = spec::plan(&spec, &c);
+  assert_eq!(planned[0], Action::Create { template: "download".to_string(), bdevtype: None,
+                                          args: vec!["-d".to_string(), "alpine".to_string()] });
+  assert!(!c.is_defined());
+
+  let done = spec::apply(&spec, &c).unwrap();
+  assert!(!done.contains(&Action::Restart));
+  assert_eq!(stub.argv(Some(&lxcpath), "web", "create"), vec!["-d", "alpine"]);
+  assert_eq!(c.get_config_item("lxc.start.auto"), Ok("1".to_string()));
+  assert_eq!(c.get_config_item("lxc.cgroup.memory.limit_in_bytes"), Ok("536870912".to_string()));
+  assert_eq!(c.get_config_item("lxc.network.0.link"), Ok("lxcbr0".to_string()));
+  assert_eq!(c.get_config_item("lxc.network.0.ipv4"), Ok("10.0.3.10/24".to_string()));
+  assert_eq!(spec::plan(&spec, &c), vec![]);
+  assert_eq!(spec::apply(&spec, &c), Ok(vec![]));
+
+  let broken = LxcContainer::new("broken", Some(&lxcpath)).unwrap();
+  stub.fail("broken", "save_config");
+  assert_eq!(spec::apply(&spec, &broken), Err("Couldn't save configuration"));
+  assert!(broken.is_defined());
+  std::fs::remove_dir_all(&lxcpath).unwrap();
+}
+
+#[test]
+fn running_containers_restart_only_when_needed() {
+  let _stub = support::stub();
+  let mut spec = ContainerSpec::from_toml(WEB).unwrap();
+  let lxcpath = lxcpath("restart", &["web"]);
+  let c = LxcContainer::new("web", Some(&lxcpath)).unwrap();
+  spec::apply(&spec, &c).unwrap();
+  assert!(c.start(0, None));
+  assert_eq!(spec::plan(&spec, &c), vec![]);
+
+  spec.limits.insert("memory.limit_in_bytes".to_string(), "1073741824".to_string());
+  spec.autostart = Some(false);
+  assert_eq!(spec::plan(&spec, &c),
+             vec![set("lxc.start.auto", "0"),
+                  set("lxc.cgroup.memory.limit_in_bytes", "1073741824"),
+                  Action::SetCgroup("memory.limit_in_bytes".to_string(), "1073741824".to_string())]);
+  let pid = c.init_pid();
+  spec::apply(&spec, &c).unwrap();
+  assert_eq!(c.init_pid(), pid);
+  assert_eq!(c.get_cgroup_item("memory.limit_in_bytes"), Ok("1073741824".to_string()));
+
+  spec.mounts = Some(vec!["/srv srv none bind 0 0".to_string(), "/opt opt none bind 0 0".to_string()]);
+  assert_eq!(spec::plan(&spec, &c),
+             vec![Action::ClearConfig("lxc.mount.entry".to_string()),
+                  set("lxc.mount.entry", "/srv srv none bind 0 0"),
+                  set("lxc.mount.entry", "/opt opt none bind 0 0"),
+                  Action::Restart]);
+  spec::apply(&spec, &c).unwrap();
+  assert!(c.is_running());
+  assert_eq!(c.get_config_item("lxc.mount.entry"),
+             Ok("/srv srv none bind 0 0\n/opt opt none bind 0 0".to_string()));
+  assert_eq!(spec::plan(&spec, &c), vec![]);
+  std::fs::remove_dir_all(&lxcpath).unwrap();
+}
+
+#[test]
+fn list_keys_are_replaced() {
+  let _stub = support::stub();
+  let mut spec = ContainerSpec::from_toml(WEB).unwrap();
+  let lxcpath = lxcpath("lists", &["web"]);
+  let c = LxcContainer::new("web", Some(&lxcpath)).unwrap();
+  spec.config.insert("lxc.group".to_string(), "web".to_string());
+  spec::apply(&spec, &c).unwrap();
+
+  spec.config.insert("lxc.cap.drop".to_string(), "mknod".to_string());
+  spec.config.insert("lxc.group".to_string(), "frontend".to_string());
+  assert_eq!(spec::plan(&spec, &c),
+             vec![Action::ClearConfig("lxc.cap.drop".to_string()), set("lxc.cap.drop", "mknod"),
+                  Action::ClearConfig("lxc.group".to_string()), set("lxc.group", "frontend")]);
+  spec::apply(&spec, &c).unwrap();
+  assert_eq!(c.get_config_item("lxc.cap.drop"), Ok("mknod".to_string()));
+  assert_eq!(c.get_config_item("lxc.group"), Ok("frontend".to_string()));
+  assert_eq!(spec::plan(&spec, &c), vec![]);
+  std::fs::remove_dir_all(&lxcpath).unwrap();
+}
+
+#[test]
+fn networks_are_replaced_as_a_whole() {
+  let _stub = support::stub();
+  let mut spec = ContainerSpec::from_toml(WEB).unwrap();
+  let lxcpath = lxcpath("network", &["web"]);
+  let c = LxcContainer::new("web", Some(&lxcpath)).unwrap();
+  spec::apply(&spec, &c).unwrap();
+
+  let mut second = spec.network.as_ref().unwrap()[0].clone();
+  second.link = Some("lxcbr1".to_string());
+  second.ipv4 = vec![];
+  spec.network.as_mut().unwrap().push(second);
+  let planned = spec::plan(&spec, &c);
+  assert_eq!(planned[0], Action::ClearConfig("lxc.network".to_string()));
+  assert_eq!(planned.iter().filter(|a| **a == set("lxc.network.type", "veth")).count(), 2);
+  spec::apply(&spec, &c).unwrap();
+  assert_eq!(c.get_config_item("lxc.network.1.link"), Ok("lxcbr1".to_string()));
+  assert_eq!(spec::plan(&spec, &c), vec![]);
+
+  spec.network = Some(vec![]);
+  spec::apply(&spec, &c).unwrap();
+  assert_eq!(c.get_config_item("lxc.network.0.type"), Ok(String::new()));
+  std::fs::remove_dir_all(&lxcpath).unwrap();
+}
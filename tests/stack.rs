@@ -0,0 +1,153 @@
+//! Starts and stops stacks on the liblxc stub.
+#![cfg(all(feature = "lxc-stub", feature = "spec"))]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::stack::{StackError, StackSpec};
+use liblxc::*;
+
+const STACK: &str = r#"
+[[containers]]
+name = "app"
+depends_on = ["cache", "db"]
+health = { ip = true }
+
+[[containers]]
+name = "db"
+health = { command = ["true"] }
+
+[[containers]]
+name = "cache"
+depends_on = ["db"]
+
+[[containers]]
+name = "queue"
+depends_on = ["db"]
+"#;
+
+fn create(names: &[&str]) {
+  for name in names {
+    let c = LxcContainer::new(name, None).unwrap();
+    assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  }
+}
+
+fn waves(order: &[&[&str]]) -> Vec<Vec<String>> {
+  order.iter().map(|w| w.iter().map(|n| n.to_string()).collect()).collect()
+}
+
+#[test]
+fn start_order_follows_dependencies_order_and_groups() {
+  let _stub = support::stub();
+  create(&["app", "db", "cache", "queue"]);
+  let mut stack = StackSpec::from_toml(STACK).unwrap();
+  assert_eq!(stack.start_order(None).unwrap(), waves(&[&["db"], &["cache", "queue"], &["app"]]));
+
+  let queue = LxcContainer::new("queue", None).unwrap();
+  assert!(queue.set_config_item("lxc.start.order", "10"));
+  assert_eq!(stack.start_order(None).unwrap(), waves(&[&["db"], &["queue", "cache"], &["app"]]));
+
+  // Members of earlier groups start first, whatever their lxc.start.order.
+  assert!(queue.set_config_item("lxc.group", "late"));
+  let cache = LxcContainer::new("cache", None).unwrap();
+  assert!(cache.set_config_item("lxc.group", "onboot"));
+  assert!(cache.set_config_item("lxc.group", "infra"));
+  stack.groups = vec!["infra".to_string(), "late".to_string()];
+  assert_eq!(stack.start_order(None).unwrap(), waves(&[&["db"], &["cache"], &["queue", "app"]]));
+}
+
+#[test]
+fn invalid_stacks() {
+  let _stub = support::stub();
+  create(&["app", "db", "cache"]);
+  let mut stack = StackSpec::from_toml(STACK).unwrap();
+  assert_eq!(stack.start_order(None), Err(StackError::NotFound("queue".to_string())));
+
+  stack.containers.pop();
+  stack.containers[1].depends_on = vec!["app".to_string()];
+  assert_eq!(stack.start_order(None), Err(StackError::Cycle(vec!["app".to_string(),
+                                                                  "cache".to_string(),
+                                                                  "db".to_string()])));
+  stack.containers[1].depends_on = vec!["web".to_string()];
+  assert!(matches!(stack.start_order(None), Err(StackError::Invalid(_))));
+
+  let yaml = "containers:\n  - name: db\n    timeout: 5\n  - name: db\n";
+  let stack = StackSpec::from_yaml(yaml).unwrap();
+  assert_eq!(stack.containers[0].timeout, 5);
+  assert_eq!(stack.containers[1].timeout, 60);
+  assert!(matches!(stack.start_order(None), Err(StackError::Invalid(_))));
+}
+
+#[test]
+fn up_and_down_in_order() {
+  let stub = support::stub();
+  create(&["app", "db", "cache", "queue"]);
+  let stack = StackSpec::from_toml(STACK).unwrap();
+  assert_eq!(stack.up(None), Ok(()));
+  for name in ["app", "db", "cache", "queue"].iter() {
+    assert!(LxcContainer::new(name, None).unwrap().is_running());
+  }
+  let started = |name| stub.event(None, name, "start");
+  assert_eq!(started("db"), 1);
+  assert!(started("cache") < started("queue"));
+  assert_eq!(started("app"), 4);
+
+  // Running containers are left alone.
+  assert_eq!(stack.up(None), Ok(()));
+  assert_eq!(started("app"), 4);
+
+  assert_eq!(stack.down(None), Ok(()));
+  let stopped = |name| stub.event(None, name, "stop");
+  assert_eq!(stopped("app"), 5);
+  assert!(stopped("cache") > 5 && stopped("queue") > 5);
+  assert_eq!(stopped("db"), 8);
+  assert!(!LxcContainer::new("db", None).unwrap().is_running());
+}
+
+#[test]
+fn failures_name_the_container() {
+  let stub = support::stub();
+  create(&["app", "db", "cache", "queue"]);
+  let mut stack = StackSpec::from_toml(STACK).unwrap();
+  stub.fail("cache", "start");
+  assert_eq!(stack.up(None), Err(StackError::Container { name: "cache".to_string(),
+                                                         reason: "failed to start" }));
+  assert_eq!(stub.event(None, "app", "start"), 0);
+
+  stack.containers.retain(|m| m.name == "db");
+  stack.containers[0].health.command = vec!["false".to_string()];
+  stack.containers[0].timeout = 0;
+  assert_eq!(stack.up(None), Err(StackError::Container { name: "db".to_string(),
+                                                         reason: "health command failed" }));
+  stub.fail("db", "shutdown");
+  stub.fail("db", "stop");
+  assert_eq!(stack.down(None), Err(StackError::Container { name: "db".to_string(),
+                                                           reason: "failed to stop" }));
+}
+
+#[test]
+fn health_checks() {
+  let _stub = support::stub();
+  create(&["db", "web"]);
+  let toml = r#"
+[[containers]]
+name = "db"
+health.check = { command = ["true"], interval = 0.1 }
+
+[[containers]]
+name = "web"
+depends_on = ["db"]
+health.check = { command = ["false"], interval = 0, retries = 2 }
+"#;
+  let stack = StackSpec::from_toml(toml).unwrap();
+  let check = stack.containers[0].health.check.clone().unwrap();
+  assert_eq!(check.interval, std::time::Duration::from_millis(100));
+  assert_eq!(check.retries, 3);
+  assert!(StackSpec::from_toml("[[containers]]\nname = \"db\"\nhealth.check = { interval = -1 }\n").is_err());
+
+  // "web" turns unhealthy long before its timeout.
+  assert_eq!(stack.up(None), Err(StackError::Container { name: "web".to_string(), reason: "unhealthy" }));
+  assert!(LxcContainer::new("db", None).unwrap().is_running());
+}
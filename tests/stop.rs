@@ -0,0 +1,88 @@
+//! Staged stops against the liblxc stub.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::time::Duration;
+
+fn running(name: &str) -> LxcContainer {
+  let c = LxcContainer::new(name, None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  c
+}
+
+#[test]
+fn clean_shutdown() {
+  let _stub = support::stub();
+  let c = running("calm");
+  let policy = StopPolicy { pre_stop: Some(vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()]),
+                            ..StopPolicy::default() };
+  let report = c.stop_gracefully(&policy);
+  assert_eq!(report.stopped_by, Some(StopStage::Shutdown));
+  assert_eq!(report.pre_stop_status, Some(Ok(3)));
+  assert!(report.is_stopped());
+  assert!(!c.is_running());
+
+  let report = c.stop_gracefully(&policy);
+  assert_eq!(report.stopped_by, Some(StopStage::NotRunning));
+  assert_eq!(report.pre_stop_status, None);
+}
+
+#[test]
+fn escalation() {
+  let stub = support::stub();
+  let c = running("stubborn");
+  stub.fail("stubborn", "shutdown");
+  let report = c.stop_gracefully(&StopPolicy::default());
+  assert_eq!(report.stopped_by, Some(StopStage::Kill));
+  assert_eq!(report.pre_stop_status, None);
+
+  assert!(c.start(0, None));
+  let report = c.stop_gracefully(&StopPolicy { shutdown_timeout: 0, ..StopPolicy::default() });
+  assert_eq!(report.stopped_by, Some(StopStage::Kill));
+
+  assert!(c.start(0, None));
+  stub.fail("stubborn", "stop");
+  let policy = StopPolicy { pre_stop: Some(vec![]), kill: false, ..StopPolicy::default() };
+  let report = c.stop_gracefully(&policy);
+  assert_eq!(report.stopped_by, None);
+  assert_eq!(report.pre_stop_status, Some(Err("Empty pre-stop command")));
+  assert!(!c.stop_gracefully(&StopPolicy::default()).is_stopped());
+  assert!(c.is_running());
+}
+
+#[test]
+fn pre_stop_timeout() {
+  let _stub = support::stub();
+  let c = running("draining");
+  let policy = StopPolicy { pre_stop: Some(vec!["sleep".to_string(), "5".to_string()]),
+                            pre_stop_timeout: Duration::from_millis(100),
+                            ..StopPolicy::default() };
+  let report = c.stop_gracefully(&policy);
+  assert_eq!(report.pre_stop_status, Some(Err("Attached process timed out")));
+  assert_eq!(report.stopped_by, Some(StopStage::Shutdown));
+  assert!(report.elapsed < Duration::from_secs(5));
+}
+
+#[test]
+fn shutdown_without_timeout() {
+  let _stub = support::stub();
+  let c = running("patient");
+  let report = c.stop_gracefully(&StopPolicy { shutdown_timeout: -1, kill: false, ..StopPolicy::default() });
+  assert_eq!(report.stopped_by, Some(StopStage::Shutdown));
+}
+
+#[test]
+fn stopped_meanwhile() {
+  let stub = support::stub();
+  let c = running("hasty");
+  // The container stops on the request, but the wait misses it and so does stop().
+  stub.fail("hasty", "wait");
+  let report = c.stop_gracefully(&StopPolicy::default());
+  assert_eq!(report.stopped_by, Some(StopStage::Shutdown));
+  assert!(!c.is_running());
+}
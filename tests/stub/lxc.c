@@ -0,0 +1,2053 @@
+/*
+ * Scripted stand-in for liblxc, used by the integration tests.
+ *
+ * Built by build.rs when the `lxc-stub` feature is enabled and linked in place
+ * of the system liblxc. Containers live in an in-memory "host" keyed by
+ * (lxcpath, name), so the safe wrapper can be exercised without root, without
+ * LXC installed and without touching /var/lib/lxc.
+ *
+ * The struct layout below mirrors `ffi::LxcContainer` field by field; if one
+ * changes, the other has to follow.
+ *
+ * Everything handed to the caller follows liblxc's ownership rules: strings and
+ * arrays returned by config_file_name, get_running_config_item,
+ * get_interfaces, get_ips and list_*_containers are malloc'd and must be freed
+ * by the caller, container handles are reference counted through
+ * lxc_container_get/lxc_container_put.
+ *
+ * Test-only controls are exported with the `lxc_stub_` prefix.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <pthread.h>
+#include <semaphore.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/file.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define STUB_MAX_FAILS 64
+#define STUB_MAX_ARGS 32
+
+#define LXC_LOCK_ANON_SEM 1
+#define LXC_LOCK_FLOCK 2
+
+/* Mirrors `ffi::lxclock::LxcLock`. */
+struct lxc_lock {
+	short type;
+	union {
+		sem_t *sem;
+		struct {
+			int fd;
+			char *fname;
+		} f;
+	} u;
+};
+
+struct lxc_conf;
+struct bdev_specs;
+
+/* Mirrors `ffi::attach_options::LxcAttachOptions`. */
+enum lxc_attach_env_policy_t { LXC_ATTACH_KEEP_ENV, LXC_ATTACH_CLEAR_ENV };
+
+struct lxc_attach_options_t {
+	int attach_flags;
+	int namespaces;
+	long personality;
+	char *initial_cwd;
+	uid_t uid;
+	gid_t gid;
+	enum lxc_attach_env_policy_t env_policy;
+	char **extra_env_vars;
+	char **extra_keep_env;
+	int stdin_fd;
+	int stdout_fd;
+	int stderr_fd;
+};
+
+/* Mirrors `ffi::LxcSnapshot`. */
+struct lxc_snapshot {
+	char *name;
+	char *comment_pathname;
+	char *timestamp;
+	char *lxcpath;
+	void (*free)(struct lxc_snapshot *s);
+};
+
+struct lxc_container {
+	char *name;
+	char *configfile;
+	char *pidfile;
+	struct lxc_lock *slock;
+	struct lxc_lock *privlock;
+	int numthreads;
+	struct lxc_conf *lxc_conf;
+
+	char *error_string;
+	int error_num;
+	bool daemonize;
+	char *config_path;
+
+	bool (*is_defined)(struct lxc_container *c);
+	const char *(*state)(struct lxc_container *c);
+	bool (*is_running)(struct lxc_container *c);
+	bool (*freeze)(struct lxc_container *c);
+	bool (*unfreeze)(struct lxc_container *c);
+	pid_t (*init_pid)(struct lxc_container *c);
+	bool (*load_config)(struct lxc_container *c, const char *alt_file);
+	bool (*start)(struct lxc_container *c, int useinit, char *const argv[]);
+	bool (*startl)(struct lxc_container *c, int useinit, ...);
+	bool (*stop)(struct lxc_container *c);
+	bool (*want_daemonize)(struct lxc_container *c, bool state);
+	bool (*want_close_all_fds)(struct lxc_container *c, bool state);
+	char *(*config_file_name)(struct lxc_container *c);
+	bool (*wait)(struct lxc_container *c, const char *state, int timeout);
+	bool (*set_config_item)(struct lxc_container *c, const char *key, const char *value);
+	bool (*destroy)(struct lxc_container *c);
+	bool (*save_config)(struct lxc_container *c, const char *alt_file);
+	bool (*create)(struct lxc_container *c, const char *t, const char *bdevtype,
+		       struct bdev_specs *specs, int flags, char *const argv[]);
+	bool (*createl)(struct lxc_container *c, const char *t, const char *bdevtype,
+			struct bdev_specs *specs, int flags, ...);
+	bool (*rename)(struct lxc_container *c, const char *newname);
+	bool (*reboot)(struct lxc_container *c);
+	bool (*shutdown)(struct lxc_container *c, int timeout);
+	void (*clear_config)(struct lxc_container *c);
+	bool (*clear_config_item)(struct lxc_container *c, const char *key);
+	int (*get_config_item)(struct lxc_container *c, const char *key, char *retv, int inlen);
+	char *(*get_running_config_item)(struct lxc_container *c, const char *key);
+	int (*get_keys)(struct lxc_container *c, const char *key, char *retv, int inlen);
+	char **(*get_interfaces)(struct lxc_container *c);
+	char **(*get_ips)(struct lxc_container *c, const char *interface,
+			  const char *family, int scope);
+	int (*get_cgroup_item)(struct lxc_container *c, const char *subsys, char *retv, int inlen);
+	bool (*set_cgroup_item)(struct lxc_container *c, const char *subsys, const char *value);
+	const char *(*get_config_path)(struct lxc_container *c);
+	bool (*set_config_path)(struct lxc_container *c, const char *path);
+	struct lxc_container *(*clone)(struct lxc_container *c, const char *newname,
+				       const char *lxcpath, int flags, const char *bdevtype,
+				       const char *bdevdata, uint64_t newsize, char **hookargs);
+	int (*console_getfd)(struct lxc_container *c, int *ttynum, int *masterfd);
+	int (*console)(struct lxc_container *c, int ttynum, int stdinfd, int stdoutfd,
+		       int stderrfd, int escape);
+	int (*attach)(struct lxc_container *c, int (*exec_function)(void *payload),
+		      void *exec_payload, struct lxc_attach_options_t *options,
+		      pid_t *attached_process);
+	int (*attach_run_wait)(struct lxc_container *c, struct lxc_attach_options_t *options,
+			       const char *program, const char *const argv[]);
+	int (*attach_run_waitl)(struct lxc_container *c, struct lxc_attach_options_t *options,
+				const char *program, const char *arg, ...);
+	int (*snapshot)(struct lxc_container *c, const char *commentfile);
+	int (*snapshot_list)(struct lxc_container *c, struct lxc_snapshot **snapshots);
+	bool (*snapshot_restore)(struct lxc_container *c, const char *snapname, const char *newname);
+	bool (*snapshot_destroy)(struct lxc_container *c, const char *snapname);
+	bool (*may_control)(struct lxc_container *c);
+	bool (*add_device_node)(struct lxc_container *c, const char *src_path, const char *dest_path);
+	bool (*remove_device_node)(struct lxc_container *c, const char *src_path, const char *dest_path);
+	bool (*attach_interface)(struct lxc_container *c, const char *dev, const char *dst_dev);
+	bool (*detach_interface)(struct lxc_container *c, const char *dev, const char *dst_dev);
+	bool (*checkpoint)(struct lxc_container *c, char *directory, bool stop, bool verbose);
+	bool (*restore)(struct lxc_container *c, char *directory, bool verbose);
+	bool (*destroy_with_snapshots)(struct lxc_container *c);
+	bool (*snapshot_destroy_all)(struct lxc_container *c);
+};
+
+/* -------------------------------------------------------------------------- */
+/* In-memory host                                                              */
+/* -------------------------------------------------------------------------- */
+
+struct stub_item {
+	char *key;
+	char *value;
+	struct stub_item *next;
+};
+
+struct stub_record {
+	char *lxcpath;
+	char *name;
+	int id;
+	bool defined;
+	const char *state;
+	pid_t pid;
+	char *template;
+	char *bdevtype;
+	char *timestamp;
+	char *comment;
+	char *create_argv[STUB_MAX_ARGS + 1];
+	char *start_argv[STUB_MAX_ARGS + 1];
+	int started;	/* value of stub_events when last started, 0 if never */
+	int stopped;	/* likewise for stop and shutdown */
+	struct stub_item *config;
+	struct stub_item *cgroup;
+	struct stub_record *next;
+};
+
+struct stub_fail {
+	char *name;
+	char *op;
+};
+
+static pthread_mutex_t stub_mutex = PTHREAD_MUTEX_INITIALIZER;
+static struct stub_record *stub_records;
+static struct stub_fail stub_fails[STUB_MAX_FAILS];
+static int stub_next_id = 1;
+static int stub_events;
+static int stub_live_containers;
+static char stub_lxcpath[4096] = "/var/lib/lxc";
+static char stub_version[64] = "1.1.5";
+
+/* Keys which accumulate values instead of replacing them, as in liblxc. */
+static const char *stub_list_keys[] = {
+	"lxc.mount.entry", "lxc.id_map", "lxc.idmap", "lxc.cap.drop", "lxc.cap.keep",
+	"lxc.group", "lxc.environment", NULL,
+};
+
+static const char *stub_wait_states[] = {
+	"STOPPED", "STARTING", "RUNNING", "STOPPING",
+	"ABORTING", "FREEZING", "FROZEN", "THAWED",
+};
+
+/* Keys renamed in liblxc 2.1, new name first. The old names are gone in 3.0. */
+static const char *stub_renamed_keys[][2] = {
+	{ "lxc.idmap", "lxc.id_map" },
+	{ "lxc.rootfs.path", "lxc.rootfs" },
+	{ "lxc.uts.name", "lxc.utsname" },
+	{ "lxc.init.cmd", "lxc.init_cmd" },
+	{ "lxc.init.uid", "lxc.init_uid" },
+	{ "lxc.init.gid", "lxc.init_gid" },
+};
+
+/*
+ * Whether the stubbed liblxc version knows key: the new names of renamed keys
+ * came with 2.1, along with lxc.init.cwd, and replaced the old ones in 3.0.
+ */
+static bool stub_known_key(const char *key)
+{
+	int major = 0, minor = 0;
+	size_t i;
+
+	if (strncmp(key, "lxc.", 4) != 0)
+		return false;
+	sscanf(stub_version, "%d.%d", &major, &minor);
+	if (strcmp(key, "lxc.init.cwd") == 0)
+		return major > 2 || (major == 2 && minor >= 1);
+	for (i = 0; i < sizeof(stub_renamed_keys) / sizeof(stub_renamed_keys[0]); i++) {
+		if (strcmp(key, stub_renamed_keys[i][0]) == 0)
+			return major > 2 || (major == 2 && minor >= 1);
+		if (strcmp(key, stub_renamed_keys[i][1]) == 0)
+			return major < 3;
+	}
+	return true;
+}
+
+/* Name of renamed key new_key the stubbed liblxc version writes. */
+static const char *stub_key(const char *new_key)
+{
+	size_t i;
+
+	for (i = 0; i < sizeof(stub_renamed_keys) / sizeof(stub_renamed_keys[0]); i++) {
+		if (strcmp(new_key, stub_renamed_keys[i][0]) == 0 && !stub_known_key(new_key))
+			return stub_renamed_keys[i][1];
+	}
+	return new_key;
+}
+
+static bool stub_loaded;
+static void stub_load(void);
+
+static void stub_lock(void)
+{
+	pthread_mutex_lock(&stub_mutex);
+	if (!stub_loaded) {
+		stub_loaded = true;
+		stub_load();
+	}
+}
+
+static void stub_unlock(void)
+{
+	pthread_mutex_unlock(&stub_mutex);
+}
+
+static char *stub_strdup(const char *s)
+{
+	return s ? strdup(s) : NULL;
+}
+
+static void stub_free_argv(char **argv)
+{
+	int i;
+
+	for (i = 0; argv[i]; i++) {
+		free(argv[i]);
+		argv[i] = NULL;
+	}
+}
+
+static void stub_copy_argv(char **dst, char *const *src)
+{
+	int i;
+
+	stub_free_argv(dst);
+	if (!src)
+		return;
+	for (i = 0; src[i] && i < STUB_MAX_ARGS; i++)
+		dst[i] = strdup(src[i]);
+	dst[i] = NULL;
+}
+
+static bool stub_is_list_key(const char *key)
+{
+	const char *suffix = strrchr(key, '.');
+	int i;
+
+	for (i = 0; stub_list_keys[i]; i++)
+		if (strcmp(stub_list_keys[i], key) == 0)
+			return true;
+	/* Addresses of a network accumulate too. */
+	return strncmp(key, "lxc.network.", 12) == 0 &&
+	       (strcmp(suffix, ".ipv4") == 0 || strcmp(suffix, ".ipv6") == 0);
+}
+
+static struct stub_item *stub_item_find(struct stub_item *items, const char *key)
+{
+	for (; items; items = items->next)
+		if (strcmp(items->key, key) == 0)
+			return items;
+	return NULL;
+}
+
+static void stub_item_set(struct stub_item **items, const char *key, const char *value, bool append)
+{
+	struct stub_item *item = stub_item_find(*items, key);
+	struct stub_item **tail;
+
+	if (item) {
+		if (append && item->value[0]) {
+			char *joined = malloc(strlen(item->value) + strlen(value) + 2);
+			sprintf(joined, "%s\n%s", item->value, value);
+			free(item->value);
+			item->value = joined;
+		} else {
+			free(item->value);
+			item->value = strdup(value);
+		}
+		return;
+	}
+
+	item = calloc(1, sizeof(*item));
+	item->key = strdup(key);
+	item->value = strdup(value);
+	for (tail = items; *tail; tail = &(*tail)->next)
+		;
+	*tail = item;
+}
+
+static bool stub_item_clear(struct stub_item **items, const char *key)
+{
+	struct stub_item **it;
+	bool found = false;
+
+	for (it = items; *it;) {
+		struct stub_item *item = *it;
+		size_t len = strlen(key);
+
+		/* Clearing "lxc.network" also clears "lxc.network.0.type" etc. */
+		if (strcmp(item->key, key) == 0 ||
+		    (strncmp(item->key, key, len) == 0 && item->key[len] == '.')) {
+			*it = item->next;
+			free(item->key);
+			free(item->value);
+			free(item);
+			found = true;
+		} else {
+			it = &item->next;
+		}
+	}
+	return found;
+}
+
+static void stub_items_free(struct stub_item **items)
+{
+	while (*items) {
+		struct stub_item *item = *items;
+		*items = item->next;
+		free(item->key);
+		free(item->value);
+		free(item);
+	}
+}
+
+static void stub_items_copy(struct stub_item **dst, struct stub_item *src)
+{
+	for (; src; src = src->next)
+		stub_item_set(dst, src->key, src->value, false);
+}
+
+static const char *stub_default_lxcpath(void)
+{
+	return stub_lxcpath;
+}
+
+static struct stub_record *stub_find(const char *lxcpath, const char *name)
+{
+	struct stub_record *r;
+
+	for (r = stub_records; r; r = r->next)
+		if (strcmp(r->lxcpath, lxcpath) == 0 && strcmp(r->name, name) == 0)
+			return r;
+	return NULL;
+}
+
+static struct stub_record *stub_find_or_add(const char *lxcpath, const char *name)
+{
+	struct stub_record *r = stub_find(lxcpath, name);
+
+	if (r)
+		return r;
+	r = calloc(1, sizeof(*r));
+	r->lxcpath = strdup(lxcpath);
+	r->name = strdup(name);
+	r->id = stub_next_id++;
+	r->state = "STOPPED";
+	r->pid = -1;
+	r->next = stub_records;
+	stub_records = r;
+	return r;
+}
+
+static void stub_record_undefine(struct stub_record *r)
+{
+	r->defined = false;
+	r->state = "STOPPED";
+	r->pid = -1;
+	free(r->template);
+	r->template = NULL;
+	free(r->bdevtype);
+	r->bdevtype = NULL;
+	free(r->timestamp);
+	r->timestamp = NULL;
+	free(r->comment);
+	r->comment = NULL;
+	stub_free_argv(r->create_argv);
+	stub_free_argv(r->start_argv);
+	stub_items_free(&r->config);
+	stub_items_free(&r->cgroup);
+}
+
+static struct stub_record *stub_record(struct lxc_container *c)
+{
+	return stub_find_or_add(c->config_path, c->name);
+}
+
+static bool stub_should_fail(const char *name, const char *op)
+{
+	int i;
+
+	for (i = 0; i < STUB_MAX_FAILS; i++) {
+		if (!stub_fails[i].op)
+			continue;
+		if (strcmp(stub_fails[i].op, op) != 0)
+			continue;
+		if (strcmp(stub_fails[i].name, "*") == 0 || strcmp(stub_fails[i].name, name) == 0)
+			return true;
+	}
+	return false;
+}
+
+static bool stub_is_active(struct stub_record *r)
+{
+	return strcmp(r->state, "STOPPED") != 0;
+}
+
+/* Copies `value` into `retv` the way liblxc's strprint does and returns the
+ * full length, so that a NULL/0 call can be used to size the buffer. */
+static int stub_strprint(char *retv, int inlen, const char *value)
+{
+	int len = strlen(value);
+
+	if (retv && inlen > 0) {
+		strncpy(retv, value, inlen - 1);
+		retv[inlen - 1] = '\0';
+		if (len < inlen)
+			retv[len] = '\0';
+	}
+	return len;
+}
+
+static char *stub_path(const char *lxcpath, const char *name, const char *file)
+{
+	char *path = malloc(strlen(lxcpath) + strlen(name) + strlen(file) + 3);
+
+	sprintf(path, "%s/%s/%s", lxcpath, name, file);
+	return path;
+}
+
+/* -------------------------------------------------------------------------- */
+/* Container API                                                               */
+/* -------------------------------------------------------------------------- */
+
+#define STUB_ENTER(c, op, failval)                          \
+	struct stub_record *r;                              \
+	stub_lock();                                        \
+	r = stub_record(c);                                 \
+	(void)r;                                            \
+	if (stub_should_fail((c)->name, op)) {              \
+		stub_unlock();                              \
+		return failval;                             \
+	}
+
+#define STUB_RETURN(val) \
+	do {             \
+		stub_unlock(); \
+		return val;    \
+	} while (0)
+
+static bool stub_is_defined(struct lxc_container *c)
+{
+	STUB_ENTER(c, "is_defined", false);
+	STUB_RETURN(r->defined);
+}
+
+static const char *stub_state(struct lxc_container *c)
+{
+	STUB_ENTER(c, "state", NULL);
+	STUB_RETURN(r->state);
+}
+
+static bool stub_is_running(struct lxc_container *c)
+{
+	STUB_ENTER(c, "is_running", false);
+	STUB_RETURN(stub_is_active(r));
+}
+
+static bool stub_freeze(struct lxc_container *c)
+{
+	STUB_ENTER(c, "freeze", false);
+	if (strcmp(r->state, "RUNNING") != 0)
+		STUB_RETURN(false);
+	r->state = "FROZEN";
+	STUB_RETURN(true);
+}
+
+static bool stub_unfreeze(struct lxc_container *c)
+{
+	STUB_ENTER(c, "unfreeze", false);
+	if (strcmp(r->state, "FROZEN") != 0)
+		STUB_RETURN(false);
+	r->state = "RUNNING";
+	STUB_RETURN(true);
+}
+
+static pid_t stub_init_pid(struct lxc_container *c)
+{
+	STUB_ENTER(c, "init_pid", -1);
+	STUB_RETURN(r->pid);
+}
+
+static bool stub_load_config(struct lxc_container *c, const char *alt_file)
+{
+	STUB_ENTER(c, "load_config", false);
+	(void)alt_file;
+	STUB_RETURN(true);
+}
+
+static bool stub_start(struct lxc_container *c, int useinit, char *const argv[])
+{
+	struct stub_item *item;
+
+	STUB_ENTER(c, "start", false);
+	(void)useinit;
+	if (!r->defined || stub_is_active(r))
+		STUB_RETURN(false);
+	stub_copy_argv(r->start_argv, argv);
+	r->state = "RUNNING";
+	r->pid = 10000 + r->id;
+	r->started = ++stub_events;
+	for (item = r->config; item; item = item->next)
+		if (strncmp(item->key, "lxc.cgroup.", 11) == 0)
+			stub_item_set(&r->cgroup, item->key + 11, item->value, false);
+	STUB_RETURN(true);
+}
+
+static bool stub_startl(struct lxc_container *c, int useinit, ...)
+{
+	(void)c;
+	(void)useinit;
+	return false;
+}
+
+static bool stub_stop(struct lxc_container *c)
+{
+	STUB_ENTER(c, "stop", false);
+	if (!stub_is_active(r))
+		STUB_RETURN(false);
+	r->state = "STOPPED";
+	r->pid = -1;
+	r->stopped = ++stub_events;
+	stub_items_free(&r->cgroup);
+	STUB_RETURN(true);
+}
+
+static bool stub_want_daemonize(struct lxc_container *c, bool state)
+{
+	STUB_ENTER(c, "want_daemonize", false);
+	c->daemonize = state;
+	STUB_RETURN(true);
+}
+
+static bool stub_want_close_all_fds(struct lxc_container *c, bool state)
+{
+	STUB_ENTER(c, "want_close_all_fds", false);
+	(void)state;
+	STUB_RETURN(true);
+}
+
+static char *stub_config_file_name(struct lxc_container *c)
+{
+	STUB_ENTER(c, "config_file_name", NULL);
+	STUB_RETURN(stub_strdup(c->configfile));
+}
+
+static bool stub_wait(struct lxc_container *c, const char *state, int timeout)
+{
+	STUB_ENTER(c, "wait", false);
+	(void)timeout;
+	STUB_RETURN(strcmp(r->state, state) == 0);
+}
+
+/* Number of networks, stored as "lxc.network.<n>.*" items. */
+static int stub_network_count(struct stub_record *r)
+{
+	char key[64];
+	int n = 0;
+
+	for (;;) {
+		snprintf(key, sizeof(key), "lxc.network.%d.type", n);
+		if (!stub_item_find(r->config, key))
+			return n;
+		n++;
+	}
+}
+
+static bool stub_set_config_item(struct lxc_container *c, const char *key, const char *value)
+{
+	char indexed[256];
+
+	STUB_ENTER(c, "set_config_item", false);
+	if (!stub_known_key(key))
+		STUB_RETURN(false);
+	/*
+	 * As in liblxc, "lxc.network.type" adds a network and the other unindexed
+	 * "lxc.network.*" keys apply to the last one.
+	 */
+	if (strncmp(key, "lxc.network.", 12) == 0 && (key[12] < '0' || key[12] > '9')) {
+		int n = stub_network_count(r) + (strcmp(key + 12, "type") == 0);
+
+		if (n == 0)
+			STUB_RETURN(false);
+		snprintf(indexed, sizeof(indexed), "lxc.network.%d.%s", n - 1, key + 12);
+		key = indexed;
+	}
+	if (!value[0]) {
+		stub_item_clear(&r->config, key);
+		STUB_RETURN(true);
+	}
+	stub_item_set(&r->config, key, value, stub_is_list_key(key));
+	STUB_RETURN(true);
+}
+
+static char *stub_snap_lxcpath(const char *lxcpath, const char *name)
+{
+	return stub_path(lxcpath, name, "snaps");
+}
+
+static bool stub_has_snapshots(const char *lxcpath, const char *name)
+{
+	char *snappath = stub_snap_lxcpath(lxcpath, name);
+	struct stub_record *it;
+	bool found = false;
+
+	for (it = stub_records; it; it = it->next)
+		if (it->defined && strcmp(it->lxcpath, snappath) == 0)
+			found = true;
+	free(snappath);
+	return found;
+}
+
+static bool stub_destroy(struct lxc_container *c)
+{
+	STUB_ENTER(c, "destroy", false);
+	if (!r->defined || stub_is_active(r))
+		STUB_RETURN(false);
+	if (stub_has_snapshots(c->config_path, c->name))
+		STUB_RETURN(false);
+	stub_record_undefine(r);
+	STUB_RETURN(true);
+}
+
+static bool stub_save_config(struct lxc_container *c, const char *alt_file)
+{
+	struct stub_item *item;
+	FILE *f;
+
+	STUB_ENTER(c, "save_config", false);
+	f = fopen(alt_file ? alt_file : c->configfile, "w");
+	if (!f)
+		STUB_RETURN(false);
+	for (item = r->config; item; item = item->next) {
+		char *values = strdup(item->value);
+		char *save = NULL;
+		char *value;
+
+		for (value = strtok_r(values, "\n", &save); value;
+		     value = strtok_r(NULL, "\n", &save))
+			fprintf(f, "%s = %s\n", item->key, value);
+		free(values);
+	}
+	fclose(f);
+	/* Like liblxc, a container with a configuration file is defined. */
+	if (!alt_file || strcmp(alt_file, c->configfile) == 0)
+		r->defined = true;
+	STUB_RETURN(true);
+}
+
+static bool stub_create(struct lxc_container *c, const char *t, const char *bdevtype,
+			struct bdev_specs *specs, int flags, char *const argv[])
+{
+	char *rootfs;
+
+	STUB_ENTER(c, "create", false);
+	(void)specs;
+	(void)flags;
+	if (r->defined)
+		STUB_RETURN(false);
+	r->defined = true;
+	r->state = "STOPPED";
+	r->template = stub_strdup(t);
+	r->bdevtype = stub_strdup(bdevtype ? bdevtype : "dir");
+	stub_copy_argv(r->create_argv, argv);
+	rootfs = stub_path(c->config_path, c->name, "rootfs");
+	stub_item_set(&r->config, stub_key("lxc.rootfs.path"), rootfs, false);
+	stub_item_set(&r->config, stub_key("lxc.uts.name"), c->name, false);
+	free(rootfs);
+	STUB_RETURN(true);
+}
+
+static bool stub_createl(struct lxc_container *c, const char *t, const char *bdevtype,
+			 struct bdev_specs *specs, int flags, ...)
+{
+	(void)c;
+	(void)t;
+	(void)bdevtype;
+	(void)specs;
+	(void)flags;
+	return false;
+}
+
+static bool stub_rename(struct lxc_container *c, const char *newname)
+{
+	struct stub_record *target;
+
+	STUB_ENTER(c, "rename", false);
+	if (!r->defined || stub_is_active(r))
+		STUB_RETURN(false);
+	target = stub_find(c->config_path, newname);
+	if (target && target->defined)
+		STUB_RETURN(false);
+	if (target)
+		target->name[0] = '\0';
+	free(r->name);
+	r->name = strdup(newname);
+	free(c->name);
+	c->name = strdup(newname);
+	free(c->configfile);
+	c->configfile = stub_path(c->config_path, newname, "config");
+	stub_item_set(&r->config, stub_key("lxc.uts.name"), newname, false);
+	STUB_RETURN(true);
+}
+
+static bool stub_reboot(struct lxc_container *c)
+{
+	STUB_ENTER(c, "reboot", false);
+	STUB_RETURN(strcmp(r->state, "RUNNING") == 0);
+}
+
+static bool stub_shutdown(struct lxc_container *c, int timeout)
+{
+	STUB_ENTER(c, "shutdown", false);
+	(void)timeout;
+	if (strcmp(r->state, "RUNNING") != 0)
+		STUB_RETURN(false);
+	r->state = "STOPPED";
+	r->pid = -1;
+	r->stopped = ++stub_events;
+	stub_items_free(&r->cgroup);
+	STUB_RETURN(true);
+}
+
+static void stub_clear_config(struct lxc_container *c)
+{
+	STUB_ENTER(c, "clear_config", );
+	stub_items_free(&r->config);
+	stub_unlock();
+}
+
+static bool stub_clear_config_item(struct lxc_container *c, const char *key)
+{
+	STUB_ENTER(c, "clear_config_item", false);
+	stub_item_clear(&r->config, key);
+	STUB_RETURN(true);
+}
+
+static int stub_get_config_item(struct lxc_container *c, const char *key, char *retv, int inlen)
+{
+	struct stub_item *item;
+
+	STUB_ENTER(c, "get_config_item", -1);
+	if (!stub_known_key(key))
+		STUB_RETURN(-1);
+	item = stub_item_find(r->config, key);
+	STUB_RETURN(stub_strprint(retv, inlen, item ? item->value : ""));
+}
+
+static char *stub_get_running_config_item(struct lxc_container *c, const char *key)
+{
+	struct stub_item *item;
+
+	STUB_ENTER(c, "get_running_config_item", NULL);
+	if (!stub_is_active(r))
+		STUB_RETURN(NULL);
+	item = stub_item_find(r->config, key);
+	STUB_RETURN(item ? strdup(item->value) : NULL);
+}
+
+static int stub_get_keys(struct lxc_container *c, const char *key, char *retv, int inlen)
+{
+	struct stub_item *item;
+	size_t len = 1;
+	char *keys;
+	int ret;
+
+	STUB_ENTER(c, "get_keys", -1);
+	if (strncmp(key, "lxc.", 4) != 0)
+		STUB_RETURN(-1);
+	for (item = r->config; item; item = item->next)
+		len += strlen(item->key) + 1;
+	keys = calloc(1, len);
+	for (item = r->config; item; item = item->next) {
+		if (strncmp(item->key, key, strlen(key)) != 0)
+			continue;
+		if (keys[0])
+			strcat(keys, "\n");
+		strcat(keys, item->key);
+	}
+	ret = stub_strprint(retv, inlen, keys);
+	free(keys);
+	STUB_RETURN(ret);
+}
+
+static char **stub_get_interfaces(struct lxc_container *c)
+{
+	char **interfaces;
+
+	STUB_ENTER(c, "get_interfaces", NULL);
+	if (!stub_is_active(r))
+		STUB_RETURN(NULL);
+	interfaces = calloc(3, sizeof(char *));
+	interfaces[0] = strdup("eth0");
+	interfaces[1] = strdup("lo");
+	STUB_RETURN(interfaces);
+}
+
+static char **stub_get_ips(struct lxc_container *c, const char *interface,
+			   const char *family, int scope)
+{
+	char **ips;
+	int n = 0;
+
+	STUB_ENTER(c, "get_ips", NULL);
+	(void)scope;
+	if (strcmp(r->state, "RUNNING") != 0)
+		STUB_RETURN(NULL);
+	ips = calloc(3, sizeof(char *));
+	if (!interface || strcmp(interface, "eth0") == 0) {
+		if (!family || strcmp(family, "inet") == 0) {
+			ips[n] = malloc(32);
+			sprintf(ips[n++], "10.0.3.%d", r->id % 250 + 2);
+		}
+		if (!family || strcmp(family, "inet6") == 0) {
+			ips[n] = malloc(32);
+			sprintf(ips[n++], "fe80::216:3eff:fe00:%x", r->id);
+		}
+	}
+	STUB_RETURN(ips);
+}
+
+static int stub_get_cgroup_item(struct lxc_container *c, const char *subsys, char *retv, int inlen)
+{
+	struct stub_item *item;
+
+	STUB_ENTER(c, "get_cgroup_item", -1);
+	if (!stub_is_active(r))
+		STUB_RETURN(-1);
+	item = stub_item_find(r->cgroup, subsys);
+	if (!item)
+		STUB_RETURN(-1);
+	STUB_RETURN(stub_strprint(retv, inlen, item->value));
+}
+
+static bool stub_set_cgroup_item(struct lxc_container *c, const char *subsys, const char *value)
+{
+	STUB_ENTER(c, "set_cgroup_item", false);
+	if (!stub_is_active(r))
+		STUB_RETURN(false);
+	stub_item_set(&r->cgroup, subsys, value, false);
+	STUB_RETURN(true);
+}
+
+static const char *stub_get_config_path(struct lxc_container *c)
+{
+	return c->config_path;
+}
+
+static bool stub_set_config_path(struct lxc_container *c, const char *path)
+{
+	if (!path)
+		return false;
+	stub_lock();
+	free(c->config_path);
+	c->config_path = strdup(path);
+	free(c->configfile);
+	c->configfile = stub_path(c->config_path, c->name, "config");
+	stub_unlock();
+	return true;
+}
+
+static struct lxc_container *stub_new(const char *name, const char *configpath);
+
+static struct lxc_container *stub_clone(struct lxc_container *c, const char *newname,
+					const char *lxcpath, int flags, const char *bdevtype,
+					const char *bdevdata, uint64_t newsize, char **hookargs)
+{
+	struct stub_record *target;
+	char *rootfs;
+
+	STUB_ENTER(c, "clone", NULL);
+	(void)flags;
+	(void)bdevdata;
+	(void)newsize;
+	(void)hookargs;
+	if (!newname)
+		newname = c->name;
+	if (!lxcpath)
+		lxcpath = c->config_path;
+	if (!r->defined || stub_is_active(r))
+		STUB_RETURN(NULL);
+	if (strcmp(newname, c->name) == 0 && strcmp(lxcpath, c->config_path) == 0)
+		STUB_RETURN(NULL);
+	target = stub_find_or_add(lxcpath, newname);
+	if (target->defined)
+		STUB_RETURN(NULL);
+	target->defined = true;
+	target->state = "STOPPED";
+	target->template = stub_strdup(r->template);
+	target->bdevtype = stub_strdup(bdevtype ? bdevtype : r->bdevtype);
+	stub_items_copy(&target->config, r->config);
+	rootfs = stub_path(lxcpath, newname, "rootfs");
+	stub_item_set(&target->config, stub_key("lxc.rootfs.path"), rootfs, false);
+	stub_item_set(&target->config, stub_key("lxc.uts.name"), newname, false);
+	free(rootfs);
+	stub_unlock();
+	return stub_new(newname, lxcpath);
+}
+
+static int stub_console_getfd(struct lxc_container *c, int *ttynum, int *masterfd)
+{
+	(void)c;
+	(void)ttynum;
+	(void)masterfd;
+	return -1;
+}
+
+static int stub_console(struct lxc_container *c, int ttynum, int stdinfd, int stdoutfd,
+			int stderrfd, int escape)
+{
+	(void)c;
+	(void)ttynum;
+	(void)stdinfd;
+	(void)stdoutfd;
+	(void)stderrfd;
+	(void)escape;
+	return -1;
+}
+
+/*
+ * Applies attach options in the child. Users can't be switched without
+ * privileges, so requested ids are exported as LXC_STUB_UID and LXC_STUB_GID.
+ * Standard streams are redirected like liblxc does.
+ */
+static void stub_attach_setup(struct lxc_attach_options_t *options)
+{
+	char id[32];
+	char **var;
+
+	if (!options)
+		return;
+	if (options->stdin_fd != STDIN_FILENO)
+		dup2(options->stdin_fd, STDIN_FILENO);
+	if (options->stdout_fd != STDOUT_FILENO)
+		dup2(options->stdout_fd, STDOUT_FILENO);
+	if (options->stderr_fd != STDERR_FILENO)
+		dup2(options->stderr_fd, STDERR_FILENO);
+	if (options->env_policy == LXC_ATTACH_CLEAR_ENV)
+		clearenv();
+	for (var = options->extra_env_vars; var && *var; var++)
+		putenv(*var);
+	if (options->uid != (uid_t)-1) {
+		snprintf(id, sizeof(id), "%u", (unsigned)options->uid);
+		setenv("LXC_STUB_UID", id, 1);
+	}
+	if (options->gid != (gid_t)-1) {
+		snprintf(id, sizeof(id), "%u", (unsigned)options->gid);
+		setenv("LXC_STUB_GID", id, 1);
+	}
+	if (options->initial_cwd && chdir(options->initial_cwd) < 0)
+		_exit(126);
+}
+
+/* Runs `exec_function` in a child on the host, like liblxc runs it in the container. */
+static int stub_attach(struct lxc_container *c, int (*exec_function)(void *payload),
+		       void *exec_payload, struct lxc_attach_options_t *options,
+		       pid_t *attached_process)
+{
+	pid_t pid;
+
+	STUB_ENTER(c, "attach", -1);
+	if (strcmp(r->state, "RUNNING") != 0)
+		STUB_RETURN(-1);
+	stub_unlock();
+
+	pid = fork();
+	if (pid < 0)
+		return -1;
+	if (pid == 0) {
+		stub_attach_setup(options);
+		_exit(exec_function(exec_payload));
+	}
+	*attached_process = pid;
+	return 0;
+}
+
+/* Runs the program on the host, as if the container shared everything with it. */
+static int stub_attach_run_wait(struct lxc_container *c, struct lxc_attach_options_t *options,
+				const char *program, const char *const argv[])
+{
+	pid_t pid;
+	int status;
+
+	STUB_ENTER(c, "attach_run_wait", -1);
+	if (strcmp(r->state, "RUNNING") != 0)
+		STUB_RETURN(-1);
+	stub_unlock();
+
+	pid = fork();
+	if (pid < 0)
+		return -1;
+	if (pid == 0) {
+		stub_attach_setup(options);
+		execvp(program, (char *const *)argv);
+		_exit(127);
+	}
+	while (waitpid(pid, &status, 0) < 0)
+		if (errno != EINTR)
+			return -1;
+	return status;
+}
+
+static int stub_attach_run_waitl(struct lxc_container *c, struct lxc_attach_options_t *options,
+				 const char *program, const char *arg, ...)
+{
+	(void)c;
+	(void)options;
+	(void)program;
+	(void)arg;
+	return -1;
+}
+
+struct lxc_attach_command_t {
+	char *program;
+	char **argv;
+};
+
+int lxc_attach_run_command(void *payload)
+{
+	struct lxc_attach_command_t *cmd = payload;
+
+	execvp(cmd->program, cmd->argv);
+	return -1;
+}
+
+static time_t stub_time;
+
+static time_t stub_now(void)
+{
+	return stub_time ? stub_time : time(NULL);
+}
+
+/* Copies the definition of `src` into `dst`, as clone and snapshots do. */
+static void stub_record_copy(struct stub_record *dst, struct stub_record *src)
+{
+	char *rootfs;
+
+	stub_record_undefine(dst);
+	dst->defined = true;
+	dst->state = "STOPPED";
+	dst->pid = -1;
+	dst->template = stub_strdup(src->template);
+	dst->bdevtype = stub_strdup(src->bdevtype);
+	stub_items_copy(&dst->config, src->config);
+	rootfs = stub_path(dst->lxcpath, dst->name, "rootfs");
+	stub_item_set(&dst->config, stub_key("lxc.rootfs.path"), rootfs, false);
+	free(rootfs);
+}
+
+static void stub_mkdir_p(const char *path)
+{
+	char *dir = strdup(path);
+	char *p;
+
+	for (p = dir + 1; *p; p++) {
+		if (*p == '/') {
+			*p = '\0';
+			mkdir(dir, 0755);
+			*p = '/';
+		}
+	}
+	mkdir(dir, 0755);
+	free(dir);
+}
+
+static void stub_copy_file(const char *from, const char *to)
+{
+	FILE *in = fopen(from, "r");
+	FILE *out;
+	char buf[4096];
+	size_t n;
+
+	if (!in)
+		return;
+	out = fopen(to, "w");
+	if (out) {
+		while ((n = fread(buf, 1, sizeof(buf), in)) > 0)
+			fwrite(buf, 1, n, out);
+		fclose(out);
+	}
+	fclose(in);
+}
+
+static int stub_snapshot(struct lxc_container *c, const char *commentfile)
+{
+	struct stub_record *snap;
+	char *snappath, *dir;
+	char name[32];
+	time_t now;
+	int i;
+
+	STUB_ENTER(c, "snapshot", -1);
+	if (!r->defined || stub_is_active(r))
+		STUB_RETURN(-1);
+	snappath = stub_snap_lxcpath(c->config_path, c->name);
+	for (i = 0;; i++) {
+		snprintf(name, sizeof(name), "snap%d", i);
+		snap = stub_find(snappath, name);
+		if (!snap || !snap->defined)
+			break;
+	}
+	snap = stub_find_or_add(snappath, name);
+	stub_record_copy(snap, r);
+	stub_item_set(&snap->config, stub_key("lxc.uts.name"), c->name, false);
+
+	now = stub_now();
+	snap->timestamp = malloc(32);
+	strftime(snap->timestamp, 32, "%Y:%m:%d %H:%M:%S", localtime(&now));
+	if (commentfile) {
+		dir = stub_path(snappath, name, "");
+		stub_mkdir_p(dir);
+		snap->comment = stub_path(snappath, name, "comment");
+		stub_copy_file(commentfile, snap->comment);
+		free(dir);
+	}
+	free(snappath);
+	STUB_RETURN(i);
+}
+
+static void stub_snapshot_free(struct lxc_snapshot *s)
+{
+	free(s->name);
+	free(s->comment_pathname);
+	free(s->timestamp);
+	free(s->lxcpath);
+}
+
+static int stub_snapshot_list(struct lxc_container *c, struct lxc_snapshot **snapshots)
+{
+	struct stub_record *it;
+	char *snappath;
+	int i, n = 0;
+
+	STUB_ENTER(c, "snapshot_list", -1);
+	*snapshots = NULL;
+	snappath = stub_snap_lxcpath(c->config_path, c->name);
+	for (it = stub_records; it; it = it->next) {
+		struct lxc_snapshot *s;
+
+		if (!it->defined || strcmp(it->lxcpath, snappath) != 0)
+			continue;
+		*snapshots = realloc(*snapshots, (n + 1) * sizeof(struct lxc_snapshot));
+		s = &(*snapshots)[n++];
+		s->name = strdup(it->name);
+		s->comment_pathname = stub_strdup(it->comment);
+		s->timestamp = stub_strdup(it->timestamp);
+		s->lxcpath = strdup(snappath);
+		s->free = stub_snapshot_free;
+	}
+	free(snappath);
+
+	/* liblxc returns snapshots sorted by name */
+	for (i = 1; i < n; i++) {
+		int j;
+		for (j = i; j > 0 && strcmp((*snapshots)[j - 1].name, (*snapshots)[j].name) > 0; j--) {
+			struct lxc_snapshot tmp = (*snapshots)[j];
+			(*snapshots)[j] = (*snapshots)[j - 1];
+			(*snapshots)[j - 1] = tmp;
+		}
+	}
+	STUB_RETURN(n);
+}
+
+static bool stub_snapshot_restore(struct lxc_container *c, const char *snapname, const char *newname)
+{
+	struct stub_record *snap, *target;
+	char *snappath;
+
+	STUB_ENTER(c, "snapshot_restore", false);
+	snappath = stub_snap_lxcpath(c->config_path, c->name);
+	snap = stub_find(snappath, snapname);
+	free(snappath);
+	if (!snap || !snap->defined)
+		STUB_RETURN(false);
+	if (!newname)
+		newname = c->name;
+	target = stub_find_or_add(c->config_path, newname);
+	if (target->defined && (target != r || stub_is_active(r)))
+		STUB_RETURN(false);
+	stub_record_copy(target, snap);
+	stub_item_set(&target->config, stub_key("lxc.uts.name"), newname, false);
+	STUB_RETURN(true);
+}
+
+static bool stub_snapshot_destroy(struct lxc_container *c, const char *snapname)
+{
+	struct stub_record *snap;
+	char *snappath;
+
+	STUB_ENTER(c, "snapshot_destroy", false);
+	snappath = stub_snap_lxcpath(c->config_path, c->name);
+	snap = stub_find(snappath, snapname);
+	free(snappath);
+	if (!snap || !snap->defined)
+		STUB_RETURN(false);
+	if (snap->comment)
+		unlink(snap->comment);
+	stub_record_undefine(snap);
+	STUB_RETURN(true);
+}
+
+static bool stub_snapshot_destroy_all(struct lxc_container *c)
+{
+	struct stub_record *it;
+	char *snappath;
+
+	STUB_ENTER(c, "snapshot_destroy_all", false);
+	snappath = stub_snap_lxcpath(c->config_path, c->name);
+	for (it = stub_records; it; it = it->next)
+		if (it->defined && strcmp(it->lxcpath, snappath) == 0)
+			stub_record_undefine(it);
+	free(snappath);
+	STUB_RETURN(true);
+}
+
+static bool stub_destroy_with_snapshots(struct lxc_container *c)
+{
+	return stub_snapshot_destroy_all(c) && stub_destroy(c);
+}
+
+static bool stub_may_control(struct lxc_container *c)
+{
+	(void)c;
+	return true;
+}
+
+static bool stub_device_node(struct lxc_container *c, const char *src_path, const char *dest_path)
+{
+	(void)c;
+	(void)src_path;
+	(void)dest_path;
+	return false;
+}
+
+static bool stub_interface(struct lxc_container *c, const char *dev, const char *dst_dev)
+{
+	(void)dst_dev;
+	STUB_ENTER(c, "interface", false);
+	STUB_RETURN(dev && strcmp(r->state, "RUNNING") == 0);
+}
+
+/* Checkpoints only record that they happened: a running container is dumped
+ * (and stopped if asked to), a stopped one is restored. */
+static bool stub_checkpoint(struct lxc_container *c, char *directory, bool stop, bool verbose)
+{
+	(void)verbose;
+	STUB_ENTER(c, "checkpoint", false);
+	if (!directory || strcmp(r->state, "RUNNING") != 0)
+		STUB_RETURN(false);
+	if (stop) {
+		r->state = "STOPPED";
+		r->pid = -1;
+		r->stopped = ++stub_events;
+	}
+	STUB_RETURN(true);
+}
+
+static bool stub_restore(struct lxc_container *c, char *directory, bool verbose)
+{
+	(void)verbose;
+	STUB_ENTER(c, "restore", false);
+	if (!directory || strcmp(r->state, "STOPPED") != 0)
+		STUB_RETURN(false);
+	r->state = "RUNNING";
+	r->pid = 10000 + r->id;
+	r->started = ++stub_events;
+	STUB_RETURN(true);
+}
+
+/* -------------------------------------------------------------------------- */
+/* Locks                                                                       */
+/* -------------------------------------------------------------------------- */
+
+static pthread_mutex_t stub_process_mutex = PTHREAD_MUTEX_INITIALIZER;
+
+/* Directory holding the named lock files; liblxc uses /run/lxc/lock. */
+static char *stub_lock_dir(void)
+{
+	const char *tmp = getenv("TMPDIR");
+	char *dir = malloc(4096);
+
+	snprintf(dir, 4096, "%s/lxc-stub-lock-%d", tmp ? tmp : "/tmp", (int)getuid());
+	mkdir(dir, 0700);
+	return dir;
+}
+
+struct lxc_lock *lxc_newlock(const char *lxcpath, const char *name)
+{
+	struct lxc_lock *l = calloc(1, sizeof(*l));
+	char *dir, *p;
+
+	if (!name) {
+		l->type = LXC_LOCK_ANON_SEM;
+		l->u.sem = malloc(sizeof(sem_t));
+		sem_init(l->u.sem, 0, 1);
+		return l;
+	}
+
+	l->type = LXC_LOCK_FLOCK;
+	l->u.f.fd = -1;
+	if (!lxcpath)
+		lxcpath = stub_lxcpath;
+	dir = stub_lock_dir();
+	l->u.f.fname = malloc(strlen(dir) + strlen(lxcpath) + strlen(name) + 4);
+	sprintf(l->u.f.fname, "%s/%s.%s", dir, lxcpath, name);
+	/* Flatten lxcpath into the file name. */
+	for (p = l->u.f.fname + strlen(dir) + 1; *p; p++)
+		if (*p == '/')
+			*p = '_';
+	free(dir);
+	return l;
+}
+
+int lxclock(struct lxc_lock *l, int timeout)
+{
+	if (!l)
+		return -1;
+
+	if (l->type == LXC_LOCK_ANON_SEM) {
+		struct timespec ts;
+		int ret;
+
+		if (!timeout) {
+			while ((ret = sem_wait(l->u.sem)) < 0 && errno == EINTR)
+				;
+			return ret;
+		}
+		clock_gettime(CLOCK_REALTIME, &ts);
+		ts.tv_sec += timeout;
+		while ((ret = sem_timedwait(l->u.sem, &ts)) < 0 && errno == EINTR)
+			;
+		return ret;
+	}
+
+	/* As in liblxc, file locks cannot time out. */
+	if (timeout)
+		return -2;
+	if (l->u.f.fd == -1) {
+		l->u.f.fd = open(l->u.f.fname, O_CREAT | O_RDWR | O_CLOEXEC, 0600);
+		if (l->u.f.fd < 0)
+			return -2;
+	}
+	return flock(l->u.f.fd, LOCK_EX);
+}
+
+int lxcunlock(struct lxc_lock *l)
+{
+	int ret;
+
+	if (!l)
+		return -1;
+	if (l->type == LXC_LOCK_ANON_SEM)
+		return sem_post(l->u.sem);
+	if (l->u.f.fd == -1)
+		return -2;
+	ret = flock(l->u.f.fd, LOCK_UN);
+	close(l->u.f.fd);
+	l->u.f.fd = -1;
+	return ret;
+}
+
+void lxc_putlock(struct lxc_lock *l)
+{
+	if (!l)
+		return;
+	if (l->type == LXC_LOCK_ANON_SEM) {
+		sem_destroy(l->u.sem);
+		free(l->u.sem);
+	} else {
+		if (l->u.f.fd != -1)
+			close(l->u.f.fd);
+		free(l->u.f.fname);
+	}
+	free(l);
+}
+
+void process_lock(void)
+{
+	pthread_mutex_lock(&stub_process_mutex);
+}
+
+void process_unlock(void)
+{
+	pthread_mutex_unlock(&stub_process_mutex);
+}
+
+int container_mem_lock(struct lxc_container *c)
+{
+	bool fail;
+
+	stub_lock();
+	fail = stub_should_fail(c->name, "mem_lock");
+	stub_unlock();
+	if (fail)
+		return -1;
+	return lxclock(c->privlock, 0);
+}
+
+void container_mem_unlock(struct lxc_container *c)
+{
+	lxcunlock(c->privlock);
+}
+
+int container_disk_lock(struct lxc_container *c)
+{
+	int ret;
+
+	if ((ret = lxclock(c->privlock, 0)))
+		return ret;
+	if ((ret = lxclock(c->slock, 0))) {
+		lxcunlock(c->privlock);
+		return ret;
+	}
+	return 0;
+}
+
+void container_disk_unlock(struct lxc_container *c)
+{
+	lxcunlock(c->slock);
+	lxcunlock(c->privlock);
+}
+
+/* -------------------------------------------------------------------------- */
+/* Library API                                                                 */
+/* -------------------------------------------------------------------------- */
+
+struct stub_container {
+	struct lxc_container c;
+	int refcount;
+};
+
+static struct lxc_container *stub_new(const char *name, const char *configpath)
+{
+	struct stub_container *sc;
+	struct lxc_container *c;
+
+	if (!name || !name[0])
+		return NULL;
+
+	stub_lock();
+	if (stub_should_fail(name, "lxc_container_new")) {
+		stub_unlock();
+		return NULL;
+	}
+	sc = calloc(1, sizeof(*sc));
+	sc->refcount = 1;
+	stub_live_containers++;
+	stub_unlock();
+
+	c = &sc->c;
+	c->name = strdup(name);
+	c->config_path = strdup(configpath ? configpath : stub_default_lxcpath());
+	c->configfile = stub_path(c->config_path, name, "config");
+	c->numthreads = 1;
+	c->daemonize = true;
+	c->privlock = lxc_newlock(NULL, NULL);
+	c->slock = lxc_newlock(c->config_path, name);
+
+	c->is_defined = stub_is_defined;
+	c->state = stub_state;
+	c->is_running = stub_is_running;
+	c->freeze = stub_freeze;
+	c->unfreeze = stub_unfreeze;
+	c->init_pid = stub_init_pid;
+	c->load_config = stub_load_config;
+	c->start = stub_start;
+	c->startl = stub_startl;
+	c->stop = stub_stop;
+	c->want_daemonize = stub_want_daemonize;
+	c->want_close_all_fds = stub_want_close_all_fds;
+	c->config_file_name = stub_config_file_name;
+	c->wait = stub_wait;
+	c->set_config_item = stub_set_config_item;
+	c->destroy = stub_destroy;
+	c->destroy_with_snapshots = stub_destroy_with_snapshots;
+	c->save_config = stub_save_config;
+	c->create = stub_create;
+	c->createl = stub_createl;
+	c->rename = stub_rename;
+	c->reboot = stub_reboot;
+	c->shutdown = stub_shutdown;
+	c->clear_config = stub_clear_config;
+	c->clear_config_item = stub_clear_config_item;
+	c->get_config_item = stub_get_config_item;
+	c->get_running_config_item = stub_get_running_config_item;
+	c->get_keys = stub_get_keys;
+	c->get_interfaces = stub_get_interfaces;
+	c->get_ips = stub_get_ips;
+	c->get_cgroup_item = stub_get_cgroup_item;
+	c->set_cgroup_item = stub_set_cgroup_item;
+	c->get_config_path = stub_get_config_path;
+	c->set_config_path = stub_set_config_path;
+	c->clone = stub_clone;
+	c->console_getfd = stub_console_getfd;
+	c->console = stub_console;
+	c->attach = stub_attach;
+	c->attach_run_wait = stub_attach_run_wait;
+	c->attach_run_waitl = stub_attach_run_waitl;
+	c->snapshot = stub_snapshot;
+	c->snapshot_list = stub_snapshot_list;
+	c->snapshot_restore = stub_snapshot_restore;
+	c->snapshot_destroy = stub_snapshot_destroy;
+	c->snapshot_destroy_all = stub_snapshot_destroy_all;
+	c->may_control = stub_may_control;
+	c->add_device_node = stub_device_node;
+	c->remove_device_node = stub_device_node;
+	c->attach_interface = stub_interface;
+	c->detach_interface = stub_interface;
+	c->checkpoint = stub_checkpoint;
+	c->restore = stub_restore;
+	return c;
+}
+
+struct lxc_container *lxc_container_new(const char *name, const char *configpath)
+{
+	return stub_new(name, configpath);
+}
+
+int lxc_container_get(struct lxc_container *c)
+{
+	struct stub_container *sc = (struct stub_container *)c;
+
+	if (!c)
+		return 0;
+	stub_lock();
+	sc->refcount++;
+	stub_unlock();
+	return 1;
+}
+
+int lxc_container_put(struct lxc_container *c)
+{
+	struct stub_container *sc = (struct stub_container *)c;
+
+	if (!c)
+		return -1;
+	stub_lock();
+	if (--sc->refcount > 0) {
+		stub_unlock();
+		return 0;
+	}
+	stub_live_containers--;
+	stub_unlock();
+	lxc_putlock(c->privlock);
+	lxc_putlock(c->slock);
+	free(c->name);
+	free(c->configfile);
+	free(c->config_path);
+	free(sc);
+	return 1;
+}
+
+int lxc_get_wait_states(const char **states)
+{
+	int i, n = sizeof(stub_wait_states) / sizeof(stub_wait_states[0]);
+
+	if (states)
+		for (i = 0; i < n; i++)
+			states[i] = stub_wait_states[i];
+	return n;
+}
+
+const char *lxc_get_global_config_item(const char *key)
+{
+	if (strcmp(key, "lxc.lxcpath") == 0)
+		return stub_default_lxcpath();
+	return NULL;
+}
+
+const char *lxc_get_version(void)
+{
+	return stub_version;
+}
+
+void lxc_log_close(void)
+{
+}
+
+enum stub_list_kind { STUB_DEFINED, STUB_ACTIVE, STUB_ALL };
+
+static int stub_list(const char *lxcpath, char ***names, struct lxc_container ***cret,
+		     enum stub_list_kind kind)
+{
+	struct stub_record *r;
+	char **found = NULL;
+	int i, n = 0;
+
+	if (!lxcpath)
+		lxcpath = stub_default_lxcpath();
+
+	stub_lock();
+	if (stub_should_fail("*", "list_containers")) {
+		stub_unlock();
+		return -1;
+	}
+	for (r = stub_records; r; r = r->next) {
+		bool want;
+
+		if (strcmp(r->lxcpath, lxcpath) != 0 || !r->name[0])
+			continue;
+		switch (kind) {
+		case STUB_DEFINED:
+			want = r->defined;
+			break;
+		case STUB_ACTIVE:
+			want = stub_is_active(r);
+			break;
+		default:
+			want = r->defined || stub_is_active(r);
+			break;
+		}
+		if (!want)
+			continue;
+		found = realloc(found, (n + 1) * sizeof(char *));
+		found[n++] = strdup(r->name);
+	}
+	stub_unlock();
+
+	/* liblxc returns names sorted */
+	for (i = 1; i < n; i++) {
+		int j;
+		for (j = i; j > 0 && strcmp(found[j - 1], found[j]) > 0; j--) {
+			char *tmp = found[j];
+			found[j] = found[j - 1];
+			found[j - 1] = tmp;
+		}
+	}
+
+	if (cret) {
+		*cret = n ? malloc(n * sizeof(struct lxc_container *)) : NULL;
+		for (i = 0; i < n; i++)
+			(*cret)[i] = stub_new(found[i], lxcpath);
+	}
+	if (names) {
+		*names = found;
+	} else {
+		for (i = 0; i < n; i++)
+			free(found[i]);
+		free(found);
+	}
+	return n;
+}
+
+int list_defined_containers(const char *lxcpath, char ***names, struct lxc_container ***cret)
+{
+	return stub_list(lxcpath, names, cret, STUB_DEFINED);
+}
+
+int list_active_containers(const char *lxcpath, char ***names, struct lxc_container ***cret)
+{
+	return stub_list(lxcpath, names, cret, STUB_ACTIVE);
+}
+
+int list_all_containers(const char *lxcpath, char ***names, struct lxc_container ***cret)
+{
+	return stub_list(lxcpath, names, cret, STUB_ALL);
+}
+
+/* -------------------------------------------------------------------------- */
+/* Persistence                                                                 */
+/* -------------------------------------------------------------------------- */
+
+/*
+ * When LXC_STUB_STATE names a file, the host is loaded from it on first use and
+ * written back at exit, so that separate processes (e.g. runs of main-lxc)
+ * see the same containers. Scripted failures and argv are not persisted.
+ */
+
+static void stub_write_field(FILE *f, const char *value)
+{
+	const char *p;
+
+	fputc('\t', f);
+	if (!value) {
+		fputs("\\0", f);
+		return;
+	}
+	for (p = value; *p; p++) {
+		if (*p == '\\')
+			fputs("\\\\", f);
+		else if (*p == '\t')
+			fputs("\\t", f);
+		else if (*p == '\n')
+			fputs("\\n", f);
+		else
+			fputc(*p, f);
+	}
+}
+
+/* Splits the tab-prefixed fields of a line into at most `max` unescaped fields;
+ * NULL fields come back as NULL. */
+static int stub_read_fields(char *line, char **fields, int max)
+{
+	int n = 0;
+	char *p = line;
+
+	line[strcspn(line, "\n")] = '\0';
+	if (*p++ != '\t')
+		return 0;
+	while (n < max) {
+		char *start = p;
+		char *out = p;
+		char sep;
+
+		while (*p && *p != '\t') {
+			if (*p == '\\' && p[1]) {
+				p++;
+				*out++ = *p == 't' ? '\t' : *p == 'n' ? '\n' : *p == '0' ? '\1' : *p;
+				p++;
+			} else {
+				*out++ = *p++;
+			}
+		}
+		sep = *p;
+		*out = '\0';
+		fields[n++] = strcmp(start, "\1") == 0 ? NULL : start;
+		if (!sep)
+			break;
+		p++;
+	}
+	return n;
+}
+
+static void stub_save(void)
+{
+	const char *path = getenv("LXC_STUB_STATE");
+	struct stub_record *r;
+	struct stub_item *item;
+	FILE *f;
+
+	if (!path || !(f = fopen(path, "w")))
+		return;
+	fprintf(f, "N\t%d\n", stub_next_id);
+	for (r = stub_records; r; r = r->next) {
+		char id[16];
+
+		if (!r->defined && !stub_is_active(r))
+			continue;
+		snprintf(id, sizeof(id), "%d", r->id);
+		fputc('R', f);
+		stub_write_field(f, r->lxcpath);
+		stub_write_field(f, r->name);
+		stub_write_field(f, id);
+		stub_write_field(f, r->defined ? "1" : "0");
+		stub_write_field(f, r->state);
+		stub_write_field(f, r->template);
+		stub_write_field(f, r->bdevtype);
+		stub_write_field(f, r->timestamp);
+		stub_write_field(f, r->comment);
+		fputc('\n', f);
+		for (item = r->config; item; item = item->next) {
+			fputc('K', f);
+			stub_write_field(f, item->key);
+			stub_write_field(f, item->value);
+			fputc('\n', f);
+		}
+		for (item = r->cgroup; item; item = item->next) {
+			fputc('G', f);
+			stub_write_field(f, item->key);
+			stub_write_field(f, item->value);
+			fputc('\n', f);
+		}
+	}
+	fclose(f);
+}
+
+static void stub_save_at_exit(void)
+{
+	if (pthread_mutex_trylock(&stub_mutex) != 0)
+		return;
+	stub_save();
+	pthread_mutex_unlock(&stub_mutex);
+}
+
+static void stub_load(void)
+{
+	const char *path = getenv("LXC_STUB_STATE");
+	struct stub_record *r = NULL;
+	char *line = NULL;
+	size_t cap = 0;
+	FILE *f;
+
+	if (!path)
+		return;
+	atexit(stub_save_at_exit);
+	if (!(f = fopen(path, "r")))
+		return;
+	while (getline(&line, &cap, f) > 0) {
+		char *fields[10] = { NULL };
+		int i, n = stub_read_fields(line + 1, fields, 10);
+
+		if (line[0] == 'N' && n == 1) {
+			stub_next_id = atoi(fields[0]);
+		} else if (line[0] == 'R' && n == 9) {
+			r = stub_find_or_add(fields[0], fields[1]);
+			r->id = atoi(fields[2]);
+			r->defined = strcmp(fields[3], "1") == 0;
+			for (i = 0; i < (int)(sizeof(stub_wait_states) / sizeof(stub_wait_states[0])); i++)
+				if (strcmp(stub_wait_states[i], fields[4]) == 0)
+					r->state = stub_wait_states[i];
+			r->pid = stub_is_active(r) ? 10000 + r->id : -1;
+			r->template = stub_strdup(fields[5]);
+			r->bdevtype = stub_strdup(fields[6]);
+			r->timestamp = stub_strdup(fields[7]);
+			r->comment = stub_strdup(fields[8]);
+		} else if ((line[0] == 'K' || line[0] == 'G') && n == 2 && r) {
+			stub_item_set(line[0] == 'K' ? &r->config : &r->cgroup, fields[0], fields[1], false);
+		}
+	}
+	free(line);
+	fclose(f);
+}
+
+/* -------------------------------------------------------------------------- */
+/* Test controls                                                               */
+/* -------------------------------------------------------------------------- */
+
+/* Forget every container and scripted failure. */
+void lxc_stub_reset(void)
+{
+	int i;
+
+	stub_lock();
+	while (stub_records) {
+		struct stub_record *r = stub_records;
+		stub_records = r->next;
+		stub_record_undefine(r);
+		free(r->lxcpath);
+		free(r->name);
+		free(r);
+	}
+	for (i = 0; i < STUB_MAX_FAILS; i++) {
+		free(stub_fails[i].name);
+		free(stub_fails[i].op);
+		stub_fails[i].name = NULL;
+		stub_fails[i].op = NULL;
+	}
+	stub_next_id = 1;
+	stub_events = 0;
+	stub_time = 0;
+	strcpy(stub_lxcpath, "/var/lib/lxc");
+	strcpy(stub_version, "1.1.5");
+	stub_unlock();
+}
+
+/* Make `op` fail for the container called `name` ("*" for every container)
+ * until the next reset. `op` is the name of the liblxc function pointer or
+ * library function, e.g. "start" or "lxc_container_new". */
+void lxc_stub_fail(const char *name, const char *op)
+{
+	int i;
+
+	stub_lock();
+	for (i = 0; i < STUB_MAX_FAILS; i++) {
+		if (!stub_fails[i].op) {
+			stub_fails[i].name = strdup(name);
+			stub_fails[i].op = strdup(op);
+			break;
+		}
+	}
+	stub_unlock();
+}
+
+void lxc_stub_set_lxcpath(const char *lxcpath)
+{
+	stub_lock();
+	snprintf(stub_lxcpath, sizeof(stub_lxcpath), "%s", lxcpath);
+	stub_unlock();
+}
+
+void lxc_stub_set_version(const char *version)
+{
+	stub_lock();
+	snprintf(stub_version, sizeof(stub_version), "%s", version);
+	stub_unlock();
+}
+
+/* Force the state of a container, e.g. to simulate one that died. */
+void lxc_stub_set_state(const char *lxcpath, const char *name, const char *state)
+{
+	struct stub_record *r;
+	int i, n = sizeof(stub_wait_states) / sizeof(stub_wait_states[0]);
+
+	stub_lock();
+	r = stub_find_or_add(lxcpath ? lxcpath : stub_default_lxcpath(), name);
+	for (i = 0; i < n; i++) {
+		if (strcmp(stub_wait_states[i], state) == 0) {
+			r->state = stub_wait_states[i];
+			break;
+		}
+	}
+	r->pid = stub_is_active(r) ? 10000 + r->id : -1;
+	stub_unlock();
+}
+
+/* Make a running container report `pid` as its init, e.g. a process spawned by a test. */
+void lxc_stub_set_init_pid(const char *lxcpath, const char *name, int pid)
+{
+	struct stub_record *r;
+
+	stub_lock();
+	r = stub_find(lxcpath ? lxcpath : stub_default_lxcpath(), name);
+	if (r && stub_is_active(r))
+		r->pid = pid;
+	stub_unlock();
+}
+
+/* Number of container handles that have not been released yet. */
+int lxc_stub_live_containers(void)
+{
+	int n;
+
+	stub_lock();
+	n = stub_live_containers;
+	stub_unlock();
+	return n;
+}
+
+/*
+ * When container `name` was last started ("start") or stopped ("stop", also
+ * counting shutdown): the number of starts and stops before it plus one, or 0
+ * if it never was.
+ */
+int lxc_stub_event(const char *lxcpath, const char *name, const char *op)
+{
+	struct stub_record *r;
+	int seq = 0;
+
+	stub_lock();
+	r = stub_find(lxcpath ? lxcpath : stub_default_lxcpath(), name);
+	if (r)
+		seq = strcmp(op, "start") == 0 ? r->started : r->stopped;
+	stub_unlock();
+	return seq;
+}
+
+/* Copy of the template name passed to create(), or NULL. Caller frees. */
+char *lxc_stub_template(const char *lxcpath, const char *name)
+{
+	struct stub_record *r;
+	char *ret = NULL;
+
+	stub_lock();
+	r = stub_find(lxcpath ? lxcpath : stub_default_lxcpath(), name);
+	if (r)
+		ret = stub_strdup(r->template);
+	stub_unlock();
+	return ret;
+}
+
+/* Argument `idx` of the argv passed to create() ("create") or start()
+ * ("start"), or NULL past the end. Caller frees. */
+char *lxc_stub_argv(const char *lxcpath, const char *name, const char *op, int idx)
+{
+	struct stub_record *r;
+	char *ret = NULL;
+
+	stub_lock();
+	r = stub_find(lxcpath ? lxcpath : stub_default_lxcpath(), name);
+	if (r && idx >= 0 && idx < STUB_MAX_ARGS) {
+		char **argv = strcmp(op, "start") == 0 ? r->start_argv : r->create_argv;
+		int i;
+
+		for (i = 0; i < idx && argv[i]; i++)
+			;
+		if (i == idx)
+			ret = stub_strdup(argv[idx]);
+	}
+	stub_unlock();
+	return ret;
+}
+
+/* Pretend the current time is `now` (0 for the real clock) for snapshot timestamps. */
+void lxc_stub_set_time(long now)
+{
+	stub_lock();
+	stub_time = (time_t)now;
+	stub_unlock();
+}
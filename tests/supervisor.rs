@@ -0,0 +1,134 @@
+//! Restart supervision against the liblxc stub.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use liblxc::supervisor::{Backoff, ExitReason};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn running(name: &str) -> LxcContainer {
+  let c = LxcContainer::new(name, None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  assert!(c.start(0, None));
+  c
+}
+
+fn immediate() -> Backoff {
+  Backoff { initial: Duration::from_secs(0), ..Backoff::default() }
+}
+
+#[test]
+fn backoff_grows_exponentially() {
+  let backoff = Backoff::default();
+  assert_eq!(backoff.delay(0), Duration::from_secs(1));
+  assert_eq!(backoff.delay(1), Duration::from_secs(1));
+  assert_eq!(backoff.delay(2), Duration::from_secs(2));
+  assert_eq!(backoff.delay(4), Duration::from_secs(8));
+  assert_eq!(backoff.delay(9), Duration::from_secs(256));
+  assert_eq!(backoff.delay(10), Duration::from_secs(300));
+  assert_eq!(backoff.delay(1000), Duration::from_secs(300));
+}
+
+#[test]
+fn policies() {
+  let stub = support::stub();
+  let always = running("always");
+  let never = running("never");
+  let on_failure = running("on-failure");
+  let mut supervisor = Supervisor::new(None);
+  supervisor.watch("always", RestartPolicy::Always, immediate());
+  supervisor.watch("never", RestartPolicy::Never, immediate());
+  supervisor.watch("on-failure", RestartPolicy::OnFailure { max_retries: 3 }, immediate());
+  supervisor.poll();
+
+  // Clean exits reported by the monitor.
+  for name in ["always", "never", "on-failure"].iter() {
+    supervisor.handle_event(&MonitorEvent::ExitCode { name: name.to_string(), status: 0 });
+    stub.set_state(None, name, "STOPPED");
+  }
+  supervisor.poll();
+  assert!(always.is_running());
+  assert!(!never.is_running());
+  assert!(!on_failure.is_running());
+  let states = supervisor.states();
+  assert_eq!(states.iter().map(|s| s.restarts).collect::<Vec<_>>(), vec![1, 0, 0]);
+  assert!(states.iter().all(|s| s.last_exit == Some(ExitReason::Exited(0))));
+  assert!(states.iter().all(|s| s.consecutive_failures == 0 && !s.gave_up));
+
+  // A stop without an exit status counts as a failure.
+  assert!(on_failure.start(0, None));
+  supervisor.poll();
+  stub.set_state(None, "on-failure", "STOPPED");
+  supervisor.poll();
+  assert!(on_failure.is_running());
+  let state = supervisor.state("on-failure").unwrap();
+  assert_eq!(state.restarts, 1);
+  assert_eq!(state.consecutive_failures, 1);
+  assert_eq!(state.last_exit, Some(ExitReason::Stopped));
+  assert_eq!(state.next_restart, None);
+
+  supervisor.unwatch("always");
+  assert!(supervisor.state("always").is_none());
+  stub.set_state(None, "always", "STOPPED");
+  supervisor.poll();
+  assert!(!always.is_running());
+}
+
+#[test]
+fn gives_up_after_max_retries() {
+  let stub = support::stub();
+  let c = running("flaky");
+  let mut supervisor = Supervisor::new(None);
+  supervisor.watch("flaky", RestartPolicy::OnFailure { max_retries: 2 }, immediate());
+  supervisor.poll();
+  stub.fail("flaky", "start");
+  stub.set_state(None, "flaky", "STOPPED");
+  for _ in 0..5 {
+    supervisor.poll();
+  }
+  let state = supervisor.state("flaky").unwrap();
+  assert!(!c.is_running());
+  assert!(state.gave_up);
+  assert_eq!(state.restarts, 0);
+  assert_eq!(state.consecutive_failures, 3);
+  assert_eq!(state.last_exit, Some(ExitReason::StartFailed));
+  assert_eq!(state.next_restart, None);
+}
+
+#[test]
+fn waits_for_the_backoff() {
+  let stub = support::stub();
+  let c = running("slow");
+  let mut supervisor = Supervisor::new(None);
+  let backoff = Backoff { initial: Duration::from_secs(60), ..Backoff::default() };
+  supervisor.watch("slow", RestartPolicy::Always, backoff);
+  supervisor.poll();
+  stub.set_state(None, "slow", "STOPPED");
+  let before = Instant::now();
+  supervisor.poll();
+  assert!(!c.is_running());
+  let due = supervisor.state("slow").unwrap().next_restart.unwrap();
+  assert!(due >= before + Duration::from_secs(60));
+}
+
+#[test]
+fn background_thread() {
+  let stub = support::stub();
+  let c = running("bg");
+  let mut supervisor = Supervisor::new(None);
+  supervisor.watch("bg", RestartPolicy::Always, immediate());
+  let handle = supervisor.spawn(Duration::from_millis(10));
+  thread::sleep(Duration::from_millis(50));
+  stub.set_state(None, "bg", "STOPPED");
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while handle.lock().state("bg").unwrap().restarts == 0 && Instant::now() < deadline {
+    thread::sleep(Duration::from_millis(10));
+  }
+  let supervisor = handle.stop();
+  assert_eq!(supervisor.state("bg").unwrap().restarts, 1);
+  assert!(c.is_running());
+}
@@ -0,0 +1,148 @@
+//! Bindings to the test controls exported by the liblxc stub (tests/stub/lxc.c).
+#![allow(dead_code)]
+
+extern crate libc;
+
+use self::libc::{c_char, c_int, c_void};
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+#[link(name = "lxc")]
+extern "C" {
+  fn lxc_stub_reset();
+  fn lxc_stub_fail(name: *const c_char, op: *const c_char);
+  fn lxc_stub_set_lxcpath(lxcpath: *const c_char);
+  fn lxc_stub_set_version(version: *const c_char);
+  fn lxc_stub_set_state(lxcpath: *const c_char, name: *const c_char, state: *const c_char);
+  fn lxc_stub_set_init_pid(lxcpath: *const c_char, name: *const c_char, pid: c_int);
+  fn lxc_stub_live_containers() -> c_int;
+  fn lxc_stub_template(lxcpath: *const c_char, name: *const c_char) -> *mut c_char;
+  fn lxc_stub_argv(lxcpath: *const c_char, name: *const c_char,
+                   op: *const c_char, idx: c_int) -> *mut c_char;
+  fn lxc_stub_set_time(now: libc::c_long);
+  fn lxc_stub_event(lxcpath: *const c_char, name: *const c_char, op: *const c_char) -> c_int;
+}
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Exclusive access to the stub for the duration of a test. The stub keeps a
+/// single global host, so tests inside one binary must not interleave.
+pub struct Stub {
+  _guard: MutexGuard<'static, ()>,
+}
+
+/// Takes the stub lock and resets the stub to an empty host.
+pub fn stub() -> Stub {
+  let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+  unsafe { lxc_stub_reset() };
+  Stub { _guard: guard }
+}
+
+/// Empty directory `rust-lxc-NAME-PID` in the temporary directory, for the files of
+/// test `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+  let dir = env::temp_dir().join(format!("rust-lxc-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+fn opt_cstring(s: Option<&str>) -> Option<CString> {
+  s.map(|s| CString::new(s).unwrap())
+}
+
+fn opt_ptr(s: &Option<CString>) -> *const c_char {
+  s.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null())
+}
+
+fn take_string(p: *mut c_char) -> Option<String> {
+  if p.is_null() {
+    return None;
+  }
+  let s = unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+  unsafe { libc::free(p as *mut c_void) };
+  Some(s)
+}
+
+impl Stub {
+  /// Makes `op` fail for container `name` ("*" for all of them).
+  pub fn fail(&self, name: &str, op: &str) {
+    let name = CString::new(name).unwrap();
+    let op = CString::new(op).unwrap();
+    unsafe { lxc_stub_fail(name.as_ptr(), op.as_ptr()) }
+  }
+
+  /// Changes the default lxcpath reported by the stub.
+  pub fn set_lxcpath(&self, lxcpath: &str) {
+    let lxcpath = CString::new(lxcpath).unwrap();
+    unsafe { lxc_stub_set_lxcpath(lxcpath.as_ptr()) }
+  }
+
+  /// Changes the string returned by `lxc_get_version`.
+  pub fn set_version(&self, version: &str) {
+    let version = CString::new(version).unwrap();
+    unsafe { lxc_stub_set_version(version.as_ptr()) }
+  }
+
+  /// Forces container `name` into `state` behind the library's back.
+  pub fn set_state(&self, lxcpath: Option<&str>, name: &str, state: &str) {
+    let lxcpath = opt_cstring(lxcpath);
+    let name = CString::new(name).unwrap();
+    let state = CString::new(state).unwrap();
+    unsafe { lxc_stub_set_state(opt_ptr(&lxcpath), name.as_ptr(), state.as_ptr()) }
+  }
+
+  /// Makes running container `name` report `pid` as its init process.
+  pub fn set_init_pid(&self, lxcpath: Option<&str>, name: &str, pid: u32) {
+    let lxcpath = opt_cstring(lxcpath);
+    let name = CString::new(name).unwrap();
+    unsafe { lxc_stub_set_init_pid(opt_ptr(&lxcpath), name.as_ptr(), pid as c_int) }
+  }
+
+  /// Fixes the time used for snapshot timestamps, in seconds since the epoch.
+  pub fn set_time(&self, now: i64) {
+    unsafe { lxc_stub_set_time(now as libc::c_long) }
+  }
+
+  /// Number of container handles not yet released with `lxc_container_put`.
+  pub fn live_containers(&self) -> i32 {
+    unsafe { lxc_stub_live_containers() }
+  }
+
+  /// Position of the last `op` ("start" or "stop") of container `name` among all
+  /// starts and stops, counting from 1; 0 if it never happened.
+  pub fn event(&self, lxcpath: Option<&str>, name: &str, op: &str) -> i32 {
+    let lxcpath = opt_cstring(lxcpath);
+    let name = CString::new(name).unwrap();
+    let op = CString::new(op).unwrap();
+    unsafe { lxc_stub_event(opt_ptr(&lxcpath), name.as_ptr(), op.as_ptr()) }
+  }
+
+  /// Template passed to `create` for container `name`.
+  pub fn template(&self, lxcpath: Option<&str>, name: &str) -> Option<String> {
+    let lxcpath = opt_cstring(lxcpath);
+    let name = CString::new(name).unwrap();
+    take_string(unsafe { lxc_stub_template(opt_ptr(&lxcpath), name.as_ptr()) })
+  }
+
+  /// Argv passed to `op` ("create" or "start") for container `name`.
+  pub fn argv(&self, lxcpath: Option<&str>, name: &str, op: &str) -> Vec<String> {
+    let lxcpath = opt_cstring(lxcpath);
+    let name = CString::new(name).unwrap();
+    let op = CString::new(op).unwrap();
+    let mut argv = Vec::new();
+    loop {
+      let arg = unsafe {
+        lxc_stub_argv(opt_ptr(&lxcpath), name.as_ptr(), op.as_ptr(), argv.len() as c_int)
+      };
+      match take_string(arg) {
+        Some(arg) => argv.push(arg),
+        None => return argv,
+      }
+    }
+  }
+}
@@ -0,0 +1,84 @@
+//! Template discovery and typed template arguments.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+fn strings(args: &[&str]) -> Vec<String> {
+  args.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn lists_executable_templates() {
+  let dir = env::temp_dir().join(format!("rust-lxc-templates-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(dir.join("lxc-dir")).unwrap();
+  for (file, mode) in [("lxc-ubuntu", 0o755), ("lxc-busybox", 0o755), ("lxc-readme", 0o644),
+                       ("download", 0o755), ("lxc-", 0o755)].iter() {
+    let path = dir.join(file);
+    fs::write(&path, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(*mode)).unwrap();
+  }
+  assert_eq!(list_templates(dir.to_str()), Ok(strings(&["busybox", "ubuntu"])));
+  fs::remove_dir_all(&dir).unwrap();
+  assert_eq!(list_templates(dir.to_str()), Err("Failed to read the templates directory"));
+}
+
+#[test]
+fn builders_produce_argv() {
+  let download = DownloadTemplate::new("ubuntu", "trusty", "amd64");
+  assert_eq!(download.name(), "download");
+  assert_eq!(download.args(), strings(&["--dist", "ubuntu", "--release", "trusty", "--arch", "amd64"]));
+  let download = download.variant("default").server("images.example.com").keyserver("keys.example.com")
+                         .no_validate(true).flush_cache(true);
+  assert_eq!(download.args(), strings(&["--dist", "ubuntu", "--release", "trusty", "--arch", "amd64",
+                                        "--variant", "default", "--server", "images.example.com",
+                                        "--keyserver", "keys.example.com", "--no-validate", "--flush-cache"]));
+
+  assert_eq!(DebianTemplate::new().args(), Vec::<String>::new());
+  let debian = DebianTemplate::new().release("jessie").arch("i386").mirror("http://mirror/debian")
+                                    .package("vim").package("curl");
+  assert_eq!(debian.args(), strings(&["--release", "jessie", "--arch", "i386", "--mirror", "http://mirror/debian",
+                                      "--packages", "vim,curl"]));
+
+  let ubuntu = UbuntuTemplate::new().release("trusty").user("admin").auth_key("/root/.ssh/id_rsa.pub")
+                                    .flush_cache(true);
+  assert_eq!(ubuntu.name(), "ubuntu");
+  assert_eq!(ubuntu.args(), strings(&["--release", "trusty", "--user", "admin",
+                                      "--auth-key", "/root/.ssh/id_rsa.pub", "--flush-cache"]));
+
+  assert_eq!(DownloadTemplate::new("ubuntu", "", "amd64").validate(), Err("Invalid release"));
+  assert_eq!(DownloadTemplate::new("ubuntu", "trusty", "amd 64").validate(), Err("Invalid architecture"));
+  assert_eq!(DebianTemplate::new().package("a,b").validate(), Err("Invalid package name"));
+  assert_eq!(UbuntuTemplate::new().user("").validate(), Err("Invalid user name"));
+  assert_eq!(ubuntu.validate(), Ok(()));
+}
+
+#[test]
+fn create_from_passes_the_arguments() {
+  let stub = support::stub();
+  let c = LxcContainer::new("web", None).unwrap();
+  let bad = DownloadTemplate::new("", "trusty", "amd64");
+  assert_eq!(c.create_from(&bad, None, BDevSpecs::new(), LxcCreateFlag::Quiet), Err("Invalid distribution"));
+  assert!(!c.is_defined());
+
+  let template = DownloadTemplate::new("alpine", "3.4", "amd64").variant("default");
+  assert_eq!(c.create_from(&template, None, BDevSpecs::new(), LxcCreateFlag::Quiet), Ok(()));
+  assert_eq!(stub.template(None, "web"), Some("download".to_string()));
+  assert_eq!(stub.argv(None, "web", "create"), template.args());
+
+  let boxed: Box<dyn Template> = Box::new(DebianTemplate::new());
+  let c = LxcContainer::new("db", None).unwrap();
+  assert_eq!(c.create_from(&*boxed, None, BDevSpecs::new(), LxcCreateFlag::Quiet), Ok(()));
+  assert_eq!(stub.argv(None, "db", "create"), Vec::<String>::new());
+  stub.fail("db2", "create");
+  let c = LxcContainer::new("db2", None).unwrap();
+  assert_eq!(c.create_from(&*boxed, None, BDevSpecs::new(), LxcCreateFlag::Quiet),
+             Err("Failed to create the container"));
+}
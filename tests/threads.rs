@@ -0,0 +1,132 @@
+//! Concurrent use of containers against the liblxc stub.
+#![cfg(feature = "lxc-stub")]
+
+extern crate liblxc;
+
+mod support;
+
+use liblxc::*;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const THREADS: usize = 8;
+const ROUNDS: usize = 200;
+
+#[test]
+fn handles_move_between_threads() {
+  let stub = support::stub();
+  let c = LxcContainer::new("moved", None).unwrap();
+  let c = thread::spawn(move || {
+    assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+    c
+  }).join().unwrap();
+  assert!(c.is_defined());
+  thread::spawn(move || drop(c)).join().unwrap();
+  assert_eq!(stub.live_containers(), 0);
+}
+
+#[test]
+fn shared_container_serializes_calls() {
+  let stub = support::stub();
+  let shared = SharedLxcContainer::new(LxcContainer::new("hammered", None).unwrap());
+  assert!(shared.lock().create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+
+  let workers = (0..THREADS).map(|t| {
+    let shared = shared.clone();
+    thread::spawn(move || {
+      for i in 0..ROUNDS {
+        let c = shared.lock();
+        let value = format!("{}-{}", t, i);
+        assert!(c.set_config_item("lxc.utsname", &value));
+        assert_eq!(c.get_config_item("lxc.utsname"), Ok(value));
+        if c.is_running() {
+          assert!(c.stop());
+        }
+        else {
+          assert!(c.start(0, None));
+        }
+      }
+    })
+  }).collect::<Vec<_>>();
+  for worker in workers {
+    worker.join().unwrap();
+  }
+
+  assert_eq!(shared.lock().state(), "STOPPED");
+  drop(shared);
+  assert_eq!(stub.live_containers(), 0);
+}
+
+#[test]
+fn shared_containers_of_one_container_share_the_handle() {
+  let stub = support::stub();
+  let first = SharedLxcContainer::new(LxcContainer::new("twice", None).unwrap());
+  assert!(first.lock().create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let second = SharedLxcContainer::new(LxcContainer::new("twice", None).unwrap());
+  assert!(first.lock().set_config_item("lxc.utsname", "first"));
+  assert_eq!(second.lock().get_config_item("lxc.utsname"), Ok("first".to_string()));
+  assert_eq!(stub.live_containers(), 1);
+
+  // A handle of another lxcpath is another container.
+  let other = SharedLxcContainer::new(LxcContainer::new("twice", Some("/tmp/other-lxcpath")).unwrap());
+  assert_eq!(stub.live_containers(), 2);
+  drop((first, second, other));
+  assert_eq!(stub.live_containers(), 0);
+}
+
+#[test]
+fn forking_operations_on_many_containers() {
+  let stub = support::stub();
+  let workers = (0..THREADS).map(|t| {
+    thread::spawn(move || {
+      let name = format!("parallel-{}", t);
+      let c = LxcContainer::new(&name, None).unwrap();
+      assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+      for _ in 0..ROUNDS / 10 {
+        assert!(c.start(0, None));
+        assert!(c.stop());
+      }
+      let copy = c.clone(Some(&format!("{}-copy", name)), None, LxcCloneFlag::Void,
+                         None, None, 0, None).unwrap();
+      assert!(copy.is_defined());
+    })
+  }).collect::<Vec<_>>();
+  for worker in workers {
+    worker.join().unwrap();
+  }
+  assert_eq!(stub.live_containers(), 0);
+}
+
+#[test]
+fn fork_guard_excludes_forking_operations() {
+  let _stub = support::stub();
+  let c = LxcContainer::new("guarded", None).unwrap();
+  assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+  let guard = fork_guard();
+  let starter = thread::spawn(move || c.start(0, None));
+  thread::sleep(std::time::Duration::from_millis(100));
+  let check = LxcContainer::new("guarded", None).unwrap();
+  assert!(!check.is_running());
+  drop(guard);
+  assert!(starter.join().unwrap());
+  assert!(check.is_running());
+}
+
+#[test]
+fn fork_guard_spares_long_operations() {
+  let _stub = support::stub();
+  let guard = fork_guard();
+  let (tx, rx) = mpsc::channel();
+  thread::spawn(move || {
+    let c = LxcContainer::new("long", None).unwrap();
+    assert!(c.create("busybox", None, BDevSpecs::new(), LxcCreateFlag::Quiet, None));
+    c.clone(Some("long-copy"), None, LxcCloneFlag::Void, None, None, 0, None).unwrap();
+    // In the foreground, start only returns once the container stopped.
+    assert!(c.want_daemonize(false));
+    assert!(c.start(0, None));
+    tx.send(()).unwrap();
+  });
+  assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+  drop(guard);
+}